@@ -1,8 +1,17 @@
-use std::path::PathBuf;
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use clap::{Args, Parser, Subcommand};
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
 use url::Url;
 
+use crate::{
+    client::{HeaderProfile, RotationStrategy},
+    dns::RecordType,
+};
+
 #[derive(Parser)]
 #[command(version, about)]
 pub struct ProgramArgs {
@@ -17,12 +26,50 @@ pub struct ProgramArgs {
     /// Write logging output to a file.
     pub log_file: Option<PathBuf>,
 
+    #[arg(long, value_parser = parse_byte_size, requires = "log_file")]
+    /// Rotate the log file when it exceeds the given size (such as 100M).
+    pub log_max_size: Option<u64>,
+
+    #[arg(long, default_value_t = 5, requires = "log_max_size")]
+    /// Number of rotated log files to keep.
+    pub log_max_files: usize,
+
     #[arg(long)]
-    /// Send logging output to Systemd's Journal service.
+    /// Send logging output to Systemd's Journal service (requires the
+    /// journald feature).
     pub log_journald: bool,
+
+    #[arg(long)]
+    /// Send logging output to the local syslog daemon.
+    pub log_syslog: bool,
+
+    #[arg(long)]
+    /// Export TLS session keys to a file (same as setting SSLKEYLOGFILE).
+    ///
+    /// Fails if libcurl is built with rustls, which doesn't support it.
+    pub ssl_key_log: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value = "text")]
+    /// Format of the error printed when the command fails.
+    pub error_format: ErrorFormat,
+
+    #[arg(long)]
+    /// Write the error as a JSON object to a file when the command fails.
+    pub error_file: Option<PathBuf>,
+
+    #[arg(long)]
+    /// Write a crash report file to the given directory if the program
+    /// panics.
+    pub crash_report_dir: Option<PathBuf>,
+
+    #[cfg(feature = "otel")]
+    #[arg(long)]
+    /// Export traces to an OpenTelemetry collector at the given OTLP/HTTP URL.
+    pub log_otlp: Option<Url>,
 }
 
 #[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
 pub enum Command {
     /// Download a file.
     Fetch(FetchArgs),
@@ -32,14 +79,225 @@ pub enum Command {
 }
 
 #[derive(Args)]
+#[command(group(ArgGroup::new("batch").args(["input_file", "url_template"])))]
 pub struct FetchArgs {
     /// URL of file to download.
-    pub url: Url,
+    #[arg(required_unless_present_any = ["input_file", "url_template"])]
+    pub url: Option<Url>,
 
-    /// Save downloaded file to given path.
+    /// Save downloaded file to given path, or write it to stdout with -.
+    ///
+    /// With --input-file, this is a directory in which files are named by
+    /// their line number in the input file.
     #[arg(short, long)]
     pub output: Option<PathBuf>,
 
+    /// Write only the downloaded content to stdout, unchanged, such as for
+    /// piping it to another program. Logs and warnings are written to
+    /// stderr.
+    ///
+    /// --output may only be given as "-".
+    #[arg(
+        long,
+        conflicts_with_all = ["batch", "spider", "transcode_to_utf8"]
+    )]
+    pub raw: bool,
+
+    /// Download the URLs listed one per line in the given file.
+    ///
+    /// Requires --output unless --spider is given.
+    #[arg(
+        short,
+        long,
+        conflicts_with_all = ["url", "output_response", "output_request", "output_events", "trace"]
+    )]
+    pub input_file: Option<PathBuf>,
+
+    /// Download the URLs expanded from the given template, such as
+    /// https://example.com/%Y/page[1-10].{html,txt}, like --input-file.
+    ///
+    /// Ranges [1-100], [001-100], [a-z], and [0-100:10] and sets {a,b}
+    /// are expanded. Time tokens %Y, %m, %d, %H, %M, and %S are replaced
+    /// with the current UTC time; write %% for a literal percent sign.
+    #[arg(
+        long,
+        conflicts_with_all = ["url", "input_file", "output_response", "output_request", "output_events", "trace"]
+    )]
+    pub url_template: Option<String>,
+
+    /// Name files in the output directory of --input-file or
+    /// --url-template by the given template instead of the line number.
+    ///
+    /// Variables are {host}, {path}, {ts} (start time), {ext} (file
+    /// extension), and {n} (line number or position).
+    /// The batch fails to start if two items would get the same name.
+    #[arg(long, requires = "batch")]
+    pub output_template: Option<String>,
+
+    /// Record the progress of --input-file or --url-template to the given
+    /// path, so that running the same command again resumes it.
+    ///
+    /// Defaults to .wrecv-batch.json in the output directory.
+    #[arg(long, requires = "batch")]
+    pub state_file: Option<PathBuf>,
+
+    /// Fetch only the URLs of --input-file or --url-template that failed
+    /// previously.
+    #[arg(long, requires = "batch")]
+    pub retry_failed: bool,
+
+    /// Save the statistics of each host, such as its error rate and
+    /// average time to first byte, as JSON to the given path when
+    /// --input-file or --url-template finishes.
+    #[arg(long, requires = "batch")]
+    pub host_stats: Option<PathBuf>,
+
+    /// Save a JSON report of the outcome of each URL, with totals, to the
+    /// given path when --input-file or --url-template finishes.
+    #[arg(long, requires = "batch")]
+    pub report: Option<PathBuf>,
+
+    /// Number of transfers run at the same time with --input-file or
+    /// --url-template.
+    #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    pub jobs: u16,
+
+    /// Skip the remaining URLs of a host with --input-file or
+    /// --url-template once the given number of its URLs were fetched.
+    #[arg(long, requires = "batch", value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_pages_per_host: Option<u64>,
+
+    /// Skip the remaining URLs of a host with --input-file or
+    /// --url-template once the given number of bytes were received from it.
+    #[arg(long, requires = "batch", value_parser = parse_byte_size)]
+    pub max_bytes_per_host: Option<u64>,
+
+    /// Stop --input-file or --url-template after the given duration (such
+    /// as 30m or 8h). The remaining URLs are resumed by running the same
+    /// command again.
+    #[arg(long, requires = "batch", value_parser = parse_duration)]
+    pub max_run_time: Option<Duration>,
+
+    /// Number of transfers run at the same time to a single host with
+    /// --input-file or --url-template.
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u16).range(1..))]
+    pub per_host: u16,
+
+    /// Check that the URLs exist without saving them, printing the status
+    /// code, content type, and size of each.
+    ///
+    /// A HEAD request is sent, falling back to GET with the body discarded
+    /// if the server doesn't allow HEAD.
+    #[arg(
+        long,
+        conflicts_with_all = ["output", "output_template", "state_file", "retry_failed", "max_run_time", "output_response", "output_request"]
+    )]
+    pub spider: bool,
+
+    /// Send the given data as the body of a POST request.
+    ///
+    /// Data starting with @ is read from the file of the given path, or
+    /// from stdin with @-, which is sent with chunked transfer coding as it
+    /// is read.
+    #[arg(short, long, conflicts_with_all = ["batch", "spider"])]
+    pub data: Option<String>,
+
+    /// Send the file of the given path, or stdin with -, as the body of a
+    /// PUT request.
+    #[arg(short = 'T', long, conflicts_with_all = ["data", "batch", "spider"])]
+    pub upload_file: Option<PathBuf>,
+
+    /// Reject host names that mix scripts in a confusable way.
+    #[arg(long)]
+    pub reject_confusable: bool,
+
+    /// Reject URLs that aren't HTTP or HTTPS or that resolve to loopback,
+    /// private, link-local, multicast, or reserved addresses, such as for
+    /// untrusted URL lists.
+    #[arg(long)]
+    pub public_only: bool,
+
+    /// Resolve host names with the suggested DNS-over-HTTPS servers instead
+    /// of the system resolver.
+    #[arg(long)]
+    pub doh: bool,
+
+    /// Resolve host names with the DoH server with the given URL or URI
+    /// template. Implies --doh. Can be given multiple times.
+    #[arg(long = "doh-url")]
+    pub doh_url: Vec<String>,
+
+    /// Connect through the proxy with the given URL, such as
+    /// http://proxy:3128 or socks5h://proxy:1080.
+    #[arg(long)]
+    pub proxy: Option<Url>,
+
+    /// Tunnel HTTP transfers through the HTTP proxy with CONNECT. FTP
+    /// transfers are always tunneled.
+    #[arg(long, requires = "proxy")]
+    pub proxy_tunnel: bool,
+
+    /// Send the headers of a web browser (chrome, firefox, or safari).
+    #[arg(long)]
+    pub header_profile: Option<HeaderProfile>,
+
+    /// Send the given User-Agent value. When given multiple times, requests
+    /// take turns using them.
+    #[arg(long)]
+    pub user_agent: Vec<String>,
+
+    /// How requests take turns using the values of --user-agent
+    /// (round-robin or per-host).
+    #[arg(long, default_value = "round-robin", requires = "user_agent")]
+    pub user_agent_rotation: RotationStrategy,
+
+    /// Authenticate with NTLM using the credentials of --user.
+    #[cfg(feature = "ntlm")]
+    #[arg(long, requires = "user")]
+    #[cfg_attr(feature = "spnego", arg(conflicts_with = "negotiate"))]
+    pub ntlm: bool,
+
+    /// User name and password for --ntlm, separated by a colon, such as
+    /// DOMAIN\user:password.
+    #[cfg(feature = "ntlm")]
+    #[arg(long, value_parser = parse_credentials, requires = "ntlm")]
+    pub user: Option<(String, String)>,
+
+    /// Authenticate with SPNEGO using the Kerberos credentials of the
+    /// current user.
+    #[cfg(feature = "spnego")]
+    #[arg(long)]
+    pub negotiate: bool,
+
+    /// Send the given Accept-Language value, such as de-DE,de;q=0.9,
+    /// instead of the one of the header profile.
+    #[arg(long)]
+    pub accept_language: Option<String>,
+
+    /// Abort the transfer if it takes longer than the given duration (such
+    /// as 90s or 30m).
+    #[arg(long, value_parser = parse_duration)]
+    pub max_time: Option<Duration>,
+
+    /// Stop fetching from a host for a while after the given number of
+    /// consecutive failures.
+    #[arg(long, default_value_t = 0)]
+    pub circuit_breaker: u32,
+
+    /// Free space to keep on the filesystem of the output file.
+    ///
+    /// Only Unix filesystems are checked.
+    #[arg(long, value_parser = parse_byte_size, default_value = "0")]
+    pub disk_reserve: u64,
+
+    /// Decode text content by the charset of its Content-Type or HTML meta
+    /// element and save it as UTF-8. The original charset is saved to the
+    /// output path with a ".charset" suffix, or logged without an output path.
+    /// Content that is not text or has no supported charset is saved
+    /// unchanged, recording its charset label or "unknown".
+    #[arg(long)]
+    pub transcode_to_utf8: bool,
+
     /// Save protocol download data to given path.
     #[arg(short = 'r', long)]
     pub output_response: Option<PathBuf>,
@@ -47,14 +305,271 @@ pub struct FetchArgs {
     /// Save protocol upload data to given path.
     #[arg(short = 'q', long)]
     pub output_request: Option<PathBuf>,
+
+    /// Save a JSON Lines transcript of session events to given path.
+    #[arg(long)]
+    pub output_events: Option<PathBuf>,
+
+    /// Include the data of events in the transcript as base64.
+    #[arg(long, requires = "output_events")]
+    pub output_events_data: bool,
+
+    /// Save the full diagnostic stream of the transfer to given path.
+    #[arg(long)]
+    pub trace: Option<PathBuf>,
+
+    /// Flush written files to disk when the transfer completes.
+    #[arg(long)]
+    pub fsync: bool,
+
+    /// Also flush the directories of written files to disk.
+    #[arg(long, requires = "fsync")]
+    pub fsync_dir: bool,
+}
+
+impl FetchArgs {
+    /// Path of the downloaded file, or `None` if it is written to stdout.
+    pub fn output_file(&self) -> Option<&Path> {
+        self.output
+            .as_deref()
+            .filter(|path| path.as_os_str() != "-")
+    }
+
+    /// Whether the downloaded content is written to stdout.
+    pub fn writes_stdout(&self) -> bool {
+        self.raw
+            || !self.spider
+                && self.input_file.is_none()
+                && self.url_template.is_none()
+                && self.output_file().is_none()
+    }
 }
 
 #[derive(Args)]
 pub struct LookupArgs {
     /// Domain name of the host.
-    pub name: String,
+    #[arg(required_unless_present = "input_file")]
+    pub name: Option<String>,
 
-    /// Output in JSON format.
-    #[arg(short, long)]
+    /// Look up the names listed one per line in the given file, printing
+    /// each result as soon as it arrives.
+    #[arg(short, long, conflicts_with = "name")]
+    pub input_file: Option<PathBuf>,
+
+    /// Number of lookups run at the same time with --input-file.
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u16).range(1..))]
+    pub jobs: u16,
+
+    /// Output in JSON format. Same as --format json.
+    #[arg(short, long, conflicts_with = "format")]
     pub json: bool,
+
+    /// Format of the output.
+    #[arg(long, value_enum, default_value_t = LookupFormat::Plain)]
+    pub format: LookupFormat,
+
+    /// Look up records of the given type (A, AAAA, TXT, MX, SRV, NS, CNAME,
+    /// HTTPS, or ANY) instead of addresses.
+    #[arg(short = 't', long = "type")]
+    pub record_type: Option<RecordType>,
+
+    /// Reject domain names that mix scripts in a confusable way.
+    #[arg(long)]
+    pub reject_confusable: bool,
+
+    /// Query the DoH server with the given URL or URI template instead of
+    /// the suggested servers. Can be given multiple times.
+    #[arg(long)]
+    pub doh_url: Vec<String>,
+
+    /// Query the DoH server with the given address and TLS name, such as
+    /// 1.1.1.1,cloudflare-dns.com, instead of the suggested servers. Can be
+    /// given multiple times.
+    #[arg(long, value_parser = parse_doh_server)]
+    pub doh: Vec<(SocketAddr, String)>,
+
+    /// Query the DNS-over-TLS server with the given address and TLS name,
+    /// such as 9.9.9.9,dns.quad9.net, instead of the suggested servers.
+    /// Can be given multiple times.
+    #[arg(long, value_parser = parse_dot_server)]
+    pub dot: Vec<(SocketAddr, String)>,
+
+    /// Query the plain DNS server with the given address instead of the
+    /// suggested servers. Can be given multiple times.
+    #[arg(long, value_parser = parse_dns_server_address)]
+    pub udp: Vec<SocketAddr>,
+
+    /// Query the resolver of the operating system instead of the suggested
+    /// servers.
+    #[arg(long)]
+    pub system: bool,
+
+    /// Resolve the host names of --doh-url with the given plain DNS server
+    /// address. Can be given multiple times.
+    #[arg(long, value_parser = parse_dns_server_address, requires = "doh_url")]
+    pub bootstrap_dns: Vec<SocketAddr>,
+
+    /// Resolve names listed in the given file, in the /etc/hosts format,
+    /// to its addresses without querying servers.
+    #[arg(long)]
+    pub hosts_file: Option<PathBuf>,
+
+    /// Load cached answers from the given file and save them back after
+    /// the lookup, so repeated runs don't query servers again until the
+    /// TTL expires.
+    #[arg(long)]
+    pub dns_cache: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ErrorFormat {
+    /// Error message.
+    Text,
+    /// A JSON object with the category, message, libcurl error code, URL,
+    /// and whether the error is retriable.
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LookupFormat {
+    /// Addresses one per line, or a tab separated line per name with
+    /// --input-file.
+    Plain,
+    /// Answer records like the answer section of dig.
+    Dig,
+    /// Pretty printed JSON document, or JSON lines with --input-file.
+    Json,
+    /// A JSON document per line.
+    Jsonl,
+    /// A row per answer record with a header row.
+    Csv,
+}
+
+/// Parses a byte count with an optional K, M, G, or T binary suffix.
+pub fn parse_byte_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((index, suffix)) if suffix.is_ascii_alphabetic() => {
+            let multiplier = match suffix.to_ascii_uppercase() {
+                'K' => 1u64 << 10,
+                'M' => 1 << 20,
+                'G' => 1 << 30,
+                'T' => 1 << 40,
+                _ => return Err(format!("unknown size suffix {:?}", suffix)),
+            };
+            (&value[..index], multiplier)
+        }
+        _ => (value, 1),
+    };
+
+    let number = number
+        .parse::<u64>()
+        .map_err(|error| format!("invalid size {:?}: {}", value, error))?;
+
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size {:?} is too large", value))
+}
+
+/// Parses a duration in seconds with an optional s, m, or h suffix.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((index, suffix)) if suffix.is_ascii_alphabetic() => {
+            let multiplier = match suffix.to_ascii_lowercase() {
+                's' => 1.0,
+                'm' => 60.0,
+                'h' => 3600.0,
+                _ => return Err(format!("unknown duration suffix {:?}", suffix)),
+            };
+            (&value[..index], multiplier)
+        }
+        _ => (value, 1.0),
+    };
+
+    let number = number
+        .parse::<f64>()
+        .map_err(|error| format!("invalid duration {:?}: {}", value, error))?;
+
+    Duration::try_from_secs_f64(number * multiplier)
+        .map_err(|error| format!("invalid duration {:?}: {}", value, error))
+}
+
+/// Parses an IP address with an optional port, defaulting to port 53.
+pub fn parse_dns_server_address(value: &str) -> Result<SocketAddr, String> {
+    parse_server_address(value, 53)
+}
+
+/// Parses `address,name` of a DoH server, defaulting to port 443.
+pub fn parse_doh_server(value: &str) -> Result<(SocketAddr, String), String> {
+    parse_named_server(value, 443)
+}
+
+/// Parses `address,name` of a DoT server, defaulting to port 853.
+pub fn parse_dot_server(value: &str) -> Result<(SocketAddr, String), String> {
+    parse_named_server(value, 853)
+}
+
+fn parse_server_address(value: &str, default_port: u16) -> Result<SocketAddr, String> {
+    if let Ok(address) = value.parse::<SocketAddr>() {
+        return Ok(address);
+    }
+
+    value
+        .parse::<IpAddr>()
+        .map(|address| SocketAddr::new(address, default_port))
+        .map_err(|error| format!("invalid server address {:?}: {}", value, error))
+}
+
+/// Parses a user name and password separated by a colon.
+#[cfg(feature = "ntlm")]
+pub fn parse_credentials(value: &str) -> Result<(String, String), String> {
+    match value.split_once(':') {
+        Some((username, password)) if !username.is_empty() => {
+            Ok((username.to_string(), password.to_string()))
+        }
+        _ => Err("expected a user name and password separated by a colon".to_string()),
+    }
+}
+
+/// Parses an address and the TLS server name separated by a comma.
+fn parse_named_server(value: &str, default_port: u16) -> Result<(SocketAddr, String), String> {
+    match value.split_once(',') {
+        Some((address, name)) if !name.trim().is_empty() => Ok((
+            parse_server_address(address.trim(), default_port)?,
+            name.trim().to_string(),
+        )),
+        _ => Err(format!(
+            "expected an address and server name separated by a comma, got {:?}",
+            value
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_fetch(args: &[&str]) -> Result<FetchArgs, clap::Error> {
+        let args = ProgramArgs::try_parse_from(["wrecv", "fetch"].iter().chain(args))?;
+
+        match args.command {
+            Command::Fetch(fetch_args) => Ok(fetch_args),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_raw_output() {
+        let args = parse_fetch(&["--raw", "-o", "-", "http://example.com/"]).unwrap();
+        assert!(args.raw);
+        assert!(args.writes_stdout());
+        assert_eq!(args.output_file(), None);
+
+        // Rejected when fetching instead, since the value can't be checked here
+        let args = parse_fetch(&["--raw", "-o", "a.html", "http://example.com/"]).unwrap();
+        assert!(args.output_file().is_some());
+
+        assert!(parse_fetch(&["--raw", "--transcode-to-utf8", "http://example.com/"]).is_err());
+    }
 }
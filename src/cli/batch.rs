@@ -0,0 +1,640 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::Instant,
+};
+
+use anyhow::Context;
+use serde::Serialize;
+use url::Url;
+
+use crate::{
+    client::{
+        CancellationToken, CircuitBreaker, Client, ClientStats, Config, Request, TransferStats,
+    },
+    dns::Resolver,
+    error::Error,
+};
+
+use super::{
+    args::FetchArgs,
+    batch_state::BatchState,
+    disk::DiskSpaceMonitor,
+    error_report::ErrorReport,
+    fetch::{self, FetchHandler},
+    shutdown,
+    status::TransferStatus,
+    sync, template,
+};
+
+/// A URL listed in the input file.
+#[derive(Debug, Clone)]
+pub struct BatchItem {
+    pub line_number: usize,
+    pub url: Url,
+}
+
+impl BatchItem {
+    fn host(&self) -> String {
+        self.url.host_str().unwrap_or_default().to_ascii_lowercase()
+    }
+}
+
+/// Reads URLs one per line, skipping blank lines and `#` comments.
+pub fn read_input_file(path: &Path) -> anyhow::Result<Vec<BatchItem>> {
+    let file = File::open(path).with_context(|| format!("could not open {:?}", path))?;
+    let mut items = Vec::new();
+
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line_number = index + 1;
+        let url = Url::parse(line)
+            .with_context(|| format!("invalid URL on line {} of {:?}", line_number, path))?;
+
+        items.push(BatchItem { line_number, url });
+    }
+
+    Ok(items)
+}
+
+/// Returns the URLs of --input-file or --url-template.
+pub fn read_items(args: &FetchArgs, now: time::OffsetDateTime) -> anyhow::Result<Vec<BatchItem>> {
+    match (&args.input_file, &args.url_template) {
+        (Some(input_file), _) => read_input_file(input_file),
+        (None, Some(url_template)) => Ok(template::expand_url_template(url_template, now)?
+            .into_iter()
+            .enumerate()
+            .map(|(index, url)| BatchItem {
+                line_number: index + 1,
+                url,
+            })
+            .collect()),
+        (None, None) => unreachable!("input file or URL template required"),
+    }
+}
+
+/// Hands out items so that a host never has more than a given number of
+/// transfers running.
+#[derive(Debug)]
+pub struct BatchQueue {
+    inner: Mutex<QueueInner>,
+    condvar: Condvar,
+    per_host: usize,
+}
+
+#[derive(Debug)]
+struct QueueInner {
+    pending: VecDeque<BatchItem>,
+    active_hosts: HashMap<String, usize>,
+}
+
+impl BatchQueue {
+    pub fn new(items: Vec<BatchItem>, per_host: usize) -> Self {
+        Self {
+            inner: Mutex::new(QueueInner {
+                pending: items.into(),
+                active_hosts: HashMap::new(),
+            }),
+            condvar: Condvar::new(),
+            per_host,
+        }
+    }
+
+    /// Takes the first item whose host is below the limit, waiting for
+    /// other transfers to finish if needed.
+    ///
+    /// Returns `None` when no items are left.
+    pub fn next(&self) -> Option<BatchItem> {
+        let mut inner = self.inner.lock().unwrap();
+
+        loop {
+            if inner.pending.is_empty() {
+                return None;
+            }
+
+            let position = inner.pending.iter().position(|item| {
+                inner.active_hosts.get(&item.host()).copied().unwrap_or(0) < self.per_host
+            });
+
+            if let Some(position) = position {
+                let item = inner.pending.remove(position).unwrap();
+                *inner.active_hosts.entry(item.host()).or_default() += 1;
+
+                return Some(item);
+            }
+
+            inner = self.condvar.wait(inner).unwrap();
+        }
+    }
+
+    /// Releases the host of an item returned by [`Self::next`].
+    pub fn finish(&self, item: &BatchItem) {
+        let mut inner = self.inner.lock().unwrap();
+        let host = item.host();
+
+        if let Some(count) = inner.active_hosts.get_mut(&host) {
+            *count -= 1;
+
+            if *count == 0 {
+                inner.active_hosts.remove(&host);
+            }
+        }
+
+        self.condvar.notify_all();
+    }
+}
+
+/// Pages and bytes fetched from each host, limited by
+/// --max-pages-per-host and --max-bytes-per-host.
+#[derive(Debug)]
+pub struct HostBudget {
+    max_pages: Option<u64>,
+    max_bytes: Option<u64>,
+    /// Pages and bytes by host.
+    used: Mutex<HashMap<String, (u64, u64)>>,
+}
+
+impl HostBudget {
+    pub fn new(max_pages: Option<u64>, max_bytes: Option<u64>) -> Self {
+        Self {
+            max_pages,
+            max_bytes,
+            used: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Counts a page for the host, or returns why its budget is exhausted.
+    ///
+    /// Bytes are counted once a transfer finishes, so transfers that are
+    /// running may go over the byte budget.
+    pub fn reserve(&self, host: &str) -> Result<(), &'static str> {
+        if self.max_pages.is_none() && self.max_bytes.is_none() {
+            return Ok(());
+        }
+
+        let mut used = self.used.lock().unwrap();
+        let (pages, bytes) = used.entry(host.to_string()).or_default();
+
+        if self.max_pages.is_some_and(|max_pages| *pages >= max_pages) {
+            Err("host page budget exhausted")
+        } else if self.max_bytes.is_some_and(|max_bytes| *bytes >= max_bytes) {
+            Err("host byte budget exhausted")
+        } else {
+            *pages += 1;
+            Ok(())
+        }
+    }
+
+    pub fn add_bytes(&self, host: &str, size: u64) {
+        if self.max_bytes.is_some() {
+            let mut used = self.used.lock().unwrap();
+            used.entry(host.to_string()).or_default().1 += size;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct WorkerContext {
+    config: Config,
+    queue: Arc<BatchQueue>,
+    state: Arc<Mutex<BatchState>>,
+    cancellation_token: CancellationToken,
+    circuit_breaker: CircuitBreaker,
+    resolver: Option<Arc<Resolver>>,
+    status: TransferStatus,
+    failures: Arc<AtomicUsize>,
+    budget: Arc<HostBudget>,
+    outcomes: Arc<Mutex<Vec<BatchOutcome>>>,
+    output_dir: PathBuf,
+    output_template: Option<String>,
+    start_timestamp: String,
+    disk_reserve: u64,
+    fsync: bool,
+    transcode_to_utf8: bool,
+}
+
+pub fn run(args: &FetchArgs) -> anyhow::Result<()> {
+    let start_time = time::OffsetDateTime::now_utc();
+    let output_dir = args
+        .output
+        .clone()
+        .context("--output is required with --input-file or --url-template")?;
+
+    if output_dir.as_os_str() == "-" {
+        anyhow::bail!("--output must be a directory with --input-file or --url-template");
+    }
+
+    std::fs::create_dir_all(&output_dir)?;
+
+    let state_path = match &args.state_file {
+        Some(path) => path.clone(),
+        None => output_dir.join(".wrecv-batch.json"),
+    };
+    let mut state = BatchState::load(&state_path)?;
+
+    let items = read_items(args, start_time)?;
+
+    if let Some(output_template) = &args.output_template {
+        let urls = items.iter().map(|item| (item.line_number, &item.url));
+
+        if let Some((name, first, second)) =
+            template::find_duplicate_output(output_template, urls, &format_timestamp(start_time))
+        {
+            anyhow::bail!(
+                "--output-template gives the same file name {:?} for items {} and {}; add {{n}} to make them unique",
+                name,
+                first,
+                second
+            );
+        }
+    }
+    let items = state.select(items, args.retry_failed);
+    let total = items.len();
+    state.save()?;
+
+    let context = WorkerContext {
+        config: fetch::build_config(args)?,
+        queue: Arc::new(BatchQueue::new(items, args.per_host as usize)),
+        state: Arc::new(Mutex::new(state)),
+        cancellation_token: CancellationToken::new(),
+        circuit_breaker: CircuitBreaker::new(),
+        resolver: fetch::build_resolver(args)?.map(Arc::new),
+        status: TransferStatus::new(),
+        failures: Arc::new(AtomicUsize::new(0)),
+        budget: Arc::new(HostBudget::new(
+            args.max_pages_per_host,
+            args.max_bytes_per_host,
+        )),
+        outcomes: Arc::new(Mutex::new(Vec::new())),
+        output_dir: output_dir.clone(),
+        output_template: args.output_template.clone(),
+        start_timestamp: format_timestamp(start_time),
+        disk_reserve: args.disk_reserve,
+        fsync: args.fsync,
+        transcode_to_utf8: args.transcode_to_utf8,
+    };
+
+    context.status.register_signal()?;
+    shutdown::register_signals(context.cancellation_token.clone())?;
+
+    let run_time_expired = args
+        .max_run_time
+        .map(|duration| shutdown::cancel_after(context.cancellation_token.clone(), duration));
+
+    tracing::info!(
+        total,
+        jobs = args.jobs,
+        per_host = args.per_host,
+        "batch start"
+    );
+
+    let workers = (0..args.jobs)
+        .map(|_| {
+            let mut context = context.clone();
+            context.status = context.status.slot();
+            std::thread::spawn(move || run_worker(context))
+        })
+        .collect::<Vec<_>>();
+
+    let mut stats = ClientStats::new();
+
+    for worker in workers {
+        stats.merge_hosts(&worker.join().expect("batch worker panicked"));
+    }
+
+    if let Some(path) = &args.host_stats {
+        write_host_stats(path, &stats)?;
+    }
+
+    if let Some(path) = &args.report {
+        let outcomes = std::mem::take(&mut *context.outcomes.lock().unwrap());
+        write_report(path, BatchReport::new(outcomes))?;
+    }
+
+    if args.fsync_dir {
+        sync::sync_parent_directories([output_dir.join(".").as_path()])?;
+    }
+
+    let failures = context.failures.load(Ordering::Relaxed);
+    tracing::info!(total, failures, "batch finish");
+
+    if run_time_expired.is_some_and(|expired| expired.load(Ordering::Relaxed)) {
+        anyhow::bail!("stopped by --max-run-time; run the same command again to resume")
+    } else if context.cancellation_token.is_cancelled() {
+        Err(Error::Cancelled.into())
+    } else if failures > 0 {
+        anyhow::bail!("{} of {} transfers failed", failures, total)
+    } else {
+        Ok(())
+    }
+}
+
+fn run_worker(context: WorkerContext) -> ClientStats {
+    let mut client = Client::new(context.config.clone());
+    client
+        .set_cancellation_token(context.cancellation_token.clone())
+        .set_circuit_breaker(context.circuit_breaker.clone())
+        .set_resolver(context.resolver.clone());
+
+    while let Some(item) = context.queue.next() {
+        if context.cancellation_token.is_cancelled() {
+            context.queue.finish(&item);
+            break;
+        }
+
+        if let Err(reason) = context.budget.reserve(&item.host()) {
+            context.queue.finish(&item);
+            skip_item(&context, &item, reason);
+            continue;
+        }
+
+        let start = Instant::now();
+        let mut transfer_stats = None;
+        let result = fetch_item(&client, &context, &item, &mut transfer_stats);
+        context.queue.finish(&item);
+
+        if let Some(stats) = &transfer_stats {
+            context.budget.add_bytes(&item.host(), stats.bytes_received);
+        }
+
+        context.outcomes.lock().unwrap().push(BatchOutcome::new(
+            &item,
+            transfer_stats.as_ref(),
+            start.elapsed().as_secs_f64(),
+            result.as_ref().err(),
+        ));
+
+        let mut state = context.state.lock().unwrap();
+        let cancelled = context.cancellation_token.is_cancelled();
+
+        match &result {
+            Ok(_) => state.record_done(&item),
+            Err(_) if cancelled => state.record_interrupted(&item),
+            Err(error) => state.record_failed(&item, format!("{:#}", error)),
+        }
+
+        if let Err(error) = state.save() {
+            tracing::error!(%error, "save batch state");
+            eprintln!("wrecv: could not save batch state: {:#}", error);
+        }
+
+        drop(state);
+
+        match result {
+            Ok(_) => {
+                tracing::info!(
+                    url = %item.url,
+                    line_number = item.line_number,
+                    "batch item done"
+                );
+            }
+            Err(_) if cancelled => break,
+            Err(error) => {
+                tracing::warn!(
+                    url = %item.url,
+                    line_number = item.line_number,
+                    %error,
+                    "batch item failed"
+                );
+                eprintln!("wrecv: {} failed: {:#}", item.url, error);
+                context.failures.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    client.stats()
+}
+
+/// Records that the item was not fetched because of the host budget.
+fn skip_item(context: &WorkerContext, item: &BatchItem, reason: &str) {
+    tracing::info!(
+        url = %item.url,
+        line_number = item.line_number,
+        reason,
+        "batch item skipped"
+    );
+
+    let mut state = context.state.lock().unwrap();
+    state.record_skipped(item, reason.to_string());
+
+    if let Err(error) = state.save() {
+        tracing::error!(%error, "save batch state");
+        eprintln!("wrecv: could not save batch state: {:#}", error);
+    }
+
+    drop(state);
+
+    context
+        .outcomes
+        .lock()
+        .unwrap()
+        .push(BatchOutcome::skipped(item, reason));
+}
+
+/// Fetches the item, storing the statistics of the transfer if it ran.
+fn fetch_item(
+    client: &Client,
+    context: &WorkerContext,
+    item: &BatchItem,
+    transfer_stats: &mut Option<TransferStats>,
+) -> anyhow::Result<()> {
+    let file_name = match &context.output_template {
+        Some(output_template) => template::expand_output_template(
+            output_template,
+            &item.url,
+            item.line_number,
+            &context.start_timestamp,
+        ),
+        None => PathBuf::from(item.line_number.to_string()),
+    };
+    let path = context.output_dir.join(file_name);
+
+    let monitor = DiskSpaceMonitor::new(&path, context.disk_reserve);
+    monitor.preflight()?;
+
+    let mut handler = FetchHandler::new(
+        Some(File::create(&path)?),
+        None,
+        None,
+        None,
+        None,
+        Some(monitor),
+        context.status.clone(),
+    );
+    handler.set_transcode_to_utf8(context.transcode_to_utf8);
+
+    context.status.start(item.url.as_str());
+    let (mut handler, result) = client.submit(Request::new(item.url.clone()), handler);
+    context.status.finish();
+    *transfer_stats = handler.transfer_stats().cloned();
+
+    handler.finish()?;
+
+    if let Some(transcoded) = handler.transcoded() {
+        fetch::write_charset_record(&path, &transcoded, context.fsync)?;
+    }
+
+    if context.fsync {
+        handler.sync_all()?;
+    }
+
+    result.map_err(fetch::convert_error)
+}
+
+/// Outcome of each URL of a batch with totals.
+#[derive(Debug, Serialize)]
+pub struct BatchReport {
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub bytes_received: u64,
+    pub outcomes: Vec<BatchOutcome>,
+}
+
+impl BatchReport {
+    pub fn new(mut outcomes: Vec<BatchOutcome>) -> Self {
+        outcomes.sort_by_key(|outcome| outcome.line_number);
+
+        let succeeded = outcomes.iter().filter(|outcome| outcome.success).count();
+        let skipped = outcomes
+            .iter()
+            .filter(|outcome| outcome.skipped.is_some())
+            .count();
+
+        Self {
+            total: outcomes.len(),
+            succeeded,
+            failed: outcomes.len() - succeeded - skipped,
+            skipped,
+            bytes_received: outcomes.iter().map(|outcome| outcome.bytes_received).sum(),
+            outcomes,
+        }
+    }
+}
+
+/// Outcome of fetching a URL of a batch.
+#[derive(Debug, Serialize)]
+pub struct BatchOutcome {
+    pub line_number: usize,
+    pub url: String,
+    pub success: bool,
+    pub status_code: Option<u16>,
+    pub bytes_received: u64,
+    /// Time spent on the URL in seconds.
+    pub duration: f64,
+    pub error: Option<ErrorReport>,
+    /// Reason the URL was not fetched.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<String>,
+}
+
+impl BatchOutcome {
+    pub fn new(
+        item: &BatchItem,
+        stats: Option<&TransferStats>,
+        duration: f64,
+        error: Option<&anyhow::Error>,
+    ) -> Self {
+        Self {
+            line_number: item.line_number,
+            url: item.url.to_string(),
+            success: error.is_none(),
+            status_code: stats.and_then(|stats| stats.status_code),
+            bytes_received: stats.map(|stats| stats.bytes_received).unwrap_or_default(),
+            duration,
+            error: error.map(|error| ErrorReport::new(error, None)),
+            skipped: None,
+        }
+    }
+
+    pub fn skipped(item: &BatchItem, reason: &str) -> Self {
+        Self {
+            success: false,
+            skipped: Some(reason.to_string()),
+            ..Self::new(item, None, 0.0, None)
+        }
+    }
+}
+
+fn write_report(path: &Path, report: BatchReport) -> anyhow::Result<()> {
+    let mut writer =
+        BufWriter::new(File::create(path).with_context(|| format!("could not create {:?}", path))?);
+    serde_json::to_writer_pretty(&mut writer, &report)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct HostStatsDoc {
+    transfers: u64,
+    errors: u64,
+    error_rate: f64,
+    bytes_received: u64,
+    bytes_sent: u64,
+    /// Average time to first byte in seconds.
+    average_latency: Option<f64>,
+    /// RFC 3339 time of the last transfer.
+    last_access: Option<String>,
+}
+
+/// Writes the statistics of each host as a JSON object keyed by host.
+fn write_host_stats(path: &Path, stats: &ClientStats) -> anyhow::Result<()> {
+    let doc = stats
+        .hosts
+        .iter()
+        .map(|(host, stats)| {
+            let last_access = stats
+                .last_access
+                .map(|time| {
+                    time::OffsetDateTime::from(time)
+                        .format(&time::format_description::well_known::Rfc3339)
+                })
+                .transpose()?;
+            let doc = HostStatsDoc {
+                transfers: stats.transfers,
+                errors: stats.errors,
+                error_rate: stats.error_rate(),
+                bytes_received: stats.bytes_received,
+                bytes_sent: stats.bytes_sent,
+                average_latency: stats.average_latency().map(|value| value.as_secs_f64()),
+                last_access,
+            };
+
+            Ok((host.as_str(), doc))
+        })
+        .collect::<anyhow::Result<BTreeMap<&str, HostStatsDoc>>>()?;
+
+    let mut writer =
+        BufWriter::new(File::create(path).with_context(|| format!("could not create {:?}", path))?);
+    serde_json::to_writer_pretty(&mut writer, &doc)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Formats a time as a compact ISO 8601 UTC timestamp for file names.
+fn format_timestamp(time: time::OffsetDateTime) -> String {
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        time.year(),
+        u8::from(time.month()),
+        time.day(),
+        time.hour(),
+        time.minute(),
+        time.second()
+    )
+}
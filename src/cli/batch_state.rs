@@ -0,0 +1,142 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::batch::BatchItem;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemStatus {
+    Pending,
+    Done,
+    Failed,
+    /// Not fetched because the budget of its host ran out.
+    Skipped,
+}
+
+/// Progress of a single URL of the input file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ItemState {
+    pub url: String,
+    pub status: ItemStatus,
+    pub attempts: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Progress of a batch fetch persisted as JSON so that it can be resumed.
+#[derive(Debug)]
+pub struct BatchState {
+    path: PathBuf,
+    items: BTreeMap<usize, ItemState>,
+}
+
+impl BatchState {
+    /// Loads the state file or starts empty if it doesn't exist.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let items = match File::open(path) {
+            Ok(file) => serde_json::from_reader(file)?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(error) => return Err(error.into()),
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            items,
+        })
+    }
+
+    /// Returns the items that still need to be fetched.
+    ///
+    /// Items whose line now holds a different URL are treated as new.
+    pub fn select(&mut self, items: Vec<BatchItem>, retry_failed: bool) -> Vec<BatchItem> {
+        items
+            .into_iter()
+            .filter(|item| {
+                let state = self
+                    .items
+                    .entry(item.line_number)
+                    .or_insert_with(|| ItemState::new(&item.url));
+
+                if state.url != item.url.as_str() {
+                    *state = ItemState::new(&item.url);
+                }
+
+                match state.status {
+                    ItemStatus::Pending | ItemStatus::Skipped => !retry_failed,
+                    ItemStatus::Done => false,
+                    ItemStatus::Failed => retry_failed,
+                }
+            })
+            .collect()
+    }
+
+    pub fn record_done(&mut self, item: &BatchItem) {
+        self.record(item, ItemStatus::Done, None);
+    }
+
+    pub fn record_failed(&mut self, item: &BatchItem, error: String) {
+        self.record(item, ItemStatus::Failed, Some(error));
+    }
+
+    /// Records an item that was not attempted, with the reason.
+    pub fn record_skipped(&mut self, item: &BatchItem, reason: String) {
+        let state = self
+            .items
+            .entry(item.line_number)
+            .or_insert_with(|| ItemState::new(&item.url));
+
+        state.status = ItemStatus::Skipped;
+        state.error = Some(reason);
+    }
+
+    /// Records an attempt that was interrupted so the item is fetched again.
+    pub fn record_interrupted(&mut self, item: &BatchItem) {
+        self.record(item, ItemStatus::Pending, None);
+    }
+
+    fn record(&mut self, item: &BatchItem, status: ItemStatus, error: Option<String>) {
+        let state = self
+            .items
+            .entry(item.line_number)
+            .or_insert_with(|| ItemState::new(&item.url));
+
+        state.status = status;
+        state.attempts += 1;
+        state.error = error;
+    }
+
+    /// Writes the state to a temporary file and renames it over the
+    /// state file.
+    pub fn save(&self) -> anyhow::Result<()> {
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        serde_json::to_writer_pretty(&mut writer, &self.items)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        drop(writer);
+
+        std::fs::rename(&temp_path, &self.path)?;
+
+        Ok(())
+    }
+}
+
+impl ItemState {
+    fn new(url: &url::Url) -> Self {
+        Self {
+            url: url.to_string(),
+            status: ItemStatus::Pending,
+            attempts: 0,
+            error: None,
+        }
+    }
+}
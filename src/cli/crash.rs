@@ -0,0 +1,83 @@
+use std::{
+    backtrace::Backtrace,
+    fmt::Write as _,
+    panic::PanicHookInfo,
+    path::{Path, PathBuf},
+};
+
+/// Installs a panic hook that writes a crash report file to the directory
+/// before calling the existing hook.
+pub fn set_up_crash_reports(directory: PathBuf) {
+    let previous_hook = std::panic::take_hook();
+
+    std::panic::set_hook(Box::new(move |panic_info| {
+        match write_crash_report(&directory, panic_info) {
+            Ok(path) => eprintln!("crash report written to {:?}", path),
+            Err(error) => eprintln!("failed to write crash report: {}", error),
+        }
+
+        previous_hook(panic_info);
+    }));
+}
+
+fn write_crash_report(directory: &Path, panic_info: &PanicHookInfo) -> anyhow::Result<PathBuf> {
+    let now = time::OffsetDateTime::now_utc();
+    let path = directory.join(format!(
+        "wrecv-crash-{}-{}.txt",
+        now.unix_timestamp(),
+        std::process::id()
+    ));
+
+    std::fs::create_dir_all(directory)?;
+    std::fs::write(&path, format_crash_report(now, panic_info))?;
+
+    tracing::error!(?path, "crash report written");
+
+    Ok(path)
+}
+
+fn format_crash_report(now: time::OffsetDateTime, panic_info: &PanicHookInfo) -> String {
+    let curl_version = curl::Version::get();
+    let mut report = String::new();
+
+    let _ = writeln!(report, "wrecv crash report");
+    let _ = writeln!(
+        report,
+        "time: {}",
+        now.format(&time::format_description::well_known::Rfc3339)
+            .unwrap_or_default()
+    );
+    let _ = writeln!(report, "version: {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(
+        report,
+        "libcurl: {} ({})",
+        curl_version.version(),
+        curl_version.ssl_version().unwrap_or("no TLS")
+    );
+    let _ = writeln!(
+        report,
+        "os: {} {}",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    );
+    let _ = writeln!(
+        report,
+        "thread: {}",
+        std::thread::current().name().unwrap_or("(unnamed)")
+    );
+
+    match crate::client::current_transfer() {
+        Some((transfer_id, url)) => {
+            let _ = writeln!(report, "transfer id: {}", transfer_id);
+            let _ = writeln!(report, "url: {}", url);
+        }
+        None => {
+            let _ = writeln!(report, "transfer: (none)");
+        }
+    }
+
+    let _ = writeln!(report, "panic: {}", panic_info);
+    let _ = writeln!(report, "backtrace:\n{}", Backtrace::force_capture());
+
+    report
+}
@@ -0,0 +1,135 @@
+use std::path::{Path, PathBuf};
+
+/// Amount of data written between checks of the available space.
+const CHECK_INTERVAL: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "insufficient disk space for {path:?}: {required} bytes required, {available} bytes available"
+)]
+pub struct InsufficientDiskSpaceError {
+    pub path: PathBuf,
+    pub required: u64,
+    pub available: u64,
+}
+
+/// Checks that the filesystem of an output file keeps enough free space.
+#[derive(Debug)]
+pub struct DiskSpaceMonitor {
+    path: PathBuf,
+    reserve: u64,
+    expected_size: Option<u64>,
+    written: u64,
+    unchecked: u64,
+}
+
+impl DiskSpaceMonitor {
+    /// Creates a monitor for the filesystem containing `path` that keeps
+    /// `reserve` bytes free.
+    pub fn new<P: Into<PathBuf>>(path: P, reserve: u64) -> Self {
+        Self {
+            path: path.into(),
+            reserve,
+            expected_size: None,
+            written: 0,
+            unchecked: 0,
+        }
+    }
+
+    /// Checks there is room for the reserve before anything is written.
+    pub fn preflight(&self) -> Result<(), InsufficientDiskSpaceError> {
+        self.check(0)
+    }
+
+    /// Checks there is room for the announced size of the download.
+    ///
+    /// Only the first known size is used.
+    pub fn set_expected_size(&mut self, size: u64) -> Result<(), InsufficientDiskSpaceError> {
+        if self.expected_size.is_some() {
+            return Ok(());
+        }
+
+        self.expected_size = Some(size);
+        self.check(size.saturating_sub(self.written))
+    }
+
+    /// Records written bytes and periodically checks the available space.
+    pub fn add_written(&mut self, amount: u64) -> Result<(), InsufficientDiskSpaceError> {
+        self.written += amount;
+        self.unchecked += amount;
+
+        if self.unchecked >= CHECK_INTERVAL {
+            self.unchecked = 0;
+            let remaining = self
+                .expected_size
+                .map(|size| size.saturating_sub(self.written))
+                .unwrap_or(0);
+            self.check(remaining)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check(&self, size: u64) -> Result<(), InsufficientDiskSpaceError> {
+        let available = match available_space(&self.path) {
+            Ok(value) => value,
+            Err(error) if error.kind() == std::io::ErrorKind::Unsupported => return Ok(()),
+            Err(error) => {
+                tracing::warn!(?error, path = ?self.path, "could not get available disk space");
+                return Ok(());
+            }
+        };
+        let required = size.saturating_add(self.reserve);
+
+        tracing::trace!(available, required, "disk space check");
+
+        if available < required {
+            Err(InsufficientDiskSpaceError {
+                path: self.path.clone(),
+                required,
+                available,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Returns the bytes available to unprivileged users on the filesystem
+/// containing the given file or directory.
+fn available_space(path: &Path) -> std::io::Result<u64> {
+    let path = if path.is_dir() {
+        path
+    } else {
+        match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        }
+    };
+
+    filesystem_available_space(path)
+}
+
+#[cfg(unix)]
+fn filesystem_available_space(path: &Path) -> std::io::Result<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+
+    let code = unsafe { libc::statvfs(c_path.as_ptr(), stat.as_mut_ptr()) };
+
+    if code != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let stat = unsafe { stat.assume_init() };
+
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn filesystem_available_space(_path: &Path) -> std::io::Result<u64> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
@@ -0,0 +1,85 @@
+use std::path::Path;
+
+use serde::Serialize;
+use url::Url;
+
+use crate::error::{Error, ProtocolError};
+
+use super::args::{Command, ErrorFormat, ProgramArgs};
+
+/// Machine readable description of a failed command.
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    pub category: String,
+    pub message: String,
+    pub curl_code: Option<curl_sys::CURLcode>,
+    /// Reason of a TLS verification failure.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_verification: Option<&'static str>,
+    pub url: Option<String>,
+    pub retriable: bool,
+}
+
+impl ErrorReport {
+    pub fn new(error: &anyhow::Error, url: Option<&Url>) -> Self {
+        let (category, curl_code, retriable) = match error.downcast_ref::<Error>() {
+            Some(error) => (
+                error.category().as_str(),
+                error.curl_code(),
+                error.is_retriable(),
+            ),
+            None if error.is::<std::io::Error>() => ("io", None, false),
+            None => ("other", None, false),
+        };
+        let tls_verification = match error.downcast_ref::<Error>() {
+            Some(Error::Protocol(ProtocolError::TlsVerification(error))) => {
+                Some(error.kind().as_str())
+            }
+            _ => None,
+        };
+
+        Self {
+            category: category.to_string(),
+            message: error.to_string(),
+            curl_code,
+            tls_verification,
+            url: url.map(|url| url.to_string()),
+            retriable,
+        }
+    }
+}
+
+/// Prints the error to stderr and writes it to the error file if given.
+pub fn report_error(args: &ProgramArgs, error: &anyhow::Error) {
+    let report = ErrorReport::new(error, command_url(args));
+
+    match args.error_format {
+        ErrorFormat::Text => eprintln!("{}", error),
+        ErrorFormat::Json => eprintln!(
+            "{}",
+            serde_json::to_string(&report).expect("serialize error report")
+        ),
+    }
+
+    if let Some(path) = &args.error_file {
+        if let Err(error) = write_report(path, &report) {
+            tracing::error!(?error, ?path, "write error file failed");
+            eprintln!("failed to write error file: {}", error);
+        }
+    }
+}
+
+fn command_url(args: &ProgramArgs) -> Option<&Url> {
+    match &args.command {
+        Command::Fetch(fetch_args) => fetch_args.url.as_ref(),
+        Command::Lookup(_) => None,
+    }
+}
+
+fn write_report(path: &Path, report: &ErrorReport) -> anyhow::Result<()> {
+    let mut content = serde_json::to_string(report)?;
+    content.push('\n');
+    std::fs::write(path, content)?;
+
+    Ok(())
+}
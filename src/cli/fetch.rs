@@ -1,17 +1,68 @@
-use std::{fs::File, io::Write};
+use std::{
+    cell::RefCell,
+    ffi::OsString,
+    fs::File,
+    io::{BufWriter, Cursor, Read, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
+};
 
-use crate::client::{Client, Config, Request, SessionControl, SessionEvent, SessionHandler};
+use anyhow::Context;
 
-use super::args::FetchArgs;
+use crate::{
+    client::{
+        BodyCharset, BodyPipeline, Client, Config, EventFilter, EventKind, Method, Request,
+        RequestPolicy, SessionControl, SessionEvent, SessionHandler, TranscriptWriter,
+        TransferStats, UserAgentRotation, WriterSink,
+    },
+    dns::{self, Resolver},
+    error::{Error, OtherError},
+};
+
+#[cfg(any(feature = "ntlm", feature = "spnego"))]
+use crate::client::HttpAuth;
+
+use super::{
+    args::FetchArgs,
+    batch,
+    disk::{DiskSpaceMonitor, InsufficientDiskSpaceError},
+    shutdown, spider,
+    status::TransferStatus,
+    sync,
+    trace::TraceWriter,
+    writer::BackgroundWriter,
+};
 
 pub fn run(args: &FetchArgs) -> anyhow::Result<()> {
-    let mut config = Config::new();
-    config.set_http_compression(true);
+    if args.spider {
+        return spider::run(args);
+    }
 
-    let client = Client::new(config);
-    let request = Request::new(args.url.clone());
+    if args.input_file.is_some() || args.url_template.is_some() {
+        return batch::run(args);
+    }
+
+    if args.raw && args.output_file().is_some() {
+        anyhow::bail!("--raw writes to stdout, so --output may only be \"-\"");
+    }
 
-    let output_file = match &args.output {
+    let mut client = Client::new(build_config(args)?);
+    client.set_resolver(build_resolver(args)?.map(Arc::new));
+
+    let mut request = Request::new(args.url.clone().expect("url or input file"));
+    let upload = set_up_upload(args, &mut request)?;
+
+    let disk_monitor = match args.output_file() {
+        Some(path) => {
+            let monitor = DiskSpaceMonitor::new(path, args.disk_reserve);
+            monitor.preflight()?;
+            Some(monitor)
+        }
+        None => None,
+    };
+
+    let output_file = match args.output_file() {
         Some(path) => Some(File::create(path)?),
         None => None,
     };
@@ -26,26 +77,404 @@ pub fn run(args: &FetchArgs) -> anyhow::Result<()> {
         None => None,
     };
 
-    let handler = FetchHandler::new(output_file, response_file, request_file);
-    let (_handler, result) = client.submit(request, handler);
-    result?;
+    let events_writer = match &args.output_events {
+        Some(path) => {
+            let mut writer = TranscriptWriter::new(BufWriter::new(File::create(path)?));
+            writer.set_include_data(args.output_events_data);
+            Some(writer)
+        }
+        None => None,
+    };
+
+    let trace_writer = match &args.trace {
+        Some(path) => Some(TraceWriter::new(BufWriter::new(File::create(path)?))),
+        None => None,
+    };
+
+    let status = TransferStatus::new();
+    status.register_signal()?;
+    shutdown::register_signals(client.cancellation_token().clone())?;
+
+    let mut handler = FetchHandler::new(
+        output_file,
+        response_file,
+        request_file,
+        events_writer,
+        trace_writer,
+        disk_monitor,
+        status.clone(),
+    );
+    handler.set_transcode_to_utf8(args.transcode_to_utf8);
+
+    if let Some(upload) = upload {
+        handler.set_upload(upload);
+    }
+
+    status.start(request.url().as_str());
+    let (mut handler, result) = client.submit(request, handler);
+    status.finish();
+
+    handler.finish()?;
+
+    if let Some(transcoded) = handler.transcoded() {
+        match args.output_file() {
+            Some(path) => write_charset_record(path, &transcoded, args.fsync)?,
+            None => transcoded.log(),
+        }
+    }
+
+    if args.fsync {
+        handler.sync_all()?;
+    }
+    if args.fsync_dir {
+        sync::sync_parent_directories(
+            [
+                &args.output_response,
+                &args.output_request,
+                &args.output_events,
+                &args.trace,
+            ]
+            .into_iter()
+            .flatten()
+            .map(PathBuf::as_path)
+            .chain(args.output_file()),
+        )?;
+    }
+
+    result.map_err(convert_error)
+}
+
+/// Returns the client config, checked so that conflicting options fail
+/// before any transfer.
+pub fn build_config(args: &FetchArgs) -> Result<Config, Error> {
+    let mut config = Config::builder();
+    config
+        .set_http_compression(true)
+        .set_reject_confusable(args.reject_confusable)
+        .set_max_transfer_duration(args.max_time)
+        .set_circuit_breaker_threshold(args.circuit_breaker)
+        .set_proxy(args.proxy.clone())
+        .set_proxy_tunnel(args.proxy_tunnel);
+
+    if let Some(profile) = args.header_profile {
+        config.set_header_profile(profile);
+    }
+
+    config.set_http_accept_language(args.accept_language.clone());
+
+    if args.public_only {
+        config.set_request_policy(Some(RequestPolicy::new()));
+    }
+
+    #[cfg(feature = "ntlm")]
+    if let (true, Some((username, password))) = (args.ntlm, &args.user) {
+        config.set_http_auth(Some(HttpAuth::Ntlm {
+            username: username.clone(),
+            password: password.clone(),
+        }));
+    }
+    #[cfg(feature = "spnego")]
+    if args.negotiate {
+        config.set_http_auth(Some(HttpAuth::Negotiate));
+    }
+
+    match args.user_agent.as_slice() {
+        [] => {}
+        [user_agent] => {
+            config.set_http_user_agent(user_agent.clone());
+        }
+        user_agents => {
+            config.set_http_user_agent_rotation(Some(UserAgentRotation::new(
+                user_agents.to_vec(),
+                args.user_agent_rotation,
+            )));
+        }
+    }
+
+    config.build()
+}
+
+/// Returns the resolver for host names if --doh or --doh-url is given.
+pub fn build_resolver(args: &FetchArgs) -> anyhow::Result<Option<Resolver>> {
+    if !args.doh && args.doh_url.is_empty() {
+        return Ok(None);
+    }
+
+    let mut config = if args.doh_url.is_empty() {
+        dns::Config::new().with_suggested_servers()
+    } else {
+        dns::Config::new()
+    };
+    config.set_reject_confusable(args.reject_confusable);
+
+    for url in &args.doh_url {
+        config.add_doh_url(url)?;
+    }
+
+    Ok(Some(Resolver::new(config)?))
+}
+
+/// Unwraps errors raised by the handler from the client error.
+pub fn convert_error(error: Error) -> anyhow::Error {
+    match error {
+        Error::Other(OtherError::Custom(error)) => {
+            match error.downcast::<InsufficientDiskSpaceError>() {
+                Ok(error) => (*error).into(),
+                Err(error) => Error::Other(OtherError::Custom(error)).into(),
+            }
+        }
+        error => error.into(),
+    }
+}
+
+/// Sets the method and content of --data or --upload-file on the request,
+/// returning the reader of the content.
+fn set_up_upload(args: &FetchArgs, request: &mut Request) -> anyhow::Result<Option<Box<dyn Read>>> {
+    let (method, path) = match (&args.data, &args.upload_file) {
+        (Some(data), _) => {
+            request
+                .http_headers_mut()
+                .append("Content-Type", "application/x-www-form-urlencoded");
+
+            match data.strip_prefix('@') {
+                Some(path) => (Method::Post, PathBuf::from(path)),
+                None => {
+                    request
+                        .set_method(Method::Post)
+                        .set_upload_size(Some(data.len() as u64));
+
+                    return Ok(Some(Box::new(Cursor::new(data.clone().into_bytes()))));
+                }
+            }
+        }
+        (None, Some(path)) => (Method::Put, path.clone()),
+        (None, None) => return Ok(None),
+    };
+
+    request.set_method(method);
+
+    if path.as_os_str() == "-" {
+        return Ok(Some(Box::new(std::io::stdin())));
+    }
+
+    let file = File::open(&path).with_context(|| format!("could not open {:?}", path))?;
+    request.set_upload_size(Some(file.metadata()?.len()));
+
+    Ok(Some(Box::new(file)))
+}
+
+/// Outcome of transcoding content to UTF-8.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transcoded {
+    /// Content was decoded from the charset.
+    From(BodyCharset),
+    /// Content was written unchanged as it isn't text or its charset is
+    /// unsupported, with the unsupported charset label if declared.
+    Unchanged(Option<String>),
+}
+
+impl Transcoded {
+    /// Label of the original charset, or `unknown` if not detected.
+    pub fn label(&self) -> &str {
+        match self {
+            Self::From(charset) => charset.label(),
+            Self::Unchanged(Some(label)) => label,
+            Self::Unchanged(None) => "unknown",
+        }
+    }
+
+    fn log(&self) {
+        match self {
+            Self::From(_) => tracing::info!(charset = self.label(), "transcoded content to UTF-8"),
+            Self::Unchanged(_) => tracing::warn!(
+                charset = self.label(),
+                "content not transcoded as it is not text with a supported charset"
+            ),
+        }
+    }
+}
+
+/// Saves the original charset of transcoded content next to the output file.
+pub fn write_charset_record(
+    path: &Path,
+    transcoded: &Transcoded,
+    fsync: bool,
+) -> std::io::Result<()> {
+    let mut record_path = OsString::from(path);
+    record_path.push(".charset");
+
+    if let Transcoded::Unchanged(_) = transcoded {
+        tracing::warn!(path = ?path, charset = transcoded.label(), "content saved without transcoding");
+    }
+
+    let mut file = File::create(record_path)?;
+    writeln!(file, "{}", transcoded.label())?;
+
+    if fsync {
+        file.sync_all()?;
+    }
 
     Ok(())
 }
 
-struct FetchHandler {
-    output: Option<File>,
-    response: Option<File>,
-    request: Option<File>,
+/// Decodes content into UTF-8 text.
+struct Transcoder {
+    pipeline: BodyPipeline,
+    text: Rc<RefCell<WriterSink<Vec<u8>>>>,
+}
+
+impl Transcoder {
+    fn new() -> Self {
+        let text = Rc::new(RefCell::new(WriterSink(Vec::new())));
+        let mut pipeline = BodyPipeline::new();
+        pipeline
+            .set_known_charset_only(true)
+            .set_sniff_charset(true)
+            .add_text_sink(text.clone());
+
+        Self { pipeline, text }
+    }
+
+    /// Passes the event to the pipeline and returns the text decoded so far.
+    fn write_event(&mut self, event: &SessionEvent) -> Result<Vec<u8>, Error> {
+        self.pipeline.write_event(event)?;
+        Ok(std::mem::take(&mut self.text.borrow_mut().0))
+    }
+
+    fn finish(&mut self) -> Result<Vec<u8>, Error> {
+        self.pipeline.finish()?;
+        Ok(std::mem::take(&mut self.text.borrow_mut().0))
+    }
+}
+
+pub struct FetchHandler {
+    output: Option<BackgroundWriter>,
+    response: Option<BackgroundWriter>,
+    request: Option<BackgroundWriter>,
+    events: Option<TranscriptWriter<BufWriter<File>>>,
+    trace: Option<TraceWriter<BufWriter<File>>>,
+    disk_monitor: Option<DiskSpaceMonitor>,
+    status: TransferStatus,
+    transcoder: Option<Transcoder>,
+    transfer_stats: Option<TransferStats>,
+    upload: Option<Box<dyn Read>>,
 }
 
 impl FetchHandler {
-    fn new(output: Option<File>, response: Option<File>, request: Option<File>) -> Self {
+    pub fn new(
+        output: Option<File>,
+        response: Option<File>,
+        request: Option<File>,
+        events: Option<TranscriptWriter<BufWriter<File>>>,
+        trace: Option<TraceWriter<BufWriter<File>>>,
+        disk_monitor: Option<DiskSpaceMonitor>,
+        status: TransferStatus,
+    ) -> Self {
         Self {
-            output,
-            response,
-            request,
+            output: output.map(BackgroundWriter::new),
+            response: response.map(BackgroundWriter::new),
+            request: request.map(BackgroundWriter::new),
+            events,
+            trace,
+            disk_monitor,
+            status,
+            transcoder: None,
+            transfer_stats: None,
+            upload: None,
+        }
+    }
+
+    /// Sets the reader of the content sent by the request.
+    pub fn set_upload(&mut self, reader: Box<dyn Read>) -> &mut Self {
+        self.upload = Some(reader);
+        self
+    }
+
+    /// Sets whether text content is decoded by its detected charset and
+    /// written as UTF-8. Other content is written unchanged.
+    pub fn set_transcode_to_utf8(&mut self, enabled: bool) -> &mut Self {
+        self.transcoder = enabled.then(Transcoder::new);
+        self
+    }
+
+    /// Charset the content was transcoded from, if transcoding.
+    pub fn transcoded(&self) -> Option<Transcoded> {
+        let pipeline = &self.transcoder.as_ref()?.pipeline;
+
+        Some(match pipeline.decoded_charset() {
+            Some(charset) => Transcoded::From(charset),
+            None => Transcoded::Unchanged(pipeline.unknown_charset().map(str::to_string)),
+        })
+    }
+
+    /// Statistics of the transfer once it has finished.
+    pub fn transfer_stats(&self) -> Option<&TransferStats> {
+        self.transfer_stats.as_ref()
+    }
+
+    fn write_output(&mut self, data: &[u8]) -> std::io::Result<()> {
+        match &mut self.output {
+            Some(writer) => writer.write(data),
+            None => std::io::stdout().write_all(data),
+        }
+    }
+
+    /// Waits for the output files to be written and flushes the event and
+    /// trace writers.
+    pub fn finish(&mut self) -> anyhow::Result<()> {
+        if let Some(transcoder) = &mut self.transcoder {
+            let text = transcoder.finish()?;
+
+            if let Some(monitor) = &mut self.disk_monitor {
+                monitor.add_written(text.len() as u64)?;
+            }
+
+            self.write_output(&text)?;
+        }
+
+        if self.output.is_none() {
+            std::io::stdout().flush()?;
+        }
+
+        for writer in [&mut self.output, &mut self.response, &mut self.request]
+            .into_iter()
+            .flatten()
+        {
+            writer.finish()?;
+        }
+
+        if let Some(writer) = &mut self.events {
+            writer.flush()?;
+        }
+        if let Some(writer) = &mut self.trace {
+            writer.flush()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn sync_all(&mut self) -> std::io::Result<()> {
+        tracing::debug!("sync files");
+
+        let mut files = Vec::new();
+
+        for writer in [&mut self.output, &mut self.response, &mut self.request]
+            .into_iter()
+            .flatten()
+        {
+            files.push(writer.finish()?);
         }
+
+        sync::sync_files(
+            files
+                .into_iter()
+                .chain(
+                    self.events
+                        .as_ref()
+                        .map(|writer| writer.get_ref().get_ref()),
+                )
+                .chain(self.trace.as_ref().map(|writer| writer.get_ref().get_ref())),
+        )
     }
 }
 
@@ -53,9 +482,12 @@ impl SessionHandler for FetchHandler {
     fn upload_content(
         &mut self,
         _control: &mut dyn SessionControl,
-        _buf: &mut [u8],
+        buf: &mut [u8],
     ) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
-        Ok(0)
+        match &mut self.upload {
+            Some(reader) => Ok(reader.read(buf)?),
+            None => Ok(0),
+        }
     }
 
     fn event(
@@ -63,28 +495,113 @@ impl SessionHandler for FetchHandler {
         _control: &mut dyn SessionControl,
         event: SessionEvent,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if let SessionEvent::Trace(kind, data) = event {
+            if let Some(writer) = &mut self.trace {
+                writer.write_trace(kind, data)?;
+            }
+
+            return Ok(());
+        }
+
+        if let Some(writer) = &mut self.events {
+            writer.write_event(&event)?;
+        }
+
+        if let Some(transcoder) = &mut self.transcoder {
+            let text = transcoder.write_event(&event)?;
+
+            // The text is counted rather than the content, as UTF-8 may be larger
+            if let Some(monitor) = &mut self.disk_monitor {
+                monitor.add_written(text.len() as u64)?;
+            }
+
+            self.write_output(&text)?;
+        }
+
         match event {
             SessionEvent::HeaderReceived(data) | SessionEvent::BodyReceived(data) => {
                 match &mut self.response {
-                    Some(file) => file.write_all(data)?,
-                    None => {},
+                    Some(writer) => writer.write(data)?,
+                    None => {}
                 }
             }
             SessionEvent::HeaderSent(data) | SessionEvent::BodySent(data) => {
                 match &mut self.request {
-                    Some(file) => file.write_all(data)?,
-                    None => {},
+                    Some(writer) => writer.write(data)?,
+                    None => {}
+                }
+            }
+
+            SessionEvent::HttpResponse(_data, header) => {
+                if let (Some(monitor), Some(content_length)) =
+                    (&mut self.disk_monitor, header.fields.content_length())
+                {
+                    monitor.set_expected_size(content_length)?;
                 }
             }
 
+            SessionEvent::ContentReceived(_) if self.transcoder.is_some() => {}
             SessionEvent::ContentReceived(data) => match &mut self.output {
-                Some(file) => file.write_all(data)?,
+                Some(writer) => {
+                    if let Some(monitor) = &mut self.disk_monitor {
+                        monitor.add_written(data.len() as u64)?;
+                    }
+                    writer.write(data)?
+                }
                 None => std::io::stdout().write_all(data)?,
             },
 
+            SessionEvent::Finished(stats) => self.transfer_stats = Some(stats),
+
+            SessionEvent::Progress {
+                download_total,
+                download_current,
+                upload_total,
+                upload_current,
+                ..
+            } => {
+                self.status.update_progress(
+                    download_total,
+                    download_current,
+                    upload_total,
+                    upload_current,
+                );
+
+                if let (Some(monitor), true) = (&mut self.disk_monitor, download_total > 0) {
+                    monitor.set_expected_size(download_total)?;
+                }
+            }
+
             _ => {}
         }
 
         Ok(())
     }
+
+    fn event_filter(&self) -> EventFilter {
+        if self.events.is_some() {
+            return EventFilter::all();
+        }
+
+        let mut filter = EventFilter::from_iter([
+            EventKind::HttpResponse,
+            EventKind::ContentReceived,
+            EventKind::Progress,
+            EventKind::Finished,
+        ]);
+
+        if self.trace.is_some() {
+            filter = filter.with(EventKind::Trace);
+        }
+        if self.response.is_some() {
+            filter = filter
+                .with(EventKind::HeaderReceived)
+                .with(EventKind::BodyReceived);
+        }
+        if self.request.is_some() {
+            filter = filter.with(EventKind::HeaderSent).with(EventKind::BodySent);
+        }
+
+        filter
+    }
 }
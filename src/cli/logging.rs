@@ -1,12 +1,37 @@
-use std::{fs::File, sync::Mutex};
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
 
 use reopen::Reopen;
 use tracing::metadata::LevelFilter;
 use tracing_subscriber::{filter::Targets, prelude::*};
 
-use super::args::ProgramArgs;
+use super::{
+    args::{Command, ProgramArgs},
+    syslog::SyslogMakeWriter,
+};
 
-pub fn set_up_logging(args: &ProgramArgs) -> anyhow::Result<()> {
+/// Flushes buffered logging output when dropped.
+pub struct LoggingGuard {
+    #[cfg(feature = "otel")]
+    tracer_provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+impl Drop for LoggingGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.tracer_provider.take() {
+            if let Err(error) = provider.shutdown() {
+                eprintln!("OpenTelemetry shutdown error: {}", error);
+            }
+        }
+    }
+}
+
+pub fn set_up_logging(args: &ProgramArgs) -> anyhow::Result<LoggingGuard> {
     let subscriber = tracing_subscriber::Registry::default();
     let target_str = if args.log_level == LevelFilter::TRACE {
         ""
@@ -16,8 +41,16 @@ pub fn set_up_logging(args: &ProgramArgs) -> anyhow::Result<()> {
     let filter = Targets::new().with_target(target_str, args.log_level);
     let subscriber = subscriber.with(filter);
 
-    let layer = if args.log_file.is_none() && !args.log_journald {
-        let layer = tracing_subscriber::fmt::layer();
+    let layer = if args.log_file.is_none() && !args.log_journald && !args.log_syslog {
+        // Keep stdout for the downloaded content only
+        let layer = match &args.command {
+            Command::Fetch(fetch_args) if fetch_args.writes_stdout() => {
+                tracing_subscriber::fmt::layer()
+                    .with_writer(std::io::stderr)
+                    .boxed()
+            }
+            _ => tracing_subscriber::fmt::layer().boxed(),
+        };
         Some(layer)
     } else {
         None
@@ -26,13 +59,10 @@ pub fn set_up_logging(args: &ProgramArgs) -> anyhow::Result<()> {
 
     let layer = if let Some(path) = &args.log_file {
         let path = path.to_owned();
+        let max_size = args.log_max_size;
+        let max_files = args.log_max_files;
         let file = Reopen::new(Box::new(move || {
-            let path = path.clone();
-            File::options()
-                .create(true)
-                .write(true)
-                .append(true)
-                .open(path)
+            RotatingFile::open(path.clone(), max_size, max_files)
         }))?;
         file.handle().register_signal(signal_hook::consts::SIGHUP)?;
         let file = Mutex::new(file);
@@ -45,17 +75,160 @@ pub fn set_up_logging(args: &ProgramArgs) -> anyhow::Result<()> {
     let subscriber = subscriber.with(layer);
 
     let layer = if args.log_journald {
-        Some(tracing_journald::layer()?)
+        Some(journald_layer()?)
     } else {
         None
     };
     let subscriber = subscriber.with(layer);
 
+    let layer = if args.log_syslog {
+        let layer = tracing_subscriber::fmt::layer()
+            .without_time()
+            .with_level(false)
+            .with_ansi(false)
+            .with_writer(SyslogMakeWriter::new());
+        Some(layer)
+    } else {
+        None
+    };
+    let subscriber = subscriber.with(layer);
+
+    #[cfg(feature = "otel")]
+    let (subscriber, tracer_provider) = {
+        let tracer_provider = match &args.log_otlp {
+            Some(endpoint) => Some(build_otlp_tracer_provider(endpoint)?),
+            None => None,
+        };
+        let layer = tracer_provider.as_ref().map(|provider| {
+            use opentelemetry::trace::TracerProvider;
+
+            tracing_opentelemetry::layer().with_tracer(provider.tracer("wrecv"))
+        });
+
+        (subscriber.with(layer), tracer_provider)
+    };
+
     tracing::subscriber::set_global_default(subscriber)?;
 
     if args.log_level == LevelFilter::TRACE {
         tracing_log::LogTracer::init()?;
     }
 
-    Ok(())
+    Ok(LoggingGuard {
+        #[cfg(feature = "otel")]
+        tracer_provider,
+    })
+}
+
+#[cfg(feature = "journald")]
+fn journald_layer() -> anyhow::Result<tracing_journald::Layer> {
+    Ok(tracing_journald::layer()?)
+}
+
+#[cfg(not(feature = "journald"))]
+fn journald_layer() -> anyhow::Result<tracing_subscriber::layer::Identity> {
+    anyhow::bail!("journald logging is not enabled in this build")
+}
+
+#[cfg(feature = "otel")]
+fn build_otlp_tracer_provider(
+    endpoint: &url::Url,
+) -> anyhow::Result<opentelemetry_sdk::trace::SdkTracerProvider> {
+    use opentelemetry_otlp::WithExportConfig;
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint.as_str())
+        .build()?;
+    let resource = opentelemetry_sdk::Resource::builder()
+        .with_service_name("wrecv")
+        .build();
+
+    Ok(opentelemetry_sdk::trace::SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource)
+        .build())
+}
+
+/// Log file that is renamed with a numbered suffix once it reaches the
+/// maximum size, keeping at most the given number of old files.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: Option<u64>,
+    max_files: usize,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_size: Option<u64>, max_files: usize) -> std::io::Result<Self> {
+        let file = Self::open_file(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_size,
+            max_files,
+        })
+    }
+
+    fn open_file(path: &Path) -> std::io::Result<File> {
+        File::options().create(true).append(true).open(path)
+    }
+
+    fn numbered_path(&self, number: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", number));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+
+        if self.max_files == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            let oldest = self.numbered_path(self.max_files);
+
+            if oldest.exists() {
+                std::fs::remove_file(oldest)?;
+            }
+
+            for number in (1..self.max_files).rev() {
+                let source = self.numbered_path(number);
+
+                if source.exists() {
+                    std::fs::rename(source, self.numbered_path(number + 1))?;
+                }
+            }
+
+            std::fs::rename(&self.path, self.numbered_path(1))?;
+        }
+
+        self.file = Self::open_file(&self.path)?;
+        self.size = 0;
+
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if let Some(max_size) = self.max_size {
+            if self.size > 0 && self.size + buf.len() as u64 > max_size {
+                self.rotate()?;
+            }
+        }
+
+        let size = self.file.write(buf)?;
+        self.size += size as u64;
+
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
 }
@@ -1,35 +1,403 @@
-use std::net::IpAddr;
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    fs::File,
+    io::{BufRead, BufReader},
+    net::{IpAddr, SocketAddr},
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
 
+use anyhow::Context;
 use serde::Serialize;
 
-use crate::dns::{Config, Resolver};
+use crate::{
+    dns::{
+        AnswerSource, Config, DnsRecord, IpAddressLookup, RecordFields, RecordLookup, RecordType,
+        Resolver, ServerProtocol,
+    },
+    error::Error,
+};
 
-use super::args::LookupArgs;
+use super::args::{LookupArgs, LookupFormat};
+
+const CSV_HEADER: &str = "name,record_type,ttl,data,error";
 
 pub fn run(args: &LookupArgs) -> anyhow::Result<()> {
-    let config = Config::new().with_suggested_servers();
-    let resolver = Resolver::new(config)?;
+    let config = build_config(args)?;
+    let resolver = Resolver::new(config.clone())?;
 
-    let lookup = resolver.lookup_ip_address(&args.name)?;
+    match (&args.name, &args.input_file) {
+        (_, Some(input_file)) => run_batch(args, config, &resolver, input_file)?,
+        (Some(name), None) => run_single(args, &resolver, name)?,
+        (None, None) => unreachable!("name or input file required"),
+    }
 
+    Ok(())
+}
+
+fn build_config(args: &LookupArgs) -> anyhow::Result<Config> {
+    let custom_servers = !args.doh_url.is_empty()
+        || !args.doh.is_empty()
+        || !args.dot.is_empty()
+        || !args.udp.is_empty()
+        || args.system;
+
+    let mut config = if custom_servers {
+        Config::new()
+    } else {
+        Config::new().with_suggested_servers()
+    };
+    config
+        .set_doh_servers(args.doh.clone())
+        .set_dot_servers(args.dot.clone())
+        .set_udp_servers(args.udp.clone())
+        .set_system_resolver(args.system)
+        .set_reject_confusable(args.reject_confusable)
+        .set_bootstrap_servers(args.bootstrap_dns.clone())
+        .set_hosts_file(args.hosts_file.clone())
+        .set_cache_file(args.dns_cache.clone());
+
+    for url in &args.doh_url {
+        config.add_doh_url(url)?;
+    }
+
+    Ok(config)
+}
+
+fn output_format(args: &LookupArgs) -> LookupFormat {
     if args.json {
-        let doc = OutputDoc {
-            ip_addresses: lookup.ip_addresses().to_vec(),
-            text_record: lookup.to_record_string()
-        };
-        let output = serde_json::to_string_pretty(&doc)?;
-        println!("{}", output);
+        LookupFormat::Json
     } else {
-        for address in lookup.ip_addresses() {
-            println!("{}", address);
+        args.format
+    }
+}
+
+/// Result of a lookup of addresses or records of a given type.
+struct Answer {
+    name: String,
+    ascii_name: String,
+    ip_addresses: Vec<IpAddr>,
+    records: Vec<DnsRecord>,
+    source: AnswerSource,
+}
+
+impl From<IpAddressLookup> for Answer {
+    fn from(lookup: IpAddressLookup) -> Self {
+        Self {
+            name: lookup.name().to_string(),
+            ascii_name: lookup.ascii_name().to_string(),
+            ip_addresses: lookup.ip_addresses().to_vec(),
+            records: lookup.records(),
+            source: lookup.source().clone(),
+        }
+    }
+}
+
+impl From<RecordLookup> for Answer {
+    fn from(lookup: RecordLookup) -> Self {
+        let ip_addresses = lookup
+            .records()
+            .iter()
+            .filter_map(|record| match record.fields {
+                Some(RecordFields::Address { address }) => Some(address),
+                _ => None,
+            })
+            .collect();
+
+        Self {
+            name: lookup.name().to_string(),
+            ascii_name: lookup.ascii_name().to_string(),
+            ip_addresses,
+            records: lookup.records().to_vec(),
+            source: lookup.source().clone(),
+        }
+    }
+}
+
+fn lookup(
+    resolver: &Resolver,
+    name: &str,
+    record_type: Option<RecordType>,
+) -> Result<Answer, Error> {
+    match record_type {
+        Some(record_type) => resolver.lookup_records(name, record_type).map(Answer::from),
+        None => resolver.lookup_ip_address(name).map(Answer::from),
+    }
+}
+
+fn run_single(args: &LookupArgs, resolver: &Resolver, name: &str) -> anyhow::Result<()> {
+    let answer = lookup(resolver, name, args.record_type)?;
+    resolver.save_cache()?;
+
+    let doc = OutputDoc {
+        name: answer.name,
+        ascii_name: answer.ascii_name,
+        ip_addresses: answer.ip_addresses,
+        records: answer.records,
+        source: SourceDoc::from(&answer.source),
+    };
+
+    match output_format(args) {
+        LookupFormat::Plain if args.record_type.is_some() => {
+            for record in &doc.records {
+                println!("{}", record.data);
+            }
+        }
+        LookupFormat::Plain => {
+            for address in &doc.ip_addresses {
+                println!("{}", address);
+            }
+        }
+        LookupFormat::Dig => print!("{}", format_dig(&doc.records)),
+        LookupFormat::Json => println!("{}", serde_json::to_string_pretty(&doc)?),
+        LookupFormat::Jsonl => println!("{}", serde_json::to_string(&doc)?),
+        LookupFormat::Csv => {
+            println!("{}", CSV_HEADER);
+            print!("{}", format_csv(name, &doc.records, None));
+        }
+    }
+
+    Ok(())
+}
+
+/// Looks up the names of the input file with worker threads that each own
+/// a resolver sharing the answer cache of the main resolver.
+fn run_batch(
+    args: &LookupArgs,
+    mut config: Config,
+    resolver: &Resolver,
+    input_file: &Path,
+) -> anyhow::Result<()> {
+    let names = read_names(input_file)?;
+    let total = names.len();
+    let queue = Mutex::new(VecDeque::from(names));
+    let failures = AtomicUsize::new(0);
+    let format = output_format(args);
+
+    config.set_cache_file(None::<&Path>);
+
+    tracing::info!(total, jobs = args.jobs, "batch lookup start");
+
+    if format == LookupFormat::Csv {
+        println!("{}", CSV_HEADER);
+    }
+
+    std::thread::scope(|scope| -> anyhow::Result<()> {
+        for _ in 0..args.jobs {
+            let mut worker_resolver = Resolver::new(config.clone())?;
+            worker_resolver.share_cache(resolver);
+
+            let queue = &queue;
+            let failures = &failures;
+
+            scope.spawn(move || {
+                while let Some(name) = queue.lock().unwrap().pop_front() {
+                    let result = lookup(&worker_resolver, &name, args.record_type);
+
+                    if result.is_err() {
+                        failures.fetch_add(1, Ordering::Relaxed);
+                    }
+
+                    // Printed at once so that lines of workers don't interleave
+                    print!(
+                        "{}",
+                        format_batch_result(format, &name, args.record_type, result)
+                    );
+                }
+            });
         }
+
+        Ok(())
+    })?;
+
+    resolver.save_cache()?;
+
+    let failures = failures.load(Ordering::Relaxed);
+    tracing::info!(total, failures, "batch lookup finish");
+
+    if failures > 0 {
+        anyhow::bail!("{} of {} lookups failed", failures, total)
     }
 
     Ok(())
 }
 
+/// Formats the result of a name of the input file.
+///
+/// The plain format is a tab separated line of the name, addresses (or
+/// record data with a record type), and error. JSON formats give a JSON
+/// document per line.
+fn format_batch_result(
+    format: LookupFormat,
+    name: &str,
+    record_type: Option<RecordType>,
+    result: Result<Answer, Error>,
+) -> String {
+    let (addresses, records, source, error) = match result {
+        Ok(answer) => (
+            answer.ip_addresses,
+            answer.records,
+            Some(SourceDoc::from(&answer.source)),
+            None,
+        ),
+        Err(error) => (Vec::new(), Vec::new(), None, Some(error.to_string())),
+    };
+
+    match format {
+        LookupFormat::Plain => {
+            let values = match record_type {
+                Some(_) => records
+                    .iter()
+                    .map(|record| record.data.clone())
+                    .collect::<Vec<String>>(),
+                None => addresses.iter().map(IpAddr::to_string).collect(),
+            };
+
+            format!(
+                "{}\t{}\t{}\n",
+                name,
+                values.join(","),
+                error.unwrap_or_default()
+            )
+        }
+        LookupFormat::Dig => match error {
+            Some(error) => format!(";; {}: {}\n", name, error),
+            None => format_dig(&records),
+        },
+        LookupFormat::Json | LookupFormat::Jsonl => {
+            let doc = BatchOutputDoc {
+                name,
+                ip_addresses: addresses,
+                records,
+                source,
+                error,
+            };
+            format!("{}\n", serde_json::to_string(&doc).unwrap())
+        }
+        LookupFormat::Csv => format_csv(name, &records, error.as_deref()),
+    }
+}
+
+/// Formats records like the answer section of dig.
+fn format_dig(records: &[DnsRecord]) -> String {
+    let mut output = String::new();
+
+    for record in records {
+        writeln!(
+            output,
+            "{}\t{}\tIN\t{}\t{}",
+            record.name, record.ttl, record.record_type, record.data
+        )
+        .unwrap();
+    }
+
+    output
+}
+
+/// Formats a CSV row per record, or a single row if there is an error.
+fn format_csv(name: &str, records: &[DnsRecord], error: Option<&str>) -> String {
+    let mut output = String::new();
+
+    if let Some(error) = error {
+        writeln!(output, "{},,,,{}", csv_field(name), csv_field(error)).unwrap();
+    }
+
+    for record in records {
+        writeln!(
+            output,
+            "{},{},{},{},",
+            csv_field(name),
+            csv_field(&record.record_type),
+            record.ttl,
+            csv_field(&record.data)
+        )
+        .unwrap();
+    }
+
+    output
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Reads names one per line, skipping blank lines and `#` comments.
+fn read_names(path: &Path) -> anyhow::Result<Vec<String>> {
+    let file = File::open(path).with_context(|| format!("could not open {:?}", path))?;
+    let mut names = Vec::new();
+
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let line = line.trim();
+
+        if !line.is_empty() && !line.starts_with('#') {
+            names.push(line.to_string());
+        }
+    }
+
+    Ok(names)
+}
+
 #[derive(Serialize)]
 struct OutputDoc {
+    name: String,
+    ascii_name: String,
     ip_addresses: Vec<IpAddr>,
-    text_record: String,
+    records: Vec<DnsRecord>,
+    source: SourceDoc,
+}
+
+#[derive(Serialize)]
+struct BatchOutputDoc<'a> {
+    name: &'a str,
+    ip_addresses: Vec<IpAddr>,
+    records: Vec<DnsRecord>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<SourceDoc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Where an answer came from. The round trip time is in seconds.
+#[derive(Serialize)]
+struct SourceDoc {
+    kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    address: Option<SocketAddr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    protocol: Option<ServerProtocol>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    round_trip: Option<f64>,
+}
+
+impl From<&AnswerSource> for SourceDoc {
+    fn from(source: &AnswerSource) -> Self {
+        let kind = match source {
+            AnswerSource::Static => "static",
+            AnswerSource::Cache => "cache",
+            AnswerSource::Server(_) => "server",
+        };
+        let server = match source {
+            AnswerSource::Server(server) => Some(server),
+            _ => None,
+        };
+
+        Self {
+            kind,
+            server: server.map(|server| server.name.clone()),
+            address: server.and_then(|server| server.address),
+            protocol: server.map(|server| server.protocol),
+            round_trip: server.map(|server| server.round_trip.as_secs_f64()),
+        }
+    }
 }
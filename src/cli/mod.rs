@@ -1,21 +1,75 @@
 mod args;
+mod batch;
+mod batch_state;
+mod crash;
+mod disk;
+mod error_report;
 mod fetch;
 mod logging;
 mod lookup;
+mod shutdown;
+mod spider;
+mod status;
+mod sync;
+mod syslog;
+mod template;
+mod trace;
+mod writer;
+
+use std::process::ExitCode;
 
 use clap::Parser;
 
 use self::args::{Command, ProgramArgs};
 
-pub fn run() -> anyhow::Result<()> {
-    curl::init();
+/// Points libcurl at the `--ssl-key-log` file.
+///
+/// libcurl only reads `SSLKEYLOGFILE` when globally initialized, and has no
+/// option for it, so the variable is set before any other thread is started.
+fn set_up_ssl_key_log(args: &ProgramArgs) -> anyhow::Result<()> {
+    if let Some(path) = &args.ssl_key_log {
+        if !crate::client::tls_key_log_supported() {
+            anyhow::bail!(
+                "TLS key logging is not supported by the TLS library {}",
+                curl::Version::get().ssl_version().unwrap_or("(none)")
+            );
+        }
+
+        std::env::set_var("SSLKEYLOGFILE", path);
+    }
 
+    Ok(())
+}
+
+pub fn run() -> ExitCode {
     let args = ProgramArgs::parse();
+    let key_log_result = set_up_ssl_key_log(&args);
+
+    if let Some(directory) = &args.crash_report_dir {
+        crash::set_up_crash_reports(directory.clone());
+    }
 
-    logging::set_up_logging(&args)?;
+    match key_log_result.and_then(|_| run_command(&args)) {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(error) => {
+            tracing::error!(?error, "main exit failure");
+            error_report::report_error(&args, &error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run_command(args: &ProgramArgs) -> anyhow::Result<()> {
+    let _logging_guard = logging::set_up_logging(args)?;
+
+    if std::env::var_os("SSLKEYLOGFILE").is_some() && !crate::client::tls_key_log_supported() {
+        tracing::warn!("SSLKEYLOGFILE is set but not supported by the TLS library");
+    }
+
+    curl::init();
 
-    match args.command {
-        Command::Fetch(fetch_args) => fetch::run(&fetch_args),
-        Command::Lookup(lookup_args) => lookup::run(&lookup_args),
+    match &args.command {
+        Command::Fetch(fetch_args) => fetch::run(fetch_args),
+        Command::Lookup(lookup_args) => lookup::run(lookup_args),
     }
 }
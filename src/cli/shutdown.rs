@@ -0,0 +1,60 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    iterator::Signals,
+};
+
+use crate::client::CancellationToken;
+
+/// Exit status used when a second signal forces the program to quit.
+const FORCED_EXIT_STATUS: i32 = 130;
+
+/// Cancels the token on the first SIGINT or SIGTERM and exits immediately
+/// on the second.
+pub fn register_signals(token: CancellationToken) -> anyhow::Result<()> {
+    let mut signals = Signals::new([SIGINT, SIGTERM])?;
+
+    std::thread::spawn(move || {
+        for signal in signals.forever() {
+            if token.is_cancelled() {
+                tracing::warn!(signal, "forced exit");
+                eprintln!("wrecv: exiting immediately");
+                std::process::exit(FORCED_EXIT_STATUS);
+            }
+
+            tracing::info!(signal, "shutdown requested");
+            eprintln!("wrecv: stopping the transfer, interrupt again to exit immediately");
+            token.cancel();
+        }
+    });
+
+    Ok(())
+}
+
+/// Cancels the token once the duration has passed.
+///
+/// The returned flag is set if the token was cancelled by the time limit.
+pub fn cancel_after(token: CancellationToken, duration: Duration) -> Arc<AtomicBool> {
+    let expired = Arc::new(AtomicBool::new(false));
+    let flag = expired.clone();
+
+    std::thread::spawn(move || {
+        std::thread::sleep(duration);
+
+        if !token.is_cancelled() {
+            tracing::info!(?duration, "run time limit reached");
+            eprintln!("wrecv: run time limit reached, stopping the transfers");
+            flag.store(true, Ordering::Relaxed);
+            token.cancel();
+        }
+    });
+
+    expired
+}
@@ -0,0 +1,189 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use url::Url;
+
+use crate::{
+    client::{
+        CancellationToken, CircuitBreaker, Client, EventFilter, EventKind, Method, Request,
+        SessionControl, SessionEvent, SessionHandler,
+    },
+    error::Error,
+};
+
+use super::{
+    args::FetchArgs,
+    batch::{self, BatchItem, BatchQueue},
+    fetch, shutdown,
+};
+
+/// Checks the URL or the URLs of the batch, printing a line per URL of the
+/// URL, status code, content type, size, and error separated by tabs.
+///
+/// URLs that fail or give a 4xx or 5xx status count as failures.
+pub fn run(args: &FetchArgs) -> anyhow::Result<()> {
+    let items = match &args.url {
+        Some(url) => vec![BatchItem {
+            line_number: 1,
+            url: url.clone(),
+        }],
+        None => batch::read_items(args, time::OffsetDateTime::now_utc())?,
+    };
+    let total = items.len();
+
+    let queue = BatchQueue::new(items, args.per_host as usize);
+    let failures = AtomicUsize::new(0);
+    let cancellation_token = CancellationToken::new();
+    let circuit_breaker = CircuitBreaker::new();
+    let resolver = fetch::build_resolver(args)?.map(Arc::new);
+    let config = fetch::build_config(args)?;
+
+    shutdown::register_signals(cancellation_token.clone())?;
+
+    tracing::info!(total, jobs = args.jobs, "spider start");
+
+    std::thread::scope(|scope| {
+        for _ in 0..args.jobs {
+            let queue = &queue;
+            let failures = &failures;
+            let cancellation_token = &cancellation_token;
+            let config = &config;
+            let circuit_breaker = &circuit_breaker;
+            let resolver = &resolver;
+
+            scope.spawn(move || {
+                let mut client = Client::new(config.clone());
+                client
+                    .set_cancellation_token(cancellation_token.clone())
+                    .set_circuit_breaker(circuit_breaker.clone())
+                    .set_resolver(resolver.clone());
+
+                while let Some(item) = queue.next() {
+                    if cancellation_token.is_cancelled() {
+                        queue.finish(&item);
+                        break;
+                    }
+
+                    let result = check_url(&client, &item.url);
+                    queue.finish(&item);
+
+                    let failed = match &result {
+                        Ok(report) => report.status_code.is_some_and(|code| code >= 400),
+                        Err(_) => true,
+                    };
+
+                    if failed {
+                        failures.fetch_add(1, Ordering::Relaxed);
+                    }
+
+                    // Printed at once so that lines of workers don't interleave
+                    print!("{}", format_result(&item.url, result));
+                }
+            });
+        }
+    });
+
+    let failures = failures.load(Ordering::Relaxed);
+    tracing::info!(total, failures, "spider finish");
+
+    if cancellation_token.is_cancelled() {
+        Err(Error::Cancelled.into())
+    } else if failures > 0 {
+        anyhow::bail!("{} of {} URLs failed", failures, total)
+    } else {
+        Ok(())
+    }
+}
+
+/// Sends a HEAD request, or a GET request if the server doesn't allow HEAD.
+fn check_url(client: &Client, url: &Url) -> Result<SpiderHandler, Error> {
+    let mut request = Request::new(url.clone());
+    request.set_method(Method::Head);
+
+    let (handler, result) = client.submit(request, SpiderHandler::default());
+    result?;
+
+    if matches!(handler.status_code, Some(405 | 501)) {
+        tracing::debug!(%url, status_code = handler.status_code, "HEAD not allowed");
+
+        let (handler, result) = client.submit(Request::new(url.clone()), SpiderHandler::default());
+        result?;
+
+        return Ok(handler);
+    }
+
+    Ok(handler)
+}
+
+fn format_result(url: &Url, result: Result<SpiderHandler, Error>) -> String {
+    match result {
+        Ok(report) => format!(
+            "{}\t{}\t{}\t{}\t\n",
+            url,
+            report
+                .status_code
+                .map(|code| code.to_string())
+                .unwrap_or_default(),
+            report.content_type.as_deref().unwrap_or_default(),
+            report
+                .size()
+                .map(|size| size.to_string())
+                .unwrap_or_default(),
+        ),
+        Err(error) => format!("{}\t\t\t\t{}\n", url, error),
+    }
+}
+
+/// Records the response of a check, discarding the content.
+#[derive(Debug, Default)]
+struct SpiderHandler {
+    status_code: Option<u16>,
+    content_type: Option<String>,
+    content_length: Option<u64>,
+    download_total: u64,
+    content_received: u64,
+}
+
+impl SpiderHandler {
+    /// Size given by the server, or else the size of the received content.
+    fn size(&self) -> Option<u64> {
+        self.content_length
+            .or((self.download_total > 0).then_some(self.download_total))
+            .or((self.content_received > 0).then_some(self.content_received))
+    }
+}
+
+impl SessionHandler for SpiderHandler {
+    fn event(
+        &mut self,
+        _control: &mut dyn SessionControl,
+        event: SessionEvent,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        match event {
+            SessionEvent::HttpResponse(_data, header) => {
+                self.status_code = Some(header.status_code);
+                self.content_type = header.fields.content_type();
+                self.content_length = header.fields.content_length();
+            }
+            SessionEvent::ContentReceived(data) => {
+                self.content_received += data.len() as u64;
+            }
+            SessionEvent::Progress { download_total, .. } => {
+                self.download_total = download_total;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn event_filter(&self) -> EventFilter {
+        EventFilter::from_iter([
+            EventKind::HttpResponse,
+            EventKind::ContentReceived,
+            EventKind::Progress,
+        ])
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use signal_hook::{consts::SIGUSR1, iterator::Signals};
+
+/// Snapshot of the running transfers printed on SIGUSR1.
+///
+/// Each transfer running at the same time uses its own slot from
+/// [`Self::slot`].
+#[derive(Debug, Clone, Default)]
+pub struct TransferStatus {
+    slots: Arc<Mutex<BTreeMap<usize, StatusInner>>>,
+    next_slot: Arc<AtomicUsize>,
+    slot: usize,
+}
+
+#[derive(Debug)]
+struct StatusInner {
+    url: String,
+    start_time: Instant,
+    download_total: u64,
+    download_current: u64,
+    upload_total: u64,
+    upload_current: u64,
+}
+
+impl TransferStatus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a status sharing the signal output for another transfer
+    /// running at the same time.
+    pub fn slot(&self) -> Self {
+        Self {
+            slots: self.slots.clone(),
+            next_slot: self.next_slot.clone(),
+            slot: self.next_slot.fetch_add(1, Ordering::Relaxed) + 1,
+        }
+    }
+
+    pub fn start(&self, url: &str) {
+        self.slots.lock().unwrap().insert(
+            self.slot,
+            StatusInner {
+                url: url.to_string(),
+                start_time: Instant::now(),
+                download_total: 0,
+                download_current: 0,
+                upload_total: 0,
+                upload_current: 0,
+            },
+        );
+    }
+
+    pub fn update_progress(
+        &self,
+        download_total: u64,
+        download_current: u64,
+        upload_total: u64,
+        upload_current: u64,
+    ) {
+        if let Some(inner) = self.slots.lock().unwrap().get_mut(&self.slot) {
+            inner.download_total = download_total;
+            inner.download_current = download_current;
+            inner.upload_total = upload_total;
+            inner.upload_current = upload_current;
+        }
+    }
+
+    pub fn finish(&self) {
+        self.slots.lock().unwrap().remove(&self.slot);
+    }
+
+    /// Formats each active transfer.
+    fn format(&self) -> Vec<String> {
+        let slots = self.slots.lock().unwrap();
+
+        if slots.is_empty() {
+            return vec!["no active transfer".to_string()];
+        }
+
+        slots.values().map(StatusInner::format).collect()
+    }
+
+    /// Prints the status to stderr and the log whenever SIGUSR1 is received.
+    pub fn register_signal(&self) -> anyhow::Result<()> {
+        let mut signals = Signals::new([SIGUSR1])?;
+        let status = self.clone();
+
+        std::thread::spawn(move || {
+            for _signal in signals.forever() {
+                for text in status.format() {
+                    tracing::info!(status = text, "status");
+                    eprintln!("wrecv status: {}", text);
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+impl StatusInner {
+    fn format(&self) -> String {
+        let elapsed = self.start_time.elapsed().as_secs_f64();
+        let speed = if elapsed > 0.0 {
+            self.download_current as f64 / elapsed
+        } else {
+            0.0
+        };
+
+        format!(
+            "{} downloaded {}/{} bytes, uploaded {}/{} bytes, {:.1} KiB/s, elapsed {:.1}s",
+            self.url,
+            self.download_current,
+            self.download_total,
+            self.upload_current,
+            self.upload_total,
+            speed / 1024.0,
+            elapsed
+        )
+    }
+}
@@ -0,0 +1,39 @@
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+/// Flushes the contents and metadata of the files to disk.
+pub fn sync_files<'a, I: IntoIterator<Item = &'a File>>(files: I) -> std::io::Result<()> {
+    for file in files {
+        file.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// Flushes the directories containing the paths to disk so that newly
+/// created or renamed entries survive a crash.
+pub fn sync_parent_directories<'a, I: IntoIterator<Item = &'a Path>>(
+    paths: I,
+) -> std::io::Result<()> {
+    let directories = paths
+        .into_iter()
+        .map(parent_directory)
+        .collect::<BTreeSet<PathBuf>>();
+
+    for directory in directories {
+        tracing::debug!(?directory, "sync directory");
+        File::open(&directory)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+fn parent_directory(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
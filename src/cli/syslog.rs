@@ -0,0 +1,83 @@
+use std::{ffi::CString, io::Write, sync::Once};
+
+use tracing::{Level, Metadata};
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Sends each formatted log event as a message to the local syslog daemon.
+pub struct SyslogMakeWriter;
+
+impl SyslogMakeWriter {
+    pub fn new() -> Self {
+        static OPEN_LOG: Once = Once::new();
+
+        OPEN_LOG.call_once(|| unsafe {
+            libc::openlog(c"wrecv".as_ptr(), libc::LOG_PID, libc::LOG_USER);
+        });
+
+        Self
+    }
+}
+
+impl<'a> MakeWriter<'a> for SyslogMakeWriter {
+    type Writer = SyslogWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        SyslogWriter::new(libc::LOG_INFO)
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        let priority = match *meta.level() {
+            Level::ERROR => libc::LOG_ERR,
+            Level::WARN => libc::LOG_WARNING,
+            Level::INFO => libc::LOG_INFO,
+            Level::DEBUG | Level::TRACE => libc::LOG_DEBUG,
+        };
+
+        SyslogWriter::new(priority)
+    }
+}
+
+/// Buffers a log event and sends it when dropped.
+pub struct SyslogWriter {
+    priority: libc::c_int,
+    buf: Vec<u8>,
+}
+
+impl SyslogWriter {
+    fn new(priority: libc::c_int) -> Self {
+        Self {
+            priority,
+            buf: Vec::new(),
+        }
+    }
+}
+
+impl Write for SyslogWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for SyslogWriter {
+    fn drop(&mut self) {
+        while self.buf.last() == Some(&b'\n') {
+            self.buf.pop();
+        }
+
+        if self.buf.is_empty() {
+            return;
+        }
+
+        self.buf.retain(|&byte| byte != 0);
+        let message = CString::new(std::mem::take(&mut self.buf)).expect("no NUL bytes");
+
+        unsafe {
+            libc::syslog(self.priority, c"%s".as_ptr(), message.as_ptr());
+        }
+    }
+}
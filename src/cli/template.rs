@@ -0,0 +1,321 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use time::OffsetDateTime;
+use url::Url;
+
+use crate::string::sanitize_file_name;
+
+/// Largest number of URLs a template may expand to.
+const MAX_EXPANSIONS: usize = 1_000_000;
+
+/// Expands a URL template into URLs.
+///
+/// Supports curl-style globs: numeric ranges `[1-100]` (zero padded if
+/// the start is, as in `[001-100]`), letter ranges `[a-z]`, an optional
+/// step `[0-100:10]`, and sets `{a,b,c}`. A backslash escapes the next
+/// character.
+///
+/// Before globbing, strftime tokens (`%Y`, `%m`, `%d`, `%H`, `%M`, `%S`)
+/// are replaced with the given UTC time. Other percent-escapes such as
+/// `%20` are kept, and a literal `%` can be written as `%%`.
+pub fn expand_url_template(template: &str, now: OffsetDateTime) -> anyhow::Result<Vec<Url>> {
+    let template = expand_strftime(template, now)?;
+    let mut expansions = vec![String::new()];
+
+    for part in parse_glob(&template)? {
+        let count = expansions.len().saturating_mul(part.len());
+
+        if count > MAX_EXPANSIONS {
+            anyhow::bail!("URL template expands to more than {} URLs", MAX_EXPANSIONS);
+        }
+
+        let mut next = Vec::with_capacity(count);
+
+        for prefix in &expansions {
+            for value in &part {
+                next.push(format!("{}{}", prefix, value));
+            }
+        }
+
+        expansions = next;
+    }
+
+    expansions
+        .into_iter()
+        .map(|url| {
+            Url::parse(&url).map_err(|error| anyhow::anyhow!("invalid URL {:?}: {}", url, error))
+        })
+        .collect()
+}
+
+fn expand_strftime(template: &str, now: OffsetDateTime) -> anyhow::Result<String> {
+    let mut output = String::new();
+    let mut chars = template.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+
+        let mut lookahead = chars.clone();
+
+        match (lookahead.next(), lookahead.next()) {
+            (Some(high), Some(low))
+                if !matches!(high, 'Y' | 'm' | 'd' | 'H' | 'M' | 'S')
+                    && high.is_ascii_hexdigit()
+                    && low.is_ascii_hexdigit() =>
+            {
+                output.push(c);
+                continue;
+            }
+            _ => {}
+        }
+
+        match chars.next() {
+            Some('Y') => output.push_str(&format!("{:04}", now.year())),
+            Some('m') => output.push_str(&format!("{:02}", u8::from(now.month()))),
+            Some('d') => output.push_str(&format!("{:02}", now.day())),
+            Some('H') => output.push_str(&format!("{:02}", now.hour())),
+            Some('M') => output.push_str(&format!("{:02}", now.minute())),
+            Some('S') => output.push_str(&format!("{:02}", now.second())),
+            Some('%') => output.push('%'),
+            Some(other) => anyhow::bail!("unknown time token %{} in URL template", other),
+            None => anyhow::bail!("unterminated time token in URL template"),
+        }
+    }
+
+    Ok(output)
+}
+
+/// Splits a template into parts, each being the alternatives at that
+/// position.
+fn parse_glob(template: &str) -> anyhow::Result<Vec<Vec<String>>> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(c) => literal.push(c),
+                None => anyhow::bail!("trailing backslash in URL template"),
+            },
+            '[' | '{' => {
+                let close = if c == '[' { ']' } else { '}' };
+                let mut body = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some(c) if c == close => break,
+                        Some(c) => body.push(c),
+                        None => anyhow::bail!("unclosed {:?} in URL template", c),
+                    }
+                }
+
+                if !literal.is_empty() {
+                    parts.push(vec![std::mem::take(&mut literal)]);
+                }
+
+                if c == '[' {
+                    parts.push(expand_range(&body)?);
+                } else {
+                    parts.push(body.split(',').map(str::to_string).collect());
+                }
+            }
+            _ => literal.push(c),
+        }
+    }
+
+    if !literal.is_empty() {
+        parts.push(vec![literal]);
+    }
+
+    Ok(parts)
+}
+
+fn expand_range(body: &str) -> anyhow::Result<Vec<String>> {
+    let invalid = || anyhow::anyhow!("invalid range [{}] in URL template", body);
+
+    let (range, step) = match body.split_once(':') {
+        Some((range, step)) => (range, step.parse::<usize>().map_err(|_| invalid())?),
+        None => (body, 1),
+    };
+    let (start, end) = range.split_once('-').ok_or_else(invalid)?;
+
+    if step == 0 {
+        return Err(invalid());
+    }
+
+    if let (Ok(start_number), Ok(end_number)) = (start.parse::<u64>(), end.parse::<u64>()) {
+        if start_number > end_number {
+            return Err(invalid());
+        }
+
+        let width = if start.starts_with('0') {
+            start.len()
+        } else {
+            0
+        };
+
+        return Ok((start_number..=end_number)
+            .step_by(step)
+            .take(MAX_EXPANSIONS + 1)
+            .map(|number| format!("{:0width$}", number, width = width))
+            .collect());
+    }
+
+    let mut start_chars = start.chars();
+    let mut end_chars = end.chars();
+
+    match (
+        start_chars.next(),
+        start_chars.next(),
+        end_chars.next(),
+        end_chars.next(),
+    ) {
+        (Some(start), None, Some(end), None)
+            if start.is_ascii_alphabetic() && end.is_ascii_alphabetic() && start <= end =>
+        {
+            Ok((start..=end).step_by(step).map(|c| c.to_string()).collect())
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Makes a file name for a URL from a template with the variables
+/// `{host}`, `{path}`, `{ts}`, `{ext}`, and `{n}` (position in the batch).
+///
+/// Slashes of the path become underscores so that files stay in the output
+/// directory.
+pub fn expand_output_template(template: &str, url: &Url, number: usize, ts: &str) -> PathBuf {
+    let path = url.path().trim_matches('/');
+    let last_segment = path.rsplit('/').next().unwrap_or_default();
+    let ext = match last_segment.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => ext,
+        _ => "",
+    };
+
+    let name = template
+        .replace("{host}", url.host_str().unwrap_or_default())
+        .replace("{path}", &path.replace('/', "_"))
+        .replace("{ts}", ts)
+        .replace("{ext}", ext)
+        .replace("{n}", &number.to_string());
+
+    match sanitize_file_name(&name) {
+        Some(name) => PathBuf::from(name),
+        None => PathBuf::from(number.to_string()),
+    }
+}
+
+/// Returns a file name that the output template makes for more than one
+/// URL, with the numbers of the first two.
+pub fn find_duplicate_output<'a>(
+    template: &str,
+    urls: impl IntoIterator<Item = (usize, &'a Url)>,
+    ts: &str,
+) -> Option<(PathBuf, usize, usize)> {
+    let mut numbers = HashMap::new();
+
+    for (number, url) in urls {
+        let name = expand_output_template(template, url, number, ts);
+
+        if let Some(first) = numbers.insert(name.clone(), number) {
+            return Some((name, first, number));
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2026-03-04 05:06:07 UTC.
+    fn now() -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(1772600767).unwrap()
+    }
+
+    fn expand(template: &str) -> Vec<String> {
+        expand_url_template(template, now())
+            .unwrap()
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_expand_url_template() {
+        assert_eq!(
+            expand("http://example.com/[08-10]/{a,b}"),
+            [
+                "http://example.com/08/a",
+                "http://example.com/08/b",
+                "http://example.com/09/a",
+                "http://example.com/09/b",
+                "http://example.com/10/a",
+                "http://example.com/10/b",
+            ]
+        );
+        assert_eq!(
+            expand("http://example.com/[a-e:2]\\[x\\]"),
+            [
+                "http://example.com/a[x]",
+                "http://example.com/c[x]",
+                "http://example.com/e[x]",
+            ]
+        );
+        assert_eq!(
+            expand("http://example.com/%Y-%m-%d/%H%M%S"),
+            ["http://example.com/2026-03-04/050607"]
+        );
+        assert_eq!(
+            expand("http://example.com/a%20b%2F%%/%Y"),
+            ["http://example.com/a%20b%2F%/2026"]
+        );
+
+        let now = now();
+        assert!(expand_url_template("http://example.com/%q", now).is_err());
+        assert!(expand_url_template("http://example.com/[5-1]", now).is_err());
+        assert!(expand_url_template("http://example.com/[1-9:0]", now).is_err());
+        assert!(expand_url_template("http://example.com/{a,b", now).is_err());
+        assert!(expand_url_template("http://example.com/[1-1000][1-1000][1-2]", now).is_err());
+    }
+
+    #[test]
+    fn test_expand_output_template() {
+        let url = Url::parse("http://example.com/dir/file.tar.gz").unwrap();
+
+        assert_eq!(
+            expand_output_template("{host}-{path}-{n}.{ext}", &url, 3, "20260304"),
+            PathBuf::from("example.com-dir_file.tar.gz-3.gz")
+        );
+        assert_eq!(
+            expand_output_template("{ts}_{n}", &url, 3, "20260304"),
+            PathBuf::from("20260304_3")
+        );
+        assert_eq!(
+            expand_output_template("..", &url, 7, "20260304"),
+            PathBuf::from("7")
+        );
+    }
+
+    #[test]
+    fn test_find_duplicate_output() {
+        let urls = [
+            Url::parse("http://example.com/a/index.html").unwrap(),
+            Url::parse("http://example.com/b/page.html").unwrap(),
+            Url::parse("http://example.com/c/index.html").unwrap(),
+        ];
+        let numbered = || urls.iter().enumerate().map(|(index, url)| (index + 1, url));
+
+        assert_eq!(find_duplicate_output("{path}", numbered(), "ts"), None);
+        assert_eq!(find_duplicate_output("{host}_{n}", numbered(), "ts"), None);
+        assert_eq!(
+            find_duplicate_output("{host}.{ext}", numbered(), "ts"),
+            Some((PathBuf::from("example.com.html"), 1, 2))
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use std::io::Write;
+
+use crate::client::TraceKind;
+
+/// Writes the transfer backend's diagnostic stream in a format similar to
+/// curl's `--trace-ascii` option, except data is written as a hex dump.
+pub struct TraceWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn write_trace(&mut self, kind: TraceKind, data: &[u8]) -> std::io::Result<()> {
+        let timestamp = time::OffsetDateTime::now_utc()
+            .format(&time::format_description::well_known::Rfc3339)
+            .map_err(std::io::Error::other)?;
+
+        let (marker, label) = match kind {
+            TraceKind::Text => {
+                let text = String::from_utf8_lossy(data);
+                return writeln!(self.writer, "{} == Info: {}", timestamp, text.trim_end());
+            }
+            TraceKind::HeaderIn => ("<=", "Recv header"),
+            TraceKind::HeaderOut => ("=>", "Send header"),
+            TraceKind::DataIn => ("<=", "Recv data"),
+            TraceKind::DataOut => ("=>", "Send data"),
+            TraceKind::SslDataIn => ("<=", "Recv SSL data"),
+            TraceKind::SslDataOut => ("=>", "Send SSL data"),
+        };
+
+        writeln!(
+            self.writer,
+            "{} {} {}, {} bytes",
+            timestamp,
+            marker,
+            label,
+            data.len()
+        )?;
+
+        match kind {
+            TraceKind::HeaderIn | TraceKind::HeaderOut => {
+                self.writer.write_all(data)?;
+
+                if !data.ends_with(b"\n") {
+                    self.writer.write_all(b"\n")?;
+                }
+            }
+            TraceKind::DataIn | TraceKind::DataOut => {
+                self.writer
+                    .write_all(crate::string::hex_dump(data).as_bytes())?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    sync::mpsc::{self, SyncSender, TrySendError},
+    thread::JoinHandle,
+};
+
+/// Number of chunks queued before writes block.
+const QUEUE_LENGTH: usize = 64;
+
+/// Writes a file through a buffered writer on a dedicated thread.
+///
+/// Writes only queue the data, so transfer callbacks don't wait on the disk
+/// unless the bounded queue is full. A full queue blocks the callback,
+/// which pauses the transfer until the disk catches up.
+pub struct BackgroundWriter {
+    sender: Option<SyncSender<Vec<u8>>>,
+    thread: Option<JoinHandle<std::io::Result<File>>>,
+    /// File, or the error that stopped the thread, once finished.
+    result: Option<Result<File, (std::io::ErrorKind, String)>>,
+}
+
+impl BackgroundWriter {
+    pub fn new(file: File) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<Vec<u8>>(QUEUE_LENGTH);
+
+        let thread = std::thread::spawn(move || {
+            let mut writer = BufWriter::new(file);
+
+            for data in receiver {
+                writer.write_all(&data)?;
+            }
+
+            writer.into_inner().map_err(|error| error.into_error())
+        });
+
+        Self {
+            sender: Some(sender),
+            thread: Some(thread),
+            result: None,
+        }
+    }
+
+    /// Queues the data, returning an error of a previous write if the
+    /// thread stopped.
+    pub fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+        let Some(sender) = &self.sender else {
+            return Err(std::io::Error::other("writer is finished"));
+        };
+
+        let sent = match sender.try_send(data.to_vec()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(data)) => {
+                tracing::debug!("write queue full, waiting for disk");
+                sender.send(data).is_ok()
+            }
+            Err(TrySendError::Disconnected(_data)) => false,
+        };
+
+        if sent {
+            Ok(())
+        } else {
+            self.finish()?;
+            Err(std::io::Error::other("writer stopped"))
+        }
+    }
+
+    /// Waits for the queued data to be written and returns the file.
+    pub fn finish(&mut self) -> std::io::Result<&File> {
+        self.sender = None;
+
+        if let Some(thread) = self.thread.take() {
+            let result = thread.join().expect("writer thread panicked");
+            self.result = Some(result.map_err(|error| (error.kind(), error.to_string())));
+        }
+
+        match self.result.as_ref().expect("writer finished") {
+            Ok(file) => Ok(file),
+            Err((kind, message)) => Err(std::io::Error::new(*kind, message.clone())),
+        }
+    }
+}
+
+impl Drop for BackgroundWriter {
+    fn drop(&mut self) {
+        if self.thread.is_some() {
+            if let Err(error) = self.finish() {
+                tracing::warn!(%error, "background write");
+            }
+        }
+    }
+}
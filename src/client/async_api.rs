@@ -0,0 +1,152 @@
+use std::{
+    panic::AssertUnwindSafe,
+    sync::{mpsc, Arc, Mutex},
+};
+
+use tokio::sync::oneshot;
+use url::Url;
+
+use crate::error::{Error, OtherError};
+
+use super::{CancellationToken, CircuitBreaker, Client, Config, Request, Response, SessionHandler};
+
+type Job = Box<dyn FnOnce(&Client) + Send>;
+
+/// Client for async applications that runs transfers on worker threads.
+///
+/// Each worker thread owns a [`Client`] created from the config. The
+/// workers share cookies, the circuit breaker, and the cancellation token.
+///
+/// Dropping a future doesn't stop its transfer; use the cancellation token
+/// to stop all transfers. Dropping the client doesn't wait for the workers,
+/// which exit once their queued transfers are done; use [`Self::shutdown`]
+/// to wait for them.
+#[derive(Debug)]
+pub struct AsyncClient {
+    sender: mpsc::Sender<Job>,
+    /// Closed when the worker thread exits.
+    workers_exited: Vec<oneshot::Receiver<()>>,
+    cancellation_token: CancellationToken,
+}
+
+impl AsyncClient {
+    /// Creates a client with the given number of worker threads, which is
+    /// the number of transfers that can run at the same time.
+    pub fn new(config: Config, workers: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let cookie_jar = Client::new(config.clone()).cookie_jar().clone();
+        let cancellation_token = CancellationToken::new();
+        let circuit_breaker = CircuitBreaker::new();
+
+        let workers_exited = (0..workers.max(1))
+            .map(|index| {
+                let config = config.clone();
+                let receiver = receiver.clone();
+                let cookie_jar = cookie_jar.clone();
+                let cancellation_token = cancellation_token.clone();
+                let circuit_breaker = circuit_breaker.clone();
+                let (exit_sender, exit_receiver) = oneshot::channel::<()>();
+
+                std::thread::Builder::new()
+                    .name(format!("wrecv-client-{}", index))
+                    .spawn(move || {
+                        let _exit_sender = exit_sender;
+                        let mut client = Client::new(config);
+                        client
+                            .set_cancellation_token(cancellation_token)
+                            .set_circuit_breaker(circuit_breaker);
+                        *client.cookie_jar_mut() = cookie_jar;
+
+                        run_worker(&client, &receiver);
+                    })
+                    .expect("spawn client worker thread");
+
+                exit_receiver
+            })
+            .collect();
+
+        Self {
+            sender,
+            workers_exited,
+            cancellation_token,
+        }
+    }
+
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.cancellation_token
+    }
+
+    /// Runs [`Client::submit`] on a worker thread.
+    ///
+    /// The outer error is returned without the handler if the transfer
+    /// couldn't run or the handler panicked.
+    pub async fn submit<H: SessionHandler + Send + 'static>(
+        &self,
+        request: Request,
+        handler: H,
+    ) -> Result<(H, Result<(), Error>), Error> {
+        self.run(move |client| client.submit(request, handler))
+            .await
+    }
+
+    /// Runs [`Client::get`] on a worker thread.
+    pub async fn get(&self, url: Url) -> Result<Response, Error> {
+        self.run(move |client| client.get(url)).await?
+    }
+
+    /// Runs [`Client::fetch`] on a worker thread.
+    pub async fn fetch(&self, request: Request) -> Result<Response, Error> {
+        self.run(move |client| client.fetch(request)).await?
+    }
+
+    /// Waits for the submitted transfers to finish and the worker threads
+    /// to exit.
+    pub async fn shutdown(self) {
+        drop(self.sender);
+
+        for worker_exited in self.workers_exited {
+            // Closed without a value when the thread exits
+            let _ = worker_exited.await;
+        }
+    }
+
+    async fn run<T, F>(&self, function: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Client) -> T + Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        let job: Job = Box::new(move |client| {
+            let _ = sender.send(function(client));
+        });
+
+        self.sender
+            .send(job)
+            .map_err(|_| worker_error("client workers stopped"))?;
+
+        receiver
+            .await
+            .map_err(|_| worker_error("transfer panicked on the client worker"))
+    }
+}
+
+fn worker_error(message: &str) -> Error {
+    Error::Other(OtherError::Custom(message.into()))
+}
+
+fn run_worker(client: &Client, receiver: &Mutex<mpsc::Receiver<Job>>) {
+    loop {
+        // The lock is released before running the job
+        let job = receiver.lock().unwrap().recv();
+
+        match job {
+            Ok(job) => {
+                // A panicking handler drops the result sender, which the
+                // future reports, and the worker goes on
+                let _ = std::panic::catch_unwind(AssertUnwindSafe(|| job(client)));
+            }
+            Err(_) => break,
+        }
+    }
+}
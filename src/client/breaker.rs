@@ -0,0 +1,152 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Longest time a circuit stays open.
+const MAX_OPEN_DURATION: Duration = Duration::from_secs(3600);
+
+/// Change of the circuit of a host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitTransition {
+    /// Transfers to the host are refused for the duration.
+    Opened(Duration),
+    Closed,
+}
+
+/// Tracks consecutive failures per host and refuses transfers to hosts that
+/// keep failing.
+///
+/// Once the open duration elapses, transfers are allowed again and the
+/// next result decides whether the circuit closes or opens for longer.
+/// Successes gradually shorten the open duration again.
+#[derive(Debug, Clone, Default)]
+pub struct CircuitBreaker {
+    hosts: Arc<Mutex<HashMap<String, HostCircuit>>>,
+}
+
+#[derive(Debug, Default)]
+struct HostCircuit {
+    consecutive_failures: u32,
+    trips: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the time remaining if the circuit of the host is open.
+    pub fn check(&self, host: &str, now: Instant) -> Result<(), Duration> {
+        let hosts = self.hosts.lock().unwrap();
+
+        match hosts.get(host).and_then(|circuit| circuit.open_until) {
+            Some(open_until) if open_until > now => Err(open_until - now),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn record_success(&self, host: &str) -> Option<CircuitTransition> {
+        let mut hosts = self.hosts.lock().unwrap();
+        let circuit = hosts.get_mut(host)?;
+
+        circuit.consecutive_failures = 0;
+        circuit.trips = circuit.trips.saturating_sub(1);
+
+        let transition = circuit.open_until.take().map(|_| CircuitTransition::Closed);
+
+        if circuit.trips == 0 {
+            hosts.remove(host);
+        }
+
+        transition
+    }
+
+    /// Records a failure and opens the circuit after `threshold` consecutive
+    /// failures, or immediately if it was open before.
+    ///
+    /// The open duration starts at `base_duration` and doubles every time
+    /// the circuit opens again.
+    pub fn record_failure(
+        &self,
+        host: &str,
+        threshold: u32,
+        base_duration: Duration,
+        now: Instant,
+    ) -> Option<CircuitTransition> {
+        let mut hosts = self.hosts.lock().unwrap();
+        let circuit = hosts.entry(host.to_string()).or_default();
+
+        circuit.consecutive_failures += 1;
+
+        if circuit.open_until.is_some() || circuit.consecutive_failures >= threshold {
+            circuit.trips += 1;
+            circuit.consecutive_failures = 0;
+
+            let duration = open_duration(base_duration, circuit.trips);
+            circuit.open_until = Some(now + duration);
+
+            Some(CircuitTransition::Opened(duration))
+        } else {
+            None
+        }
+    }
+}
+
+fn open_duration(base_duration: Duration, trips: u32) -> Duration {
+    let factor = 1u32
+        .checked_shl(trips.saturating_sub(1))
+        .unwrap_or(u32::MAX);
+
+    base_duration
+        .saturating_mul(factor)
+        .min(MAX_OPEN_DURATION.max(base_duration))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::new();
+        let base = Duration::from_secs(10);
+        let now = Instant::now();
+
+        assert_eq!(breaker.record_failure("example.com", 2, base, now), None);
+        assert!(breaker.check("example.com", now).is_ok());
+        assert_eq!(
+            breaker.record_failure("example.com", 2, base, now),
+            Some(CircuitTransition::Opened(base))
+        );
+        assert_eq!(breaker.check("example.com", now), Err(base));
+        assert!(breaker.check("example.net", now).is_ok());
+
+        let now = now + base;
+        assert!(breaker.check("example.com", now).is_ok());
+        assert_eq!(
+            breaker.record_failure("example.com", 2, base, now),
+            Some(CircuitTransition::Opened(base * 2))
+        );
+
+        let now = now + base * 2;
+        assert!(breaker.check("example.com", now).is_ok());
+        assert_eq!(
+            breaker.record_success("example.com"),
+            Some(CircuitTransition::Closed)
+        );
+        assert_eq!(breaker.record_success("example.com"), None);
+        assert_eq!(breaker.record_success("example.com"), None);
+    }
+
+    #[test]
+    fn test_open_duration() {
+        let base = Duration::from_secs(10);
+
+        assert_eq!(open_duration(base, 1), base);
+        assert_eq!(open_duration(base, 3), base * 4);
+        assert_eq!(open_duration(base, 100), MAX_OPEN_DURATION);
+    }
+}
@@ -1,25 +1,68 @@
 use std::{
     fmt::Debug,
     net::{IpAddr, Ipv4Addr, SocketAddr},
-    sync::OnceLock,
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
+    time::Duration,
 };
 
+use serde::{Deserialize, Serialize};
 use url::Url;
 
 use crate::{
     error::{BoxedError, Error},
-    http::{HeaderFields, RequestHeader, ResponseHeader, ResponseTrailer},
+    http::{FieldName, HeaderFields, RequestHeader, ResponseHeader, ResponseTrailer},
+};
+
+use super::{
+    CookieCapture, HeaderProfile, HostPattern, RequestPolicy, TransferRate, TransferStats,
+    UserAgentRotation,
 };
 
 #[derive(Debug, Clone)]
 pub struct Config {
+    backend: Backend,
     bind_address: IpAddr,
+    connect_timeout: Duration,
+    dns_timeout: Option<Duration>,
+    happy_eyeballs_timeout: Duration,
+    max_transfer_duration: Option<Duration>,
+    progress_interval: Duration,
+    circuit_breaker_threshold: u32,
+    circuit_breaker_duration: Duration,
+    tcp_nodelay: bool,
+    tcp_keepalive: Option<TcpKeepalive>,
+    ip_tos: Option<u8>,
+    socket_mark: Option<u32>,
+    proxy: Option<Url>,
+    proxy_tunnel: bool,
     http_user_agent: String,
+    http_user_agent_rotation: Option<UserAgentRotation>,
+    http_accept_language: Option<String>,
+    http_auth: Option<HttpAuth>,
     http_headers: HeaderFields,
+    http_header_order: Vec<FieldName>,
+    http_suppressed_headers: Vec<FieldName>,
     http_09: bool,
     http_compression: bool,
+    http_content_decoding: bool,
+    http_raw_body: bool,
     http_cookies: bool,
+    http_cookie_capture: CookieCapture,
+    http_max_header_size: usize,
     tls_verification: bool,
+    tls_session_resumption: bool,
+    tls_key_log: Option<PathBuf>,
+    verify_status: bool,
+    reject_confusable: bool,
+    header_profile: Option<HeaderProfile>,
+    request_policy: Option<RequestPolicy>,
+    host_allowlist: Option<Vec<HostPattern>>,
+    host_denylist: Vec<HostPattern>,
 }
 
 impl Default for Config {
@@ -31,13 +74,151 @@ impl Default for Config {
 impl Config {
     pub fn new() -> Self {
         Self {
+            backend: Backend::default(),
             bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            connect_timeout: Duration::from_secs(30),
+            dns_timeout: None,
+            happy_eyeballs_timeout: Duration::from_millis(200),
+            max_transfer_duration: None,
+            progress_interval: Duration::from_millis(100),
+            circuit_breaker_threshold: 0,
+            circuit_breaker_duration: Duration::from_secs(30),
+            tcp_nodelay: true,
+            tcp_keepalive: None,
+            ip_tos: None,
+            socket_mark: None,
+            proxy: None,
+            proxy_tunnel: false,
             http_user_agent: default_user_agent().to_string(),
+            http_user_agent_rotation: None,
+            http_accept_language: None,
+            http_auth: None,
             http_headers: Self::make_default_http_headers(),
+            http_header_order: Vec::new(),
+            http_suppressed_headers: Vec::new(),
             http_09: false,
             http_compression: false,
+            http_content_decoding: true,
+            http_raw_body: true,
             http_cookies: false,
+            http_cookie_capture: CookieCapture::default(),
+            http_max_header_size: 262144,
             tls_verification: true,
+            tls_session_resumption: true,
+            tls_key_log: None,
+            verify_status: false,
+            reject_confusable: false,
+            header_profile: None,
+            request_policy: None,
+            host_allowlist: None,
+            host_denylist: Vec::new(),
+        }
+    }
+
+    /// Returns a builder of a config that is checked when it is built.
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
+    /// Checks that the backend supports the settings and that they don't
+    /// conflict, so that problems are reported before transfers fail.
+    pub fn validate(&self) -> Result<(), Error> {
+        let unsupported = |feature: &str| Error::UnsupportedFeature {
+            feature: feature.to_string(),
+        };
+        let invalid = |value: &str, reason: &str| Error::InvalidArgument {
+            value: value.to_string(),
+            reason: reason.to_string(),
+        };
+
+        if !self.backend.is_available() {
+            return Err(unsupported(&format!("{:?} backend", self.backend)));
+        }
+
+        if let (Backend::Native, Some(feature)) = (self.backend, self.native_unsupported_feature())
+        {
+            return Err(unsupported(&format!("{} with the native backend", feature)));
+        }
+
+        match &self.proxy {
+            Some(proxy) => {
+                let scheme = proxy.scheme();
+
+                if !PROXY_SCHEMES.contains(&scheme) {
+                    return Err(invalid(proxy.as_str(), "unsupported proxy scheme"));
+                }
+
+                if proxy.host_str().unwrap_or_default().is_empty() {
+                    return Err(invalid(proxy.as_str(), "proxy without a host"));
+                }
+
+                if self.proxy_tunnel && scheme.starts_with("socks") {
+                    return Err(invalid(proxy.as_str(), "proxy tunnel needs an HTTP proxy"));
+                }
+            }
+            None if self.proxy_tunnel => {
+                return Err(invalid("proxy tunnel", "needs a proxy"));
+            }
+            None => {}
+        }
+
+        if self.verify_status && !self.tls_verification {
+            return Err(invalid(
+                "certificate status verification",
+                "needs TLS verification",
+            ));
+        }
+
+        #[cfg(feature = "curl")]
+        if self.backend == Backend::Curl {
+            if self.verify_status && !verify_status_supported() {
+                return Err(unsupported("TLS certificate status verification"));
+            }
+
+            if self.tls_key_log.is_some() {
+                return Err(unsupported("TLS key log file with the curl backend"));
+            }
+
+            match &self.http_auth {
+                Some(HttpAuth::Ntlm { .. }) if !ntlm_supported() => {
+                    return Err(unsupported("NTLM authentication"));
+                }
+                Some(HttpAuth::Negotiate) if !negotiate_supported() => {
+                    return Err(unsupported("Negotiate authentication"));
+                }
+                _ => {}
+            }
+        }
+
+        if self.socket_mark.is_some() && !cfg!(target_os = "linux") {
+            return Err(unsupported("socket mark"));
+        }
+
+        if self.ip_tos.is_some() && !cfg!(unix) {
+            return Err(unsupported("IP type of service"));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the first setting that the native backend doesn't support.
+    pub(crate) fn native_unsupported_feature(&self) -> Option<&'static str> {
+        if !self.bind_address.is_unspecified() {
+            Some("bind address")
+        } else if self.tcp_keepalive.is_some() {
+            Some("TCP keepalive")
+        } else if self.ip_tos.is_some() {
+            Some("IP type of service")
+        } else if self.socket_mark.is_some() {
+            Some("socket mark")
+        } else if self.proxy.is_some() {
+            Some("proxy")
+        } else if self.http_auth.is_some() {
+            Some("HTTP authentication")
+        } else if self.verify_status {
+            Some("certificate status verification")
+        } else {
+            None
         }
     }
 
@@ -51,6 +232,16 @@ impl Config {
         fields
     }
 
+    /// Transfer backend that runs the requests.
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    pub fn set_backend(&mut self, backend: Backend) -> &mut Self {
+        self.backend = backend;
+        self
+    }
+
     pub fn bind_address(&self) -> IpAddr {
         self.bind_address
     }
@@ -60,6 +251,158 @@ impl Config {
         self
     }
 
+    /// Time allowed for establishing a connection.
+    ///
+    /// While other addresses remain to be tried, an attempt to a single
+    /// address is given half of the remaining time.
+    pub fn connect_timeout(&self) -> Duration {
+        self.connect_timeout
+    }
+
+    pub fn set_connect_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Time allowed for resolving the host name, separately from
+    /// [`Self::connect_timeout`] which also includes it.
+    ///
+    /// With curl, this is checked about once a second. It does not apply
+    /// to lookups by a resolver set on the client, which has its own
+    /// timeouts.
+    pub fn dns_timeout(&self) -> Option<Duration> {
+        self.dns_timeout
+    }
+
+    pub fn set_dns_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.dns_timeout = timeout;
+        self
+    }
+
+    /// Head start given to IPv6 addresses before IPv4 addresses are also
+    /// attempted.
+    pub fn happy_eyeballs_timeout(&self) -> Duration {
+        self.happy_eyeballs_timeout
+    }
+
+    pub fn set_happy_eyeballs_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.happy_eyeballs_timeout = timeout;
+        self
+    }
+
+    /// Time allowed for a whole transfer, regardless of its progress.
+    pub fn max_transfer_duration(&self) -> Option<Duration> {
+        self.max_transfer_duration
+    }
+
+    pub fn set_max_transfer_duration(&mut self, duration: Option<Duration>) -> &mut Self {
+        self.max_transfer_duration = duration;
+        self
+    }
+
+    /// Minimum time between progress events of a transfer.
+    ///
+    /// The last progress of a completed transfer is always given.
+    pub fn progress_interval(&self) -> Duration {
+        self.progress_interval
+    }
+
+    pub fn set_progress_interval(&mut self, interval: Duration) -> &mut Self {
+        self.progress_interval = interval;
+        self
+    }
+
+    /// Number of consecutive failed transfers to a host after which further
+    /// transfers to it are refused for a while, disabled if 0.
+    ///
+    /// Network errors and 5xx responses count as failures.
+    pub fn circuit_breaker_threshold(&self) -> u32 {
+        self.circuit_breaker_threshold
+    }
+
+    pub fn set_circuit_breaker_threshold(&mut self, threshold: u32) -> &mut Self {
+        self.circuit_breaker_threshold = threshold;
+        self
+    }
+
+    /// Time transfers to a failing host are first refused, doubled each
+    /// time the host fails again.
+    pub fn circuit_breaker_duration(&self) -> Duration {
+        self.circuit_breaker_duration
+    }
+
+    pub fn set_circuit_breaker_duration(&mut self, duration: Duration) -> &mut Self {
+        self.circuit_breaker_duration = duration;
+        self
+    }
+
+    /// Whether Nagle's algorithm is disabled on TCP connections.
+    pub fn tcp_nodelay(&self) -> bool {
+        self.tcp_nodelay
+    }
+
+    pub fn set_tcp_nodelay(&mut self, enabled: bool) -> &mut Self {
+        self.tcp_nodelay = enabled;
+        self
+    }
+
+    /// TCP keepalive probing, disabled if `None`.
+    pub fn tcp_keepalive(&self) -> Option<TcpKeepalive> {
+        self.tcp_keepalive
+    }
+
+    pub fn set_tcp_keepalive(&mut self, keepalive: Option<TcpKeepalive>) -> &mut Self {
+        self.tcp_keepalive = keepalive;
+        self
+    }
+
+    /// Type of service (IPv4) or traffic class (IPv6) byte of outgoing
+    /// packets, such as a DSCP value shifted left by 2.
+    pub fn ip_tos(&self) -> Option<u8> {
+        self.ip_tos
+    }
+
+    pub fn set_ip_tos(&mut self, tos: Option<u8>) -> &mut Self {
+        self.ip_tos = tos;
+        self
+    }
+
+    /// Firewall mark (`SO_MARK`) of sockets, for policy routing.
+    ///
+    /// Only supported on Linux.
+    pub fn socket_mark(&self) -> Option<u32> {
+        self.socket_mark
+    }
+
+    pub fn set_socket_mark(&mut self, mark: Option<u32>) -> &mut Self {
+        self.socket_mark = mark;
+        self
+    }
+
+    /// Proxy that transfers go through, such as `http://proxy:3128` or
+    /// `socks5h://proxy:1080`.
+    pub fn proxy(&self) -> Option<&Url> {
+        self.proxy.as_ref()
+    }
+
+    pub fn set_proxy(&mut self, proxy: Option<Url>) -> &mut Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Whether transfers go through an HTTP proxy in a CONNECT tunnel
+    /// instead of having the proxy make the request.
+    ///
+    /// FTP transfers always use a tunnel with an HTTP proxy.
+    pub fn proxy_tunnel(&self) -> bool {
+        self.proxy_tunnel
+    }
+
+    pub fn set_proxy_tunnel(&mut self, enabled: bool) -> &mut Self {
+        self.proxy_tunnel = enabled;
+        self
+    }
+
     pub fn http_user_agent(&self) -> &str {
         self.http_user_agent.as_ref()
     }
@@ -69,6 +412,46 @@ impl Config {
         self
     }
 
+    /// User agents used in turn instead of [`Self::http_user_agent()`].
+    pub fn http_user_agent_rotation(&self) -> Option<&UserAgentRotation> {
+        self.http_user_agent_rotation.as_ref()
+    }
+
+    pub fn set_http_user_agent_rotation(
+        &mut self,
+        rotation: Option<UserAgentRotation>,
+    ) -> &mut Self {
+        self.http_user_agent_rotation = rotation;
+        self
+    }
+
+    /// Value of the Accept-Language field sent with every request, such as
+    /// `de-DE,de;q=0.9`.
+    ///
+    /// It replaces the value of the header profile or default header fields
+    /// but not one given on a request.
+    pub fn http_accept_language(&self) -> Option<&str> {
+        self.http_accept_language.as_deref()
+    }
+
+    pub fn set_http_accept_language(&mut self, value: Option<String>) -> &mut Self {
+        self.http_accept_language = value;
+        self
+    }
+
+    /// Authentication sent when a server asks for it.
+    ///
+    /// Transfers fail with [`Error::UnsupportedFeature`](crate::error::Error::UnsupportedFeature)
+    /// if libcurl was built without the mechanism.
+    pub fn http_auth(&self) -> Option<&HttpAuth> {
+        self.http_auth.as_ref()
+    }
+
+    pub fn set_http_auth(&mut self, auth: Option<HttpAuth>) -> &mut Self {
+        self.http_auth = auth;
+        self
+    }
+
     pub fn http_headers(&self) -> &HeaderFields {
         &self.http_headers
     }
@@ -82,6 +465,55 @@ impl Config {
         self
     }
 
+    pub fn header_profile(&self) -> Option<HeaderProfile> {
+        self.header_profile
+    }
+
+    /// Impersonates a web browser by replacing the user agent, default
+    /// header fields, and header order with those of the profile.
+    pub fn set_header_profile(&mut self, profile: HeaderProfile) -> &mut Self {
+        let mut fields = HeaderFields::new();
+        let mut order = Vec::new();
+
+        for (name, value) in profile.http_headers() {
+            if !value.is_empty() {
+                fields.append(*name, *value);
+            }
+            order.push(FieldName::new(*name));
+        }
+
+        self.header_profile = Some(profile);
+        self.http_user_agent = profile.user_agent().to_string();
+        self.http_headers = fields;
+        self.http_header_order = order;
+        self
+    }
+
+    /// Names of request header fields in the order they are sent.
+    ///
+    /// Fields not listed are sent afterwards in their original order. When
+    /// set, the user agent is sent as a regular field so it can be ordered.
+    /// Host is always sent first unless suppressed.
+    pub fn http_header_order(&self) -> &[FieldName] {
+        &self.http_header_order
+    }
+
+    pub fn set_http_header_order(&mut self, names: Vec<FieldName>) -> &mut Self {
+        self.http_header_order = names;
+        self
+    }
+
+    /// Names of implicit request header fields, such as Accept, Host, or
+    /// Content-Length, that are not sent unless given explicitly.
+    pub fn http_suppressed_headers(&self) -> &[FieldName] {
+        &self.http_suppressed_headers
+    }
+
+    pub fn set_http_suppressed_headers(&mut self, names: Vec<FieldName>) -> &mut Self {
+        self.http_suppressed_headers = names;
+        self
+    }
+
     pub fn http_09(&self) -> bool {
         self.http_09
     }
@@ -100,6 +532,30 @@ impl Config {
         self
     }
 
+    /// Whether the content coding (such as gzip) of a response body is
+    /// removed from the bytes of [`SessionEvent::ContentReceived`].
+    ///
+    /// The transfer coding (such as chunked) is always removed.
+    pub fn http_content_decoding(&self) -> bool {
+        self.http_content_decoding
+    }
+
+    pub fn set_http_content_decoding(&mut self, enabled: bool) -> &mut Self {
+        self.http_content_decoding = enabled;
+        self
+    }
+
+    /// Whether the bytes of a response body as received on the wire are
+    /// given by [`SessionEvent::BodyReceived`], regardless of decoding.
+    pub fn http_raw_body(&self) -> bool {
+        self.http_raw_body
+    }
+
+    pub fn set_http_raw_body(&mut self, enabled: bool) -> &mut Self {
+        self.http_raw_body = enabled;
+        self
+    }
+
     pub fn http_cookies(&self) -> bool {
         self.http_cookies
     }
@@ -109,6 +565,28 @@ impl Config {
         self
     }
 
+    /// Responses that cookies are stored from when
+    /// [`Self::http_cookies`] is enabled.
+    pub fn http_cookie_capture(&self) -> CookieCapture {
+        self.http_cookie_capture
+    }
+
+    pub fn set_http_cookie_capture(&mut self, capture: CookieCapture) -> &mut Self {
+        self.http_cookie_capture = capture;
+        self
+    }
+
+    /// Maximum size in bytes of a request or response header, including
+    /// trailers.
+    pub fn http_max_header_size(&self) -> usize {
+        self.http_max_header_size
+    }
+
+    pub fn set_http_max_header_size(&mut self, size: usize) -> &mut Self {
+        self.http_max_header_size = size;
+        self
+    }
+
     pub fn tls_verification(&self) -> bool {
         self.tls_verification
     }
@@ -117,20 +595,249 @@ impl Config {
         self.tls_verification = enabled;
         self
     }
+
+    /// Whether TLS sessions are cached and resumed on new connections.
+    ///
+    /// Session IDs and session tickets are both kept in the same cache, so
+    /// they are controlled together.
+    pub fn tls_session_resumption(&self) -> bool {
+        self.tls_session_resumption
+    }
+
+    pub fn set_tls_session_resumption(&mut self, enabled: bool) -> &mut Self {
+        self.tls_session_resumption = enabled;
+        self
+    }
+
+    /// File that TLS session secrets are appended to in the NSS key log
+    /// format, for decrypting captured traffic.
+    ///
+    /// Only the native backend supports it. libcurl reads the
+    /// `SSLKEYLOGFILE` environment variable instead, and only when it is
+    /// globally initialized (see [`tls_key_log_supported`]).
+    pub fn tls_key_log(&self) -> Option<&Path> {
+        self.tls_key_log.as_deref()
+    }
+
+    pub fn set_tls_key_log(&mut self, path: Option<PathBuf>) -> &mut Self {
+        self.tls_key_log = path;
+        self
+    }
+
+    /// Whether a stapled OCSP response proving the certificate is not
+    /// revoked is required.
+    ///
+    /// Transfers fail with [`Error::UnsupportedFeature`](crate::error::Error::UnsupportedFeature)
+    /// if the TLS library doesn't support it, which [`Self::validate`]
+    /// reports up front.
+    pub fn verify_status(&self) -> bool {
+        self.verify_status
+    }
+
+    pub fn set_verify_status(&mut self, enabled: bool) -> &mut Self {
+        self.verify_status = enabled;
+        self
+    }
+
+    /// Whether requests to host names mixing scripts in a confusable way
+    /// are rejected.
+    pub fn reject_confusable(&self) -> bool {
+        self.reject_confusable
+    }
+
+    pub fn set_reject_confusable(&mut self, enabled: bool) -> &mut Self {
+        self.reject_confusable = enabled;
+        self
+    }
+
+    /// Schemes and addresses that requests are restricted to.
+    pub fn request_policy(&self) -> Option<&RequestPolicy> {
+        self.request_policy.as_ref()
+    }
+
+    pub fn set_request_policy(&mut self, policy: Option<RequestPolicy>) -> &mut Self {
+        self.request_policy = policy;
+        self
+    }
+
+    /// Hosts that requests are restricted to, or `None` to allow all hosts.
+    ///
+    /// Host names not in the list by name are allowed if they resolve only
+    /// to addresses of networks in the list. When a proxy is used, the proxy
+    /// resolves host names and networks only match addresses written in
+    /// URLs.
+    pub fn host_allowlist(&self) -> Option<&[HostPattern]> {
+        self.host_allowlist.as_deref()
+    }
+
+    pub fn set_host_allowlist(&mut self, patterns: Option<Vec<HostPattern>>) -> &mut Self {
+        self.host_allowlist = patterns;
+        self
+    }
+
+    /// Hosts that requests are refused for, which takes precedence over the
+    /// allowlist.
+    pub fn host_denylist(&self) -> &[HostPattern] {
+        &self.host_denylist
+    }
+
+    pub fn set_host_denylist(&mut self, patterns: Vec<HostPattern>) -> &mut Self {
+        self.host_denylist = patterns;
+        self
+    }
+}
+
+/// HTTP authentication mechanism for Windows servers.
+#[derive(Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HttpAuth {
+    /// NTLM with a user name, which may be given as `DOMAIN\user`, and a
+    /// password.
+    Ntlm { username: String, password: String },
+    /// SPNEGO (Negotiate) with the Kerberos credentials of the current user.
+    Negotiate,
+}
+
+impl Debug for HttpAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ntlm { username, .. } => f
+                .debug_struct("Ntlm")
+                .field("username", username)
+                .field("password", &"...")
+                .finish(),
+            Self::Negotiate => f.write_str("Negotiate"),
+        }
+    }
+}
+
+/// Schemes of the proxies supported by libcurl.
+const PROXY_SCHEMES: &[&str] = &["http", "https", "socks4", "socks4a", "socks5", "socks5h"];
+
+/// Builder of a [`Config`] that is validated by [`Self::build`].
+///
+/// The settings are those of [`Config`], which the builder dereferences
+/// to.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    /// Returns the config if [`Config::validate`] accepts it.
+    pub fn build(self) -> Result<Config, Error> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+impl Deref for ConfigBuilder {
+    type Target = Config;
+
+    fn deref(&self) -> &Self::Target {
+        &self.config
+    }
+}
+
+impl DerefMut for ConfigBuilder {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.config
+    }
+}
+
+/// Implementation of the transfers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Backend {
+    /// libcurl, supporting all features. Requires the `curl` feature.
+    Curl,
+    /// Minimal HTTP/1.1 implementation using rustls, for platforms where
+    /// linking libcurl is impractical. Requires the `native` feature.
+    ///
+    /// Features that it lacks, such as FTP, proxies, and authentication,
+    /// fail with [`Error::UnsupportedFeature`]. Content codings are neither
+    /// requested nor decoded.
+    Native,
+}
+
+impl Backend {
+    /// Returns whether the backend was enabled at compile time.
+    pub fn is_available(&self) -> bool {
+        match self {
+            Backend::Curl => cfg!(feature = "curl"),
+            Backend::Native => cfg!(feature = "native"),
+        }
+    }
+}
+
+impl Default for Backend {
+    /// libcurl if available, otherwise the native backend.
+    fn default() -> Self {
+        if cfg!(feature = "curl") || !cfg!(feature = "native") {
+            Backend::Curl
+        } else {
+            Backend::Native
+        }
+    }
+}
+
+/// Method of a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Method {
+    #[default]
+    Get,
+    /// Requests only the header of the response, or only the size of a
+    /// file with FTP.
+    Head,
+    /// Sends the content given by [`SessionHandler::upload_content`] as the
+    /// body of the request.
+    Post,
+    /// Sends the content given by [`SessionHandler::upload_content`] as the
+    /// body of the request, or uploads it as a file with FTP.
+    Put,
+}
+
+impl Method {
+    /// Whether the request sends content.
+    pub fn is_upload(&self) -> bool {
+        matches!(self, Self::Post | Self::Put)
+    }
+}
+
+/// Representation type of an FTP transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FtpTransferType {
+    /// Type A, where the server may convert line endings.
+    Ascii,
+    /// Type I, where the file is sent as is.
+    Binary,
 }
 
 #[derive(Debug, Clone)]
 pub struct Request {
     url: Url,
+    method: Method,
+    ftp_transfer_type: Option<FtpTransferType>,
+    http_user_agent: Option<String>,
     http_headers: HeaderFields,
+    http_raw_target: Option<Vec<u8>>,
+    http_cookies: bool,
+    upload_size: Option<u64>,
 }
 
 impl Request {
     pub fn new(url: Url) -> Self {
         Self {
             url,
-
+            method: Method::default(),
+            ftp_transfer_type: None,
+            http_user_agent: None,
             http_headers: HeaderFields::new(),
+            http_raw_target: None,
+            http_cookies: true,
+            upload_size: None,
         }
     }
 
@@ -143,6 +850,51 @@ impl Request {
         self
     }
 
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    pub fn set_method(&mut self, method: Method) -> &mut Self {
+        self.method = method;
+        self
+    }
+
+    /// Size of the content sent by a POST or PUT request.
+    ///
+    /// If the size is not known, HTTP/1.1 sends the content with chunked
+    /// transfer coding until the handler returns no more content.
+    pub fn upload_size(&self) -> Option<u64> {
+        self.upload_size
+    }
+
+    pub fn set_upload_size(&mut self, size: Option<u64>) -> &mut Self {
+        self.upload_size = size;
+        self
+    }
+
+    /// Transfer type of an FTP transfer, binary if not given.
+    ///
+    /// A `;type=a` or `;type=i` suffix of the URL path also selects the
+    /// type and takes precedence.
+    pub fn ftp_transfer_type(&self) -> Option<FtpTransferType> {
+        self.ftp_transfer_type
+    }
+
+    pub fn set_ftp_transfer_type(&mut self, transfer_type: Option<FtpTransferType>) -> &mut Self {
+        self.ftp_transfer_type = transfer_type;
+        self
+    }
+
+    /// User agent of this request instead of the one of the config.
+    pub fn http_user_agent(&self) -> Option<&str> {
+        self.http_user_agent.as_deref()
+    }
+
+    pub fn set_http_user_agent(&mut self, user_agent: Option<String>) -> &mut Self {
+        self.http_user_agent = user_agent;
+        self
+    }
+
     pub fn http_headers(&self) -> &HeaderFields {
         &self.http_headers
     }
@@ -155,17 +907,120 @@ impl Request {
         self.http_headers = http_headers;
         self
     }
+
+    /// Request target sent as is in the HTTP request line instead of the
+    /// normalized path and query of [`Self::url()`].
+    pub fn http_raw_target(&self) -> Option<&[u8]> {
+        self.http_raw_target.as_deref()
+    }
+
+    /// Sets the exact request target, such as a path that is not valid
+    /// UTF-8 or one with dot segments and percent-encoding that URL
+    /// normalization would change.
+    ///
+    /// The URL is still used for connecting and for cookies.
+    pub fn set_http_raw_target(&mut self, target: Option<Vec<u8>>) -> &mut Self {
+        self.http_raw_target = target;
+        self
+    }
+
+    /// Whether cookies of the jar are sent with this request and cookies
+    /// of the response are stored, if [`Config::http_cookies`] is enabled.
+    ///
+    /// Default is true.
+    pub fn http_cookies(&self) -> bool {
+        self.http_cookies
+    }
+
+    pub fn set_http_cookies(&mut self, enabled: bool) -> &mut Self {
+        self.http_cookies = enabled;
+        self
+    }
+}
+
+/// Shared flag for requesting that running transfers stop.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Returns the header fields of the config and of the request to be sent,
+/// in the order of [`Config::http_header_order`] if set, which includes the
+/// User-Agent field.
+pub(super) fn request_header_fields(
+    config: &Config,
+    request: &Request,
+    user_agent: &str,
+) -> HeaderFields {
+    let mut fields = config.http_headers().clone();
+
+    if let Some(value) = config.http_accept_language() {
+        // Replaced in place to keep the position of a header profile
+        fields.insert("Accept-Language", value);
+    }
+
+    fields.merge(request.http_headers());
+
+    let order = config.http_header_order();
+
+    if !order.is_empty() {
+        if !user_agent.is_empty() {
+            fields.entry("User-Agent").or_insert(user_agent);
+        }
+
+        fields.sort_by_key(|name, _value| {
+            order
+                .iter()
+                .position(|item| item == name)
+                .unwrap_or(order.len())
+        });
+    }
+
+    fields
 }
 
 pub trait Session<H: SessionHandler>: Debug {
     fn wait(&mut self) -> (H, Result<(), Error>);
+
+    /// Statistics of the transfer, once it has run.
+    fn transfer_stats(&self) -> Option<&TransferStats>;
 }
 
 pub trait SessionControl: Debug {
     fn abort(&mut self);
+
+    /// Stops calling [`SessionHandler::upload_content`] until
+    /// [`Self::resume_upload`] is called, such as on a progress event,
+    /// which continue while paused.
+    ///
+    /// Backends that don't upload ignore it.
+    fn pause_upload(&mut self) {}
+
+    fn resume_upload(&mut self) {}
+
+    /// Signals from [`SessionHandler::upload_content`] that no content is
+    /// available yet, so that the returned 0 doesn't mean the end of the
+    /// content and it is called again after the next progress event.
+    fn upload_pending(&mut self) {}
 }
 
 pub trait SessionHandler {
+    /// Fills the buffer with request body content and returns its size,
+    /// where 0 is the end of the content.
     fn upload_content(
         &mut self,
         control: &mut dyn SessionControl,
@@ -185,27 +1040,241 @@ pub trait SessionHandler {
         let _ = event;
         Ok(())
     }
+
+    /// Kinds of events that [`Self::event`] is called with, so that
+    /// backends can skip the others, queried when a transfer starts.
+    fn event_filter(&self) -> EventFilter {
+        EventFilter::all()
+    }
 }
 
+/// Event of a transfer given to [`SessionHandler::event`].
+///
+/// Data borrows the buffers of the backend, which pass body data as
+/// received without copying it, and is valid only for the duration of the
+/// call. Handlers that keep data, such as archival writers, must copy it.
 #[non_exhaustive]
 #[derive(Debug, Clone)]
 pub enum SessionEvent<'a> {
+    /// A connection to the address is being attempted.
+    ///
+    /// The curl backend only reports it on Unix, where it opens the sockets
+    /// itself.
+    ConnectAttempt(SocketAddr),
+    /// A connection attempt failed with the given reason.
+    ///
+    /// The curl backend takes it from libcurl's log messages, so it may be
+    /// missing with other libcurl versions.
+    ConnectFailed(SocketAddr, String),
     Connected(SocketAddr),
+    /// Connection that the transfer sends its request on, which was either
+    /// reused from a previous transfer or newly established.
+    ConnectionInfo {
+        reused: bool,
+        local_addr: SocketAddr,
+        remote_addr: SocketAddr,
+    },
+    TlsConnected(TlsInfo),
     HeaderReceived(&'a [u8]),
     HeaderSent(&'a [u8]),
+    /// Response body as received on the wire, still in its transfer and
+    /// content codings, if [`Config::http_raw_body`] is enabled.
     BodyReceived(&'a [u8]),
     BodySent(&'a [u8]),
     ContentSent(&'a [u8]),
+    /// Response body without its transfer coding, and without its content
+    /// coding if [`Config::http_content_decoding`] is enabled.
     ContentReceived(&'a [u8]),
     HttpRequest(&'a [u8], RequestHeader),
+    /// Informational (1xx) response, such as 100 Continue or 103 Early
+    /// Hints, received before the final response.
+    HttpInterimResponse(&'a [u8], ResponseHeader),
     HttpResponse(&'a [u8], ResponseHeader),
     HttpResponseTrailer(&'a [u8], ResponseTrailer),
+    /// Byte counts of the transfer, where totals are 0 if unknown.
+    ///
+    /// Rates are in bytes per second. The ETA is of the download, or of the
+    /// upload if nothing is being downloaded.
     Progress {
         download_total: u64,
         download_current: u64,
         upload_total: u64,
         upload_current: u64,
+        download_rate: TransferRate,
+        upload_rate: TransferRate,
+        eta: Option<Duration>,
+    },
+    Finished(TransferStats),
+    /// Transfers to the host are refused for the duration.
+    CircuitOpened(String, Duration),
+    /// Transfers to the host are allowed again.
+    CircuitClosed(String),
+    /// Transfer type sent to the FTP server before a transfer.
+    FtpTransferType(FtpTransferType),
+    /// Command sent on the FTP control connection, with the command name in
+    /// upper case.
+    FtpCommand {
+        command: String,
+        argument: String,
     },
+    /// Reply received on the FTP control connection, with the lines of a
+    /// multiline reply joined by line feeds.
+    FtpReply {
+        code: u16,
+        text: String,
+    },
+    /// Cookie of a response was stored in the cookie jar.
+    CookieAccepted {
+        name: String,
+    },
+    /// Cookie of a response was not stored for the given reason.
+    CookieRejected {
+        name: String,
+        reason: String,
+    },
+    /// Raw diagnostic stream of the transfer backend.
+    Trace(TraceKind, &'a [u8]),
+}
+
+impl SessionEvent<'_> {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Self::ConnectAttempt(..) => EventKind::ConnectAttempt,
+            Self::ConnectFailed(..) => EventKind::ConnectFailed,
+            Self::Connected(..) => EventKind::Connected,
+            Self::ConnectionInfo { .. } => EventKind::ConnectionInfo,
+            Self::TlsConnected(..) => EventKind::TlsConnected,
+            Self::HeaderReceived(..) => EventKind::HeaderReceived,
+            Self::HeaderSent(..) => EventKind::HeaderSent,
+            Self::BodyReceived(..) => EventKind::BodyReceived,
+            Self::BodySent(..) => EventKind::BodySent,
+            Self::ContentSent(..) => EventKind::ContentSent,
+            Self::ContentReceived(..) => EventKind::ContentReceived,
+            Self::HttpRequest(..) => EventKind::HttpRequest,
+            Self::HttpInterimResponse(..) => EventKind::HttpInterimResponse,
+            Self::HttpResponse(..) => EventKind::HttpResponse,
+            Self::HttpResponseTrailer(..) => EventKind::HttpResponseTrailer,
+            Self::Progress { .. } => EventKind::Progress,
+            Self::Finished(..) => EventKind::Finished,
+            Self::CircuitOpened(..) => EventKind::CircuitOpened,
+            Self::CircuitClosed(..) => EventKind::CircuitClosed,
+            Self::FtpTransferType(..) => EventKind::FtpTransferType,
+            Self::FtpCommand { .. } => EventKind::FtpCommand,
+            Self::FtpReply { .. } => EventKind::FtpReply,
+            Self::CookieAccepted { .. } => EventKind::CookieAccepted,
+            Self::CookieRejected { .. } => EventKind::CookieRejected,
+            Self::Trace(..) => EventKind::Trace,
+        }
+    }
+}
+
+/// Kind of a [`SessionEvent`] without its data.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    ConnectAttempt,
+    ConnectFailed,
+    Connected,
+    ConnectionInfo,
+    TlsConnected,
+    HeaderReceived,
+    HeaderSent,
+    BodyReceived,
+    BodySent,
+    ContentSent,
+    ContentReceived,
+    HttpRequest,
+    HttpInterimResponse,
+    HttpResponse,
+    HttpResponseTrailer,
+    Progress,
+    Finished,
+    CircuitOpened,
+    CircuitClosed,
+    FtpTransferType,
+    FtpCommand,
+    FtpReply,
+    CookieAccepted,
+    CookieRejected,
+    Trace,
+}
+
+/// Set of event kinds that a handler is interested in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventFilter {
+    kinds: u64,
+}
+
+impl EventFilter {
+    pub const fn all() -> Self {
+        Self { kinds: u64::MAX }
+    }
+
+    pub const fn none() -> Self {
+        Self { kinds: 0 }
+    }
+
+    pub const fn with(self, kind: EventKind) -> Self {
+        Self {
+            kinds: self.kinds | 1 << kind as u64,
+        }
+    }
+
+    pub const fn without(self, kind: EventKind) -> Self {
+        Self {
+            kinds: self.kinds & !(1 << kind as u64),
+        }
+    }
+
+    pub const fn contains(self, kind: EventKind) -> bool {
+        self.kinds & 1 << kind as u64 != 0
+    }
+}
+
+impl Default for EventFilter {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl FromIterator<EventKind> for EventFilter {
+    fn from_iter<T: IntoIterator<Item = EventKind>>(iter: T) -> Self {
+        iter.into_iter().fold(Self::none(), Self::with)
+    }
+}
+
+/// TCP keepalive probe timing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcpKeepalive {
+    /// Idle time before the first probe is sent.
+    pub idle: Duration,
+    /// Time between probes.
+    pub interval: Duration,
+}
+
+/// Details of an established TLS connection.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TlsInfo {
+    /// Whether the handshake resumed a previous session, if the TLS library
+    /// reports it.
+    pub session_resumed: Option<bool>,
+    /// Whether the stapled OCSP response of the certificate was verified.
+    pub status_verified: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceKind {
+    /// Informational text.
+    Text,
+    HeaderIn,
+    HeaderOut,
+    DataIn,
+    DataOut,
+    /// Encrypted TLS data received.
+    SslDataIn,
+    /// Encrypted TLS data sent.
+    SslDataOut,
 }
 
 pub fn default_user_agent() -> &'static str {
@@ -213,20 +1282,88 @@ pub fn default_user_agent() -> &'static str {
 
     let version = DEFAULT_USER_AGENT.get_or_init(|| {
         let crate_version = crate::version::get_crate_version_mmp();
-        let curl_version = curl::Version::get();
-
-        format!(
-            "Mozilla/5.0 (compatible; not Gecko KHTML AppleWebKit Firefox Chrome Safari) wrecv/{}.{} curl/{}.{}",
-            crate_version.0,
-            crate_version.1,
-            (curl_version.version_num() >> 16) as u8,
-            (curl_version.version_num() >> 8) as u8
-        )
+        let user_agent = format!(
+            "Mozilla/5.0 (compatible; not Gecko KHTML AppleWebKit Firefox Chrome Safari) wrecv/{}.{}",
+            crate_version.0, crate_version.1,
+        );
+
+        #[cfg(feature = "curl")]
+        let user_agent = {
+            let curl_version = curl::Version::get();
+
+            format!(
+                "{} curl/{}.{}",
+                user_agent,
+                (curl_version.version_num() >> 16) as u8,
+                (curl_version.version_num() >> 8) as u8
+            )
+        };
+
+        user_agent
     });
 
     version
 }
 
+/// Returns whether the TLS backend of libcurl is one of the named libraries.
+#[cfg(feature = "curl")]
+fn ssl_backend_is(names: &[&str]) -> bool {
+    match curl::Version::get().ssl_version() {
+        Some(ssl_version) => names.iter().any(|name| ssl_version.starts_with(name)),
+        None => false,
+    }
+}
+
+/// Returns whether the TLS backend of libcurl exports session keys to the
+/// file named by the `SSLKEYLOGFILE` environment variable.
+///
+/// The variable is only read when libcurl is globally initialized.
+#[cfg(feature = "curl")]
+pub fn tls_key_log_supported() -> bool {
+    ssl_backend_is(&["OpenSSL", "BoringSSL", "LibreSSL", "quictls", "wolfSSL"])
+}
+
+/// Returns whether the TLS library reports resumed sessions in
+/// [`TlsInfo::session_resumed`].
+///
+/// The rustls backend doesn't resume sessions at all.
+#[cfg(feature = "curl")]
+pub fn tls_session_resumption_supported() -> bool {
+    ssl_backend_is(&["OpenSSL", "BoringSSL", "LibreSSL", "quictls"])
+}
+
+/// Returns whether the TLS backend of libcurl reports handshake messages to
+/// the debug callback, for [`TransferStats::tls_overhead_bytes`].
+#[cfg(feature = "curl")]
+pub(crate) fn tls_overhead_supported() -> bool {
+    ssl_backend_is(&["OpenSSL", "BoringSSL", "LibreSSL", "quictls"])
+}
+
+/// Returns whether the TLS backend of libcurl supports
+/// [`Config::verify_status`].
+#[cfg(feature = "curl")]
+pub fn verify_status_supported() -> bool {
+    ssl_backend_is(&["OpenSSL", "LibreSSL", "quictls", "GnuTLS", "wolfSSL"])
+}
+
+/// Returns whether libcurl supports [`HttpAuth::Ntlm`].
+pub fn ntlm_supported() -> bool {
+    #[cfg(feature = "curl")]
+    return curl::Version::get().feature_ntlm();
+
+    #[cfg(not(feature = "curl"))]
+    false
+}
+
+/// Returns whether libcurl supports [`HttpAuth::Negotiate`].
+pub fn negotiate_supported() -> bool {
+    #[cfg(feature = "curl")]
+    return curl::Version::get().feature_spnego();
+
+    #[cfg(not(feature = "curl"))]
+    false
+}
+
 pub fn default_http_headers() -> &'static [(&'static str, &'static str)] {
     &[]
 }
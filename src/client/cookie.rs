@@ -6,24 +6,73 @@ use std::{
 use cookie_store::CookieStore;
 use url::Url;
 
-use crate::http::HeaderFields;
+use crate::http::{HeaderFields, ResponseHeader};
+
+use super::{Config, Request, SessionEvent};
 
 const MAX_HEADER_VALUE_LEN: usize = 4096usize;
 
+/// Responses that cookies are automatically stored from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CookieCapture {
+    /// Cookies are stored only by calling
+    /// [`CookieJar::parse_from_response`].
+    Manual,
+    /// Final responses except redirects (3xx).
+    IgnoreRedirects,
+    /// All final responses, including redirects.
+    #[default]
+    All,
+}
+
+/// Result of storing the cookie of a `Set-Cookie` field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CookieOutcome {
+    Accepted { name: String },
+    Rejected { name: String, reason: String },
+}
+
+impl From<CookieOutcome> for SessionEvent<'_> {
+    fn from(value: CookieOutcome) -> Self {
+        match value {
+            CookieOutcome::Accepted { name } => Self::CookieAccepted { name },
+            CookieOutcome::Rejected { name, reason } => Self::CookieRejected { name, reason },
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CookieJar {
     store: Option<Arc<Mutex<CookieStore>>>,
+    read_only: bool,
 }
 
 impl CookieJar {
     pub fn new() -> Self {
         Self {
-            store:Some(Arc::new(Mutex::new(CookieStore::new(None))))
+            store: Some(Arc::new(Mutex::new(CookieStore::new(None)))),
+            read_only: false,
         }
     }
 
     pub fn new_disabled() -> Self {
-        Self { store: None }
+        Self {
+            store: None,
+            read_only: false,
+        }
+    }
+
+    /// Whether cookies are only sent and cookies of responses are never
+    /// stored.
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Sets read-only mode on this jar and on the clones made from it
+    /// afterwards, which share the same cookies.
+    pub fn set_read_only(&mut self, read_only: bool) -> &mut Self {
+        self.read_only = read_only;
+        self
     }
 
     pub fn get_request_string(&self, url: &Url) -> String {
@@ -35,14 +84,39 @@ impl CookieJar {
         }
     }
 
-    pub fn parse_from_response(&self, url: &Url, fields: &HeaderFields) {
-        if let Some(store) = &self.store {
-            let mut store = store.lock().unwrap();
+    /// Stores the cookies of the `Set-Cookie` fields of a response to the
+    /// URL.
+    ///
+    /// Returns nothing if the jar is disabled.
+    pub fn parse_from_response(&self, url: &Url, fields: &HeaderFields) -> Vec<CookieOutcome> {
+        let store = match &self.store {
+            Some(store) => store,
+            None => return Vec::new(),
+        };
+        let mut store = store.lock().unwrap();
 
-            for value in fields.get_all("Set-Cookie") {
-                let _ = store.parse(&value.to_string_lossy(), url);
-            }
-        }
+        fields
+            .get_all("Set-Cookie")
+            .map(|value| {
+                let value = value.to_string_lossy();
+                let name = cookie_name(&value).to_string();
+
+                if self.read_only {
+                    return CookieOutcome::Rejected {
+                        name,
+                        reason: "cookie jar is read-only".to_string(),
+                    };
+                }
+
+                match store.parse(&value, url) {
+                    Ok(_action) => CookieOutcome::Accepted { name },
+                    Err(error) => CookieOutcome::Rejected {
+                        name,
+                        reason: error.to_string(),
+                    },
+                }
+            })
+            .collect()
     }
 
     pub fn clear(&self) {
@@ -60,6 +134,50 @@ impl Default for CookieJar {
     }
 }
 
+/// Stores the cookies of the responses of a transfer.
+#[derive(Debug, Clone)]
+pub(super) struct CookieCapturer {
+    jar: CookieJar,
+    url: Url,
+    capture: CookieCapture,
+}
+
+impl CookieCapturer {
+    /// Returns `None` if cookies are not captured for the request.
+    pub fn new(jar: &CookieJar, config: &Config, request: &Request) -> Option<Self> {
+        if !config.http_cookies()
+            || !request.http_cookies()
+            || config.http_cookie_capture() == CookieCapture::Manual
+        {
+            return None;
+        }
+
+        Some(Self {
+            jar: jar.clone(),
+            url: request.url().clone(),
+            capture: config.http_cookie_capture(),
+        })
+    }
+
+    /// Stores the cookies of a final response.
+    pub fn capture(&self, header: &ResponseHeader) -> Vec<CookieOutcome> {
+        if self.capture == CookieCapture::IgnoreRedirects
+            && (300..400).contains(&header.status_code)
+        {
+            return Vec::new();
+        }
+
+        self.jar.parse_from_response(&self.url, &header.fields)
+    }
+}
+
+fn cookie_name(set_cookie: &str) -> &str {
+    let pair = set_cookie.split(';').next().unwrap_or_default();
+    pair.split_once('=')
+        .map_or(pair, |(name, _value)| name)
+        .trim()
+}
+
 fn format_client_header<'a, I: IntoIterator<Item = (&'a str, &'a str)>>(
     cookies: I,
     max_len: usize,
@@ -93,13 +211,56 @@ mod tests {
 
     #[test]
     fn test_format_client_header() {
-        let result = format_client_header([("k1", "v1")].into_iter(), 4096);
+        let result = format_client_header([("k1", "v1")], 4096);
         assert_eq!(&result, "k1=v1");
 
-        let result = format_client_header(
-            [("k1", "v1"), ("k2", "v2"), ("k3", "v 3")].into_iter(),
-            4096,
-        );
+        let result = format_client_header([("k1", "v1"), ("k2", "v2"), ("k3", "v 3")], 4096);
         assert_eq!(&result, "k1=v1; k2=v2; k3=\"v 3\"");
     }
+
+    #[test]
+    fn test_read_only() {
+        let url = Url::parse("http://example.com/").unwrap();
+        let mut fields = HeaderFields::new();
+        fields.append("Set-Cookie", "k1=v1");
+
+        let mut jar = CookieJar::new();
+        jar.parse_from_response(&url, &fields);
+        jar.set_read_only(true);
+
+        let mut fields = HeaderFields::new();
+        fields.append("Set-Cookie", "k2=v2");
+        jar.clone().parse_from_response(&url, &fields);
+
+        assert!(jar.read_only());
+        assert_eq!(jar.get_request_string(&url), "k1=v1");
+    }
+
+    #[test]
+    fn test_parse_from_response_outcome() {
+        let url = Url::parse("http://example.com/").unwrap();
+        let mut fields = HeaderFields::new();
+        fields.append("Set-Cookie", "k1=v1; Path=/");
+        fields.append("Set-Cookie", "k2=v2; Domain=example.net");
+
+        let mut jar = CookieJar::new();
+        let outcomes = jar.parse_from_response(&url, &fields);
+
+        assert_eq!(outcomes.len(), 2);
+        assert_eq!(
+            outcomes[0],
+            CookieOutcome::Accepted {
+                name: "k1".to_string()
+            }
+        );
+        assert!(matches!(&outcomes[1], CookieOutcome::Rejected { name, .. } if name == "k2"));
+
+        jar.set_read_only(true);
+        let outcomes = jar.parse_from_response(&url, &fields);
+        assert!(matches!(&outcomes[0], CookieOutcome::Rejected { name, .. } if name == "k1"));
+
+        assert!(CookieJar::new_disabled()
+            .parse_from_response(&url, &fields)
+            .is_empty());
+    }
 }
@@ -1,94 +1,112 @@
 use std::{
     cell::RefCell,
+    ffi::CString,
     fmt::Debug,
     net::{IpAddr, SocketAddr},
     rc::Rc,
     str::FromStr,
-    sync::OnceLock, time::Duration,
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
 };
 
 use curl::easy::{Easy, InfoType, Transfer};
 use regex::Regex;
 
 use crate::{
-    error::{BoxedError, Error, OtherError},
-    http::{FieldName, FieldValue, RequestHeader, ResponseHeader, ResponseTrailer},
+    error::{BoxedError, Error, NetworkError, OtherError, ProtocolError},
+    http::{FieldName, FieldValue, RequestHeader, ResponseHeader, ResponseTrailer, SecurityPolicy},
 };
 
 use super::{
-    cookie::CookieJar, pool::ConnectionPool, Config, Request, Session, SessionControl,
-    SessionEvent, SessionHandler,
+    cookie::{CookieCapturer, CookieJar},
+    negotiate_supported, ntlm_supported,
+    policy::HostLists,
+    pool::ConnectionPool,
+    progress::{estimate_remaining, ProgressMeter, TransferRate},
+    request_header_fields,
+    socket::{set_opensocket_function, set_sockopt_function, SocketOptions, SocketPolicy},
+    tls_overhead_supported, tls_session_resumption_supported,
+    user_agent::select_user_agent,
+    CancellationToken, Config, EventFilter, EventKind, FtpTransferType, HttpAuth, HttpVersion,
+    Method, Request, Session, SessionControl, SessionEvent, SessionHandler, TlsInfo, TraceKind,
+    TransferStats,
 };
 
+// Not defined by curl-sys
+const CURLOPT_REQUEST_TARGET: curl_sys::CURLoption = curl_sys::CURLOPTTYPE_OBJECTPOINT + 266;
+const CURLOPT_SSL_VERIFYSTATUS: curl_sys::CURLoption = curl_sys::CURLOPTTYPE_LONG + 232;
+const CURLOPT_HAPPY_EYEBALLS_TIMEOUT_MS: curl_sys::CURLoption = curl_sys::CURLOPTTYPE_LONG + 271;
+const CURLOPT_SUPPRESS_CONNECT_HEADERS: curl_sys::CURLoption = curl_sys::CURLOPTTYPE_LONG + 265;
+const CURLE_NOT_BUILT_IN: curl_sys::CURLcode = 4;
+const CURLINFO_HTTP_VERSION: curl_sys::CURLINFO = curl_sys::CURLINFO_LONG + 46;
+const CURLINFO_SCHEME: curl_sys::CURLINFO = curl_sys::CURLINFO_STRING + 49;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum SessionMode {
     Http,
     Ftp,
 }
 
+/// Client state shared with a session.
+#[derive(Debug, Clone)]
+pub struct SessionContext {
+    pub transfer_id: u64,
+    pub config: Rc<RefCell<Config>>,
+    pub connection_pool: ConnectionPool,
+    pub cookie_jar: CookieJar,
+    pub cancellation_token: CancellationToken,
+    /// Curl resolve entry overriding the resolution of the request host.
+    pub resolve_entry: Option<String>,
+}
+
 pub struct CurlSession<H: SessionHandler> {
+    transfer_id: u64,
     config: Rc<RefCell<Config>>,
     request: Request,
     handler: Option<H>,
     mode: SessionMode,
     connection_pool: ConnectionPool,
     cookie_jar: CookieJar,
+    cancellation_token: CancellationToken,
     curl_handle: Option<Easy>,
+    resolve_entry: Option<String>,
+    user_agent: String,
+    socket_options: Box<SocketOptions>,
+    socket_policy: Option<Box<SocketPolicy>>,
+    transfer_stats: Option<TransferStats>,
 }
 
 impl<H: SessionHandler> CurlSession<H> {
-    pub fn new_http(
-        config: Rc<RefCell<Config>>,
-        request: Request,
-        handler: H,
-        connection_pool: ConnectionPool,
-        cookie_jar: CookieJar,
-    ) -> Self {
-        Self::new(
-            config,
-            request,
-            handler,
-            connection_pool,
-            cookie_jar,
-            SessionMode::Http,
-        )
+    pub fn new_http(context: SessionContext, request: Request, handler: H) -> Self {
+        Self::new(context, request, handler, SessionMode::Http)
     }
 
-    pub fn new_ftp(
-        config: Rc<RefCell<Config>>,
-        request: Request,
-        handler: H,
-        connection_pool: ConnectionPool,
-        cookie_jar: CookieJar,
-    ) -> Self {
-        Self::new(
-            config,
-            request,
-            handler,
-            connection_pool,
-            cookie_jar,
-            SessionMode::Ftp,
-        )
+    pub fn new_ftp(context: SessionContext, request: Request, handler: H) -> Self {
+        Self::new(context, request, handler, SessionMode::Ftp)
     }
 
-    fn new(
-        config: Rc<RefCell<Config>>,
-        request: Request,
-        handler: H,
-        connection_pool: ConnectionPool,
-        cookie_jar: CookieJar,
-        mode: SessionMode,
-    ) -> Self {
-        let curl_handle = connection_pool.get_curl_handle();
+    fn new(context: SessionContext, request: Request, handler: H, mode: SessionMode) -> Self {
+        let curl_handle = context.connection_pool.get_curl_handle();
+        let user_agent = match mode {
+            SessionMode::Http => select_user_agent(&context.config.borrow(), &request),
+            SessionMode::Ftp => String::new(),
+        };
 
         Self {
-            config,
+            transfer_id: context.transfer_id,
+            config: context.config,
             request,
             handler: Some(handler),
             mode,
-            connection_pool,
-            cookie_jar,
+            connection_pool: context.connection_pool,
+            cookie_jar: context.cookie_jar,
+            cancellation_token: context.cancellation_token,
             curl_handle: Some(curl_handle),
+            resolve_entry: context.resolve_entry,
+            user_agent,
+            socket_options: Box::default(),
+            socket_policy: None,
+            transfer_stats: None,
         }
     }
 
@@ -109,15 +127,118 @@ impl<H: SessionHandler> CurlSession<H> {
 
             curl_handle.interface(&bind_address)?;
             curl_handle.verbose(true)?;
+            curl_handle.progress(true)?;
             curl_handle.url(self.request.url().as_str())?;
+            curl_handle.nobody(self.request.method() == Method::Head)?;
+
+            match self.request.method() {
+                Method::Post => {
+                    curl_handle.post(true)?;
+
+                    if let Some(size) = self.request.upload_size() {
+                        curl_handle.post_field_size(size)?;
+                    }
+                }
+                Method::Put => {
+                    curl_handle.upload(true)?;
+
+                    if let Some(size) = self.request.upload_size() {
+                        curl_handle.in_filesize(size)?;
+                    }
+                }
+                _ => {}
+            }
+
+            set_transfer_text(
+                curl_handle,
+                self.request.ftp_transfer_type() == Some(FtpTransferType::Ascii),
+            )?;
             curl_handle.ssl_verify_host(config.tls_verification())?;
             curl_handle.ssl_verify_peer(config.tls_verification())?;
-            curl_handle.connect_timeout(Duration::from_secs(30))?;
+            curl_handle.ssl_sessionid_cache(config.tls_session_resumption())?;
+
+            if config.verify_status() {
+                set_verify_status(curl_handle)?;
+            }
+
+            if config.tls_key_log().is_some() {
+                return Err(Error::UnsupportedFeature {
+                    feature: "TLS key log file with the curl backend".to_string(),
+                });
+            }
+
+            curl_handle.connect_timeout(config.connect_timeout())?;
+            set_happy_eyeballs_timeout(curl_handle, config.happy_eyeballs_timeout())?;
+            curl_handle.tcp_nodelay(config.tcp_nodelay())?;
+
+            if let Some(duration) = config.max_transfer_duration() {
+                curl_handle.timeout(duration)?;
+            }
+
+            if let Some(keepalive) = config.tcp_keepalive() {
+                curl_handle.tcp_keepalive(true)?;
+                curl_handle.tcp_keepidle(keepalive.idle)?;
+                curl_handle.tcp_keepintvl(keepalive.interval)?;
+            }
+
+            *self.socket_options = SocketOptions {
+                tos: config.ip_tos(),
+                mark: config.socket_mark(),
+            };
+
+            if !self.socket_options.is_empty() {
+                set_sockopt_function(curl_handle, &self.socket_options)?;
+            }
+
+            let policy = config.request_policy().cloned();
+            let hosts = HostLists::from_config(&config);
+
+            // The proxy resolves host names, so only its own address would be checked
+            let (policy, hosts) = match config.proxy() {
+                Some(_) => (None, None),
+                None => (policy, hosts),
+            };
+
+            // Also installed without a policy for the connect attempt events,
+            // where sockets can be opened
+            if policy.is_some() || hosts.is_some() || cfg!(unix) {
+                let socket_policy = self.socket_policy.insert(Box::new(SocketPolicy {
+                    policy,
+                    hosts,
+                    host: self
+                        .request
+                        .url()
+                        .host_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                    blocked: RefCell::new(None),
+                    opened: Arc::new(Mutex::new(Vec::new())),
+                }));
+                set_opensocket_function(curl_handle, socket_policy)?;
+            }
+
+            if let Some(proxy) = config.proxy() {
+                curl_handle.proxy(proxy.as_str())?;
+                // An HTTP proxy would otherwise be sent FTP URLs as HTTP requests
+                curl_handle
+                    .http_proxy_tunnel(config.proxy_tunnel() || self.mode == SessionMode::Ftp)?;
+                set_suppress_connect_headers(curl_handle)?;
+            }
+
+            if let Some(entry) = &self.resolve_entry {
+                let mut list = curl::easy::List::new();
+                list.append(entry)?;
+                curl_handle.resolve(list)?;
+            }
         }
 
         if self.mode == SessionMode::Http {
             self.set_up_http_settings()?;
-            self.set_up_http_cookies()?;
+
+            if self.request.http_cookies() {
+                self.set_up_http_cookies()?;
+            }
+
             self.set_up_http_headers()?;
         }
 
@@ -130,14 +251,24 @@ impl<H: SessionHandler> CurlSession<H> {
 
         curl_handle.http_09_allowed(config.http_09())?;
 
-        if !config.http_user_agent().is_empty() {
-            curl_handle.useragent(config.http_user_agent())?;
+        if !self.user_agent.is_empty() && config.http_header_order().is_empty() {
+            curl_handle.useragent(&self.user_agent)?;
         }
 
         if !config.http_compression() {
             curl_handle.accept_encoding("gzip")?;
         }
 
+        curl_handle.http_content_decoding(config.http_content_decoding())?;
+
+        if let Some(auth) = config.http_auth() {
+            set_http_auth(curl_handle, auth)?;
+        }
+
+        if let Some(target) = self.request.http_raw_target() {
+            set_request_target(curl_handle, target)?;
+        }
+
         Ok(())
     }
 
@@ -157,18 +288,20 @@ impl<H: SessionHandler> CurlSession<H> {
         let config = self.config.borrow();
         let curl_handle = self.curl_handle.as_mut().unwrap();
 
-        for (name, value) in config.http_headers() {
-            if !self.request.http_headers().contains_key(name) {
-                let field = format_header_field(name, value)?;
-                header_list.append(&field)?;
-            }
-        }
+        let fields = request_header_fields(&config, &self.request, &self.user_agent);
 
-        for (name, value) in self.request.http_headers() {
+        for (name, value) in &fields {
             let field = format_header_field(name, value)?;
             header_list.append(&field)?;
         }
 
+        for name in config.http_suppressed_headers() {
+            if !fields.contains_key(name) {
+                // A field without a value removes curl's internal one
+                header_list.append(&format!("{}:", name))?;
+            }
+        }
+
         curl_handle.http_headers(header_list)?;
 
         Ok(())
@@ -177,7 +310,28 @@ impl<H: SessionHandler> CurlSession<H> {
     fn perform_with_callbacks(&mut self) -> Result<(), Error> {
         let handler = self.handler.take().unwrap();
 
-        let callback_handler = CallbackHandler::new(handler, self.mode);
+        let mut callback_handler = CallbackHandler::new(
+            handler,
+            self.mode,
+            self.cancellation_token.clone(),
+            self.config.borrow().http_max_header_size(),
+            self.config.borrow().verify_status(),
+            self.config.borrow().progress_interval(),
+            self.config.borrow().http_raw_body(),
+        );
+        callback_handler.raw_handle = self.curl_handle.as_ref().unwrap().raw();
+        callback_handler.head_request = self.request.method() == Method::Head;
+        callback_handler.dns_timeout = self.config.borrow().dns_timeout();
+        callback_handler.opened_sockets = self
+            .socket_policy
+            .as_ref()
+            .map(|socket_policy| socket_policy.opened.clone());
+
+        if self.mode == SessionMode::Http {
+            callback_handler.cookie_capturer =
+                CookieCapturer::new(&self.cookie_jar, &self.config.borrow(), &self.request);
+        }
+
         let callback_handler = Rc::new(RefCell::new(callback_handler));
 
         let result = {
@@ -193,21 +347,114 @@ impl<H: SessionHandler> CurlSession<H> {
             curl_session.perform()
         };
 
-        let callback_handler = Rc::into_inner(callback_handler).unwrap().into_inner();
-        let handler = callback_handler.handler;
-        let error = callback_handler.error;
+        let mut callback_handler = Rc::into_inner(callback_handler).unwrap().into_inner();
 
-        self.handler = Some(handler);
+        if let Err(error) = callback_handler.emit_connect_attempts() {
+            callback_handler.error.get_or_insert(error);
+        }
+
+        if let Err(error) = callback_handler.finish_progress() {
+            callback_handler.error.get_or_insert(error);
+        }
+
+        // The handler is given back and the transfer's own error is returned
+        // even if curl doesn't report the statistics
+        let stats = self
+            .get_transfer_stats(&callback_handler)
+            .unwrap_or_else(|error| {
+                tracing::warn!(%error, "could not get transfer statistics");
+
+                TransferStats {
+                    transfer_id: self.transfer_id,
+                    ..Default::default()
+                }
+            });
+
+        let event = SessionEvent::Finished(stats.clone());
+        let event_result = callback_handler.emit(event);
+
+        self.transfer_stats = Some(stats);
+        self.handler = Some(callback_handler.handler);
+
+        if result.is_err() && self.cancellation_token.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+
+        // curl only reports that it couldn't connect
+        if let (Err(_), Some(socket_policy)) = (&result, &self.socket_policy) {
+            if let Some(error) = socket_policy.blocked.take() {
+                return Err(error);
+            }
+        }
+
+        // An error from a callback is the cause of curl's generic abort error
+        if let Some(error) = callback_handler.error {
+            return Err(Self::convert_handler_error(error));
+        }
 
         result?;
 
-        if let Some(error) = error {
-            Err(Error::Other(OtherError::Custom(error)))
-        } else {
-            Ok(())
+        event_result.map_err(Self::convert_handler_error)
+    }
+
+    fn convert_handler_error(error: BoxedError) -> Error {
+        match error.downcast::<Error>() {
+            Ok(error) => *error,
+            Err(error) => Error::Other(OtherError::Custom(error)),
         }
     }
 
+    fn get_transfer_stats<C: SessionHandler>(
+        &mut self,
+        callback_handler: &CallbackHandler<C>,
+    ) -> Result<TransferStats, Error> {
+        let curl_handle = self.curl_handle.as_mut().unwrap();
+        let status_code = curl_handle.response_code()?;
+
+        Ok(TransferStats {
+            transfer_id: self.transfer_id,
+            status_code: if status_code != 0 {
+                Some(status_code as u16)
+            } else {
+                None
+            },
+            bytes_received: callback_handler.response_header_bytes
+                + callback_handler.response_body_bytes,
+            bytes_sent: callback_handler.request_header_bytes + callback_handler.request_body_bytes,
+            request_header_bytes: callback_handler.request_header_bytes,
+            request_body_bytes: callback_handler.request_body_bytes,
+            response_header_bytes: callback_handler.response_header_bytes,
+            response_body_bytes: callback_handler.response_body_bytes,
+            tls_overhead_bytes: callback_handler
+                .tls_bytes
+                .filter(|_| tls_overhead_supported()),
+            name_lookup_duration: curl_handle.namelookup_time()?,
+            resolved_addresses: match &self.resolve_entry {
+                Some(entry) => parse_resolve_entry_addresses(entry),
+                None => {
+                    let mut addresses = Vec::new();
+
+                    for address in &callback_handler.connect_attempts {
+                        if !addresses.contains(&address.ip()) {
+                            addresses.push(address.ip());
+                        }
+                    }
+
+                    addresses
+                }
+            },
+            connect_duration: curl_handle.connect_time()?,
+            first_byte_duration: curl_handle.starttransfer_time()?,
+            total_duration: curl_handle.total_time()?,
+            connection_reused: get_num_connects(curl_handle)? == 0,
+            http_version: get_http_version(curl_handle)?,
+            scheme: get_info_string(curl_handle.raw(), CURLINFO_SCHEME)?
+                .map(|scheme| scheme.to_ascii_lowercase()),
+            alpn_protocol: callback_handler.alpn_protocol.clone(),
+            security_policy: callback_handler.security_policy.clone(),
+        })
+    }
+
     fn set_up_debug_function<'a, C: SessionHandler + 'a>(
         curl_session: &mut Transfer<'_, 'a>,
         callback_handler: Rc<RefCell<CallbackHandler<C>>>,
@@ -278,16 +525,26 @@ impl<H: SessionHandler> Session<H> for CurlSession<H> {
 
         (handler, result)
     }
+
+    fn transfer_stats(&self) -> Option<&TransferStats> {
+        self.transfer_stats.as_ref()
+    }
 }
 
 #[derive(Debug)]
 pub struct CurlSessionControl {
     aborted: bool,
+    upload_paused: bool,
+    upload_pending: bool,
 }
 
 impl CurlSessionControl {
     fn new() -> Self {
-        Self { aborted: false }
+        Self {
+            aborted: false,
+            upload_paused: false,
+            upload_pending: false,
+        }
     }
 }
 
@@ -295,6 +552,18 @@ impl SessionControl for CurlSessionControl {
     fn abort(&mut self) {
         self.aborted = true;
     }
+
+    fn pause_upload(&mut self) {
+        self.upload_paused = true;
+    }
+
+    fn resume_upload(&mut self) {
+        self.upload_paused = false;
+    }
+
+    fn upload_pending(&mut self) {
+        self.upload_pending = true;
+    }
 }
 
 impl<H: SessionHandler> Debug for CurlSession<H> {
@@ -315,32 +584,135 @@ enum CallbackState {
 struct CallbackHandler<H: SessionHandler> {
     handler: H,
     control: CurlSessionControl,
+    cancellation_token: CancellationToken,
     state: CallbackState,
     error: Option<BoxedError>,
+    /// Lines of the response header or trailer being received. Body data
+    /// is never buffered and is given to the handler as the slices of the
+    /// libcurl callbacks.
     receive_buf: Vec<u8>,
+    /// Lines of the request header being sent.
     send_buf: Vec<u8>,
+    max_header_size: usize,
+    tls_session_resumed: bool,
+    alpn_protocol: Option<String>,
+    security_policy: Option<Box<SecurityPolicy>>,
+    verify_status: bool,
+    connect_attempts: Vec<SocketAddr>,
+    /// Addresses recorded by the socket open callback.
+    opened_sockets: Option<Arc<Mutex<Vec<SocketAddr>>>>,
+    /// Handle of the transfer for querying info from within callbacks.
+    raw_handle: *mut curl_sys::CURL,
+    /// Time allowed for resolving the host name.
+    dns_timeout: Option<Duration>,
+    /// Lines of an FTP reply received so far.
+    ftp_reply: FtpReplyBuffer,
+    /// Whether the request is HEAD, whose response never has a body or
+    /// trailer.
+    head_request: bool,
+    cookie_capturer: Option<CookieCapturer>,
+    /// Whether sending is paused in libcurl by the read callback.
+    send_paused: bool,
+    connected_emitted: bool,
+    connection_info_emitted: bool,
+    request_header_bytes: u64,
+    request_body_bytes: u64,
+    response_header_bytes: u64,
+    response_body_bytes: u64,
+    tls_bytes: Option<u64>,
+    progress_interval: Duration,
+    raw_body: bool,
+    download_meter: ProgressMeter,
+    upload_meter: ProgressMeter,
+    event_filter: EventFilter,
+    /// Time and byte counts of the last progress event.
+    last_progress: Option<(Instant, [u64; 4])>,
+    /// Byte counts of the last progress callback, emitted or not.
+    latest_progress: Option<[u64; 4]>,
 }
 
 impl<H: SessionHandler> CallbackHandler<H> {
-    fn new(handler: H, mode: SessionMode) -> Self {
+    fn new(
+        handler: H,
+        mode: SessionMode,
+        cancellation_token: CancellationToken,
+        max_header_size: usize,
+        verify_status: bool,
+        progress_interval: Duration,
+        raw_body: bool,
+    ) -> Self {
         let state = match mode {
             SessionMode::Http => CallbackState::HttpRequest,
             SessionMode::Ftp => CallbackState::Ftp,
         };
 
         Self {
+            event_filter: handler.event_filter(),
             handler,
             control: CurlSessionControl::new(),
+            cancellation_token,
             error: None,
             state,
             receive_buf: Vec::new(),
             send_buf: Vec::new(),
+            max_header_size,
+            tls_session_resumed: false,
+            alpn_protocol: None,
+            security_policy: None,
+            verify_status,
+            connect_attempts: Vec::new(),
+            opened_sockets: None,
+            raw_handle: std::ptr::null_mut(),
+            dns_timeout: None,
+            ftp_reply: FtpReplyBuffer::default(),
+            head_request: false,
+            cookie_capturer: None,
+            send_paused: false,
+            connected_emitted: false,
+            connection_info_emitted: false,
+            request_header_bytes: 0,
+            request_body_bytes: 0,
+            response_header_bytes: 0,
+            response_body_bytes: 0,
+            tls_bytes: None,
+            progress_interval,
+            raw_body,
+            download_meter: ProgressMeter::new(Instant::now()),
+            upload_meter: ProgressMeter::new(Instant::now()),
+            last_progress: None,
+            latest_progress: None,
+        }
+    }
+
+    /// Passes the event to the handler if it is interested in its kind.
+    fn emit(&mut self, event: SessionEvent) -> Result<(), BoxedError> {
+        if self.event_filter.contains(event.kind()) {
+            self.handler.event(&mut self.control, event)
+        } else {
+            Ok(())
         }
     }
 
     fn debug_function(&mut self, info_type: InfoType, data: &[u8]) {
         tracing::trace!(?info_type, data = ?crate::string::preview_bytes(data, 100), "debug");
 
+        if let Err(error) = self
+            .emit_connect_attempts()
+            .and_then(|_| self.find_and_emit_connect_event())
+        {
+            self.error = Some(error);
+            self.control.abort();
+        }
+
+        if let Some(kind) = trace_kind(&info_type) {
+            let event = SessionEvent::Trace(kind, data);
+
+            if let Err(error) = self.emit(event) {
+                self.error = Some(error);
+                self.control.abort();
+            }
+        }
+
         match info_type {
             InfoType::Text => {
                 let result = self.handle_curl_log(data);
@@ -376,8 +748,9 @@ impl<H: SessionHandler> CallbackHandler<H> {
                     self.control.abort();
                 }
             }
-            InfoType::SslDataIn => {}
-            InfoType::SslDataOut => {}
+            InfoType::SslDataIn | InfoType::SslDataOut => {
+                *self.tls_bytes.get_or_insert(0) += data.len() as u64;
+            }
             _ => {}
         }
     }
@@ -392,6 +765,8 @@ impl<H: SessionHandler> CallbackHandler<H> {
             self.control.abort();
         }
 
+        self.check_cancelled();
+
         !self.control.aborted
     }
 
@@ -410,30 +785,47 @@ impl<H: SessionHandler> CallbackHandler<H> {
             "progress"
         );
 
-        let result = self.handle_progress(
-            download_total as u64,
-            download_current as u64,
-            upload_total as u64,
-            upload_current as u64,
-        );
+        let result = self
+            .check_dns_timeout()
+            .and_then(|_| self.emit_connect_attempts())
+            .and_then(|_| self.find_and_emit_connect_event())
+            .and_then(|_| {
+                self.handle_progress(
+                    download_total as u64,
+                    download_current as u64,
+                    upload_total as u64,
+                    upload_current as u64,
+                )
+            })
+            .and_then(|_| self.resume_send());
 
         if let Err(error) = result {
             self.error = Some(error);
             self.control.abort();
         }
 
+        self.check_cancelled();
+
         !self.control.aborted
     }
 
     fn read_function(&mut self, buf: &mut [u8]) -> Result<usize, curl::easy::ReadError> {
         tracing::trace!("read");
 
+        if self.control.upload_paused {
+            return self.pause_send();
+        }
+
         let result = self.handle_send_content(buf);
 
         match result {
             Ok(size) => {
+                let pending = std::mem::take(&mut self.control.upload_pending);
+
                 if self.control.aborted {
                     Err(curl::easy::ReadError::Abort)
+                } else if size == 0 && (pending || self.control.upload_paused) {
+                    self.pause_send()
                 } else {
                     Ok(size)
                 }
@@ -445,6 +837,39 @@ impl<H: SessionHandler> CallbackHandler<H> {
         }
     }
 
+    fn pause_send(&mut self) -> Result<usize, curl::easy::ReadError> {
+        tracing::debug!("upload paused");
+        self.send_paused = true;
+
+        Err(curl::easy::ReadError::Pause)
+    }
+
+    /// Unpauses sending once the upload is no longer paused by the handler,
+    /// so that libcurl calls the read callback again.
+    fn resume_send(&mut self) -> Result<(), BoxedError> {
+        if !self.send_paused || self.control.upload_paused || self.control.aborted {
+            return Ok(());
+        }
+
+        tracing::debug!("upload resumed");
+        self.send_paused = false;
+
+        // SAFETY: The handle is valid for the duration of the transfer and
+        // receiving is never paused, so no write callback is made from here.
+        let code = unsafe {
+            curl_sys::curl_easy_pause(
+                self.raw_handle,
+                curl_sys::CURLPAUSE_RECV_CONT | curl_sys::CURLPAUSE_SEND_CONT,
+            )
+        };
+
+        if code == curl_sys::CURLE_OK {
+            Ok(())
+        } else {
+            Err(Box::new(Error::from(curl::Error::new(code))))
+        }
+    }
+
     fn write_function(&mut self, data: &[u8]) -> Result<usize, curl::easy::WriteError> {
         tracing::trace!(data = ?crate::string::preview_bytes(data, 100), "write");
 
@@ -455,6 +880,8 @@ impl<H: SessionHandler> CallbackHandler<H> {
             self.control.abort();
         }
 
+        self.check_cancelled();
+
         if self.control.aborted {
             Ok(0)
         } else {
@@ -462,94 +889,305 @@ impl<H: SessionHandler> CallbackHandler<H> {
         }
     }
 
+    fn check_cancelled(&mut self) {
+        if self.cancellation_token.is_cancelled() && !self.control.aborted {
+            tracing::debug!("cancelled");
+            self.control.abort();
+        }
+    }
+
     fn handle_curl_log(&mut self, data: &[u8]) -> Result<(), BoxedError> {
         let text = String::from_utf8_lossy(data);
         let text = text.trim_end();
         tracing::debug!(text, "curl");
 
-        self.find_and_emit_connect_event(text)?;
+        self.find_and_emit_connect_failed_event(text)?;
+        self.find_and_emit_tls_event(text)?;
 
         Ok(())
     }
 
-    fn find_and_emit_connect_event(&mut self, text: &str) -> Result<(), BoxedError> {
-        // FIXME: Upstream curl crate needs CURLOPT_PREREQFUNCTION support
-        if let Some(address) = parse_connect_address(text) {
+    fn find_and_emit_tls_event(&mut self, text: &str) -> Result<(), BoxedError> {
+        // curl has no info for the ALPN result, only this message
+        if let Some(protocol) = parse_alpn_protocol(text) {
+            tracing::debug!(protocol, "ALPN");
+            self.alpn_protocol = Some(protocol.to_string());
+        }
+
+        // Messages of the OpenSSL and rustls backends
+        if text.starts_with("SSL re-using session ID") {
+            self.tls_session_resumed = true;
+        } else if text.starts_with("SSL connection using ") || text == "Done handshaking" {
+            let info = TlsInfo {
+                session_resumed: tls_session_resumption_supported()
+                    .then_some(self.tls_session_resumed),
+                // curl fails the handshake if the status couldn't be verified
+                status_verified: self.verify_status,
+            };
+            self.tls_session_resumed = false;
+
+            tracing::info!(
+                session_resumed = ?info.session_resumed,
+                status_verified = info.status_verified,
+                "TLS connected"
+            );
+            let event = SessionEvent::TlsConnected(info);
+            self.emit(event)?;
+        }
+
+        Ok(())
+    }
+
+    /// Emits the connect attempts for the sockets opened since the last
+    /// callback.
+    fn emit_connect_attempts(&mut self) -> Result<(), BoxedError> {
+        let opened = match &self.opened_sockets {
+            Some(opened) => std::mem::take(&mut *opened.lock().unwrap()),
+            None => return Ok(()),
+        };
+
+        for address in opened {
+            tracing::debug!(%address, "connect attempt");
+            self.connect_attempts.push(address);
+
+            let event = SessionEvent::ConnectAttempt(address);
+            self.emit(event)?;
+        }
+
+        Ok(())
+    }
+
+    /// Emits a failed connect attempt found in curl's log.
+    ///
+    /// curl has no callback or info for why a connection failed, so this
+    /// falls back to its log messages, which may change between versions.
+    /// Unrecognized messages only lose the event.
+    fn find_and_emit_connect_failed_event(&mut self, text: &str) -> Result<(), BoxedError> {
+        if let Some((address, reason)) = parse_connect_failure(text, &self.connect_attempts) {
+            tracing::debug!(%address, reason, "connect failed");
+            let event = SessionEvent::ConnectFailed(address, reason);
+            self.emit(event)?;
+        }
+
+        Ok(())
+    }
+
+    /// Fails if the host name is still being resolved after the DNS
+    /// timeout, since curl only limits resolving and connecting together.
+    fn check_dns_timeout(&mut self) -> Result<(), BoxedError> {
+        let timeout = match self.dns_timeout {
+            Some(timeout) if !self.connected_emitted && !self.connection_info_emitted => timeout,
+            _ => return Ok(()),
+        };
+
+        let resolved = get_info_double(self.raw_handle, curl_sys::CURLINFO_NAMELOOKUP_TIME)? > 0.0;
+        let elapsed = get_info_double(self.raw_handle, curl_sys::CURLINFO_TOTAL_TIME)?;
+
+        if resolved || elapsed < timeout.as_secs_f64() {
+            return Ok(());
+        }
+
+        tracing::debug!(?timeout, "dns timeout");
+        self.dns_timeout = None;
+
+        Err(Box::new(Error::Network(NetworkError::Dns(
+            format!("resolving timed out after {:?}", timeout).into(),
+        ))))
+    }
+
+    /// Emits the connected event once curl reports a connect time for a new
+    /// connection.
+    ///
+    /// Called from callbacks since curl has no callback for it.
+    fn find_and_emit_connect_event(&mut self) -> Result<(), BoxedError> {
+        if self.connected_emitted {
+            return Ok(());
+        }
+
+        if let Some(address) = get_connected_address(self.raw_handle)? {
+            self.connected_emitted = true;
+
             tracing::info!(address = %address.ip(), port = address.port(), "connected");
             let event = SessionEvent::Connected(address);
-            self.handler.event(&mut self.control, event)?;
+            self.emit(event)?;
         }
 
         Ok(())
     }
 
     fn handle_send_header(&mut self, data: &[u8]) -> Result<(), BoxedError> {
+        self.request_header_bytes += data.len() as u64;
+
+        if !self.connection_info_emitted {
+            self.emit_connection_info()?;
+        }
+
         let event = SessionEvent::HeaderSent(data);
 
-        self.handler.event(&mut self.control, event)?;
+        self.emit(event)?;
 
         if self.state == CallbackState::HttpRequest {
-            self.send_buf.extend_from_slice(data);
+            Self::buffer_header(&mut self.send_buf, data, self.max_header_size)?;
 
             if let Some(_index) = crate::http::scan_header_boundary(&self.send_buf) {
                 let header = RequestHeader::parse(&self.send_buf)?;
+                self.send_buf.clear();
+
+                // Sent by curl to set up a tunnel through a proxy
+                if header.method == "CONNECT" {
+                    tracing::debug!(uri = &header.uri, "proxy connect request");
+                    return Ok(());
+                }
+
                 tracing::info!(method = &header.method, uri = &header.uri, "http request");
 
                 let event = SessionEvent::HttpRequest(data, header);
-                self.handler.event(&mut self.control, event)?;
+                self.emit(event)?;
 
                 self.state = CallbackState::HttpResponse;
             }
+        } else if self.state == CallbackState::Ftp {
+            if let Some((command, argument)) = parse_ftp_command(data) {
+                tracing::debug!(command, "ftp command");
+
+                let event = SessionEvent::FtpCommand { command, argument };
+                self.emit(event)?;
+            }
+
+            if let Some(transfer_type) = parse_ftp_type_command(data) {
+                tracing::debug!(?transfer_type, "ftp transfer type");
+
+                let event = SessionEvent::FtpTransferType(transfer_type);
+                self.emit(event)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn emit_connection_info(&mut self) -> Result<(), BoxedError> {
+        if let Some((reused, local_addr, remote_addr)) = get_connection_info(self.raw_handle)? {
+            self.connection_info_emitted = true;
+
+            tracing::debug!(reused, %local_addr, %remote_addr, "connection info");
+            let event = SessionEvent::ConnectionInfo {
+                reused,
+                local_addr,
+                remote_addr,
+            };
+            self.emit(event)?;
         }
 
         Ok(())
     }
 
     fn handle_receive_header(&mut self, data: &[u8]) -> Result<(), BoxedError> {
+        self.response_header_bytes += data.len() as u64;
+
         let event = SessionEvent::HeaderReceived(data);
-        self.handler.event(&mut self.control, event)?;
+        self.emit(event)?;
 
         if self.state == CallbackState::HttpResponse {
-            self.receive_buf.extend_from_slice(data);
+            Self::buffer_header(&mut self.receive_buf, data, self.max_header_size)?;
 
             if let Some(_index) = crate::http::scan_header_boundary(&self.receive_buf) {
                 let header = ResponseHeader::parse(&self.receive_buf)?;
-                tracing::info!(
-                    status_code = header.status_code,
-                    reason_phrase = &header.reason_phrase,
-                    "http response"
-                );
-
-                let event = SessionEvent::HttpResponse(data, header);
-                self.handler.event(&mut self.control, event)?;
-
-                self.state = CallbackState::HttpResponseTrailer;
+                self.receive_buf.clear();
+
+                // 101 Switching Protocols is the final response of an upgrade
+                if (100..200).contains(&header.status_code) && header.status_code != 101 {
+                    tracing::debug!(
+                        status_code = header.status_code,
+                        reason_phrase = &header.reason_phrase,
+                        "http interim response"
+                    );
+
+                    let event = SessionEvent::HttpInterimResponse(data, header);
+                    self.emit(event)?;
+                } else {
+                    tracing::info!(
+                        status_code = header.status_code,
+                        reason_phrase = &header.reason_phrase,
+                        "http response"
+                    );
+
+                    self.security_policy =
+                        SecurityPolicy::from_fields(&header.fields).map(Box::new);
+
+                    let cookie_outcomes = match &self.cookie_capturer {
+                        Some(capturer) => capturer.capture(&header),
+                        None => Vec::new(),
+                    };
+
+                    let event = SessionEvent::HttpResponse(data, header);
+                    self.emit(event)?;
+
+                    for outcome in cookie_outcomes {
+                        self.emit(outcome.into())?;
+                    }
+
+                    self.state = if self.head_request {
+                        CallbackState::Finished
+                    } else {
+                        CallbackState::HttpResponseTrailer
+                    };
+                }
             }
         } else if self.state == CallbackState::HttpResponseTrailer {
-            self.receive_buf.extend_from_slice(data);
+            Self::buffer_header(&mut self.receive_buf, data, self.max_header_size)?;
 
             if let Some(_index) = crate::http::scan_header_boundary(&self.receive_buf) {
                 let header = ResponseTrailer::parse(&self.receive_buf)?;
                 let event = SessionEvent::HttpResponseTrailer(data, header);
 
-                self.handler.event(&mut self.control, event)?;
+                self.emit(event)?;
 
                 self.state = CallbackState::Finished;
             }
+        } else if self.state == CallbackState::Ftp {
+            let line = String::from_utf8_lossy(data);
+
+            if let Some((code, text)) = self
+                .ftp_reply
+                .push_line(line.trim_end_matches(['\r', '\n']))
+            {
+                tracing::debug!(code, "ftp reply");
+
+                let event = SessionEvent::FtpReply { code, text };
+                self.emit(event)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn buffer_header(buf: &mut Vec<u8>, data: &[u8], limit: usize) -> Result<(), Error> {
+        if buf.len() + data.len() > limit {
+            tracing::debug!(limit, "header too large");
+            return Err(ProtocolError::HeaderTooLarge { limit }.into());
         }
 
+        buf.extend_from_slice(data);
+
         Ok(())
     }
 
     fn handle_send_body(&mut self, data: &[u8]) -> Result<(), BoxedError> {
+        self.request_body_bytes += data.len() as u64;
+
         let event = SessionEvent::BodySent(data);
-        self.handler.event(&mut self.control, event)?;
+        self.emit(event)?;
         Ok(())
     }
 
     fn handle_receive_body(&mut self, data: &[u8]) -> Result<(), BoxedError> {
-        let event = SessionEvent::BodyReceived(data);
-        self.handler.event(&mut self.control, event)?;
+        self.response_body_bytes += data.len() as u64;
+
+        if self.raw_body {
+            let event = SessionEvent::BodyReceived(data);
+            self.emit(event)?;
+        }
+
         Ok(())
     }
 
@@ -557,14 +1195,14 @@ impl<H: SessionHandler> CallbackHandler<H> {
         let size = self.handler.upload_content(&mut self.control, buf)?;
 
         let event = SessionEvent::ContentSent(&buf[0..size]);
-        self.handler.event(&mut self.control, event)?;
+        self.emit(event)?;
 
         Ok(size)
     }
 
     fn handle_receive_content(&mut self, data: &[u8]) -> Result<(), BoxedError> {
         let event = SessionEvent::ContentReceived(data);
-        self.handler.event(&mut self.control, event)?;
+        self.emit(event)?;
         Ok(())
     }
 
@@ -575,69 +1213,768 @@ impl<H: SessionHandler> CallbackHandler<H> {
         upload_total: u64,
         upload_current: u64,
     ) -> Result<(), BoxedError> {
+        if !self.event_filter.contains(EventKind::Progress) {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let download_rate = self.download_meter.update(now, download_current);
+        let upload_rate = self.upload_meter.update(now, upload_current);
+        let counts = [
+            download_total,
+            download_current,
+            upload_total,
+            upload_current,
+        ];
+        self.latest_progress = Some(counts);
+
+        let due = match self.last_progress {
+            Some((time, last_counts)) => {
+                let completed = (download_total > 0 && download_current == download_total)
+                    || (upload_total > 0 && upload_current == upload_total);
+
+                now.duration_since(time) >= self.progress_interval
+                    || completed && counts != last_counts
+            }
+            None => true,
+        };
+
+        if !due {
+            return Ok(());
+        }
+
+        self.emit_progress(now, counts, download_rate, upload_rate)
+    }
+
+    /// Emits a last progress event if the final byte counts weren't emitted,
+    /// such as when the size of the content isn't known.
+    fn finish_progress(&mut self) -> Result<(), BoxedError> {
+        if !self.event_filter.contains(EventKind::Progress) {
+            return Ok(());
+        }
+
+        match (self.latest_progress, self.last_progress) {
+            (Some(counts), Some((_, last_counts))) if counts == last_counts => Ok(()),
+            (Some(counts), _) => {
+                let now = Instant::now();
+                let download_rate = self.download_meter.update(now, counts[1]);
+                let upload_rate = self.upload_meter.update(now, counts[3]);
+
+                self.emit_progress(now, counts, download_rate, upload_rate)
+            }
+            (None, _) => Ok(()),
+        }
+    }
+
+    fn emit_progress(
+        &mut self,
+        now: Instant,
+        counts: [u64; 4],
+        download_rate: TransferRate,
+        upload_rate: TransferRate,
+    ) -> Result<(), BoxedError> {
+        let [download_total, download_current, upload_total, upload_current] = counts;
+
+        self.last_progress = Some((now, counts));
+
+        let eta = if download_total > 0 {
+            estimate_remaining(download_total, download_current, download_rate)
+        } else {
+            estimate_remaining(upload_total, upload_current, upload_rate)
+        };
+
         let event = SessionEvent::Progress {
             download_total,
             download_current,
             upload_total,
             upload_current,
+            download_rate,
+            upload_rate,
+            eta,
         };
 
-        self.handler.event(&mut self.control, event)?;
+        self.emit(event)
+    }
+}
 
-        Ok(())
+fn trace_kind(info_type: &InfoType) -> Option<TraceKind> {
+    match info_type {
+        InfoType::Text => Some(TraceKind::Text),
+        InfoType::HeaderIn => Some(TraceKind::HeaderIn),
+        InfoType::HeaderOut => Some(TraceKind::HeaderOut),
+        InfoType::DataIn => Some(TraceKind::DataIn),
+        InfoType::DataOut => Some(TraceKind::DataOut),
+        InfoType::SslDataIn => Some(TraceKind::SslDataIn),
+        InfoType::SslDataOut => Some(TraceKind::SslDataOut),
+        _ => None,
     }
 }
 
 fn format_header_field(name: &FieldName, value: &FieldValue) -> Result<String, Error> {
-    Ok(format!("{}:{}", name, value))
+    if value.as_bytes().is_empty() {
+        // curl's syntax for sending a field with an empty value
+        Ok(format!("{};", name))
+    } else {
+        Ok(format!("{}:{}", name, value))
+    }
 }
 
-fn parse_connect_address(text: &str) -> Option<SocketAddr> {
-    // Extract from Curl_verboseconnect
-    static PATTERN: OnceLock<Regex> = OnceLock::new();
-    let re =
-        PATTERN.get_or_init(|| Regex::new(r"Connected to .+ \(([0-9.:]+)\) port (\d+)").unwrap());
+fn set_request_target(curl_handle: &mut Easy, target: &[u8]) -> Result<(), Error> {
+    if target
+        .iter()
+        .any(|byte| matches!(byte, b'\r' | b'\n' | b' '))
+    {
+        return Err(Error::InvalidArgument {
+            value: crate::string::parse_utf8_escaped(target),
+            reason: "request target contains whitespace".to_string(),
+        });
+    }
 
-    let captures = re.captures(text);
+    let target = CString::new(target).map_err(|_| Error::InvalidArgument {
+        value: crate::string::parse_utf8_escaped(target),
+        reason: "request target contains NUL".to_string(),
+    })?;
 
-    if let Some(captures) = captures {
-        let address = captures.get(1).unwrap();
-        let port = captures.get(2).unwrap();
+    // SAFETY: CURLOPT_REQUEST_TARGET takes a NUL terminated string which
+    // curl copies.
+    let code = unsafe {
+        curl_sys::curl_easy_setopt(curl_handle.raw(), CURLOPT_REQUEST_TARGET, target.as_ptr())
+    };
 
-        let address = match IpAddr::from_str(address.as_str()) {
-            Ok(address) => address,
-            Err(error) => {
-                tracing::debug!(?error, "curl info parse ip addr");
-                return None;
+    if code == curl_sys::CURLE_OK {
+        Ok(())
+    } else {
+        Err(curl::Error::new(code).into())
+    }
+}
+
+fn set_verify_status(curl_handle: &mut Easy) -> Result<(), Error> {
+    // SAFETY: CURLOPT_SSL_VERIFYSTATUS takes a long.
+    let code = unsafe {
+        curl_sys::curl_easy_setopt(
+            curl_handle.raw(),
+            CURLOPT_SSL_VERIFYSTATUS,
+            1 as std::ffi::c_long,
+        )
+    };
+
+    match code {
+        curl_sys::CURLE_OK => Ok(()),
+        CURLE_NOT_BUILT_IN => Err(Error::UnsupportedFeature {
+            feature: "TLS certificate status verification".to_string(),
+        }),
+        _ => Err(curl::Error::new(code).into()),
+    }
+}
+
+fn set_happy_eyeballs_timeout(curl_handle: &mut Easy, timeout: Duration) -> Result<(), Error> {
+    let timeout = timeout.as_millis().min(std::ffi::c_long::MAX as u128) as std::ffi::c_long;
+
+    // SAFETY: CURLOPT_HAPPY_EYEBALLS_TIMEOUT_MS takes a long.
+    let code = unsafe {
+        curl_sys::curl_easy_setopt(
+            curl_handle.raw(),
+            CURLOPT_HAPPY_EYEBALLS_TIMEOUT_MS,
+            timeout,
+        )
+    };
+
+    if code == curl_sys::CURLE_OK {
+        Ok(())
+    } else {
+        Err(curl::Error::new(code).into())
+    }
+}
+
+fn set_http_auth(curl_handle: &mut Easy, auth: &HttpAuth) -> Result<(), Error> {
+    let mut curl_auth = curl::easy::Auth::new();
+
+    match auth {
+        HttpAuth::Ntlm { username, password } => {
+            if !ntlm_supported() {
+                return Err(Error::UnsupportedFeature {
+                    feature: "NTLM authentication".to_string(),
+                });
             }
-        };
-        let port = match u16::from_str(port.as_str()) {
-            Ok(port) => port,
-            Err(error) => {
-                tracing::debug!(?error, "curl info parse port");
-                return None;
+
+            curl_auth.ntlm(true);
+            curl_handle.username(username)?;
+            curl_handle.password(password)?;
+        }
+        HttpAuth::Negotiate => {
+            if !negotiate_supported() {
+                return Err(Error::UnsupportedFeature {
+                    feature: "Negotiate authentication".to_string(),
+                });
             }
-        };
 
-        Some(SocketAddr::new(address, port))
+            curl_auth.gssnegotiate(true);
+            // Empty credentials enable authentication, like `curl -u :`
+            curl_handle.username("")?;
+            curl_handle.password("")?;
+        }
+    }
+
+    curl_handle.http_auth(&curl_auth)?;
+
+    Ok(())
+}
+
+/// Returns the addresses of a curl resolve entry (`host:port:addresses`).
+fn parse_resolve_entry_addresses(entry: &str) -> Vec<IpAddr> {
+    let addresses = entry.splitn(3, ':').nth(2).unwrap_or_default();
+
+    addresses
+        .split(',')
+        .filter_map(|address| {
+            address
+                .trim()
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse()
+                .ok()
+        })
+        .collect()
+}
+
+/// Parses a command line sent on the FTP control connection into its name
+/// and argument.
+///
+/// Data with more than one line, such as a proxy CONNECT request, is not a
+/// command.
+fn parse_ftp_command(data: &[u8]) -> Option<(String, String)> {
+    let line = String::from_utf8_lossy(data);
+    let line = line.strip_suffix('\n')?.trim_end_matches('\r');
+
+    if line.contains(['\r', '\n']) {
+        return None;
+    }
+
+    let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+
+    if command.is_empty() || !command.bytes().all(|byte| byte.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    Some((command.to_ascii_uppercase(), argument.to_string()))
+}
+
+/// Collects the lines of single and multiline FTP replies.
+#[derive(Debug, Default)]
+struct FtpReplyBuffer {
+    code: Option<u16>,
+    lines: Vec<String>,
+}
+
+impl FtpReplyBuffer {
+    /// Adds a line without its line ending and returns the code and text
+    /// once the reply is complete.
+    ///
+    /// Lines outside of a reply, such as the headers curl generates for
+    /// file information, are ignored.
+    fn push_line(&mut self, line: &str) -> Option<(u16, String)> {
+        let parsed = parse_ftp_reply_line(line);
+
+        match (self.code, parsed) {
+            (None, Some((code, true, text))) => Some((code, text.to_string())),
+            (None, Some((code, false, text))) => {
+                self.code = Some(code);
+                self.lines.push(text.to_string());
+                None
+            }
+            (None, None) => None,
+            (Some(code), Some((line_code, true, text))) if line_code == code => {
+                self.lines.push(text.to_string());
+                self.code = None;
+
+                let text = self.lines.join("\n");
+                self.lines.clear();
+
+                Some((code, text))
+            }
+            (Some(code), Some((line_code, false, text))) if line_code == code => {
+                self.lines.push(text.to_string());
+                None
+            }
+            (Some(_), _) => {
+                self.lines.push(line.to_string());
+                None
+            }
+        }
+    }
+}
+
+/// Parses a line starting with a reply code into the code, whether it is
+/// the last line of the reply, and the text.
+fn parse_ftp_reply_line(line: &str) -> Option<(u16, bool, &str)> {
+    let code = line.get(0..3)?;
+
+    if !code.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+
+    let code = code.parse().ok()?;
+
+    match line.as_bytes().get(3) {
+        None => Some((code, true, "")),
+        Some(b' ') => Some((code, true, &line[4..])),
+        Some(b'-') => Some((code, false, &line[4..])),
+        _ => None,
+    }
+}
+
+/// Parses the FTP command that sets the transfer type.
+fn parse_ftp_type_command(data: &[u8]) -> Option<FtpTransferType> {
+    let command = String::from_utf8_lossy(data);
+    let mut parts = command.split_ascii_whitespace();
+
+    if !parts.next()?.eq_ignore_ascii_case("TYPE") {
+        return None;
+    }
+
+    match parts.next()? {
+        "A" | "a" => Some(FtpTransferType::Ascii),
+        "I" | "i" => Some(FtpTransferType::Binary),
+        _ => None,
+    }
+}
+
+fn set_transfer_text(curl_handle: &mut Easy, enabled: bool) -> Result<(), Error> {
+    // SAFETY: CURLOPT_TRANSFERTEXT takes a long.
+    let code = unsafe {
+        curl_sys::curl_easy_setopt(
+            curl_handle.raw(),
+            curl_sys::CURLOPT_TRANSFERTEXT,
+            enabled as std::ffi::c_long,
+        )
+    };
+
+    if code == curl_sys::CURLE_OK {
+        Ok(())
     } else {
-        None
+        Err(curl::Error::new(code).into())
+    }
+}
+
+/// Keeps the response of the proxy to CONNECT out of the header callback.
+fn set_suppress_connect_headers(curl_handle: &mut Easy) -> Result<(), Error> {
+    // SAFETY: CURLOPT_SUPPRESS_CONNECT_HEADERS takes a long.
+    let code = unsafe {
+        curl_sys::curl_easy_setopt(
+            curl_handle.raw(),
+            CURLOPT_SUPPRESS_CONNECT_HEADERS,
+            1 as std::ffi::c_long,
+        )
+    };
+
+    if code == curl_sys::CURLE_OK {
+        Ok(())
+    } else {
+        Err(curl::Error::new(code).into())
+    }
+}
+
+fn get_num_connects(curl_handle: &Easy) -> Result<u32, Error> {
+    Ok(get_info_long(curl_handle.raw(), curl_sys::CURLINFO_NUM_CONNECTS)? as u32)
+}
+
+fn get_http_version(curl_handle: &Easy) -> Result<Option<HttpVersion>, Error> {
+    let version = get_info_long(curl_handle.raw(), CURLINFO_HTTP_VERSION)?;
+
+    Ok(match version as std::ffi::c_int {
+        curl_sys::CURL_HTTP_VERSION_1_0 => Some(HttpVersion::Http10),
+        curl_sys::CURL_HTTP_VERSION_1_1 => Some(HttpVersion::Http11),
+        curl_sys::CURL_HTTP_VERSION_2_0 => Some(HttpVersion::Http2),
+        curl_sys::CURL_HTTP_VERSION_3 => Some(HttpVersion::Http3),
+        _ => None,
+    })
+}
+
+/// Returns the remote address of a new connection of the transfer once it is
+/// established.
+fn get_connected_address(raw_handle: *mut curl_sys::CURL) -> Result<Option<SocketAddr>, Error> {
+    if get_info_long(raw_handle, curl_sys::CURLINFO_NUM_CONNECTS)? == 0
+        || get_info_double(raw_handle, curl_sys::CURLINFO_CONNECT_TIME)? <= 0.0
+    {
+        return Ok(None);
+    }
+
+    let ip = get_info_string(raw_handle, curl_sys::CURLINFO_PRIMARY_IP)?;
+    let port = get_info_long(raw_handle, curl_sys::CURLINFO_PRIMARY_PORT)?;
+
+    Ok(ip
+        .and_then(|ip| ip.parse().ok())
+        .map(|ip| SocketAddr::new(ip, port as u16)))
+}
+
+/// Returns whether the connection of the transfer was reused, and its local
+/// and remote addresses, once the connection is established.
+fn get_connection_info(
+    raw_handle: *mut curl_sys::CURL,
+) -> Result<Option<(bool, SocketAddr, SocketAddr)>, Error> {
+    let reused = get_info_long(raw_handle, curl_sys::CURLINFO_NUM_CONNECTS)? == 0;
+    let local_ip = get_info_string(raw_handle, curl_sys::CURLINFO_LOCAL_IP)?;
+    let local_port = get_info_long(raw_handle, curl_sys::CURLINFO_LOCAL_PORT)?;
+    let remote_ip = get_info_string(raw_handle, curl_sys::CURLINFO_PRIMARY_IP)?;
+    let remote_port = get_info_long(raw_handle, curl_sys::CURLINFO_PRIMARY_PORT)?;
+
+    let local_addr = local_ip
+        .and_then(|ip| ip.parse().ok())
+        .map(|ip| SocketAddr::new(ip, local_port as u16));
+    let remote_addr = remote_ip
+        .and_then(|ip| ip.parse().ok())
+        .map(|ip| SocketAddr::new(ip, remote_port as u16));
+
+    Ok(local_addr
+        .zip(remote_addr)
+        .map(|(local_addr, remote_addr)| (reused, local_addr, remote_addr)))
+}
+
+fn get_info_long(
+    raw_handle: *mut curl_sys::CURL,
+    info: curl_sys::CURLINFO,
+) -> Result<std::ffi::c_long, Error> {
+    let mut value: std::ffi::c_long = 0;
+
+    // SAFETY: Info of the long type writes a long to the given pointer.
+    let code = unsafe { curl_sys::curl_easy_getinfo(raw_handle, info, &mut value) };
+
+    if code == curl_sys::CURLE_OK {
+        Ok(value)
+    } else {
+        Err(curl::Error::new(code).into())
+    }
+}
+
+fn get_info_double(
+    raw_handle: *mut curl_sys::CURL,
+    info: curl_sys::CURLINFO,
+) -> Result<f64, Error> {
+    let mut value: std::ffi::c_double = 0.0;
+
+    // SAFETY: Info of the double type writes a double to the given pointer.
+    let code = unsafe { curl_sys::curl_easy_getinfo(raw_handle, info, &mut value) };
+
+    if code == curl_sys::CURLE_OK {
+        Ok(value)
+    } else {
+        Err(curl::Error::new(code).into())
+    }
+}
+
+fn get_info_string(
+    raw_handle: *mut curl_sys::CURL,
+    info: curl_sys::CURLINFO,
+) -> Result<Option<String>, Error> {
+    let mut value: *const std::ffi::c_char = std::ptr::null();
+
+    // SAFETY: Info of the string type writes a pointer to a C string owned
+    // by the handle, or null, to the given pointer.
+    let code = unsafe { curl_sys::curl_easy_getinfo(raw_handle, info, &mut value) };
+
+    if code != curl_sys::CURLE_OK {
+        return Err(curl::Error::new(code).into());
+    }
+
+    if value.is_null() {
+        return Ok(None);
+    }
+
+    // SAFETY: The pointer is non-null and valid until the handle changes.
+    let value = unsafe { std::ffi::CStr::from_ptr(value) };
+
+    Ok(Some(value.to_string_lossy().into_owned()))
+}
+
+fn parse_alpn_protocol(text: &str) -> Option<&str> {
+    // "ALPN: server accepted h2" since 7.88, "ALPN, server accepted to use h2" before
+    text.strip_prefix("ALPN: server accepted ")
+        .or_else(|| text.strip_prefix("ALPN, server accepted to use "))
+        .map(|protocol| protocol.trim())
+}
+
+fn parse_connect_failure(text: &str, attempts: &[SocketAddr]) -> Option<(SocketAddr, String)> {
+    // Extract from cf_socket_connect and do_connect
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let re = PATTERN.get_or_init(|| {
+        Regex::new(
+            r"^(?:connect to (\S+) port (\d+) failed|Immediate connect fail for (\S+)): (.*)$",
+        )
+        .unwrap()
+    });
+
+    let captures = re.captures(text)?;
+    let reason = captures.get(4).unwrap().as_str().to_string();
+
+    if let (Some(address), Some(port)) = (captures.get(1), captures.get(2)) {
+        let address = IpAddr::from_str(address.as_str()).ok()?;
+        let port = u16::from_str(port.as_str()).ok()?;
+
+        Some((SocketAddr::new(address, port), reason))
+    } else {
+        // Message doesn't include the port
+        let address = IpAddr::from_str(captures.get(3)?.as_str()).ok()?;
+        let address = attempts
+            .iter()
+            .rev()
+            .find(|attempt| attempt.ip() == address)?;
+
+        Some((*address, reason))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::net::Ipv4Addr;
+    use std::net::{Ipv4Addr, Ipv6Addr};
 
     use super::*;
 
     #[test]
-    fn test_parse_connect_address() {
-        let result = parse_connect_address("Connected to 127.0.0.1 (127.0.0.1) port 39753 (#0)\n");
-        let expect = Some(SocketAddr::new(
-            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
-            39753,
+    fn test_parse_alpn_protocol() {
+        assert_eq!(parse_alpn_protocol("ALPN: server accepted h2"), Some("h2"));
+        assert_eq!(
+            parse_alpn_protocol("ALPN, server accepted to use http/1.1"),
+            Some("http/1.1")
+        );
+        assert_eq!(parse_alpn_protocol("ALPN: curl offers h2,http/1.1"), None);
+    }
+
+    #[test]
+    fn test_parse_resolve_entry_addresses() {
+        assert_eq!(
+            parse_resolve_entry_addresses("example.com:443:192.0.2.1,[2001:db8::1]"),
+            vec![
+                "192.0.2.1".parse::<IpAddr>().unwrap(),
+                "2001:db8::1".parse::<IpAddr>().unwrap()
+            ]
+        );
+        assert!(parse_resolve_entry_addresses("example.com:443").is_empty());
+    }
+
+    #[test]
+    fn test_parse_ftp_command() {
+        assert_eq!(
+            parse_ftp_command(b"RETR file name.txt\r\n"),
+            Some(("RETR".to_string(), "file name.txt".to_string()))
+        );
+        assert_eq!(
+            parse_ftp_command(b"pwd\r\n"),
+            Some(("PWD".to_string(), String::new()))
+        );
+        assert_eq!(parse_ftp_command(b"PWD"), None);
+        assert_eq!(
+            parse_ftp_command(b"CONNECT example.com:21 HTTP/1.1\r\nHost: example.com:21\r\n\r\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_ftp_reply_buffer() {
+        let mut buffer = FtpReplyBuffer::default();
+
+        assert_eq!(buffer.push_line("Content-Length: 6"), None);
+        assert_eq!(
+            buffer.push_line("220 Welcome"),
+            Some((220, "Welcome".to_string()))
+        );
+        assert_eq!(buffer.push_line("211-Features:"), None);
+        assert_eq!(buffer.push_line(" SIZE"), None);
+        assert_eq!(buffer.push_line("211-MDTM"), None);
+        assert_eq!(
+            buffer.push_line("211 End"),
+            Some((211, "Features:\n SIZE\nMDTM\nEnd".to_string()))
+        );
+        assert_eq!(buffer.push_line("226"), Some((226, String::new())));
+    }
+
+    #[test]
+    fn test_parse_ftp_type_command() {
+        assert_eq!(
+            parse_ftp_type_command(b"TYPE A\r\n"),
+            Some(FtpTransferType::Ascii)
+        );
+        assert_eq!(
+            parse_ftp_type_command(b"TYPE I\r\n"),
+            Some(FtpTransferType::Binary)
+        );
+        assert_eq!(parse_ftp_type_command(b"TYPE L 8\r\n"), None);
+        assert_eq!(parse_ftp_type_command(b"PASV\r\n"), None);
+    }
+
+    #[test]
+    fn test_parse_connect_failure() {
+        let address = SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 443);
+
+        assert_eq!(
+            parse_connect_failure(
+                "connect to 127.0.0.1 port 80 failed: Connection refused",
+                &[]
+            ),
+            Some((
+                SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 80),
+                "Connection refused".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_connect_failure(
+                "Immediate connect fail for ::1: Network is unreachable",
+                &[address]
+            ),
+            Some((address, "Network is unreachable".to_string()))
+        );
+        assert_eq!(
+            parse_connect_failure(
+                "Immediate connect fail for ::1: Network is unreachable",
+                &[]
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_upload_pause() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _address) = listener.accept().unwrap();
+            let mut data = Vec::new();
+            let mut buf = [0u8; 1024];
+
+            loop {
+                let size = stream.read(&mut buf).unwrap();
+                data.extend_from_slice(&buf[..size]);
+
+                if size == 0 || data.ends_with(b"abcdef") {
+                    break;
+                }
+            }
+
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            data
+        });
+
+        #[derive(Default)]
+        struct MyHandler {
+            calls: usize,
+            paused: bool,
+        }
+
+        impl SessionHandler for MyHandler {
+            fn upload_content(
+                &mut self,
+                control: &mut dyn SessionControl,
+                buf: &mut [u8],
+            ) -> Result<usize, BoxedError> {
+                self.calls += 1;
+
+                match self.calls {
+                    1 => {
+                        control.upload_pending();
+                        Ok(0)
+                    }
+                    2 => {
+                        buf[..3].copy_from_slice(b"abc");
+                        Ok(3)
+                    }
+                    3 => {
+                        control.pause_upload();
+                        self.paused = true;
+                        Ok(0)
+                    }
+                    4 => {
+                        buf[..3].copy_from_slice(b"def");
+                        Ok(3)
+                    }
+                    _ => Ok(0),
+                }
+            }
+
+            fn event(
+                &mut self,
+                control: &mut dyn SessionControl,
+                event: SessionEvent,
+            ) -> Result<(), BoxedError> {
+                if let SessionEvent::Progress { .. } = event {
+                    if self.paused {
+                        self.paused = false;
+                        control.resume_upload();
+                    }
+                }
+
+                Ok(())
+            }
+        }
+
+        let mut curl_handle = Easy::new();
+        curl_handle.url(&format!("http://{}/", address)).unwrap();
+        curl_handle.upload(true).unwrap();
+        curl_handle.in_filesize(6).unwrap();
+        curl_handle.progress(true).unwrap();
+
+        let mut callback_handler = CallbackHandler::new(
+            MyHandler::default(),
+            SessionMode::Http,
+            CancellationToken::new(),
+            4096,
+            false,
+            Duration::ZERO,
+            false,
+        );
+        callback_handler.raw_handle = curl_handle.raw();
+        let callback_handler = Rc::new(RefCell::new(callback_handler));
+
+        {
+            let mut transfer = curl_handle.transfer();
+            CurlSession::<MyHandler>::set_up_read_function(&mut transfer, callback_handler.clone())
+                .unwrap();
+            CurlSession::<MyHandler>::set_up_progress_function(
+                &mut transfer,
+                callback_handler.clone(),
+            )
+            .unwrap();
+            transfer.perform().unwrap();
+        }
+
+        assert!(server.join().unwrap().ends_with(b"\r\n\r\nabcdef"));
+        assert_eq!(callback_handler.borrow().handler.calls, 4);
+        assert!(!callback_handler.borrow().send_paused);
+    }
+
+    #[test]
+    fn test_check_dns_timeout() {
+        struct NoopHandler;
+
+        impl SessionHandler for NoopHandler {}
+
+        // A handle that hasn't resolved yet, as while curl is resolving
+        let curl_handle = Easy::new();
+
+        let mut callback_handler = CallbackHandler::new(
+            NoopHandler,
+            SessionMode::Http,
+            CancellationToken::new(),
+            4096,
+            false,
+            Duration::ZERO,
+            false,
+        );
+        callback_handler.raw_handle = curl_handle.raw();
+
+        callback_handler.dns_timeout = Some(Duration::from_secs(60));
+        assert!(callback_handler.check_dns_timeout().is_ok());
+
+        callback_handler.dns_timeout = Some(Duration::ZERO);
+        let error = callback_handler.check_dns_timeout().unwrap_err();
+        assert!(matches!(
+            *error.downcast::<Error>().unwrap(),
+            Error::Network(NetworkError::Dns(_))
         ));
-        assert_eq!(result, expect);
+
+        // Only reported once
+        assert!(callback_handler.check_dns_timeout().is_ok());
     }
 }
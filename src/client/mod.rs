@@ -1,21 +1,75 @@
+#[cfg(feature = "async")]
+mod async_api;
+mod breaker;
 mod common;
 mod cookie;
+#[cfg(feature = "curl")]
 mod curl;
+#[cfg(feature = "native")]
+mod native;
+mod pipeline;
+mod policy;
+#[cfg(feature = "curl")]
 mod pool;
+mod profile;
+mod progress;
+mod response;
+#[cfg(feature = "curl")]
+mod socket;
+mod stats;
+mod transcript;
+mod user_agent;
 
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Instant, SystemTime},
+};
 
-use crate::{client::curl::CurlSession, error::Error};
+use url::Url;
 
-use self::{cookie::CookieJar, pool::ConnectionPool};
+#[cfg(feature = "curl")]
+use crate::client::curl::{CurlSession, SessionContext};
+#[cfg(feature = "native")]
+use crate::client::native::NativeSession;
+#[cfg(feature = "dns")]
+use crate::dns::Resolver;
+use crate::error::{Error, ErrorCategory, NetworkError};
 
+use self::policy::HostLists;
+#[cfg(feature = "curl")]
+use self::pool::ConnectionPool;
+use self::response::ResponseHandler;
+
+#[cfg(feature = "async")]
+pub use async_api::*;
+pub use breaker::*;
 pub use common::*;
+pub use cookie::*;
+pub use pipeline::*;
+pub use policy::*;
+pub use profile::*;
+pub use progress::TransferRate;
+pub use response::Response;
+pub use stats::*;
+pub use transcript::*;
+pub use user_agent::*;
 
 #[derive(Debug, Clone)]
 pub struct Client {
     config: Rc<RefCell<Config>>,
+    #[cfg(feature = "curl")]
     connection_pool: ConnectionPool,
     cookie_jar: CookieJar,
+    stats: Arc<Mutex<ClientStats>>,
+    cancellation_token: CancellationToken,
+    circuit_breaker: CircuitBreaker,
+    #[cfg(feature = "dns")]
+    resolver: Option<Arc<Resolver>>,
 }
 
 impl Client {
@@ -28,16 +82,22 @@ impl Client {
 
         Self {
             config: Rc::new(RefCell::new(config)),
+            #[cfg(feature = "curl")]
             connection_pool: ConnectionPool::new(),
             cookie_jar,
+            stats: Arc::new(Mutex::new(ClientStats::new())),
+            cancellation_token: CancellationToken::new(),
+            circuit_breaker: CircuitBreaker::new(),
+            #[cfg(feature = "dns")]
+            resolver: None,
         }
     }
 
-    pub fn config(&self) -> std::cell::Ref<Config> {
+    pub fn config(&self) -> std::cell::Ref<'_, Config> {
         self.config.borrow()
     }
 
-    pub fn config_mut(&mut self) -> std::cell::RefMut<Config> {
+    pub fn config_mut(&mut self) -> std::cell::RefMut<'_, Config> {
         self.config.borrow_mut()
     }
 
@@ -49,49 +109,313 @@ impl Client {
         &mut self.cookie_jar
     }
 
+    /// Returns a snapshot of the statistics of all submitted transfers.
+    pub fn stats(&self) -> ClientStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    pub fn reset_stats(&self) {
+        *self.stats.lock().unwrap() = ClientStats::new();
+    }
+
+    /// Returns the token that stops the running and any further transfers
+    /// when cancelled.
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.cancellation_token
+    }
+
+    /// Replaces the cancellation token, such as to share one between
+    /// clients.
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) -> &mut Self {
+        self.cancellation_token = token;
+        self
+    }
+
+    pub fn circuit_breaker(&self) -> &CircuitBreaker {
+        &self.circuit_breaker
+    }
+
+    /// Replaces the circuit breaker, such as to share one between clients.
+    pub fn set_circuit_breaker(&mut self, circuit_breaker: CircuitBreaker) -> &mut Self {
+        self.circuit_breaker = circuit_breaker;
+        self
+    }
+
+    /// Resolver used for host names of requests instead of the resolver of
+    /// the transfer backend.
+    #[cfg(feature = "dns")]
+    pub fn resolver(&self) -> Option<&Arc<Resolver>> {
+        self.resolver.as_ref()
+    }
+
+    #[cfg(feature = "dns")]
+    pub fn set_resolver(&mut self, resolver: Option<Arc<Resolver>>) -> &mut Self {
+        self.resolver = resolver;
+        self
+    }
+
+    /// Sends a GET request and returns the response with its content
+    /// buffered in memory.
+    pub fn get(&self, url: Url) -> Result<Response, Error> {
+        self.fetch(Request::new(url))
+    }
+
+    /// Sends the request and returns the response with its content
+    /// buffered in memory.
+    ///
+    /// Requests with content to upload, or responses too large to buffer,
+    /// need a handler given to [`Self::submit`].
+    pub fn fetch(&self, request: Request) -> Result<Response, Error> {
+        let (handler, result) = self.submit(request, ResponseHandler::default());
+        result?;
+
+        Ok(handler.into_response())
+    }
+
     pub fn submit<H: SessionHandler + 'static>(
         &self,
         request: Request,
         handler: H,
     ) -> (H, Result<(), Error>) {
+        let transfer_id = next_transfer_id();
         let url = request.url().as_str();
-        let span = tracing::info_span!("client_session", url);
+        let host = request.url().domain().map(crate::string::domain_to_unicode);
+        let span = tracing::info_span!("client_session", transfer_id, url, host);
         let _guard = span.enter();
+        let _current_guard = CurrentTransferGuard::new(transfer_id, request.url());
 
-        let mut session = match request.url().scheme() {
-            "http" | "https" => {
-                tracing::debug!(mode = "http", "init session");
+        if self.cancellation_token.is_cancelled() {
+            tracing::debug!("not starting transfer, already cancelled");
+            return (handler, Err(Error::Cancelled));
+        }
 
-                Box::new(CurlSession::new_http(
-                    self.config.clone(),
-                    request,
-                    handler,
-                    self.connection_pool.clone(),
-                    self.cookie_jar.clone(),
-                ))
+        if let Some(host) = host {
+            if self.config.borrow().reject_confusable()
+                && crate::string::is_mixed_script_confusable(&host)
+            {
+                let error = Error::InvalidArgument {
+                    value: host,
+                    reason: "mixed-script confusable host name".to_string(),
+                };
+                self.stats.lock().unwrap().record_error(error.category());
+
+                return (handler, Err(error));
+            }
+        }
+
+        if let Some(policy) = self.config.borrow().request_policy() {
+            if let Err(error) = policy.check_url(request.url()) {
+                tracing::debug!(%error, "blocked by request policy");
+                self.stats.lock().unwrap().record_error(error.category());
+
+                return (handler, Err(error));
+            }
+        }
+
+        if let Some(hosts) = HostLists::from_config(&self.config.borrow()) {
+            let check_addresses = self.config.borrow().proxy().is_none();
+
+            if let Err(error) = hosts.check_url(request.url(), check_addresses) {
+                tracing::debug!(%error, "blocked by host list");
+                self.stats.lock().unwrap().record_error(error.category());
+
+                return (handler, Err(error));
             }
-            "ftp" => {
-                tracing::debug!(mode = "ftp", "init session");
+        }
 
-                Box::new(CurlSession::new_ftp(
+        let circuit_host = request.url().host_str().map(str::to_ascii_lowercase);
+        let circuit_threshold = self.config.borrow().circuit_breaker_threshold();
+
+        if let (Some(host), true) = (&circuit_host, circuit_threshold > 0) {
+            if let Err(retry_after) = self.circuit_breaker.check(host, Instant::now()) {
+                tracing::debug!(?retry_after, "circuit open");
+                let error = Error::Network(NetworkError::CircuitOpen {
+                    host: host.clone(),
+                    retry_after,
+                });
+                self.stats.lock().unwrap().record_error(error.category());
+
+                return (handler, Err(error));
+            }
+        }
+
+        let resolve_entry = match self.resolve_entry(request.url()) {
+            Ok(entry) => entry,
+            Err(error) => {
+                self.stats.lock().unwrap().record_error(error.category());
+                return (handler, Err(error));
+            }
+        };
+
+        tracing::info!("transfer start");
+
+        let backend = self.config.borrow().backend();
+        let scheme = request.url().scheme().to_string();
+
+        let mut session: Box<dyn Session<H>> = match (backend, scheme.as_str()) {
+            #[cfg(feature = "curl")]
+            (Backend::Curl, "http" | "https" | "ftp") => {
+                let context = SessionContext {
+                    transfer_id,
+                    config: self.config.clone(),
+                    connection_pool: self.connection_pool.clone(),
+                    cookie_jar: self.cookie_jar.clone(),
+                    cancellation_token: self.cancellation_token.clone(),
+                    resolve_entry,
+                };
+
+                if scheme == "ftp" {
+                    tracing::debug!(mode = "ftp", "init session");
+
+                    Box::new(CurlSession::new_ftp(context, request, handler))
+                } else {
+                    tracing::debug!(mode = "http", "init session");
+
+                    Box::new(CurlSession::new_http(context, request, handler))
+                }
+            }
+            #[cfg(feature = "native")]
+            (Backend::Native, "http" | "https") if resolve_entry.is_none() => {
+                tracing::debug!(mode = "http", backend = "native", "init session");
+
+                Box::new(NativeSession::new(
+                    transfer_id,
                     self.config.clone(),
+                    self.cookie_jar.clone(),
+                    self.cancellation_token.clone(),
                     request,
                     handler,
-                    self.connection_pool.clone(),
-                    self.cookie_jar.clone(),
                 ))
             }
             _ => {
-                return (
-                    handler,
-                    Err(Error::UnsupportedFeature {
-                        feature: request.url().scheme().to_string(),
-                    }),
-                )
+                let feature = if !backend.is_available() {
+                    format!("{:?} backend", backend)
+                } else if resolve_entry.is_some() {
+                    format!("resolver with the {:?} backend", backend)
+                } else {
+                    scheme
+                };
+                let error = Error::UnsupportedFeature { feature };
+                self.stats.lock().unwrap().record_error(error.category());
+
+                return (handler, Err(error));
+            }
+        };
+
+        let (mut handler, result) = session.wait();
+
+        {
+            let mut stats = self.stats.lock().unwrap();
+
+            if let Some(transfer_stats) = session.transfer_stats() {
+                tracing::info!(
+                    status_code = transfer_stats.status_code,
+                    bytes_received = transfer_stats.bytes_received,
+                    bytes_sent = transfer_stats.bytes_sent,
+                    total_duration = ?transfer_stats.total_duration,
+                    connection_reused = transfer_stats.connection_reused,
+                    ok = result.is_ok(),
+                    "transfer finish"
+                );
+                stats.record_transfer(transfer_stats);
+            }
+            if let Err(error) = &result {
+                stats.record_error(error.category());
+            }
+            if let Some(host) = &circuit_host {
+                stats.record_host_transfer(
+                    host,
+                    session.transfer_stats(),
+                    result.is_err() && !matches!(result, Err(Error::Cancelled)),
+                    SystemTime::now(),
+                );
+            }
+        }
+
+        if let (Some(host), true) = (circuit_host, circuit_threshold > 0) {
+            let status_code = session.transfer_stats().and_then(|stats| stats.status_code);
+            self.update_circuit(host, &result, status_code, &mut handler);
+        }
+
+        (handler, result)
+    }
+
+    /// Resolves the host name of the URL with the resolver, if set, as a
+    /// curl resolve entry (`host:port:addresses`).
+    #[cfg(feature = "dns")]
+    fn resolve_entry(&self, url: &Url) -> Result<Option<String>, Error> {
+        let (resolver, host) = match (&self.resolver, url.domain()) {
+            (Some(resolver), Some(host)) => (resolver, host),
+            _ => return Ok(None),
+        };
+        let port = url.port_or_known_default().unwrap_or(80);
+
+        let lookup = resolver.lookup_ip_address(host)?;
+        let addresses = lookup
+            .ip_addresses()
+            .iter()
+            .map(|address| match address {
+                std::net::IpAddr::V4(address) => address.to_string(),
+                std::net::IpAddr::V6(address) => format!("[{}]", address),
+            })
+            .collect::<Vec<String>>();
+
+        tracing::debug!(host, ?addresses, "resolved with resolver");
+
+        Ok(Some(format!("{}:{}:{}", host, port, addresses.join(","))))
+    }
+
+    #[cfg(not(feature = "dns"))]
+    fn resolve_entry(&self, _url: &Url) -> Result<Option<String>, Error> {
+        Ok(None)
+    }
+
+    fn update_circuit<H: SessionHandler>(
+        &self,
+        host: String,
+        result: &Result<(), Error>,
+        status_code: Option<u16>,
+        handler: &mut H,
+    ) {
+        let failed = match result {
+            Err(error) => error.category() == ErrorCategory::Network,
+            Ok(_) => status_code.unwrap_or(0) >= 500,
+        };
+        let cancelled = matches!(result, Err(Error::Cancelled));
+
+        let transition = if cancelled {
+            None
+        } else if failed {
+            let config = self.config.borrow();
+            self.circuit_breaker.record_failure(
+                &host,
+                config.circuit_breaker_threshold(),
+                config.circuit_breaker_duration(),
+                Instant::now(),
+            )
+        } else {
+            self.circuit_breaker.record_success(&host)
+        };
+
+        let event = match transition {
+            Some(CircuitTransition::Opened(duration)) => {
+                tracing::warn!(host, ?duration, "circuit opened");
+                SessionEvent::CircuitOpened(host, duration)
             }
+            Some(CircuitTransition::Closed) => {
+                tracing::info!(host, "circuit closed");
+                SessionEvent::CircuitClosed(host)
+            }
+            None => return,
         };
 
-        session.wait()
+        if !handler.event_filter().contains(event.kind()) {
+            return;
+        }
+
+        if let Err(error) = handler.event(&mut DetachedControl, event) {
+            tracing::warn!(%error, "handler circuit event");
+        }
     }
 }
 
@@ -100,3 +424,48 @@ impl Default for Client {
         Self::new(Config::default())
     }
 }
+
+/// Control given to a handler for events outside of a running transfer.
+#[derive(Debug)]
+struct DetachedControl;
+
+impl SessionControl for DetachedControl {
+    fn abort(&mut self) {}
+}
+
+thread_local! {
+    static CURRENT_TRANSFER: RefCell<Option<(u64, Url)>> = const { RefCell::new(None) };
+}
+
+/// Returns the ID and URL of the transfer running on the current thread.
+///
+/// Intended for diagnostics such as panic hooks.
+pub fn current_transfer() -> Option<(u64, Url)> {
+    CURRENT_TRANSFER.with(|current| current.borrow().clone())
+}
+
+/// Sets the current transfer of the thread until dropped.
+struct CurrentTransferGuard {
+    previous: Option<(u64, Url)>,
+}
+
+impl CurrentTransferGuard {
+    fn new(transfer_id: u64, url: &Url) -> Self {
+        let previous = CURRENT_TRANSFER
+            .with(|current| current.borrow_mut().replace((transfer_id, url.clone())));
+
+        Self { previous }
+    }
+}
+
+impl Drop for CurrentTransferGuard {
+    fn drop(&mut self) {
+        CURRENT_TRANSFER.with(|current| *current.borrow_mut() = self.previous.take());
+    }
+}
+
+fn next_transfer_id() -> u64 {
+    static NEXT_TRANSFER_ID: AtomicU64 = AtomicU64::new(1);
+
+    NEXT_TRANSFER_ID.fetch_add(1, Ordering::Relaxed)
+}
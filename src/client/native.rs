@@ -0,0 +1,1306 @@
+use std::{
+    cell::{Cell, RefCell},
+    fmt::Debug,
+    fs::{File, OpenOptions},
+    io::{ErrorKind, Read, Write},
+    net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs},
+    ops::Range,
+    path::Path,
+    rc::Rc,
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant, SystemTime},
+};
+
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+    Certificate, ClientConfig, ClientConnection, OwnedTrustAnchor, RootCertStore, ServerName,
+    StreamOwned,
+};
+use url::{Host, Position};
+
+use crate::{
+    error::{
+        BoxedError, Error, NetworkError, OtherError, ParseError, ProtocolError,
+        TlsVerificationError, TlsVerificationKind,
+    },
+    http::{HeaderFields, RequestHeader, ResponseHeader, ResponseTrailer, SecurityPolicy},
+};
+
+use super::{
+    cookie::{CookieCapturer, CookieJar},
+    policy::HostLists,
+    progress::{estimate_remaining, ProgressMeter},
+    request_header_fields,
+    user_agent::select_user_agent,
+    CancellationToken, Config, EventFilter, EventKind, HttpVersion, Method, Request, Session,
+    SessionControl, SessionEvent, SessionHandler, TlsInfo, TransferStats,
+};
+
+/// Read timeout of the socket, so that cancellation and the transfer
+/// duration limit are checked while waiting for data.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+const MAX_CHUNK_SIZE_LINE: usize = 1024;
+
+/// HTTP/1.1 session of the native backend.
+///
+/// A connection is opened for each request and closed afterwards.
+pub struct NativeSession<H: SessionHandler> {
+    transfer_id: u64,
+    config: Rc<RefCell<Config>>,
+    request: Request,
+    handler: Option<H>,
+    cookie_jar: CookieJar,
+    cancellation_token: CancellationToken,
+    transfer_stats: Option<TransferStats>,
+}
+
+impl<H: SessionHandler> NativeSession<H> {
+    pub fn new(
+        transfer_id: u64,
+        config: Rc<RefCell<Config>>,
+        cookie_jar: CookieJar,
+        cancellation_token: CancellationToken,
+        request: Request,
+        handler: H,
+    ) -> Self {
+        Self {
+            transfer_id,
+            config,
+            request,
+            handler: Some(handler),
+            cookie_jar,
+            cancellation_token,
+            transfer_stats: None,
+        }
+    }
+}
+
+impl<H: SessionHandler> Session<H> for NativeSession<H> {
+    fn wait(&mut self) -> (H, Result<(), Error>) {
+        let config = self.config.borrow().clone();
+        let mut transfer = Transfer::new(
+            &config,
+            &self.request,
+            self.handler.take().unwrap(),
+            self.cancellation_token.clone(),
+        );
+
+        let result =
+            check_supported(&config, &self.request).and_then(|_| transfer.run(&self.cookie_jar));
+
+        let stats = transfer.transfer_stats(self.transfer_id);
+        let event_result = transfer.emit(SessionEvent::Finished(stats.clone()));
+        self.transfer_stats = Some(stats);
+
+        let result = result.and(event_result);
+
+        (transfer.handler, result)
+    }
+
+    fn transfer_stats(&self) -> Option<&TransferStats> {
+        self.transfer_stats.as_ref()
+    }
+}
+
+impl<H: SessionHandler> Debug for NativeSession<H> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NativeSession")
+            .field("transfer_id", &self.transfer_id)
+            .finish()
+    }
+}
+
+/// Returns an error for settings that the backend doesn't implement.
+fn check_supported(config: &Config, request: &Request) -> Result<(), Error> {
+    let unsupported = if request.method().is_upload() {
+        Some("request content")
+    } else {
+        config.native_unsupported_feature()
+    };
+
+    match unsupported {
+        Some(feature) => Err(Error::UnsupportedFeature {
+            feature: format!("{} with the native backend", feature),
+        }),
+        None => Ok(()),
+    }
+}
+
+#[derive(Debug)]
+struct NativeSessionControl {
+    aborted: bool,
+}
+
+impl SessionControl for NativeSessionControl {
+    fn abort(&mut self) {
+        self.aborted = true;
+    }
+}
+
+enum Stream {
+    Plain(TcpStream),
+    Tls(Box<StreamOwned<ClientConnection, CountingStream>>),
+}
+
+/// Socket that counts the bytes read and written, which are TLS records
+/// when wrapped by a TLS stream.
+struct CountingStream {
+    stream: TcpStream,
+    count: Rc<Cell<u64>>,
+}
+
+impl Read for CountingStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let size = self.stream.read(buf)?;
+        self.count.set(self.count.get() + size as u64);
+        Ok(size)
+    }
+}
+
+impl Write for CountingStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let size = self.stream.write(buf)?;
+        self.count.set(self.count.get() + size as u64);
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.read(buf),
+            Stream::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.write(buf),
+            Stream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Stream::Plain(stream) => stream.flush(),
+            Stream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BodyFraming {
+    Empty,
+    Length(u64),
+    Chunked,
+    Close,
+}
+
+/// State of a single request and response.
+struct Transfer<'a, H: SessionHandler> {
+    config: &'a Config,
+    request: &'a Request,
+    handler: H,
+    control: NativeSessionControl,
+    cancellation_token: CancellationToken,
+    start: Instant,
+    name_lookup_duration: Duration,
+    resolved_addresses: Vec<IpAddr>,
+    connect_duration: Duration,
+    first_byte_duration: Duration,
+    status_code: Option<u16>,
+    http_version: Option<HttpVersion>,
+    alpn_protocol: Option<String>,
+    security_policy: Option<Box<SecurityPolicy>>,
+    request_header_bytes: u64,
+    response_header_bytes: u64,
+    response_body_bytes: u64,
+    /// Bytes read from the stream, including any past the end of the body.
+    bytes_read: u64,
+    /// Bytes of TLS records read and written.
+    tls_record_bytes: Option<Rc<Cell<u64>>>,
+    download_meter: ProgressMeter,
+    last_progress: Option<Instant>,
+    event_filter: EventFilter,
+}
+
+impl<'a, H: SessionHandler> Transfer<'a, H> {
+    fn new(
+        config: &'a Config,
+        request: &'a Request,
+        handler: H,
+        cancellation_token: CancellationToken,
+    ) -> Self {
+        let start = Instant::now();
+
+        Self {
+            config,
+            request,
+            event_filter: handler.event_filter(),
+            handler,
+            control: NativeSessionControl { aborted: false },
+            cancellation_token,
+            start,
+            name_lookup_duration: Duration::ZERO,
+            resolved_addresses: Vec::new(),
+            connect_duration: Duration::ZERO,
+            first_byte_duration: Duration::ZERO,
+            status_code: None,
+            http_version: None,
+            alpn_protocol: None,
+            security_policy: None,
+            request_header_bytes: 0,
+            response_header_bytes: 0,
+            response_body_bytes: 0,
+            bytes_read: 0,
+            tls_record_bytes: None,
+            download_meter: ProgressMeter::new(start),
+            last_progress: None,
+        }
+    }
+
+    fn transfer_stats(&self, transfer_id: u64) -> TransferStats {
+        TransferStats {
+            transfer_id,
+            status_code: self.status_code,
+            bytes_received: self.response_header_bytes + self.response_body_bytes,
+            bytes_sent: self.request_header_bytes,
+            request_header_bytes: self.request_header_bytes,
+            request_body_bytes: 0,
+            response_header_bytes: self.response_header_bytes,
+            response_body_bytes: self.response_body_bytes,
+            tls_overhead_bytes: self.tls_record_bytes.as_ref().map(|count| {
+                count
+                    .get()
+                    .saturating_sub(self.request_header_bytes + self.bytes_read)
+            }),
+            name_lookup_duration: self.name_lookup_duration,
+            resolved_addresses: self.resolved_addresses.clone(),
+            connect_duration: self.connect_duration,
+            first_byte_duration: self.first_byte_duration,
+            total_duration: self.start.elapsed(),
+            connection_reused: false,
+            http_version: self.http_version,
+            scheme: Some(self.request.url().scheme().to_string()),
+            alpn_protocol: self.alpn_protocol.clone(),
+            security_policy: self.security_policy.clone(),
+        }
+    }
+
+    /// Passes the event to the handler if it is interested in its kind.
+    fn emit(&mut self, event: SessionEvent) -> Result<(), Error> {
+        if !self.event_filter.contains(event.kind()) {
+            return Ok(());
+        }
+
+        self.handler
+            .event(&mut self.control, event)
+            .map_err(convert_handler_error)
+    }
+
+    fn run(&mut self, cookie_jar: &CookieJar) -> Result<(), Error> {
+        let tcp_stream = self.connect()?;
+        tcp_stream.set_nodelay(self.config.tcp_nodelay())?;
+        tcp_stream.set_read_timeout(Some(POLL_INTERVAL))?;
+
+        let local_addr = tcp_stream.local_addr()?;
+        let remote_addr = tcp_stream.peer_addr()?;
+
+        let mut stream = if self.request.url().scheme() == "https" {
+            self.connect_tls(tcp_stream)?
+        } else {
+            Stream::Plain(tcp_stream)
+        };
+
+        tracing::debug!(%local_addr, %remote_addr, "connection info");
+        self.emit(SessionEvent::ConnectionInfo {
+            reused: false,
+            local_addr,
+            remote_addr,
+        })?;
+
+        self.send_request(&mut stream, cookie_jar)?;
+
+        let mut buf = Vec::new();
+        let header = self.receive_response_header(&mut stream, &mut buf)?;
+
+        if let Some(capturer) = CookieCapturer::new(cookie_jar, self.config, self.request) {
+            for outcome in capturer.capture(&header) {
+                self.emit(outcome.into())?;
+            }
+        }
+
+        self.receive_body(&mut stream, &header, buf)?;
+
+        Ok(())
+    }
+
+    fn check_interrupted(&self) -> Result<(), Error> {
+        if self.cancellation_token.is_cancelled() {
+            tracing::debug!("cancelled");
+            return Err(Error::Cancelled);
+        }
+
+        if self.control.aborted {
+            return Err(Error::Other(OtherError::Custom(
+                "transfer aborted by handler".into(),
+            )));
+        }
+
+        if let Some(duration) = self.config.max_transfer_duration() {
+            if self.start.elapsed() >= duration {
+                return Err(NetworkError::TimedOut(Box::new(std::io::Error::new(
+                    ErrorKind::TimedOut,
+                    "maximum transfer duration exceeded",
+                )))
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn connect(&mut self) -> Result<TcpStream, Error> {
+        let url = self.request.url();
+        let port = url.port_or_known_default().unwrap_or(80);
+
+        let addresses = match url.host() {
+            Some(Host::Domain(domain)) => resolve(domain, port, self.config.dns_timeout())?,
+            Some(Host::Ipv4(address)) => vec![SocketAddr::new(address.into(), port)],
+            Some(Host::Ipv6(address)) => vec![SocketAddr::new(address.into(), port)],
+            None => {
+                return Err(Error::InvalidArgument {
+                    value: url.to_string(),
+                    reason: "URL has no host".to_string(),
+                })
+            }
+        };
+
+        self.name_lookup_duration = self.start.elapsed();
+        self.resolved_addresses = addresses.iter().map(SocketAddr::ip).collect();
+
+        let addresses = match self.config.request_policy() {
+            Some(policy) => {
+                let mut blocked = None;
+                let allowed = addresses
+                    .into_iter()
+                    .filter(|address| match policy.check_address(address.ip()) {
+                        Ok(_) => true,
+                        Err(error) => {
+                            tracing::debug!(%error, "address refused");
+                            blocked = Some(error);
+                            false
+                        }
+                    })
+                    .collect::<Vec<SocketAddr>>();
+
+                match blocked {
+                    Some(error) if allowed.is_empty() => return Err(error),
+                    _ => allowed,
+                }
+            }
+            None => addresses,
+        };
+
+        let addresses = match HostLists::from_config(self.config) {
+            Some(hosts) => {
+                let host = url.host_str().unwrap_or_default();
+                let mut blocked = None;
+                let allowed = addresses
+                    .into_iter()
+                    .filter(|address| match hosts.check_address(host, address.ip()) {
+                        Ok(_) => true,
+                        Err(error) => {
+                            tracing::debug!(%error, "address refused");
+                            blocked = Some(error);
+                            false
+                        }
+                    })
+                    .collect::<Vec<SocketAddr>>();
+
+                match blocked {
+                    Some(error) if allowed.is_empty() => return Err(error),
+                    _ => allowed,
+                }
+            }
+            None => addresses,
+        };
+
+        let mut last_error = None;
+
+        for (index, address) in addresses.iter().enumerate() {
+            self.check_interrupted()?;
+
+            let remaining = self
+                .config
+                .connect_timeout()
+                .saturating_sub(self.start.elapsed());
+            let timeout = if index + 1 < addresses.len() {
+                remaining / 2
+            } else {
+                remaining
+            };
+
+            if timeout.is_zero() {
+                last_error = Some(std::io::Error::new(
+                    ErrorKind::TimedOut,
+                    "connect timeout exceeded",
+                ));
+                break;
+            }
+
+            tracing::debug!(%address, "connect attempt");
+            self.emit(SessionEvent::ConnectAttempt(*address))?;
+
+            match TcpStream::connect_timeout(address, timeout) {
+                Ok(stream) => {
+                    tracing::info!(address = %address.ip(), port = address.port(), "connected");
+                    self.connect_duration = self.start.elapsed();
+                    self.emit(SessionEvent::Connected(*address))?;
+
+                    return Ok(stream);
+                }
+                Err(error) => {
+                    tracing::debug!(%address, %error, "connect failed");
+                    self.emit(SessionEvent::ConnectFailed(*address, error.to_string()))?;
+                    last_error = Some(error);
+                }
+            }
+        }
+
+        Err(match last_error {
+            Some(error) if error.kind() == ErrorKind::TimedOut => {
+                NetworkError::TimedOut(Box::new(error))
+            }
+            Some(error) => NetworkError::Connect(Box::new(error)),
+            None => NetworkError::Dns(format!("no addresses for {}", url).into()),
+        }
+        .into())
+    }
+
+    fn connect_tls(&mut self, tcp_stream: TcpStream) -> Result<Stream, Error> {
+        let server_name = match self.request.url().host() {
+            Some(Host::Domain(domain)) => {
+                ServerName::try_from(domain).map_err(|error| Error::InvalidArgument {
+                    value: domain.to_string(),
+                    reason: error.to_string(),
+                })?
+            }
+            Some(Host::Ipv4(address)) => ServerName::IpAddress(address.into()),
+            Some(Host::Ipv6(address)) => ServerName::IpAddress(address.into()),
+            None => unreachable!("host checked when connecting"),
+        };
+
+        let mut tls_config = tls_client_config(
+            self.config.tls_verification(),
+            self.config.tls_session_resumption(),
+        );
+
+        if let Some(path) = self.config.tls_key_log() {
+            // Cloned so that the session cache stays shared
+            let mut config = ClientConfig::clone(&tls_config);
+            config.key_log = Arc::new(KeyLogWriter::open(path)?);
+            tls_config = Arc::new(config);
+        }
+
+        let connection = ClientConnection::new(tls_config, server_name).map_err(|error| {
+            ProtocolError::TlsVerification(
+                TlsVerificationError::new(TlsVerificationKind::Other, error.to_string())
+                    .with_source(Box::new(error)),
+            )
+        })?;
+        let count = Rc::new(Cell::new(0));
+        self.tls_record_bytes = Some(count.clone());
+
+        let mut stream = StreamOwned::new(
+            connection,
+            CountingStream {
+                stream: tcp_stream,
+                count,
+            },
+        );
+
+        REJECTED_CERTIFICATES.with(|certificates| certificates.borrow_mut().clear());
+
+        while stream.conn.is_handshaking() {
+            self.check_interrupted()?;
+
+            match stream.conn.complete_io(&mut stream.sock) {
+                Ok(_) => {}
+                Err(error) if is_retry_error(&error) => {}
+                Err(error) if error.kind() == ErrorKind::InvalidData => {
+                    let message = error.to_string();
+                    let certificates =
+                        REJECTED_CERTIFICATES.with(|certificates| certificates.take());
+
+                    return Err(ProtocolError::TlsVerification(
+                        TlsVerificationError::new(
+                            TlsVerificationKind::from_message(&message),
+                            message,
+                        )
+                        .with_certificates(certificates)
+                        .with_source(Box::new(error)),
+                    )
+                    .into());
+                }
+                Err(error) => return Err(convert_io_error(error)),
+            }
+        }
+
+        self.alpn_protocol = stream
+            .conn
+            .alpn_protocol()
+            .map(|protocol| String::from_utf8_lossy(protocol).into_owned());
+
+        let info = TlsInfo {
+            session_resumed: None,
+            status_verified: false,
+        };
+
+        tracing::info!("TLS connected");
+        self.emit(SessionEvent::TlsConnected(info))?;
+
+        Ok(Stream::Tls(Box::new(stream)))
+    }
+
+    fn send_request(&mut self, stream: &mut Stream, cookie_jar: &CookieJar) -> Result<(), Error> {
+        let data = self.format_request_header(cookie_jar)?;
+        let header = RequestHeader::parse(&data)?;
+
+        loop {
+            self.check_interrupted()?;
+
+            match stream.write_all(&data).and_then(|_| stream.flush()) {
+                Ok(_) => break,
+                Err(error) if is_retry_error(&error) => {}
+                Err(error) => return Err(convert_io_error(error)),
+            }
+        }
+
+        self.request_header_bytes += data.len() as u64;
+        self.emit(SessionEvent::HeaderSent(&data))?;
+
+        tracing::info!(method = &header.method, uri = &header.uri, "http request");
+        self.emit(SessionEvent::HttpRequest(&data, header))?;
+
+        Ok(())
+    }
+
+    fn format_request_header(&self, cookie_jar: &CookieJar) -> Result<Vec<u8>, Error> {
+        let url = self.request.url();
+        let user_agent = select_user_agent(self.config, self.request);
+        let method = match self.request.method() {
+            Method::Head => "HEAD",
+            _ => "GET",
+        };
+        let target = match self.request.http_raw_target() {
+            Some(target) => {
+                if target
+                    .iter()
+                    .any(|byte| matches!(byte, b'\r' | b'\n' | b' '))
+                {
+                    return Err(Error::InvalidArgument {
+                        value: crate::string::parse_utf8_escaped(target),
+                        reason: "request target contains whitespace".to_string(),
+                    });
+                }
+
+                target.to_vec()
+            }
+            None => url[Position::BeforePath..Position::AfterQuery]
+                .as_bytes()
+                .to_vec(),
+        };
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+
+        let fields = request_header_fields(self.config, self.request, &user_agent);
+
+        // Fields that curl would add on its own, which the config and
+        // request fields replace
+        let mut builtin_fields = HeaderFields::new();
+        builtin_fields.append("Host", host);
+
+        if !user_agent.is_empty() && self.config.http_header_order().is_empty() {
+            builtin_fields.append("User-Agent", user_agent.as_str());
+        }
+
+        builtin_fields.append("Accept", "*/*");
+
+        if self.request.http_cookies() {
+            let cookie_value = cookie_jar.get_request_string(url);
+
+            if !cookie_value.is_empty() {
+                builtin_fields.append("Cookie", cookie_value);
+            }
+        }
+
+        builtin_fields.retain(|name, _value| {
+            !fields.contains_key(name) && !self.config.http_suppressed_headers().contains(name)
+        });
+
+        let mut data = Vec::new();
+        data.extend_from_slice(method.as_bytes());
+        data.push(b' ');
+        data.extend_from_slice(&target);
+        data.extend_from_slice(b" HTTP/1.1\r\n");
+
+        for (name, value) in builtin_fields.iter().chain(fields.iter()) {
+            if value
+                .as_bytes()
+                .iter()
+                .any(|byte| matches!(byte, b'\r' | b'\n'))
+            {
+                return Err(Error::InvalidArgument {
+                    value: name.to_string(),
+                    reason: "header field value contains a line break".to_string(),
+                });
+            }
+
+            data.extend_from_slice(name.to_string().as_bytes());
+            data.extend_from_slice(b": ");
+            data.extend_from_slice(value.as_bytes());
+            data.extend_from_slice(b"\r\n");
+        }
+
+        data.extend_from_slice(b"\r\n");
+
+        Ok(data)
+    }
+
+    /// Reads data into the buffer, returning 0 at the end of the stream.
+    fn read(&mut self, stream: &mut Stream, buf: &mut Vec<u8>) -> Result<usize, Error> {
+        let mut chunk = [0u8; 16384];
+
+        loop {
+            self.check_interrupted()?;
+
+            match stream.read(&mut chunk) {
+                Ok(size) => {
+                    if self.bytes_read == 0 && size > 0 {
+                        self.first_byte_duration = self.start.elapsed();
+                    }
+
+                    self.bytes_read += size as u64;
+                    buf.extend_from_slice(&chunk[..size]);
+
+                    return Ok(size);
+                }
+                Err(error) if is_retry_error(&error) => {}
+                // Servers commonly close TLS connections without close_notify
+                Err(error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(0),
+                Err(error) => return Err(convert_io_error(error)),
+            }
+        }
+    }
+
+    fn receive_response_header(
+        &mut self,
+        stream: &mut Stream,
+        buf: &mut Vec<u8>,
+    ) -> Result<ResponseHeader, Error> {
+        let limit = self.config.http_max_header_size();
+
+        loop {
+            if let Some(index) = crate::http::scan_header_boundary(buf) {
+                if index > limit {
+                    return Err(ProtocolError::HeaderTooLarge { limit }.into());
+                }
+
+                let data = buf.drain(..index).collect::<Vec<u8>>();
+                self.response_header_bytes += data.len() as u64;
+                self.emit(SessionEvent::HeaderReceived(&data))?;
+
+                let header = ResponseHeader::parse(&data)?;
+
+                // 101 Switching Protocols is the final response of an upgrade
+                if (100..200).contains(&header.status_code) && header.status_code != 101 {
+                    tracing::debug!(
+                        status_code = header.status_code,
+                        reason_phrase = &header.reason_phrase,
+                        "http interim response"
+                    );
+                    self.emit(SessionEvent::HttpInterimResponse(&data, header))?;
+                    continue;
+                }
+
+                tracing::info!(
+                    status_code = header.status_code,
+                    reason_phrase = &header.reason_phrase,
+                    "http response"
+                );
+                self.status_code = Some(header.status_code);
+                self.http_version = header.version.parse().ok();
+                self.security_policy = SecurityPolicy::from_fields(&header.fields).map(Box::new);
+                self.emit(SessionEvent::HttpResponse(&data, header.clone()))?;
+
+                return Ok(header);
+            }
+
+            if buf.len() > limit {
+                tracing::debug!(limit, "header too large");
+                return Err(ProtocolError::HeaderTooLarge { limit }.into());
+            }
+
+            if self.read(stream, buf)? == 0 {
+                return Err(disconnected("connection closed before the response header"));
+            }
+        }
+    }
+
+    fn receive_body(
+        &mut self,
+        stream: &mut Stream,
+        header: &ResponseHeader,
+        mut buf: Vec<u8>,
+    ) -> Result<(), Error> {
+        let framing = self.body_framing(header);
+        let download_total = match framing {
+            BodyFraming::Length(length) => length,
+            _ => 0,
+        };
+        let mut decoder = ChunkedDecoder::new(self.config.http_max_header_size());
+        let mut received = 0u64;
+        // Ranges of the content within the buffer, which are passed to the
+        // handler without copying
+        let mut content = Vec::new();
+
+        tracing::debug!(?framing, "body framing");
+
+        if framing == BodyFraming::Empty {
+            self.emit_progress(0, 0, true)?;
+            return self.check_interrupted();
+        }
+
+        loop {
+            self.check_interrupted()?;
+
+            let size = match framing {
+                BodyFraming::Length(length) => {
+                    let size = buf.len().min((length - received) as usize);
+                    content.push(0..size);
+                    size
+                }
+                BodyFraming::Chunked => decoder.decode(&buf, &mut content)?,
+                _ => {
+                    content.push(0..buf.len());
+                    buf.len()
+                }
+            };
+
+            received += size as u64;
+            self.response_body_bytes += size as u64;
+
+            if size > 0 && self.config.http_raw_body() {
+                self.emit(SessionEvent::BodyReceived(&buf[..size]))?;
+            }
+
+            let done = match framing {
+                BodyFraming::Length(length) => received == length,
+                BodyFraming::Chunked => decoder.is_done(),
+                _ => false,
+            };
+
+            for range in content.drain(..) {
+                if !range.is_empty() {
+                    self.emit(SessionEvent::ContentReceived(&buf[range]))?;
+                }
+            }
+
+            buf.clear();
+
+            self.emit_progress(download_total, received, done)?;
+
+            if done {
+                self.check_interrupted()?;
+                break;
+            }
+
+            if self.read(stream, &mut buf)? == 0 {
+                if framing == BodyFraming::Close {
+                    self.emit_progress(download_total, received, true)?;
+                    break;
+                }
+
+                return Err(disconnected("connection closed before the end of the body"));
+            }
+        }
+
+        if decoder.has_trailer() {
+            let trailer = decoder.trailer().to_vec();
+            self.emit(SessionEvent::HeaderReceived(&trailer))?;
+
+            let fields = ResponseTrailer::parse(&trailer)?;
+            self.emit(SessionEvent::HttpResponseTrailer(&trailer, fields))?;
+        }
+
+        Ok(())
+    }
+
+    fn body_framing(&self, header: &ResponseHeader) -> BodyFraming {
+        if self.request.method() == Method::Head
+            || (100..200).contains(&header.status_code)
+            || header.status_code == 204
+            || header.status_code == 304
+        {
+            return BodyFraming::Empty;
+        }
+
+        let chunked = header
+            .fields
+            .get_all("Transfer-Encoding")
+            .last()
+            .is_some_and(|value| {
+                value
+                    .to_string_lossy()
+                    .trim()
+                    .to_ascii_lowercase()
+                    .ends_with("chunked")
+            });
+
+        if chunked {
+            return BodyFraming::Chunked;
+        }
+
+        match header.fields.content_length() {
+            Some(0) => BodyFraming::Empty,
+            Some(length) => BodyFraming::Length(length),
+            None => BodyFraming::Close,
+        }
+    }
+
+    fn emit_progress(
+        &mut self,
+        download_total: u64,
+        download_current: u64,
+        completed: bool,
+    ) -> Result<(), Error> {
+        if !self.event_filter.contains(EventKind::Progress) {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let download_rate = self.download_meter.update(now, download_current);
+
+        let due = match self.last_progress {
+            Some(time) => completed || now.duration_since(time) >= self.config.progress_interval(),
+            None => true,
+        };
+
+        if !due {
+            return Ok(());
+        }
+
+        self.last_progress = Some(now);
+
+        let event = SessionEvent::Progress {
+            download_total,
+            download_current,
+            upload_total: 0,
+            upload_current: 0,
+            download_rate,
+            upload_rate: Default::default(),
+            eta: estimate_remaining(download_total, download_current, download_rate),
+        };
+
+        self.emit(event)
+    }
+}
+
+/// Decoder of the chunked transfer coding.
+#[derive(Debug)]
+struct ChunkedDecoder {
+    state: ChunkState,
+    line: Vec<u8>,
+    trailer: Vec<u8>,
+    max_trailer_size: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChunkState {
+    Size,
+    Data(u64),
+    DataEnd,
+    Trailer,
+    Done,
+}
+
+impl ChunkedDecoder {
+    /// Creates a decoder that fails if the trailer exceeds
+    /// `max_trailer_size` bytes.
+    fn new(max_trailer_size: usize) -> Self {
+        Self {
+            state: ChunkState::Size,
+            line: Vec::new(),
+            trailer: Vec::new(),
+            max_trailer_size,
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.state == ChunkState::Done
+    }
+
+    /// Returns whether the last chunk was followed by trailer fields.
+    fn has_trailer(&self) -> bool {
+        self.is_done() && !self.trailer.iter().all(|byte| byte.is_ascii_whitespace())
+    }
+
+    /// Trailer fields including the terminating empty line.
+    fn trailer(&self) -> &[u8] {
+        &self.trailer
+    }
+
+    /// Appends the ranges of the content of the chunks within the data to
+    /// `content` and returns the number of bytes consumed, which is less
+    /// than the length of the data only after the end of the body.
+    fn decode(&mut self, mut data: &[u8], content: &mut Vec<Range<usize>>) -> Result<usize, Error> {
+        let total = data.len();
+
+        while !data.is_empty() && self.state != ChunkState::Done {
+            match self.state {
+                ChunkState::Size | ChunkState::DataEnd | ChunkState::Trailer => {
+                    let (line_complete, size) = match data.iter().position(|&byte| byte == b'\n') {
+                        Some(index) => (true, index + 1),
+                        None => (false, data.len()),
+                    };
+
+                    self.line.extend_from_slice(&data[..size]);
+                    data = &data[size..];
+
+                    if self.state == ChunkState::Trailer {
+                        if self.trailer.len() + self.line.len() > self.max_trailer_size {
+                            return Err(ProtocolError::HeaderTooLarge {
+                                limit: self.max_trailer_size,
+                            }
+                            .into());
+                        }
+                    } else if self.line.len() > MAX_CHUNK_SIZE_LINE {
+                        return Err(ParseError::new("chunk size line too long").into());
+                    }
+
+                    if line_complete {
+                        self.end_line()?;
+                    }
+                }
+                ChunkState::Data(remaining) => {
+                    let size = data.len().min(remaining as usize);
+                    let start = total - data.len();
+                    content.push(start..start + size);
+                    data = &data[size..];
+
+                    self.state = if size as u64 == remaining {
+                        ChunkState::DataEnd
+                    } else {
+                        ChunkState::Data(remaining - size as u64)
+                    };
+                }
+                ChunkState::Done => unreachable!(),
+            }
+        }
+
+        Ok(total - data.len())
+    }
+
+    fn end_line(&mut self) -> Result<(), ParseError> {
+        let line = std::mem::take(&mut self.line);
+        let is_empty = line.iter().all(|byte| byte.is_ascii_whitespace());
+
+        match self.state {
+            ChunkState::Size => {
+                let text = String::from_utf8_lossy(&line);
+                let size_text = text.split(';').next().unwrap_or_default().trim();
+                let size = u64::from_str_radix(size_text, 16)
+                    .map_err(|_| ParseError::new(format!("bad chunk size {:?}", size_text)))?;
+
+                self.state = if size == 0 {
+                    ChunkState::Trailer
+                } else {
+                    ChunkState::Data(size)
+                };
+            }
+            ChunkState::DataEnd => {
+                if !is_empty {
+                    return Err(ParseError::new("missing line break after chunk"));
+                }
+
+                self.state = ChunkState::Size;
+            }
+            ChunkState::Trailer => {
+                self.trailer.extend_from_slice(&line);
+
+                if is_empty {
+                    self.state = ChunkState::Done;
+                }
+            }
+            ChunkState::Data(_) | ChunkState::Done => unreachable!(),
+        }
+
+        Ok(())
+    }
+}
+
+fn is_retry_error(error: &std::io::Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted
+    )
+}
+
+fn disconnected(reason: &str) -> Error {
+    NetworkError::Disconnected(reason.into()).into()
+}
+
+fn convert_io_error(error: std::io::Error) -> Error {
+    match error.kind() {
+        ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::BrokenPipe => {
+            NetworkError::Disconnected(Box::new(error)).into()
+        }
+        ErrorKind::InvalidData => ProtocolError::InvalidResponse(Box::new(error)).into(),
+        _ => Error::Io(error),
+    }
+}
+
+/// Resolves the host name, giving up after the timeout if set.
+///
+/// The lookup blocks in the system resolver, so on timeout it is left to
+/// finish on its own thread.
+fn resolve(domain: &str, port: u16, timeout: Option<Duration>) -> Result<Vec<SocketAddr>, Error> {
+    let lookup = move |domain: &str| {
+        (domain, port)
+            .to_socket_addrs()
+            .map(|addresses| addresses.collect::<Vec<SocketAddr>>())
+    };
+
+    let result = match timeout {
+        Some(timeout) => {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            let domain = domain.to_string();
+
+            std::thread::spawn(move || {
+                let _ = sender.send(lookup(&domain));
+            });
+
+            match receiver.recv_timeout(timeout) {
+                Ok(result) => result,
+                Err(_) => {
+                    return Err(NetworkError::Dns(
+                        format!("resolving timed out after {:?}", timeout).into(),
+                    )
+                    .into())
+                }
+            }
+        }
+        None => lookup(domain),
+    };
+
+    result.map_err(|error| NetworkError::Dns(Box::new(error)).into())
+}
+
+fn convert_handler_error(error: BoxedError) -> Error {
+    match error.downcast::<Error>() {
+        Ok(error) => *error,
+        Err(error) => Error::Other(OtherError::Custom(error)),
+    }
+}
+
+/// Appends TLS secrets to a file in the NSS key log format.
+struct KeyLogWriter {
+    file: Mutex<File>,
+}
+
+impl KeyLogWriter {
+    fn open(path: &Path) -> Result<Self, Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl rustls::KeyLog for KeyLogWriter {
+    fn log(&self, label: &str, client_random: &[u8], secret: &[u8]) {
+        let hex = |data: &[u8]| {
+            data.iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>()
+        };
+        let line = format!("{} {} {}\n", label, hex(client_random), hex(secret));
+        let mut file = self.file.lock().unwrap();
+
+        if let Err(error) = file.write_all(line.as_bytes()) {
+            tracing::warn!(%error, "TLS key log write failed");
+        }
+    }
+}
+
+/// Returns the shared TLS config for the settings, so that sessions can be
+/// resumed across transfers.
+fn tls_client_config(verification: bool, resumption: bool) -> Arc<ClientConfig> {
+    static CONFIGS: OnceLock<[Arc<ClientConfig>; 4]> = OnceLock::new();
+
+    let configs = CONFIGS.get_or_init(|| {
+        [(false, false), (false, true), (true, false), (true, true)]
+            .map(|(verification, resumption)| Arc::new(build_tls_config(verification, resumption)))
+    });
+
+    configs[(verification as usize) * 2 + resumption as usize].clone()
+}
+
+fn build_tls_config(verification: bool, resumption: bool) -> ClientConfig {
+    let builder = ClientConfig::builder().with_safe_defaults();
+
+    let mut config = if verification {
+        let mut roots = RootCertStore::empty();
+        roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|anchor| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                anchor.subject,
+                anchor.spki,
+                anchor.name_constraints,
+            )
+        }));
+
+        builder
+            .with_custom_certificate_verifier(Arc::new(RecordingVerifier {
+                inner: WebPkiVerifier::new(roots, None),
+            }))
+            .with_no_client_auth()
+    } else {
+        builder
+            .with_custom_certificate_verifier(Arc::new(NoCertificateVerification))
+            .with_no_client_auth()
+    };
+
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    if !resumption {
+        config.session_storage = Arc::new(rustls::client::NoClientSessionStorage {});
+    }
+
+    config
+}
+
+thread_local! {
+    /// Certificates of the last failed verification on the thread.
+    ///
+    /// Verifiers are shared by the connections of a TLS config and called
+    /// during the handshake on the thread of the session.
+    static REJECTED_CERTIFICATES: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Verifies server certificates with the web PKI roots and keeps the
+/// certificates of a failed verification for its error.
+struct RecordingVerifier {
+    inner: WebPkiVerifier,
+}
+
+impl ServerCertVerifier for RecordingVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let result = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        );
+
+        if result.is_err() {
+            let certificates = std::iter::once(end_entity)
+                .chain(intermediates)
+                .map(|certificate| certificate.0.clone())
+                .collect();
+            REJECTED_CERTIFICATES.with(|rejected| *rejected.borrow_mut() = certificates);
+        }
+
+        result
+    }
+}
+
+/// Accepts any server certificate.
+struct NoCertificateVerification;
+
+impl ServerCertVerifier for NoCertificateVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunked_decoder() {
+        let body = b"5\r\nHello\r\n7;ext=1\r\n world!\r\n0\r\nAbc: xyz\r\n\r\nextra";
+        let mut decoder = ChunkedDecoder::new(1024);
+        let mut output = Vec::new();
+        let mut content = Vec::new();
+        let mut consumed = 0;
+
+        for chunk in body.chunks(3) {
+            consumed += decoder.decode(chunk, &mut content).unwrap();
+
+            for range in content.drain(..) {
+                output.extend_from_slice(&chunk[range]);
+            }
+        }
+
+        assert!(decoder.is_done());
+        assert!(decoder.has_trailer());
+        assert_eq!(output, b"Hello world!");
+        assert_eq!(consumed, body.len() - b"extra".len());
+        assert_eq!(decoder.trailer(), b"Abc: xyz\r\n\r\n");
+
+        let mut decoder = ChunkedDecoder::new(1024);
+        decoder.decode(b"0\r\n\r\n", &mut content).unwrap();
+        assert!(content.is_empty());
+        assert!(decoder.is_done());
+        assert!(!decoder.has_trailer());
+
+        let mut decoder = ChunkedDecoder::new(1024);
+        assert!(decoder.decode(b"xyz\r\n", &mut content).is_err());
+
+        let mut decoder = ChunkedDecoder::new(16);
+        decoder.decode(b"0\r\nAbc: xyz\r\n", &mut content).unwrap();
+        assert!(matches!(
+            decoder.decode(b"Defghi: xyz\r\n\r\n", &mut content),
+            Err(Error::Protocol(ProtocolError::HeaderTooLarge { limit: 16 }))
+        ));
+
+        // Trailer line without a line break
+        let mut data = b"0\r\n".to_vec();
+        data.extend_from_slice(&[b'a'; 64]);
+        let mut decoder = ChunkedDecoder::new(16);
+        assert!(matches!(
+            decoder.decode(&data, &mut content),
+            Err(Error::Protocol(ProtocolError::HeaderTooLarge { limit: 16 }))
+        ));
+    }
+}
@@ -0,0 +1,672 @@
+use std::{cell::RefCell, io::Write, rc::Rc, sync::OnceLock};
+
+use regex::Regex;
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+
+use super::SessionEvent;
+
+/// Length of the start of a body that is searched for a charset declaration.
+const SNIFF_LENGTH: usize = 1024;
+
+/// Code points of the Windows-1252 bytes 0x80 to 0x9F, where the rest are
+/// the same as in ISO-8859-1.
+const WINDOWS_1252_C1: [char; 32] = [
+    '\u{20AC}', '\u{81}', '\u{201A}', '\u{192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2C6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8D}', '\u{17D}', '\u{8F}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2DC}', '\u{2122}', '\u{161}', '\u{203A}', '\u{153}', '\u{9D}', '\u{17E}', '\u{178}',
+];
+
+/// Receives response body data from a [`BodyPipeline`].
+pub trait BodySink {
+    fn write(&mut self, data: &[u8]) -> Result<(), Error>;
+
+    /// Called once after the last data.
+    fn finish(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Shared sinks let the caller read results back after the transfer.
+impl<S: BodySink> BodySink for Rc<RefCell<S>> {
+    fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.borrow_mut().write(data)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.borrow_mut().finish()
+    }
+}
+
+/// Adapts a writer, such as a file, into a [`BodySink`].
+#[derive(Debug)]
+pub struct WriterSink<W: Write>(pub W);
+
+impl<W: Write> BodySink for WriterSink<W> {
+    fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        Ok(self.0.write_all(data)?)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        Ok(self.0.flush()?)
+    }
+}
+
+/// Character encoding that bodies are decoded from for text sinks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyCharset {
+    Utf8,
+    /// ISO-8859-1, where every byte is a code point.
+    Latin1,
+    Windows1252,
+}
+
+impl BodyCharset {
+    /// Returns the charset of a label such as `utf-8` or `iso-8859-1`.
+    pub fn from_label(label: &str) -> Option<Self> {
+        match label.trim().trim_matches('"').to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" | "us-ascii" | "ascii" => Some(Self::Utf8),
+            "iso-8859-1" | "iso8859-1" | "latin1" | "l1" => Some(Self::Latin1),
+            "windows-1252" | "cp1252" | "x-cp1252" => Some(Self::Windows1252),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Utf8 => "utf-8",
+            Self::Latin1 => "iso-8859-1",
+            Self::Windows1252 => "windows-1252",
+        }
+    }
+
+    /// Returns the charset of the `charset` parameter of a `Content-Type`
+    /// value.
+    pub fn from_content_type(value: &str) -> Option<Self> {
+        content_type_charset(value).and_then(Self::from_label)
+    }
+
+    /// Returns the charset of a `<meta charset>` or `<meta http-equiv>`
+    /// element in the start of an HTML document.
+    pub fn from_html_meta(data: &[u8]) -> Option<Self> {
+        html_meta_charset(data).and_then(|label| Self::from_label(&label))
+    }
+}
+
+/// Returns the label of the `charset` parameter of a `Content-Type` value.
+fn content_type_charset(value: &str) -> Option<&str> {
+    value.split(';').skip(1).find_map(|parameter| {
+        let (name, value) = parameter.split_once('=')?;
+
+        name.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches('"'))
+    })
+}
+
+/// Returns whether a `Content-Type` value is text or HTML.
+fn is_text_content_type(value: &str) -> bool {
+    let essence = value.split(';').next().unwrap_or_default().trim();
+
+    essence
+        .get(..5)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("text/"))
+        || essence.eq_ignore_ascii_case("application/xhtml+xml")
+}
+
+/// Returns the charset label of the first `<meta>` element declaring one.
+fn html_meta_charset(data: &[u8]) -> Option<String> {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let re = PATTERN.get_or_init(|| {
+        Regex::new(r#"(?i)<meta\s[^>]*charset\s*=\s*["']?([a-z0-9_:.-]+)"#).unwrap()
+    });
+
+    let text = String::from_utf8_lossy(&data[..data.len().min(SNIFF_LENGTH)]);
+
+    re.captures(&text).map(|captures| captures[1].to_string())
+}
+
+/// Passes response body data in a single pass through a SHA-256 digest
+/// to byte sinks, and through charset decoding to text sinks that receive
+/// UTF-8.
+///
+/// Handlers call [`Self::write_event`] with their session events.
+/// Decompression is done by the client before `ContentReceived` when
+/// [`Config::http_content_decoding`](super::Config::http_content_decoding)
+/// is enabled.
+pub struct BodyPipeline {
+    digest: Option<Sha256>,
+    sha256: Option<String>,
+    charset: Option<BodyCharset>,
+    detected_charset: Option<BodyCharset>,
+    unknown_charset: Option<String>,
+    /// Whether the response `Content-Type` is text, `None` without one.
+    text_content: Option<bool>,
+    sniff_charset: bool,
+    known_charset_only: bool,
+    /// Start of the body held back from text sinks until its charset
+    /// declaration is found.
+    sniff_buf: Option<Vec<u8>>,
+    sinks: Vec<Box<dyn BodySink>>,
+    text_sinks: Vec<Box<dyn BodySink>>,
+    incomplete: Vec<u8>,
+    bytes_written: u64,
+    finished: bool,
+}
+
+impl Default for BodyPipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BodyPipeline {
+    pub fn new() -> Self {
+        Self {
+            digest: None,
+            sha256: None,
+            charset: None,
+            detected_charset: None,
+            unknown_charset: None,
+            text_content: None,
+            sniff_charset: false,
+            known_charset_only: false,
+            sniff_buf: Some(Vec::new()),
+            sinks: Vec::new(),
+            text_sinks: Vec::new(),
+            incomplete: Vec::new(),
+            bytes_written: 0,
+            finished: false,
+        }
+    }
+
+    pub fn digest(&self) -> bool {
+        self.digest.is_some() || self.sha256.is_some()
+    }
+
+    /// Sets whether the SHA-256 digest of the body is computed.
+    pub fn set_digest(&mut self, enabled: bool) -> &mut Self {
+        self.digest = enabled.then(Sha256::new);
+        self
+    }
+
+    /// Charset of the body for text sinks.
+    ///
+    /// If `None`, the charset of the response `Content-Type` is used,
+    /// falling back to UTF-8.
+    pub fn charset(&self) -> Option<BodyCharset> {
+        self.charset
+    }
+
+    pub fn set_charset(&mut self, charset: Option<BodyCharset>) -> &mut Self {
+        self.charset = charset;
+        self
+    }
+
+    pub fn sniff_charset(&self) -> bool {
+        self.sniff_charset
+    }
+
+    /// Sets whether the start of the body is searched for an HTML `<meta>`
+    /// charset declaration if the `Content-Type` has no charset.
+    pub fn set_sniff_charset(&mut self, enabled: bool) -> &mut Self {
+        self.sniff_charset = enabled;
+        self
+    }
+
+    pub fn known_charset_only(&self) -> bool {
+        self.known_charset_only
+    }
+
+    /// Sets whether text sinks receive the body unchanged unless it is text
+    /// or HTML content with a known charset.
+    pub fn set_known_charset_only(&mut self, enabled: bool) -> &mut Self {
+        self.known_charset_only = enabled;
+        self
+    }
+
+    /// Charset that text sinks receive the body decoded from.
+    pub fn source_charset(&self) -> BodyCharset {
+        self.charset
+            .or(self.detected_charset)
+            .unwrap_or(BodyCharset::Utf8)
+    }
+
+    /// Charset that text sinks received the body decoded from, or `None` if
+    /// they received it unchanged as set by [`Self::set_known_charset_only`].
+    ///
+    /// Only final once the charset sniffing has ended.
+    pub fn decoded_charset(&self) -> Option<BodyCharset> {
+        if !self.known_charset_only {
+            Some(self.source_charset())
+        } else if self.text_content == Some(false) {
+            None
+        } else {
+            self.charset.or(self.detected_charset)
+        }
+    }
+
+    /// Charset label declared by the response that is not supported, in
+    /// which case text sinks receive the body decoded as UTF-8.
+    pub fn unknown_charset(&self) -> Option<&str> {
+        if self.charset.is_none() && self.detected_charset.is_none() {
+            self.unknown_charset.as_deref()
+        } else {
+            None
+        }
+    }
+
+    /// Adds a sink that receives the body bytes as is.
+    pub fn add_sink<S: BodySink + 'static>(&mut self, sink: S) -> &mut Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    /// Adds a sink that receives the body decoded into UTF-8 text.
+    ///
+    /// Invalid sequences are replaced with U+FFFD and a character is never
+    /// split between writes. See [`Self::set_known_charset_only`] for
+    /// receiving the body unchanged instead.
+    pub fn add_text_sink<S: BodySink + 'static>(&mut self, sink: S) -> &mut Self {
+        self.text_sinks.push(Box::new(sink));
+        self
+    }
+
+    /// Number of body bytes written to the pipeline.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Hex encoded SHA-256 digest of the body, once finished.
+    pub fn sha256(&self) -> Option<&str> {
+        self.sha256.as_deref()
+    }
+
+    /// Writes body data from `ContentReceived` events, picks up the
+    /// charset from the final response header, and finishes on `Finished`.
+    pub fn write_event(&mut self, event: &SessionEvent) -> Result<(), Error> {
+        match event {
+            SessionEvent::HttpResponse(_data, header) => {
+                let content_type = header.fields.content_type();
+
+                self.text_content = content_type.as_deref().map(is_text_content_type);
+                self.set_detected_charset(
+                    content_type
+                        .as_deref()
+                        .and_then(content_type_charset)
+                        .map(str::to_string),
+                );
+                Ok(())
+            }
+            SessionEvent::ContentReceived(data) => self.write(data),
+            SessionEvent::Finished(_) => self.finish(),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        self.bytes_written += data.len() as u64;
+
+        if let Some(digest) = &mut self.digest {
+            digest.update(data);
+        }
+
+        for sink in &mut self.sinks {
+            sink.write(data)?;
+        }
+
+        if self.text_sinks.is_empty() {
+            return Ok(());
+        }
+
+        if self.is_sniffing() {
+            let sniff_buf = self.sniff_buf.as_mut().unwrap();
+            sniff_buf.extend_from_slice(data);
+
+            if sniff_buf.len() < SNIFF_LENGTH {
+                return Ok(());
+            }
+
+            self.end_sniffing()
+        } else {
+            self.write_text(data)
+        }
+    }
+
+    fn is_sniffing(&self) -> bool {
+        self.sniff_charset
+            && self.text_content != Some(false)
+            && self.charset.is_none()
+            && self.detected_charset.is_none()
+            && self.sniff_buf.is_some()
+    }
+
+    /// Decodes the held back start of the body by the charset found in it.
+    fn end_sniffing(&mut self) -> Result<(), Error> {
+        let data = self.sniff_buf.take().unwrap_or_default();
+        self.set_detected_charset(html_meta_charset(&data));
+
+        self.write_text(&data)
+    }
+
+    fn set_detected_charset(&mut self, label: Option<String>) {
+        self.detected_charset = label.as_deref().and_then(BodyCharset::from_label);
+
+        if self.detected_charset.is_none() && label.is_some() {
+            self.unknown_charset = label;
+        }
+    }
+
+    fn write_text(&mut self, data: &[u8]) -> Result<(), Error> {
+        let Some(charset) = self.decoded_charset() else {
+            for sink in &mut self.text_sinks {
+                sink.write(data)?;
+            }
+
+            return Ok(());
+        };
+
+        let text = self.decode(charset, data);
+
+        if !text.is_empty() {
+            for sink in &mut self.text_sinks {
+                sink.write(text.as_bytes())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Flushes incomplete text, finalizes the digest and finishes the
+    /// sinks. Calling it again does nothing.
+    pub fn finish(&mut self) -> Result<(), Error> {
+        if self.finished {
+            return Ok(());
+        }
+
+        self.finished = true;
+
+        if self.is_sniffing() {
+            self.end_sniffing()?;
+        }
+
+        if let Some(digest) = self.digest.take() {
+            let digest = digest.finalize();
+            self.sha256 = Some(digest.iter().map(|byte| format!("{:02x}", byte)).collect());
+        }
+
+        if !self.incomplete.is_empty() {
+            self.incomplete.clear();
+
+            for sink in &mut self.text_sinks {
+                sink.write(char::REPLACEMENT_CHARACTER.to_string().as_bytes())?;
+            }
+        }
+
+        for sink in self.sinks.iter_mut().chain(self.text_sinks.iter_mut()) {
+            sink.finish()?;
+        }
+
+        Ok(())
+    }
+
+    fn decode(&mut self, charset: BodyCharset, data: &[u8]) -> String {
+        match charset {
+            BodyCharset::Utf8 => self.decode_utf8(data),
+            BodyCharset::Latin1 => data.iter().copied().map(char::from).collect(),
+            BodyCharset::Windows1252 => data
+                .iter()
+                .map(|&byte| match byte {
+                    0x80..=0x9f => WINDOWS_1252_C1[byte as usize - 0x80],
+                    _ => char::from(byte),
+                })
+                .collect(),
+        }
+    }
+
+    fn decode_utf8(&mut self, data: &[u8]) -> String {
+        self.incomplete.extend_from_slice(data);
+
+        let mut text = String::with_capacity(self.incomplete.len());
+        let mut remaining = self.incomplete.as_slice();
+
+        loop {
+            match std::str::from_utf8(remaining) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    remaining = &[];
+                    break;
+                }
+                Err(error) => {
+                    let (valid, rest) = remaining.split_at(error.valid_up_to());
+                    text.push_str(std::str::from_utf8(valid).unwrap());
+
+                    match error.error_len() {
+                        Some(length) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            remaining = &rest[length..];
+                        }
+                        None => {
+                            remaining = rest;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        self.incomplete = remaining.to_vec();
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_charset_from_content_type() {
+        assert_eq!(
+            BodyCharset::from_content_type("text/html; charset=\"UTF-8\""),
+            Some(BodyCharset::Utf8)
+        );
+        assert_eq!(
+            BodyCharset::from_content_type("text/plain;Charset=ISO-8859-1"),
+            Some(BodyCharset::Latin1)
+        );
+        assert_eq!(BodyCharset::from_content_type("text/plain"), None);
+        assert_eq!(
+            BodyCharset::from_content_type("text/plain; charset=shift_jis"),
+            None
+        );
+        assert_eq!(
+            BodyCharset::from_content_type("text/html; charset=cp1252"),
+            Some(BodyCharset::Windows1252)
+        );
+    }
+
+    #[test]
+    fn test_charset_from_html_meta() {
+        assert_eq!(
+            BodyCharset::from_html_meta(b"<html><head><META Charset='windows-1252'>"),
+            Some(BodyCharset::Windows1252)
+        );
+        assert_eq!(
+            BodyCharset::from_html_meta(
+                b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=ISO-8859-1\">"
+            ),
+            Some(BodyCharset::Latin1)
+        );
+        assert_eq!(BodyCharset::from_html_meta(b"<meta name=charset>"), None);
+        assert_eq!(BodyCharset::from_html_meta(b"<p>charset=utf-8</p>"), None);
+    }
+
+    #[test]
+    fn test_body_pipeline_sniff_charset() {
+        let text = Rc::new(RefCell::new(WriterSink(Vec::new())));
+
+        let mut pipeline = BodyPipeline::new();
+        pipeline.set_sniff_charset(true).add_text_sink(text.clone());
+
+        pipeline.write(b"<meta charset=windows-1252>").unwrap();
+        pipeline.write(b"\x93caf\xe9\x94").unwrap();
+
+        assert!(text.borrow().0.is_empty());
+
+        pipeline.finish().unwrap();
+
+        assert_eq!(pipeline.source_charset(), BodyCharset::Windows1252);
+        assert_eq!(
+            String::from_utf8(text.borrow().0.clone()).unwrap(),
+            "<meta charset=windows-1252>\u{201C}café\u{201D}"
+        );
+    }
+
+    #[test]
+    fn test_body_pipeline() {
+        let bytes = Rc::new(RefCell::new(WriterSink(Vec::new())));
+        let text = Rc::new(RefCell::new(WriterSink(Vec::new())));
+
+        let mut pipeline = BodyPipeline::new();
+        pipeline
+            .set_digest(true)
+            .add_sink(bytes.clone())
+            .add_text_sink(text.clone());
+
+        // "é€" with the euro sign split across writes, then an invalid byte
+        // and a truncated sequence at the end
+        pipeline.write(b"\xc3\xa9\xe2\x82").unwrap();
+        pipeline.write(b"\xac\xff").unwrap();
+        pipeline.write(b"a\xe2").unwrap();
+
+        assert_eq!(pipeline.sha256(), None);
+
+        pipeline.finish().unwrap();
+        pipeline.finish().unwrap();
+
+        assert_eq!(pipeline.bytes_written(), 8);
+        assert_eq!(bytes.borrow().0, b"\xc3\xa9\xe2\x82\xac\xffa\xe2");
+        assert_eq!(
+            String::from_utf8(text.borrow().0.clone()).unwrap(),
+            "é€\u{FFFD}a\u{FFFD}"
+        );
+        assert_eq!(
+            pipeline.sha256(),
+            Some(
+                Sha256::digest(b"\xc3\xa9\xe2\x82\xac\xffa\xe2")
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect::<String>()
+                    .as_str()
+            )
+        );
+    }
+
+    #[test]
+    fn test_body_pipeline_events() {
+        let text = Rc::new(RefCell::new(WriterSink(Vec::new())));
+
+        let mut pipeline = BodyPipeline::new();
+        pipeline.add_text_sink(text.clone());
+
+        let mut header = crate::http::ResponseHeader::new();
+        header
+            .fields
+            .append("Content-Type", "text/plain; charset=iso-8859-1");
+
+        pipeline
+            .write_event(&SessionEvent::HttpResponse(b"", header))
+            .unwrap();
+        pipeline
+            .write_event(&SessionEvent::ContentReceived(b"caf\xe9"))
+            .unwrap();
+        pipeline
+            .write_event(&SessionEvent::Finished(Default::default()))
+            .unwrap();
+
+        assert!(!pipeline.digest());
+        assert_eq!(pipeline.sha256(), None);
+        assert_eq!(pipeline.unknown_charset(), None);
+        assert_eq!(String::from_utf8(text.borrow().0.clone()).unwrap(), "café");
+    }
+
+    #[test]
+    fn test_body_pipeline_known_charset_only() {
+        let run = |content_type: Option<&str>, data: &[u8]| {
+            let text = Rc::new(RefCell::new(WriterSink(Vec::new())));
+
+            let mut pipeline = BodyPipeline::new();
+            pipeline
+                .set_known_charset_only(true)
+                .set_sniff_charset(true)
+                .add_text_sink(text.clone());
+
+            let mut header = crate::http::ResponseHeader::new();
+
+            if let Some(content_type) = content_type {
+                header.fields.append("Content-Type", content_type);
+            }
+
+            pipeline
+                .write_event(&SessionEvent::HttpResponse(b"", header))
+                .unwrap();
+            pipeline.write(data).unwrap();
+            pipeline.finish().unwrap();
+
+            let output = text.borrow().0.clone();
+            (pipeline.decoded_charset(), output)
+        };
+
+        assert_eq!(
+            run(Some("text/plain; charset=latin1"), b"caf\xe9"),
+            (Some(BodyCharset::Latin1), "café".as_bytes().to_vec())
+        );
+        assert_eq!(
+            run(Some("image/png; charset=utf-8"), b"\x89PNG\xff"),
+            (None, b"\x89PNG\xff".to_vec())
+        );
+        assert_eq!(
+            run(Some("text/plain; charset=shift_jis"), b"\x82\xa0"),
+            (None, b"\x82\xa0".to_vec())
+        );
+        assert_eq!(
+            run(Some("text/html"), b"<p>caf\xe9"),
+            (None, b"<p>caf\xe9".to_vec())
+        );
+        assert_eq!(
+            run(None, b"<meta charset=cp1252>\x93"),
+            (
+                Some(BodyCharset::Windows1252),
+                "<meta charset=cp1252>\u{201C}".as_bytes().to_vec()
+            )
+        );
+    }
+
+    #[test]
+    fn test_body_pipeline_unknown_charset() {
+        let mut pipeline = BodyPipeline::new();
+        pipeline.add_text_sink(WriterSink(Vec::new()));
+
+        let mut header = crate::http::ResponseHeader::new();
+        header
+            .fields
+            .append("Content-Type", "text/plain; charset=\"Shift_JIS\"");
+
+        pipeline
+            .write_event(&SessionEvent::HttpResponse(b"", header))
+            .unwrap();
+
+        assert_eq!(pipeline.source_charset(), BodyCharset::Utf8);
+        assert_eq!(pipeline.unknown_charset(), Some("Shift_JIS"));
+
+        pipeline.set_charset(Some(BodyCharset::Latin1));
+
+        assert_eq!(pipeline.unknown_charset(), None);
+    }
+}
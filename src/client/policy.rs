@@ -0,0 +1,618 @@
+use std::{
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+use url::{Host, Url};
+
+use crate::error::Error;
+
+use super::Config;
+
+/// Restrictions on the URLs and addresses that requests may use, such as
+/// when fetching URLs from an untrusted list.
+///
+/// Addresses are checked after host names are resolved, so a host name
+/// can't be used to reach a blocked address. When a proxy is used, the
+/// proxy resolves host names and only addresses written in URLs are
+/// checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestPolicy {
+    allowed_schemes: Vec<String>,
+    allow_loopback: bool,
+    allow_private: bool,
+    allow_link_local: bool,
+}
+
+impl Default for RequestPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RequestPolicy {
+    /// Returns a policy that allows HTTP and HTTPS to public addresses.
+    pub fn new() -> Self {
+        Self {
+            allowed_schemes: vec!["http".to_string(), "https".to_string()],
+            allow_loopback: false,
+            allow_private: false,
+            allow_link_local: false,
+        }
+    }
+
+    pub fn allowed_schemes(&self) -> &[String] {
+        &self.allowed_schemes
+    }
+
+    pub fn set_allowed_schemes(&mut self, schemes: Vec<String>) -> &mut Self {
+        self.allowed_schemes = schemes
+            .into_iter()
+            .map(|scheme| scheme.to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    /// Whether loopback and unspecified addresses are allowed.
+    pub fn allow_loopback(&self) -> bool {
+        self.allow_loopback
+    }
+
+    pub fn set_allow_loopback(&mut self, allowed: bool) -> &mut Self {
+        self.allow_loopback = allowed;
+        self
+    }
+
+    /// Whether private (RFC 1918), shared (RFC 6598), and unique local IPv6
+    /// addresses are allowed.
+    pub fn allow_private(&self) -> bool {
+        self.allow_private
+    }
+
+    pub fn set_allow_private(&mut self, allowed: bool) -> &mut Self {
+        self.allow_private = allowed;
+        self
+    }
+
+    /// Whether link-local addresses, which include cloud metadata services,
+    /// are allowed.
+    pub fn allow_link_local(&self) -> bool {
+        self.allow_link_local
+    }
+
+    pub fn set_allow_link_local(&mut self, allowed: bool) -> &mut Self {
+        self.allow_link_local = allowed;
+        self
+    }
+
+    /// Checks the scheme of the URL and its host if it is an address.
+    pub fn check_url(&self, url: &Url) -> Result<(), Error> {
+        if !self
+            .allowed_schemes
+            .iter()
+            .any(|scheme| scheme == url.scheme())
+        {
+            return Err(Error::Blocked {
+                value: url.scheme().to_string(),
+                reason: "scheme not allowed".to_string(),
+            });
+        }
+
+        match url.host() {
+            Some(Host::Ipv4(address)) => self.check_address(address.into()),
+            Some(Host::Ipv6(address)) => self.check_address(address.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks an address that a host name resolved to.
+    ///
+    /// IPv4 addresses embedded in IPv6 addresses (IPv4-mapped,
+    /// IPv4-compatible, NAT64, and 6to4) are checked as IPv4. Multicast,
+    /// broadcast, and reserved addresses are always blocked.
+    pub fn check_address(&self, address: IpAddr) -> Result<(), Error> {
+        let reason = match AddressClass::of(address) {
+            Some(AddressClass::Loopback) if !self.allow_loopback => "loopback address",
+            Some(AddressClass::Private) if !self.allow_private => "private address",
+            Some(AddressClass::LinkLocal) if !self.allow_link_local => "link-local address",
+            Some(AddressClass::Reserved) => "multicast or reserved address",
+            _ => return Ok(()),
+        };
+
+        Err(Error::Blocked {
+            value: address.to_string(),
+            reason: reason.to_string(),
+        })
+    }
+}
+
+/// Host name or address pattern of a host allowlist or denylist.
+///
+/// Patterns are parsed from strings such as `example.com`,
+/// `*.example.com`, `192.0.2.1`, or `192.0.2.0/24`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostPattern {
+    /// Host name that matches itself only.
+    Name(String),
+    /// Parent domain whose subdomains match, but not the domain itself.
+    Subdomains(String),
+    /// Network of addresses in CIDR notation, which matches addresses in
+    /// URLs and addresses that host names resolve to.
+    Network { address: IpAddr, prefix_length: u8 },
+}
+
+impl HostPattern {
+    /// Whether the host name of a URL matches a name pattern.
+    pub fn matches_name(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.');
+
+        match self {
+            Self::Name(name) => host.eq_ignore_ascii_case(name),
+            Self::Subdomains(parent) => {
+                host.len() > parent.len() + 1 && {
+                    let (subdomain, domain) = host.split_at(host.len() - parent.len());
+                    subdomain.ends_with('.') && domain.eq_ignore_ascii_case(parent)
+                }
+            }
+            Self::Network { .. } => false,
+        }
+    }
+
+    /// Whether the address is in a network pattern.
+    pub fn matches_address(&self, address: IpAddr) -> bool {
+        let Self::Network {
+            address: network,
+            prefix_length,
+        } = self
+        else {
+            return false;
+        };
+
+        let address = match address {
+            IpAddr::V6(address) => address
+                .to_ipv4_mapped()
+                .map_or(IpAddr::V6(address), IpAddr::V4),
+            address => address,
+        };
+
+        match (network, address) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => (u32::from(*network)
+                ^ u32::from(address))
+            .checked_shr(32 - *prefix_length as u32)
+            .is_none_or(|bits| bits == 0),
+            (IpAddr::V6(network), IpAddr::V6(address)) => (u128::from(*network)
+                ^ u128::from(address))
+            .checked_shr(128 - *prefix_length as u32)
+            .is_none_or(|bits| bits == 0),
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for HostPattern {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| Error::InvalidArgument {
+            value: s.to_string(),
+            reason: reason.to_string(),
+        };
+
+        if let Some((address, prefix_length)) = s.split_once('/') {
+            let address = address
+                .parse::<IpAddr>()
+                .map_err(|_| invalid("invalid network address"))?;
+            let max_length = if address.is_ipv4() { 32 } else { 128 };
+            let prefix_length = prefix_length
+                .parse::<u8>()
+                .ok()
+                .filter(|length| *length <= max_length)
+                .ok_or_else(|| invalid("invalid network prefix length"))?;
+
+            return Ok(Self::Network {
+                address,
+                prefix_length,
+            });
+        }
+
+        let (subdomains, name) = match s.strip_prefix("*.") {
+            Some(name) => (true, name),
+            None => (false, s),
+        };
+
+        let name = match Host::parse(name.trim_end_matches('.')) {
+            Ok(Host::Domain(name)) => name,
+            Ok(Host::Ipv4(address)) if !subdomains => {
+                return Ok(Self::Network {
+                    address: address.into(),
+                    prefix_length: 32,
+                })
+            }
+            Ok(Host::Ipv6(address)) if !subdomains => {
+                return Ok(Self::Network {
+                    address: address.into(),
+                    prefix_length: 128,
+                })
+            }
+            _ => match name.parse::<Ipv6Addr>() {
+                Ok(address) if !subdomains => {
+                    return Ok(Self::Network {
+                        address: address.into(),
+                        prefix_length: 128,
+                    })
+                }
+                _ => return Err(invalid("invalid host pattern")),
+            },
+        };
+
+        if subdomains {
+            Ok(Self::Subdomains(name))
+        } else {
+            Ok(Self::Name(name))
+        }
+    }
+}
+
+impl Display for HostPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Name(name) => write!(f, "{}", name),
+            Self::Subdomains(parent) => write!(f, "*.{}", parent),
+            Self::Network {
+                address,
+                prefix_length,
+            } => write!(f, "{}/{}", address, prefix_length),
+        }
+    }
+}
+
+/// Host allowlist and denylist of a config.
+#[derive(Debug, Clone)]
+pub(crate) struct HostLists {
+    allowlist: Option<Vec<HostPattern>>,
+    denylist: Vec<HostPattern>,
+}
+
+impl HostLists {
+    /// Returns the lists of the config, or `None` if it has no lists.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        if config.host_allowlist().is_none() && config.host_denylist().is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            allowlist: config.host_allowlist().map(<[HostPattern]>::to_vec),
+            denylist: config.host_denylist().to_vec(),
+        })
+    }
+
+    /// Checks the host of the URL.
+    ///
+    /// A host name that isn't in the allowlist by name is passed if
+    /// `check_addresses` is set and the allowlist has networks, which its
+    /// resolved addresses are then checked against.
+    pub fn check_url(&self, url: &Url, check_addresses: bool) -> Result<(), Error> {
+        let domain = match url.host() {
+            Some(Host::Domain(domain)) => domain,
+            Some(Host::Ipv4(address)) => return self.check_address("", address.into()),
+            Some(Host::Ipv6(address)) => return self.check_address("", address.into()),
+            None => return Ok(()),
+        };
+
+        if self
+            .denylist
+            .iter()
+            .any(|pattern| pattern.matches_name(domain))
+        {
+            return Err(Error::Blocked {
+                value: domain.to_string(),
+                reason: "host in denylist".to_string(),
+            });
+        }
+
+        match &self.allowlist {
+            Some(allowlist)
+                if !allowlist.iter().any(|pattern| {
+                    pattern.matches_name(domain)
+                        || check_addresses && matches!(pattern, HostPattern::Network { .. })
+                }) =>
+            {
+                Err(Error::Blocked {
+                    value: domain.to_string(),
+                    reason: "host not in allowlist".to_string(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks an address of the URL host, which is an empty string if the
+    /// host is an address.
+    pub fn check_address(&self, host: &str, address: IpAddr) -> Result<(), Error> {
+        if self
+            .denylist
+            .iter()
+            .any(|pattern| pattern.matches_address(address))
+        {
+            return Err(Error::Blocked {
+                value: address.to_string(),
+                reason: "address in denylist".to_string(),
+            });
+        }
+
+        match &self.allowlist {
+            Some(allowlist)
+                if !allowlist.iter().any(|pattern| {
+                    pattern.matches_name(host) || pattern.matches_address(address)
+                }) =>
+            {
+                Err(Error::Blocked {
+                    value: address.to_string(),
+                    reason: "address not in allowlist".to_string(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AddressClass {
+    Loopback,
+    Private,
+    LinkLocal,
+    Reserved,
+}
+
+impl AddressClass {
+    fn of(address: IpAddr) -> Option<Self> {
+        match address {
+            IpAddr::V4(address) => Self::of_ipv4(address),
+            IpAddr::V6(address) => match embedded_ipv4(address) {
+                Some(address) => Self::of_ipv4(address),
+                None => Self::of_ipv6(address),
+            },
+        }
+    }
+
+    fn of_ipv4(address: Ipv4Addr) -> Option<Self> {
+        let [first, second, ..] = address.octets();
+
+        // 0.0.0.0/8 reaches the local host on some systems
+        if address.is_loopback() || first == 0 {
+            Some(Self::Loopback)
+        } else if address.is_private() || (first == 100 && (64..128).contains(&second)) {
+            Some(Self::Private)
+        } else if address.is_link_local() {
+            Some(Self::LinkLocal)
+        } else if address.is_multicast() || first >= 240 {
+            // 240.0.0.0/4 includes the broadcast address
+            Some(Self::Reserved)
+        } else {
+            None
+        }
+    }
+
+    fn of_ipv6(address: Ipv6Addr) -> Option<Self> {
+        if address.is_loopback() || address.is_unspecified() {
+            Some(Self::Loopback)
+        } else if address.is_unique_local() || (address.segments()[0] & 0xffc0) == 0xfec0 {
+            // fec0::/10 is the deprecated site-local range
+            Some(Self::Private)
+        } else if address.is_unicast_link_local() {
+            Some(Self::LinkLocal)
+        } else if address.is_multicast() {
+            Some(Self::Reserved)
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns the IPv4 address that an IPv6 address translates or tunnels to.
+///
+/// `::` and `::1` are returned as `0.0.0.0` and `0.0.0.1`, which are
+/// classified as loopback all the same.
+fn embedded_ipv4(address: Ipv6Addr) -> Option<Ipv4Addr> {
+    let segments = address.segments();
+    let octets = address.octets();
+
+    if let Some(address) = address.to_ipv4() {
+        // IPv4-mapped or IPv4-compatible
+        Some(address)
+    } else if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        // NAT64 well-known prefix
+        Some(Ipv4Addr::new(
+            octets[12], octets[13], octets[14], octets[15],
+        ))
+    } else if segments[0] == 0x2002 {
+        // 6to4
+        Some(Ipv4Addr::new(octets[2], octets[3], octets[4], octets[5]))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_address() {
+        let policy = RequestPolicy::new();
+
+        for address in [
+            "127.0.0.1",
+            "0.0.0.0",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "100.64.0.1",
+            "169.254.169.254",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::127.0.0.1",
+            "::10.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+            "2002:7f00:1::1",
+            "2002:c0a8:101::1",
+            "224.0.0.1",
+            "239.255.255.250",
+            "255.255.255.255",
+            "240.0.0.1",
+            "ff02::1",
+            "fec0::1",
+        ] {
+            assert!(
+                policy.check_address(address.parse().unwrap()).is_err(),
+                "{}",
+                address
+            );
+        }
+
+        for address in [
+            "93.184.216.34",
+            "172.32.0.1",
+            "2606:4700::1111",
+            "64:ff9b::5db8:d822",
+            "2002:5db8:d822::1",
+        ] {
+            assert!(
+                policy.check_address(address.parse().unwrap()).is_ok(),
+                "{}",
+                address
+            );
+        }
+
+        let mut policy = RequestPolicy::new();
+        policy.set_allow_loopback(true);
+
+        assert!(policy.check_address("127.0.0.1".parse().unwrap()).is_ok());
+        assert!(policy.check_address("::127.0.0.1".parse().unwrap()).is_ok());
+        assert!(policy.check_address("10.0.0.1".parse().unwrap()).is_err());
+        assert!(policy.check_address("224.0.0.1".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_host_pattern() {
+        let pattern = "*.Example.com.".parse::<HostPattern>().unwrap();
+        assert_eq!(pattern, HostPattern::Subdomains("example.com".to_string()));
+        assert!(pattern.matches_name("www.example.com"));
+        assert!(pattern.matches_name("a.b.EXAMPLE.com."));
+        assert!(!pattern.matches_name("example.com"));
+        assert!(!pattern.matches_name("badexample.com"));
+
+        let pattern = "example.com".parse::<HostPattern>().unwrap();
+        assert!(pattern.matches_name("example.com"));
+        assert!(!pattern.matches_name("www.example.com"));
+
+        let pattern = "192.0.2.0/24".parse::<HostPattern>().unwrap();
+        assert!(pattern.matches_address("192.0.2.200".parse().unwrap()));
+        assert!(pattern.matches_address("::ffff:192.0.2.1".parse().unwrap()));
+        assert!(!pattern.matches_address("192.0.3.1".parse().unwrap()));
+        assert!(!pattern.matches_name("192.0.2.1"));
+
+        let pattern = "2001:db8::/32".parse::<HostPattern>().unwrap();
+        assert!(pattern.matches_address("2001:db8:1::1".parse().unwrap()));
+        assert!(!pattern.matches_address("2001:db9::1".parse().unwrap()));
+
+        assert!("0.0.0.0/0"
+            .parse::<HostPattern>()
+            .unwrap()
+            .matches_address("203.0.113.1".parse().unwrap()));
+        assert_eq!("::1".parse::<HostPattern>().unwrap().to_string(), "::1/128");
+        assert_eq!(
+            "[::1]".parse::<HostPattern>().unwrap().to_string(),
+            "::1/128"
+        );
+
+        for value in [
+            "",
+            "*.",
+            "*.192.0.2.1",
+            "192.0.2.0/33",
+            "example.com/8",
+            "a b",
+        ] {
+            assert!(value.parse::<HostPattern>().is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_host_lists() {
+        let mut config = Config::new();
+        assert!(HostLists::from_config(&config).is_none());
+
+        config
+            .set_host_allowlist(Some(vec![
+                "example.com".parse().unwrap(),
+                "10.0.0.0/8".parse().unwrap(),
+            ]))
+            .set_host_denylist(vec!["10.0.0.1".parse().unwrap()]);
+        let hosts = HostLists::from_config(&config).unwrap();
+
+        let url = "https://example.com/".parse().unwrap();
+        assert!(hosts.check_url(&url, true).is_ok());
+        assert!(hosts
+            .check_address("example.com", "203.0.113.1".parse().unwrap())
+            .is_ok());
+        assert!(hosts
+            .check_address("example.com", "10.0.0.1".parse().unwrap())
+            .is_err());
+
+        let url = "https://example.net/".parse().unwrap();
+        assert!(hosts.check_url(&url, true).is_ok());
+        assert!(hosts.check_url(&url, false).is_err());
+        assert!(hosts
+            .check_address("example.net", "10.1.2.3".parse().unwrap())
+            .is_ok());
+        assert!(hosts
+            .check_address("example.net", "203.0.113.1".parse().unwrap())
+            .is_err());
+
+        assert!(hosts
+            .check_url(&"http://10.1.2.3/".parse().unwrap(), false)
+            .is_ok());
+        assert!(hosts
+            .check_url(&"http://10.0.0.1/".parse().unwrap(), false)
+            .is_err());
+
+        config.set_host_allowlist(None);
+        let hosts = HostLists::from_config(&config).unwrap();
+
+        assert!(hosts
+            .check_url(&"http://example.net/".parse().unwrap(), false)
+            .is_ok());
+        assert!(hosts
+            .check_url(&"http://10.0.0.1/".parse().unwrap(), false)
+            .is_err());
+    }
+
+    #[test]
+    fn test_check_url() {
+        let mut policy = RequestPolicy::new();
+
+        assert!(policy
+            .check_url(&"https://example.com/".parse().unwrap())
+            .is_ok());
+        assert!(policy
+            .check_url(&"ftp://example.com/".parse().unwrap())
+            .is_err());
+        assert!(policy
+            .check_url(&"http://[::1]:8080/".parse().unwrap())
+            .is_err());
+
+        policy.set_allowed_schemes(vec!["FTP".to_string()]);
+
+        assert!(policy
+            .check_url(&"ftp://example.com/".parse().unwrap())
+            .is_ok());
+        assert!(policy
+            .check_url(&"http://example.com/".parse().unwrap())
+            .is_err());
+    }
+}
@@ -0,0 +1,105 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::error::Error;
+
+/// Request headers of a web browser that a client can impersonate.
+///
+/// Each profile mimics a specific browser version returned by
+/// [`HeaderProfile::browser_version()`]. Accept-Encoding is left to the
+/// transfer backend so that responses can be decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum HeaderProfile {
+    /// Chrome on Windows.
+    Chrome,
+    /// Firefox on Windows.
+    Firefox,
+    /// Safari on macOS.
+    Safari,
+}
+
+impl HeaderProfile {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Chrome => "chrome",
+            Self::Firefox => "firefox",
+            Self::Safari => "safari",
+        }
+    }
+
+    /// Version of the browser the profile was captured from.
+    pub fn browser_version(&self) -> &'static str {
+        match self {
+            Self::Chrome => "116",
+            Self::Firefox => "117",
+            Self::Safari => "16.6",
+        }
+    }
+
+    pub fn user_agent(&self) -> &'static str {
+        match self {
+            Self::Chrome => "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/116.0.0.0 Safari/537.36",
+            Self::Firefox => "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:109.0) Gecko/20100101 Firefox/117.0",
+            Self::Safari => "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/16.6 Safari/605.1.15",
+        }
+    }
+
+    /// Header fields of a top-level navigation in the order the browser
+    /// sends them, excluding Host.
+    ///
+    /// The User-Agent field has an empty value and stands for its position.
+    pub fn http_headers(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Self::Chrome => &[
+                ("sec-ch-ua", "\"Chromium\";v=\"116\", \"Not)A;Brand\";v=\"24\", \"Google Chrome\";v=\"116\""),
+                ("sec-ch-ua-mobile", "?0"),
+                ("sec-ch-ua-platform", "\"Windows\""),
+                ("Upgrade-Insecure-Requests", "1"),
+                ("User-Agent", ""),
+                ("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,image/apng,*/*;q=0.8,application/signed-exchange;v=b3;q=0.7"),
+                ("Sec-Fetch-Site", "none"),
+                ("Sec-Fetch-Mode", "navigate"),
+                ("Sec-Fetch-User", "?1"),
+                ("Sec-Fetch-Dest", "document"),
+                ("Accept-Language", "en-US,en;q=0.9"),
+            ],
+            Self::Firefox => &[
+                ("User-Agent", ""),
+                ("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,*/*;q=0.8"),
+                ("Accept-Language", "en-US,en;q=0.5"),
+                ("Upgrade-Insecure-Requests", "1"),
+                ("Sec-Fetch-Dest", "document"),
+                ("Sec-Fetch-Mode", "navigate"),
+                ("Sec-Fetch-Site", "none"),
+                ("Sec-Fetch-User", "?1"),
+            ],
+            Self::Safari => &[
+                ("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"),
+                ("User-Agent", ""),
+                ("Accept-Language", "en-US,en;q=0.9"),
+            ],
+        }
+    }
+}
+
+impl Display for HeaderProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for HeaderProfile {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "chrome" => Ok(Self::Chrome),
+            "firefox" => Ok(Self::Firefox),
+            "safari" => Ok(Self::Safari),
+            _ => Err(Error::InvalidArgument {
+                value: s.to_string(),
+                reason: "unknown header profile".to_string(),
+            }),
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Time span of the samples that the current rate is computed from.
+const RATE_WINDOW: Duration = Duration::from_secs(3);
+
+/// Transfer rates of one direction in bytes per second.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TransferRate {
+    /// Rate over the last few seconds.
+    pub current: f64,
+    /// Rate since the start of the transfer.
+    pub average: f64,
+}
+
+/// Computes transfer rates from progress samples.
+#[derive(Debug)]
+pub struct ProgressMeter {
+    start: Instant,
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl ProgressMeter {
+    pub fn new(start: Instant) -> Self {
+        Self {
+            start,
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Adds the byte count at the given time and returns the rates.
+    pub fn update(&mut self, now: Instant, bytes: u64) -> TransferRate {
+        self.samples.push_back((now, bytes));
+
+        while self.samples.len() > 2
+            && self
+                .samples
+                .get(1)
+                .is_some_and(|(time, _)| now.duration_since(*time) >= RATE_WINDOW)
+        {
+            self.samples.pop_front();
+        }
+
+        let (oldest_time, oldest_bytes) = self.samples[0];
+
+        TransferRate {
+            current: rate(
+                bytes.saturating_sub(oldest_bytes),
+                now.duration_since(oldest_time),
+            ),
+            average: rate(bytes, now.duration_since(self.start)),
+        }
+    }
+}
+
+fn rate(bytes: u64, duration: Duration) -> f64 {
+    if duration.is_zero() {
+        0.0
+    } else {
+        bytes as f64 / duration.as_secs_f64()
+    }
+}
+
+/// Estimates the time remaining from the current rate, or the average rate
+/// if nothing was transferred recently.
+pub fn estimate_remaining(total: u64, current: u64, rate: TransferRate) -> Option<Duration> {
+    if total == 0 || current > total {
+        return None;
+    }
+
+    let rate = if rate.current > 0.0 {
+        rate.current
+    } else {
+        rate.average
+    };
+
+    if rate > 0.0 {
+        Some(Duration::from_secs_f64((total - current) as f64 / rate))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_meter() {
+        let start = Instant::now();
+        let mut meter = ProgressMeter::new(start);
+
+        assert_eq!(meter.update(start, 0), TransferRate::default());
+
+        let rate = meter.update(start + Duration::from_secs(1), 1000);
+        assert_eq!(rate.current, 1000.0);
+        assert_eq!(rate.average, 1000.0);
+
+        meter.update(start + Duration::from_secs(2), 2000);
+        meter.update(start + Duration::from_secs(4), 2000);
+        let rate = meter.update(start + Duration::from_secs(5), 2000);
+        assert_eq!(rate.current, 0.0);
+        assert_eq!(rate.average, 400.0);
+    }
+
+    #[test]
+    fn test_estimate_remaining() {
+        let rate = TransferRate {
+            current: 100.0,
+            average: 50.0,
+        };
+
+        assert_eq!(
+            estimate_remaining(1000, 500, rate),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            estimate_remaining(
+                1000,
+                500,
+                TransferRate {
+                    current: 0.0,
+                    ..rate
+                }
+            ),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(estimate_remaining(0, 500, rate), None);
+        assert_eq!(estimate_remaining(1000, 0, TransferRate::default()), None);
+    }
+}
@@ -0,0 +1,96 @@
+use crate::{
+    error::BoxedError,
+    http::{ResponseHeader, ResponseTrailer},
+};
+
+use super::{EventFilter, EventKind, SessionControl, SessionEvent, SessionHandler, TransferStats};
+
+/// Response of a transfer with its content buffered in memory.
+///
+/// Returned by [`super::Client::get`] and [`super::Client::fetch`].
+#[derive(Debug, Clone, Default)]
+pub struct Response {
+    header: Option<ResponseHeader>,
+    trailer: Option<ResponseTrailer>,
+    body: Vec<u8>,
+    transfer_stats: Option<TransferStats>,
+}
+
+impl Response {
+    /// Final HTTP response header, `None` for FTP.
+    pub fn header(&self) -> Option<&ResponseHeader> {
+        self.header.as_ref()
+    }
+
+    pub fn status_code(&self) -> Option<u16> {
+        self.header.as_ref().map(|header| header.status_code)
+    }
+
+    pub fn trailer(&self) -> Option<&ResponseTrailer> {
+        self.trailer.as_ref()
+    }
+
+    /// Content without its transfer coding, and without its content coding
+    /// if [`super::Config::http_content_decoding`] is enabled.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn into_body(self) -> Vec<u8> {
+        self.body
+    }
+
+    pub fn transfer_stats(&self) -> Option<&TransferStats> {
+        self.transfer_stats.as_ref()
+    }
+}
+
+/// Handler that collects a [`Response`].
+#[derive(Debug, Default)]
+pub(crate) struct ResponseHandler {
+    response: Response,
+}
+
+impl ResponseHandler {
+    pub fn into_response(self) -> Response {
+        self.response
+    }
+}
+
+impl SessionHandler for ResponseHandler {
+    fn event(
+        &mut self,
+        _control: &mut dyn SessionControl,
+        event: SessionEvent,
+    ) -> Result<(), BoxedError> {
+        match event {
+            SessionEvent::HttpResponse(_, header) => {
+                // Only the content of the last response, such as after an
+                // authentication challenge, is kept
+                self.response.header = Some(header);
+                self.response.trailer = None;
+                self.response.body.clear();
+            }
+            SessionEvent::HttpResponseTrailer(_, trailer) => {
+                self.response.trailer = Some(trailer);
+            }
+            SessionEvent::ContentReceived(data) => {
+                self.response.body.extend_from_slice(data);
+            }
+            SessionEvent::Finished(stats) => {
+                self.response.transfer_stats = Some(stats);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn event_filter(&self) -> EventFilter {
+        EventFilter::none()
+            .with(EventKind::HttpResponse)
+            .with(EventKind::HttpResponseTrailer)
+            .with(EventKind::ContentReceived)
+            .with(EventKind::Finished)
+    }
+}
@@ -0,0 +1,331 @@
+use std::{
+    cell::RefCell,
+    ffi::{c_int, c_void},
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+};
+
+use curl::easy::Easy;
+
+use crate::error::Error;
+
+use super::{policy::HostLists, RequestPolicy};
+
+// Not defined by curl-sys
+const CURL_SOCKOPT_OK: c_int = 0;
+const CURL_SOCKOPT_ERROR: c_int = 1;
+
+/// Options applied to sockets as curl creates them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SocketOptions {
+    pub tos: Option<u8>,
+    pub mark: Option<u32>,
+}
+
+impl SocketOptions {
+    pub fn is_empty(&self) -> bool {
+        self.tos.is_none() && self.mark.is_none()
+    }
+}
+
+/// Installs the socket option callback on the handle.
+///
+/// The options must outlive any transfer performed with the handle until
+/// the handle is reset.
+pub fn set_sockopt_function(curl_handle: &mut Easy, options: &SocketOptions) -> Result<(), Error> {
+    if options.mark.is_some() && !cfg!(target_os = "linux") {
+        return Err(Error::UnsupportedFeature {
+            feature: "socket mark".to_string(),
+        });
+    }
+
+    if options.tos.is_some() && !cfg!(unix) {
+        return Err(Error::UnsupportedFeature {
+            feature: "IP type of service".to_string(),
+        });
+    }
+
+    let callback: extern "C" fn(*mut c_void, curl_sys::curl_socket_t, c_int) -> c_int =
+        sockopt_callback;
+
+    // SAFETY: The callback matches curl_sockopt_callback and the data
+    // pointer is only read by the callback while the options are alive.
+    let code = unsafe {
+        let code = curl_sys::curl_easy_setopt(
+            curl_handle.raw(),
+            curl_sys::CURLOPT_SOCKOPTFUNCTION,
+            callback,
+        );
+
+        if code == curl_sys::CURLE_OK {
+            curl_sys::curl_easy_setopt(
+                curl_handle.raw(),
+                curl_sys::CURLOPT_SOCKOPTDATA,
+                options as *const SocketOptions as *mut c_void,
+            )
+        } else {
+            code
+        }
+    };
+
+    if code == curl_sys::CURLE_OK {
+        Ok(())
+    } else {
+        Err(curl::Error::new(code).into())
+    }
+}
+
+extern "C" fn sockopt_callback(
+    data: *mut c_void,
+    socket: curl_sys::curl_socket_t,
+    _purpose: c_int,
+) -> c_int {
+    // SAFETY: The pointer was given by set_sockopt_function.
+    let options = unsafe { &*(data as *const SocketOptions) };
+
+    match apply_options(socket, options) {
+        Ok(_) => CURL_SOCKOPT_OK,
+        Err(error) => {
+            tracing::warn!(%error, "socket option");
+            CURL_SOCKOPT_ERROR
+        }
+    }
+}
+
+/// Address checks applied as curl opens sockets, which also records the
+/// addresses that connections are attempted to.
+#[derive(Debug)]
+pub struct SocketPolicy {
+    pub policy: Option<RequestPolicy>,
+    pub hosts: Option<HostLists>,
+    /// Host name of the request URL, which the host allowlist may match.
+    pub host: String,
+    /// Error of the last address that was refused.
+    pub blocked: RefCell<Option<Error>>,
+    /// Addresses of opened sockets not yet taken by the session.
+    pub opened: Arc<Mutex<Vec<SocketAddr>>>,
+}
+
+impl SocketPolicy {
+    fn check_address(&self, address: IpAddr) -> Result<(), Error> {
+        if let Some(policy) = &self.policy {
+            policy.check_address(address)?;
+        }
+        if let Some(hosts) = &self.hosts {
+            hosts.check_address(&self.host, address)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Installs the socket open callback on the handle, which refuses to open
+/// sockets for addresses not allowed by the policy and records the others.
+///
+/// The policy must outlive any transfer performed with the handle until
+/// the handle is reset.
+pub fn set_opensocket_function(curl_handle: &mut Easy, policy: &SocketPolicy) -> Result<(), Error> {
+    if !cfg!(unix) {
+        return Err(Error::UnsupportedFeature {
+            feature: "request policy addresses".to_string(),
+        });
+    }
+
+    let callback: curl_sys::curl_opensocket_callback = opensocket_callback;
+
+    // SAFETY: The callback matches curl_opensocket_callback and the data
+    // pointer is only read by the callback while the policy is alive.
+    let code = unsafe {
+        let code = curl_sys::curl_easy_setopt(
+            curl_handle.raw(),
+            curl_sys::CURLOPT_OPENSOCKETFUNCTION,
+            callback,
+        );
+
+        if code == curl_sys::CURLE_OK {
+            curl_sys::curl_easy_setopt(
+                curl_handle.raw(),
+                curl_sys::CURLOPT_OPENSOCKETDATA,
+                policy as *const SocketPolicy as *mut c_void,
+            )
+        } else {
+            code
+        }
+    };
+
+    if code == curl_sys::CURLE_OK {
+        Ok(())
+    } else {
+        Err(curl::Error::new(code).into())
+    }
+}
+
+extern "C" fn opensocket_callback(
+    data: *mut c_void,
+    _purpose: curl_sys::curlsocktype,
+    address: *mut curl_sys::curl_sockaddr,
+) -> curl_sys::curl_socket_t {
+    // SAFETY: The pointer was given by set_opensocket_function.
+    let policy = unsafe { &*(data as *const SocketPolicy) };
+    // SAFETY: curl gives a valid address for the duration of the callback.
+    let address = unsafe { &*address };
+
+    let socket_address = socket_address(address);
+
+    if let Some(socket_address) = socket_address {
+        if let Err(error) = policy.check_address(socket_address.ip()) {
+            tracing::debug!(%error, "socket address refused");
+            policy.blocked.replace(Some(error));
+
+            return curl_sys::CURL_SOCKET_BAD;
+        }
+    }
+
+    match open_socket(address) {
+        Ok(socket) => {
+            if let Some(socket_address) = socket_address {
+                policy.opened.lock().unwrap().push(socket_address);
+            }
+
+            socket
+        }
+        Err(error) => {
+            tracing::warn!(%error, "open socket");
+            curl_sys::CURL_SOCKET_BAD
+        }
+    }
+}
+
+#[cfg(unix)]
+fn socket_address(address: &curl_sys::curl_sockaddr) -> Option<SocketAddr> {
+    let pointer = &address.addr as *const libc::sockaddr;
+
+    // SAFETY: curl stores the address in storage large enough for the
+    // structure of its family.
+    match address.family {
+        libc::AF_INET => {
+            let address = unsafe { &*(pointer as *const libc::sockaddr_in) };
+            Some(SocketAddr::new(
+                IpAddr::from(u32::from_be(address.sin_addr.s_addr).to_be_bytes()),
+                u16::from_be(address.sin_port),
+            ))
+        }
+        libc::AF_INET6 => {
+            let address = unsafe { &*(pointer as *const libc::sockaddr_in6) };
+            Some(SocketAddr::new(
+                IpAddr::from(address.sin6_addr.s6_addr),
+                u16::from_be(address.sin6_port),
+            ))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(not(unix))]
+fn socket_address(_address: &curl_sys::curl_sockaddr) -> Option<SocketAddr> {
+    None
+}
+
+/// Opens a socket like the curl crate does, with close-on-exec set.
+#[cfg(unix)]
+fn open_socket(address: &curl_sys::curl_sockaddr) -> std::io::Result<curl_sys::curl_socket_t> {
+    // SAFETY: The arguments are plain integers given by curl.
+    let socket = unsafe { libc::socket(address.family, address.socktype, address.protocol) };
+
+    if socket < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    // SAFETY: The socket was just opened.
+    let result = unsafe { libc::fcntl(socket, libc::F_SETFD, libc::FD_CLOEXEC) };
+
+    if result < 0 {
+        let error = std::io::Error::last_os_error();
+        // SAFETY: The socket is not used elsewhere.
+        unsafe { libc::close(socket) };
+        return Err(error);
+    }
+
+    Ok(socket)
+}
+
+#[cfg(not(unix))]
+fn open_socket(_address: &curl_sys::curl_sockaddr) -> std::io::Result<curl_sys::curl_socket_t> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(unix)]
+fn apply_options(socket: curl_sys::curl_socket_t, options: &SocketOptions) -> std::io::Result<()> {
+    if let Some(tos) = options.tos {
+        let tos = tos as c_int;
+
+        if socket_family(socket)? == libc::AF_INET6 {
+            set_option(socket, libc::IPPROTO_IPV6, libc::IPV6_TCLASS, tos)?;
+        } else {
+            set_option(socket, libc::IPPROTO_IP, libc::IP_TOS, tos)?;
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Some(mark) = options.mark {
+        set_option(socket, libc::SOL_SOCKET, libc::SO_MARK, mark)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn apply_options(
+    _socket: curl_sys::curl_socket_t,
+    _options: &SocketOptions,
+) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn socket_family(socket: curl_sys::curl_socket_t) -> std::io::Result<c_int> {
+    // SAFETY: sockaddr_storage is valid when zeroed and large enough for
+    // any address.
+    let mut address: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let mut length = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+
+    // SAFETY: The pointers refer to the local storage and its length.
+    let result = unsafe {
+        libc::getsockname(
+            socket,
+            &mut address as *mut libc::sockaddr_storage as *mut libc::sockaddr,
+            &mut length,
+        )
+    };
+
+    if result == 0 {
+        Ok(address.ss_family as c_int)
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(unix)]
+fn set_option<T>(
+    socket: curl_sys::curl_socket_t,
+    level: c_int,
+    name: c_int,
+    value: T,
+) -> std::io::Result<()> {
+    // SAFETY: The pointer refers to the local value and its size.
+    let result = unsafe {
+        libc::setsockopt(
+            socket,
+            level,
+            name,
+            &value as *const T as *const c_void,
+            std::mem::size_of::<T>() as libc::socklen_t,
+        )
+    };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
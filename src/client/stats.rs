@@ -0,0 +1,550 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    net::IpAddr,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, ErrorCategory},
+    http::SecurityPolicy,
+};
+
+/// Upper bounds, in seconds, of the buckets used by the timing histograms.
+const HISTOGRAM_BOUNDS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+/// Statistics collected for a single transfer.
+///
+/// Durations are serialized as floating point seconds.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TransferStats {
+    /// Process-wide unique ID of the submission, also recorded on the
+    /// `client_session` tracing span.
+    pub transfer_id: u64,
+    pub status_code: Option<u16>,
+    /// Total of the response header and body bytes.
+    pub bytes_received: u64,
+    /// Total of the request header and body bytes.
+    pub bytes_sent: u64,
+    /// Bytes of request headers as sent, including the request line.
+    #[serde(default)]
+    pub request_header_bytes: u64,
+    /// Bytes of the request body as sent.
+    #[serde(default)]
+    pub request_body_bytes: u64,
+    /// Bytes of response headers as received, including interim responses
+    /// and the status lines.
+    #[serde(default)]
+    pub response_header_bytes: u64,
+    /// Bytes of the response body as received, before content decoding.
+    #[serde(default)]
+    pub response_body_bytes: u64,
+    /// Bytes of TLS handshake and record data besides the above, if the
+    /// transfer used TLS and the backend can measure it.
+    ///
+    /// The curl backend only counts handshake messages, and only with
+    /// OpenSSL and its forks, which report them.
+    #[serde(default)]
+    pub tls_overhead_bytes: Option<u64>,
+    #[serde(with = "duration_seconds")]
+    pub name_lookup_duration: Duration,
+    /// Addresses the host name resolved to, empty if a connection was
+    /// reused.
+    ///
+    /// With curl, unless the client has a resolver, only the addresses that
+    /// connections were attempted to are known.
+    #[serde(default)]
+    pub resolved_addresses: Vec<IpAddr>,
+    #[serde(with = "duration_seconds")]
+    pub connect_duration: Duration,
+    #[serde(with = "duration_seconds")]
+    pub first_byte_duration: Duration,
+    #[serde(with = "duration_seconds")]
+    pub total_duration: Duration,
+    pub connection_reused: bool,
+    /// HTTP version of the final response.
+    #[serde(default)]
+    pub http_version: Option<HttpVersion>,
+    /// Scheme of the URL that the final response was received from.
+    #[serde(default)]
+    pub scheme: Option<String>,
+    /// Protocol ID agreed with ALPN during the TLS handshake, such as `h2`.
+    #[serde(default)]
+    pub alpn_protocol: Option<String>,
+    /// Security policy fields of the final response, if any.
+    #[serde(default)]
+    pub security_policy: Option<Box<SecurityPolicy>>,
+}
+
+/// HTTP version negotiated for a transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HttpVersion {
+    #[serde(rename = "HTTP/1.0")]
+    Http10,
+    #[serde(rename = "HTTP/1.1")]
+    Http11,
+    #[serde(rename = "HTTP/2")]
+    Http2,
+    #[serde(rename = "HTTP/3")]
+    Http3,
+}
+
+impl FromStr for HttpVersion {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "HTTP/1.0" => Ok(Self::Http10),
+            "HTTP/1.1" => Ok(Self::Http11),
+            "HTTP/2" | "HTTP/2.0" => Ok(Self::Http2),
+            "HTTP/3" | "HTTP/3.0" => Ok(Self::Http3),
+            _ => Err(Error::InvalidArgument {
+                value: s.to_string(),
+                reason: "unknown HTTP version".to_string(),
+            }),
+        }
+    }
+}
+
+/// Aggregate statistics of all transfers submitted by a client.
+#[derive(Debug, Clone)]
+pub struct ClientStats {
+    pub transfers: u64,
+    /// Number of responses keyed by status class (`2` for 2xx, etc.).
+    pub responses_by_status_class: BTreeMap<u16, u64>,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    pub name_lookup_durations: Histogram,
+    /// Connect durations of transfers that made a new connection.
+    pub connect_durations: Histogram,
+    pub first_byte_durations: Histogram,
+    pub errors: BTreeMap<ErrorCategory, u64>,
+    pub connections_new: u64,
+    pub connections_reused: u64,
+    /// Statistics of each host, keyed by lowercase host name.
+    pub hosts: BTreeMap<String, HostStats>,
+}
+
+impl Default for ClientStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientStats {
+    pub fn new() -> Self {
+        Self {
+            transfers: 0,
+            responses_by_status_class: BTreeMap::new(),
+            bytes_received: 0,
+            bytes_sent: 0,
+            name_lookup_durations: Histogram::new(),
+            connect_durations: Histogram::new(),
+            first_byte_durations: Histogram::new(),
+            errors: BTreeMap::new(),
+            connections_new: 0,
+            connections_reused: 0,
+            hosts: BTreeMap::new(),
+        }
+    }
+
+    pub fn record_transfer(&mut self, stats: &TransferStats) {
+        self.transfers += 1;
+
+        if let Some(status_code) = stats.status_code {
+            *self
+                .responses_by_status_class
+                .entry(status_code / 100)
+                .or_default() += 1;
+        }
+
+        self.bytes_received += stats.bytes_received;
+        self.bytes_sent += stats.bytes_sent;
+        self.name_lookup_durations
+            .observe(stats.name_lookup_duration);
+        self.first_byte_durations.observe(stats.first_byte_duration);
+
+        // A reused connection has no connect time of its own
+        if stats.connection_reused {
+            self.connections_reused += 1;
+        } else {
+            self.connections_new += 1;
+            self.connect_durations.observe(stats.connect_duration);
+        }
+    }
+
+    pub fn record_error(&mut self, category: ErrorCategory) {
+        *self.errors.entry(category).or_default() += 1;
+    }
+
+    /// Records a transfer to the host, with its statistics if it got far
+    /// enough to have them.
+    pub fn record_host_transfer(
+        &mut self,
+        host: &str,
+        stats: Option<&TransferStats>,
+        failed: bool,
+        now: SystemTime,
+    ) {
+        self.hosts
+            .entry(host.to_string())
+            .or_default()
+            .record_transfer(stats, failed, now);
+    }
+
+    /// Adds the statistics of each host from other client statistics, such
+    /// as of clients on other threads.
+    pub fn merge_hosts(&mut self, other: &ClientStats) {
+        for (host, stats) in &other.hosts {
+            self.hosts.entry(host.clone()).or_default().merge(stats);
+        }
+    }
+
+    /// Fraction of transfers that reused a pooled connection.
+    pub fn pool_reuse_rate(&self) -> f64 {
+        let total = self.connections_new + self.connections_reused;
+
+        if total == 0 {
+            0.0
+        } else {
+            self.connections_reused as f64 / total as f64
+        }
+    }
+
+    /// Formats the statistics in the Prometheus text exposition format.
+    pub fn to_prometheus_text(&self) -> String {
+        let mut buf = String::new();
+
+        write_metric_header(&mut buf, "wrecv_transfers_total", "counter");
+        writeln!(buf, "wrecv_transfers_total {}", self.transfers).unwrap();
+
+        write_metric_header(&mut buf, "wrecv_responses_total", "counter");
+        for (class, count) in &self.responses_by_status_class {
+            writeln!(
+                buf,
+                "wrecv_responses_total{{class=\"{}xx\"}} {}",
+                class, count
+            )
+            .unwrap();
+        }
+
+        write_metric_header(&mut buf, "wrecv_received_bytes_total", "counter");
+        writeln!(buf, "wrecv_received_bytes_total {}", self.bytes_received).unwrap();
+
+        write_metric_header(&mut buf, "wrecv_sent_bytes_total", "counter");
+        writeln!(buf, "wrecv_sent_bytes_total {}", self.bytes_sent).unwrap();
+
+        self.name_lookup_durations
+            .write_prometheus_text(&mut buf, "wrecv_name_lookup_duration_seconds");
+        self.connect_durations
+            .write_prometheus_text(&mut buf, "wrecv_connect_duration_seconds");
+        self.first_byte_durations
+            .write_prometheus_text(&mut buf, "wrecv_first_byte_duration_seconds");
+
+        write_metric_header(&mut buf, "wrecv_errors_total", "counter");
+        for (category, count) in &self.errors {
+            writeln!(
+                buf,
+                "wrecv_errors_total{{category=\"{}\"}} {}",
+                category, count
+            )
+            .unwrap();
+        }
+
+        write_metric_header(&mut buf, "wrecv_connections_total", "counter");
+        writeln!(
+            buf,
+            "wrecv_connections_total{{reused=\"false\"}} {}",
+            self.connections_new
+        )
+        .unwrap();
+        writeln!(
+            buf,
+            "wrecv_connections_total{{reused=\"true\"}} {}",
+            self.connections_reused
+        )
+        .unwrap();
+
+        buf
+    }
+}
+
+/// Aggregate statistics of the transfers to a single host.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HostStats {
+    pub transfers: u64,
+    /// Number of transfers that ended with an error.
+    pub errors: u64,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    /// Sum of the time to first byte of the transfers that have one.
+    pub first_byte_duration_sum: Duration,
+    pub first_byte_duration_count: u64,
+    /// Time the last transfer to the host finished.
+    pub last_access: Option<SystemTime>,
+}
+
+impl HostStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_transfer(
+        &mut self,
+        stats: Option<&TransferStats>,
+        failed: bool,
+        now: SystemTime,
+    ) {
+        self.transfers += 1;
+
+        if failed {
+            self.errors += 1;
+        }
+
+        if let Some(stats) = stats {
+            self.bytes_received += stats.bytes_received;
+            self.bytes_sent += stats.bytes_sent;
+
+            if stats.first_byte_duration > Duration::ZERO {
+                self.first_byte_duration_sum += stats.first_byte_duration;
+                self.first_byte_duration_count += 1;
+            }
+        }
+
+        self.last_access = self.last_access.max(Some(now));
+    }
+
+    pub fn merge(&mut self, other: &HostStats) {
+        self.transfers += other.transfers;
+        self.errors += other.errors;
+        self.bytes_received += other.bytes_received;
+        self.bytes_sent += other.bytes_sent;
+        self.first_byte_duration_sum += other.first_byte_duration_sum;
+        self.first_byte_duration_count += other.first_byte_duration_count;
+        self.last_access = self.last_access.max(other.last_access);
+    }
+
+    /// Fraction of transfers that ended with an error.
+    pub fn error_rate(&self) -> f64 {
+        if self.transfers == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.transfers as f64
+        }
+    }
+
+    /// Average time to first byte.
+    pub fn average_latency(&self) -> Option<Duration> {
+        if self.first_byte_duration_count == 0 {
+            None
+        } else {
+            Some(self.first_byte_duration_sum / self.first_byte_duration_count as u32)
+        }
+    }
+}
+
+/// Cumulative histogram of durations with fixed bucket bounds.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    count: u64,
+    sum: Duration,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self {
+            counts: vec![0; HISTOGRAM_BOUNDS.len()],
+            count: 0,
+            sum: Duration::ZERO,
+        }
+    }
+
+    pub fn observe(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+
+        for (bound, count) in HISTOGRAM_BOUNDS.iter().zip(self.counts.iter_mut()) {
+            if seconds <= *bound {
+                *count += 1;
+            }
+        }
+
+        self.count += 1;
+        self.sum += duration;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+
+    /// Returns pairs of bucket upper bound in seconds and cumulative count.
+    pub fn buckets(&self) -> impl Iterator<Item = (f64, u64)> + '_ {
+        HISTOGRAM_BOUNDS
+            .iter()
+            .copied()
+            .zip(self.counts.iter().copied())
+    }
+
+    fn write_prometheus_text(&self, buf: &mut String, name: &str) {
+        write_metric_header(buf, name, "histogram");
+
+        for (bound, count) in self.buckets() {
+            writeln!(buf, "{}_bucket{{le=\"{}\"}} {}", name, bound, count).unwrap();
+        }
+
+        writeln!(buf, "{}_bucket{{le=\"+Inf\"}} {}", name, self.count).unwrap();
+        writeln!(buf, "{}_sum {}", name, self.sum.as_secs_f64()).unwrap();
+        writeln!(buf, "{}_count {}", name, self.count).unwrap();
+    }
+}
+
+mod duration_seconds {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(value.as_secs_f64())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let value = f64::deserialize(deserializer)?;
+        Duration::try_from_secs_f64(value).map_err(serde::de::Error::custom)
+    }
+}
+
+fn write_metric_header(buf: &mut String, name: &str, metric_type: &str) {
+    writeln!(buf, "# TYPE {} {}", name, metric_type).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::new();
+        histogram.observe(Duration::from_millis(3));
+        histogram.observe(Duration::from_millis(200));
+        histogram.observe(Duration::from_secs(60));
+
+        assert_eq!(histogram.count(), 3);
+        assert_eq!(histogram.sum(), Duration::from_millis(60203));
+
+        let buckets = histogram.buckets().collect::<Vec<(f64, u64)>>();
+        assert_eq!(buckets[0], (0.005, 1));
+        assert_eq!(buckets[5], (0.25, 2));
+        assert_eq!(buckets[11], (30.0, 2));
+    }
+
+    #[test]
+    fn test_host_stats() {
+        let mut stats = ClientStats::new();
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(100);
+
+        stats.record_host_transfer(
+            "example.com",
+            Some(&TransferStats {
+                bytes_received: 100,
+                bytes_sent: 10,
+                first_byte_duration: Duration::from_millis(100),
+                ..Default::default()
+            }),
+            false,
+            now,
+        );
+        stats.record_host_transfer("example.com", None, true, now - Duration::from_secs(1));
+
+        let host = &stats.hosts["example.com"];
+        assert_eq!(host.transfers, 2);
+        assert_eq!(host.errors, 1);
+        assert_eq!(host.error_rate(), 0.5);
+        assert_eq!(host.bytes_received, 100);
+        assert_eq!(host.average_latency(), Some(Duration::from_millis(100)));
+        assert_eq!(host.last_access, Some(now));
+
+        let mut other = ClientStats::new();
+        other.record_host_transfer(
+            "example.com",
+            Some(&TransferStats {
+                first_byte_duration: Duration::from_millis(300),
+                ..Default::default()
+            }),
+            false,
+            now + Duration::from_secs(1),
+        );
+        other.record_host_transfer("example.net", None, false, now);
+
+        stats.merge_hosts(&other);
+
+        let host = &stats.hosts["example.com"];
+        assert_eq!(host.transfers, 3);
+        assert_eq!(host.average_latency(), Some(Duration::from_millis(200)));
+        assert_eq!(host.last_access, Some(now + Duration::from_secs(1)));
+        assert_eq!(stats.hosts["example.net"].error_rate(), 0.0);
+        assert_eq!(HostStats::new().average_latency(), None);
+    }
+
+    #[test]
+    fn test_http_version_parse() {
+        assert_eq!(
+            "HTTP/1.1".parse::<HttpVersion>().unwrap(),
+            HttpVersion::Http11
+        );
+        assert_eq!("http/2".parse::<HttpVersion>().unwrap(), HttpVersion::Http2);
+        assert!("HTTP/0.9".parse::<HttpVersion>().is_err());
+        assert_eq!(
+            serde_json::to_string(&HttpVersion::Http3).unwrap(),
+            "\"HTTP/3\""
+        );
+    }
+
+    #[test]
+    fn test_client_stats() {
+        let mut stats = ClientStats::new();
+
+        stats.record_transfer(&TransferStats {
+            status_code: Some(200),
+            bytes_received: 100,
+            bytes_sent: 10,
+            ..Default::default()
+        });
+        stats.record_transfer(&TransferStats {
+            status_code: Some(404),
+            bytes_received: 50,
+            bytes_sent: 10,
+            connection_reused: true,
+            ..Default::default()
+        });
+        stats.record_error(ErrorCategory::Network);
+
+        assert_eq!(stats.transfers, 2);
+        assert_eq!(stats.bytes_received, 150);
+        assert_eq!(stats.responses_by_status_class.get(&2), Some(&1));
+        assert_eq!(stats.responses_by_status_class.get(&4), Some(&1));
+        assert_eq!(stats.errors.get(&ErrorCategory::Network), Some(&1));
+        assert_eq!(stats.pool_reuse_rate(), 0.5);
+
+        let text = stats.to_prometheus_text();
+        assert!(text.contains("wrecv_transfers_total 2\n"));
+        assert!(text.contains("wrecv_responses_total{class=\"4xx\"} 1\n"));
+        assert!(text.contains("wrecv_errors_total{category=\"network\"} 1\n"));
+        assert!(text.contains("wrecv_connect_duration_seconds_count 1\n"));
+        assert!(text.contains("wrecv_first_byte_duration_seconds_count 2\n"));
+    }
+}
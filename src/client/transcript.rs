@@ -0,0 +1,658 @@
+use std::{
+    io::{BufRead, Write},
+    net::SocketAddr,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    error::{Error, OtherError},
+    http::{RequestHeader, ResponseHeader, ResponseTrailer},
+};
+
+use super::{
+    FtpTransferType, SessionControl, SessionEvent, SessionHandler, TlsInfo, TraceKind,
+    TransferRate, TransferStats,
+};
+
+/// A line of a JSON Lines session event transcript.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptRecord {
+    /// RFC 3339 formatted time the event was recorded.
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub event: TranscriptEvent,
+}
+
+/// Serializable form of a [`SessionEvent`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum TranscriptEvent {
+    ConnectAttempt {
+        address: SocketAddr,
+    },
+    ConnectFailed {
+        address: SocketAddr,
+        reason: String,
+    },
+    Connected {
+        address: SocketAddr,
+    },
+    ConnectionInfo {
+        reused: bool,
+        local_addr: SocketAddr,
+        remote_addr: SocketAddr,
+    },
+    TlsConnected {
+        info: TlsInfo,
+    },
+    HeaderReceived {
+        data: TranscriptData,
+    },
+    HeaderSent {
+        data: TranscriptData,
+    },
+    BodyReceived {
+        data: TranscriptData,
+    },
+    BodySent {
+        data: TranscriptData,
+    },
+    ContentSent {
+        data: TranscriptData,
+    },
+    ContentReceived {
+        data: TranscriptData,
+    },
+    HttpRequest {
+        data: TranscriptData,
+        header: RequestHeader,
+    },
+    HttpInterimResponse {
+        data: TranscriptData,
+        header: ResponseHeader,
+    },
+    HttpResponse {
+        data: TranscriptData,
+        header: ResponseHeader,
+    },
+    HttpResponseTrailer {
+        data: TranscriptData,
+        trailer: ResponseTrailer,
+    },
+    Progress {
+        download_total: u64,
+        download_current: u64,
+        upload_total: u64,
+        upload_current: u64,
+        #[serde(default)]
+        download_rate: TransferRate,
+        #[serde(default)]
+        upload_rate: TransferRate,
+        /// Seconds estimated until completion.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        eta: Option<f64>,
+    },
+    Finished {
+        stats: TransferStats,
+    },
+    CircuitOpened {
+        host: String,
+        /// Seconds transfers to the host are refused.
+        duration: f64,
+    },
+    CircuitClosed {
+        host: String,
+    },
+    FtpTransferType {
+        transfer_type: FtpTransferType,
+    },
+    FtpCommand {
+        command: String,
+        argument: String,
+    },
+    FtpReply {
+        code: u16,
+        text: String,
+    },
+    CookieAccepted {
+        name: String,
+    },
+    CookieRejected {
+        name: String,
+        reason: String,
+    },
+    Trace {
+        kind: TraceKind,
+        data: TranscriptData,
+    },
+}
+
+impl TranscriptEvent {
+    pub fn from_session_event(event: &SessionEvent, include_data: bool) -> Self {
+        let data = |direction, data| TranscriptData::new(direction, data, include_data);
+
+        match event {
+            SessionEvent::ConnectAttempt(address) => Self::ConnectAttempt { address: *address },
+            SessionEvent::ConnectFailed(address, reason) => Self::ConnectFailed {
+                address: *address,
+                reason: reason.clone(),
+            },
+            SessionEvent::Connected(address) => Self::Connected { address: *address },
+            SessionEvent::ConnectionInfo {
+                reused,
+                local_addr,
+                remote_addr,
+            } => Self::ConnectionInfo {
+                reused: *reused,
+                local_addr: *local_addr,
+                remote_addr: *remote_addr,
+            },
+            SessionEvent::TlsConnected(info) => Self::TlsConnected { info: info.clone() },
+            SessionEvent::HeaderReceived(value) => Self::HeaderReceived {
+                data: data(Direction::In, value),
+            },
+            SessionEvent::HeaderSent(value) => Self::HeaderSent {
+                data: data(Direction::Out, value),
+            },
+            SessionEvent::BodyReceived(value) => Self::BodyReceived {
+                data: data(Direction::In, value),
+            },
+            SessionEvent::BodySent(value) => Self::BodySent {
+                data: data(Direction::Out, value),
+            },
+            SessionEvent::ContentSent(value) => Self::ContentSent {
+                data: data(Direction::Out, value),
+            },
+            SessionEvent::ContentReceived(value) => Self::ContentReceived {
+                data: data(Direction::In, value),
+            },
+            SessionEvent::HttpRequest(value, header) => Self::HttpRequest {
+                data: data(Direction::Out, value),
+                header: header.clone(),
+            },
+            SessionEvent::HttpInterimResponse(value, header) => Self::HttpInterimResponse {
+                data: data(Direction::In, value),
+                header: header.clone(),
+            },
+            SessionEvent::HttpResponse(value, header) => Self::HttpResponse {
+                data: data(Direction::In, value),
+                header: header.clone(),
+            },
+            SessionEvent::HttpResponseTrailer(value, trailer) => Self::HttpResponseTrailer {
+                data: data(Direction::In, value),
+                trailer: trailer.clone(),
+            },
+            SessionEvent::Progress {
+                download_total,
+                download_current,
+                upload_total,
+                upload_current,
+                download_rate,
+                upload_rate,
+                eta,
+            } => Self::Progress {
+                download_total: *download_total,
+                download_current: *download_current,
+                upload_total: *upload_total,
+                upload_current: *upload_current,
+                download_rate: *download_rate,
+                upload_rate: *upload_rate,
+                eta: eta.map(|eta| eta.as_secs_f64()),
+            },
+            SessionEvent::Finished(stats) => Self::Finished {
+                stats: stats.clone(),
+            },
+            SessionEvent::CircuitOpened(host, duration) => Self::CircuitOpened {
+                host: host.clone(),
+                duration: duration.as_secs_f64(),
+            },
+            SessionEvent::CircuitClosed(host) => Self::CircuitClosed { host: host.clone() },
+            SessionEvent::FtpTransferType(transfer_type) => Self::FtpTransferType {
+                transfer_type: *transfer_type,
+            },
+            SessionEvent::FtpCommand { command, argument } => Self::FtpCommand {
+                command: command.clone(),
+                argument: argument.clone(),
+            },
+            SessionEvent::FtpReply { code, text } => Self::FtpReply {
+                code: *code,
+                text: text.clone(),
+            },
+            SessionEvent::CookieAccepted { name } => Self::CookieAccepted { name: name.clone() },
+            SessionEvent::CookieRejected { name, reason } => Self::CookieRejected {
+                name: name.clone(),
+                reason: reason.clone(),
+            },
+            SessionEvent::Trace(kind, value) => Self::Trace {
+                kind: *kind,
+                data: data(trace_direction(*kind), value),
+            },
+        }
+    }
+
+    fn data(&self) -> Option<&TranscriptData> {
+        match self {
+            Self::HeaderReceived { data }
+            | Self::HeaderSent { data }
+            | Self::BodyReceived { data }
+            | Self::BodySent { data }
+            | Self::ContentSent { data }
+            | Self::ContentReceived { data }
+            | Self::HttpRequest { data, .. }
+            | Self::HttpInterimResponse { data, .. }
+            | Self::HttpResponse { data, .. }
+            | Self::HttpResponseTrailer { data, .. }
+            | Self::Trace { data, .. } => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Returns the session event of the record, which carries the given
+    /// decoded bytes of the event data.
+    fn to_session_event<'a>(&'a self, bytes: &'a [u8]) -> SessionEvent<'a> {
+        match self {
+            Self::ConnectAttempt { address } => SessionEvent::ConnectAttempt(*address),
+            Self::ConnectFailed { address, reason } => {
+                SessionEvent::ConnectFailed(*address, reason.clone())
+            }
+            Self::Connected { address } => SessionEvent::Connected(*address),
+            Self::ConnectionInfo {
+                reused,
+                local_addr,
+                remote_addr,
+            } => SessionEvent::ConnectionInfo {
+                reused: *reused,
+                local_addr: *local_addr,
+                remote_addr: *remote_addr,
+            },
+            Self::TlsConnected { info } => SessionEvent::TlsConnected(info.clone()),
+            Self::HeaderReceived { .. } => SessionEvent::HeaderReceived(bytes),
+            Self::HeaderSent { .. } => SessionEvent::HeaderSent(bytes),
+            Self::BodyReceived { .. } => SessionEvent::BodyReceived(bytes),
+            Self::BodySent { .. } => SessionEvent::BodySent(bytes),
+            Self::ContentSent { .. } => SessionEvent::ContentSent(bytes),
+            Self::ContentReceived { .. } => SessionEvent::ContentReceived(bytes),
+            Self::HttpRequest { header, .. } => SessionEvent::HttpRequest(bytes, header.clone()),
+            Self::HttpInterimResponse { header, .. } => {
+                SessionEvent::HttpInterimResponse(bytes, header.clone())
+            }
+            Self::HttpResponse { header, .. } => SessionEvent::HttpResponse(bytes, header.clone()),
+            Self::HttpResponseTrailer { trailer, .. } => {
+                SessionEvent::HttpResponseTrailer(bytes, trailer.clone())
+            }
+            Self::Progress {
+                download_total,
+                download_current,
+                upload_total,
+                upload_current,
+                download_rate,
+                upload_rate,
+                eta,
+            } => SessionEvent::Progress {
+                download_total: *download_total,
+                download_current: *download_current,
+                upload_total: *upload_total,
+                upload_current: *upload_current,
+                download_rate: *download_rate,
+                upload_rate: *upload_rate,
+                eta: eta.map(Duration::from_secs_f64),
+            },
+            Self::Finished { stats } => SessionEvent::Finished(stats.clone()),
+            Self::CircuitOpened { host, duration } => {
+                SessionEvent::CircuitOpened(host.clone(), Duration::from_secs_f64(*duration))
+            }
+            Self::CircuitClosed { host } => SessionEvent::CircuitClosed(host.clone()),
+            Self::FtpTransferType { transfer_type } => {
+                SessionEvent::FtpTransferType(*transfer_type)
+            }
+            Self::FtpCommand { command, argument } => SessionEvent::FtpCommand {
+                command: command.clone(),
+                argument: argument.clone(),
+            },
+            Self::FtpReply { code, text } => SessionEvent::FtpReply {
+                code: *code,
+                text: text.clone(),
+            },
+            Self::CookieAccepted { name } => SessionEvent::CookieAccepted { name: name.clone() },
+            Self::CookieRejected { name, reason } => SessionEvent::CookieRejected {
+                name: name.clone(),
+                reason: reason.clone(),
+            },
+            Self::Trace { kind, .. } => SessionEvent::Trace(*kind, bytes),
+        }
+    }
+}
+
+fn trace_direction(kind: TraceKind) -> Direction {
+    match kind {
+        TraceKind::HeaderOut | TraceKind::DataOut | TraceKind::SslDataOut => Direction::Out,
+        TraceKind::Text | TraceKind::HeaderIn | TraceKind::DataIn | TraceKind::SslDataIn => {
+            Direction::In
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    In,
+    Out,
+}
+
+/// Summary of the bytes carried by an event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptData {
+    pub direction: Direction,
+    pub size: u64,
+    /// Hex encoded SHA-256 digest of the bytes.
+    pub sha256: String,
+    /// The bytes themselves, if they were requested to be recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base64: Option<String>,
+}
+
+impl TranscriptData {
+    pub fn new(direction: Direction, data: &[u8], include_data: bool) -> Self {
+        let digest = Sha256::digest(data);
+        let sha256 = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+        Self {
+            direction,
+            size: data.len() as u64,
+            sha256,
+            base64: if include_data {
+                Some(crate::string::base64_encode(data))
+            } else {
+                None
+            },
+        }
+    }
+}
+
+impl TranscriptData {
+    /// Returns the recorded bytes, or an error if only their digest was
+    /// recorded.
+    pub fn bytes(&self) -> Result<Vec<u8>, Error> {
+        match &self.base64 {
+            Some(value) => crate::string::base64_decode(value),
+            None if self.size == 0 => Ok(Vec::new()),
+            None => Err(Error::InvalidArgument {
+                value: self.sha256.clone(),
+                reason: "transcript has no event data".to_string(),
+            }),
+        }
+    }
+}
+
+/// Feeds the events of a JSON Lines transcript to the handler as a session
+/// would, such as for testing handlers without network access.
+///
+/// The transcript must be recorded with event data included. Events not in
+/// the filter of the handler are skipped.
+pub fn replay<R: BufRead, H: SessionHandler>(reader: R, handler: &mut H) -> Result<(), Error> {
+    let mut control = ReplayControl { aborted: false };
+    let filter = handler.event_filter();
+
+    for line in reader.lines() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record = serde_json::from_str::<TranscriptRecord>(&line)
+            .map_err(|error| OtherError::Custom(Box::new(error)))?;
+        let bytes = match record.event.data() {
+            Some(data) => data.bytes()?,
+            None => Vec::new(),
+        };
+        let event = record.event.to_session_event(&bytes);
+
+        if !filter.contains(event.kind()) {
+            continue;
+        }
+
+        if let Err(error) = handler.event(&mut control, event) {
+            return Err(match error.downcast::<Error>() {
+                Ok(error) => *error,
+                Err(error) => Error::Other(OtherError::Custom(error)),
+            });
+        }
+
+        if control.aborted {
+            return Err(Error::Other(OtherError::Custom(
+                "transfer aborted by handler".into(),
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+struct ReplayControl {
+    aborted: bool,
+}
+
+impl SessionControl for ReplayControl {
+    fn abort(&mut self) {
+        self.aborted = true;
+    }
+}
+
+/// Writes session events as JSON Lines.
+pub struct TranscriptWriter<W: Write> {
+    writer: W,
+    include_data: bool,
+}
+
+impl<W: Write> TranscriptWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            include_data: false,
+        }
+    }
+
+    pub fn include_data(&self) -> bool {
+        self.include_data
+    }
+
+    /// Sets whether event bytes are recorded in addition to their digests.
+    pub fn set_include_data(&mut self, enabled: bool) -> &mut Self {
+        self.include_data = enabled;
+        self
+    }
+
+    pub fn write_event(&mut self, event: &SessionEvent) -> Result<(), Error> {
+        let timestamp = time::OffsetDateTime::now_utc()
+            .format(&time::format_description::well_known::Rfc3339)
+            .map_err(|error| OtherError::Custom(Box::new(error)))?;
+
+        let record = TranscriptRecord {
+            timestamp,
+            event: TranscriptEvent::from_session_event(event, self.include_data),
+        };
+
+        self.write_record(&record)
+    }
+
+    pub fn write_record(&mut self, record: &TranscriptRecord) -> Result<(), Error> {
+        let mut line =
+            serde_json::to_vec(record).map_err(|error| OtherError::Custom(Box::new(error)))?;
+        line.push(b'\n');
+
+        self.writer.write_all(&line)?;
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        client::{EventFilter, EventKind},
+        http::HeaderFields,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_transcript_writer() {
+        let mut writer = TranscriptWriter::new(Vec::new());
+
+        writer
+            .write_event(&SessionEvent::HeaderReceived(b"abc"))
+            .unwrap();
+
+        let mut header = ResponseHeader::new();
+        header.status_code = 200;
+        header.fields = HeaderFields::new();
+        header.fields.append("k1", b"\xff".as_slice());
+
+        writer.set_include_data(true);
+        writer
+            .write_event(&SessionEvent::HttpResponse(b"abc", header))
+            .unwrap();
+
+        let output = writer.into_inner();
+        let lines = output
+            .split(|&byte| byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice::<TranscriptRecord>(line).unwrap())
+            .collect::<Vec<TranscriptRecord>>();
+
+        assert_eq!(lines.len(), 2);
+
+        match &lines[0].event {
+            TranscriptEvent::HeaderReceived { data } => {
+                assert_eq!(data.direction, Direction::In);
+                assert_eq!(data.size, 3);
+                assert_eq!(
+                    data.sha256,
+                    "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+                );
+                assert!(data.base64.is_none());
+            }
+            _ => unreachable!(),
+        }
+
+        match &lines[1].event {
+            TranscriptEvent::HttpResponse { data, header } => {
+                assert_eq!(data.base64.as_deref(), Some("YWJj"));
+                assert_eq!(header.status_code, 200);
+                assert_eq!(header.fields.get("k1"), Some(&b"\xff".as_slice().into()));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut writer = TranscriptWriter::new(Vec::new());
+        writer.set_include_data(true);
+
+        let mut header = ResponseHeader::new();
+        header.status_code = 200;
+
+        writer
+            .write_event(&SessionEvent::HttpResponse(
+                b"HTTP/1.1 200 OK\r\n\r\n",
+                header,
+            ))
+            .unwrap();
+        writer
+            .write_event(&SessionEvent::ContentReceived(b"\x00\xffabc"))
+            .unwrap();
+        writer
+            .write_event(&SessionEvent::Progress {
+                download_total: 5,
+                download_current: 5,
+                upload_total: 0,
+                upload_current: 0,
+                download_rate: TransferRate::default(),
+                upload_rate: TransferRate::default(),
+                eta: Some(Duration::from_millis(1500)),
+            })
+            .unwrap();
+        writer
+            .write_event(&SessionEvent::ContentReceived(b"def"))
+            .unwrap();
+        let transcript = writer.into_inner();
+
+        #[derive(Default)]
+        struct MyHandler {
+            status_code: Option<u16>,
+            content: Vec<u8>,
+            abort_after: Option<usize>,
+        }
+
+        impl SessionHandler for MyHandler {
+            fn event(
+                &mut self,
+                control: &mut dyn SessionControl,
+                event: SessionEvent,
+            ) -> Result<(), crate::error::BoxedError> {
+                match event {
+                    SessionEvent::HttpResponse(_data, header) => {
+                        self.status_code = Some(header.status_code)
+                    }
+                    SessionEvent::ContentReceived(data) => self.content.extend_from_slice(data),
+                    SessionEvent::Progress { .. } => panic!("filtered event"),
+                    _ => {}
+                }
+
+                if self.abort_after == Some(self.content.len()) {
+                    control.abort();
+                }
+
+                Ok(())
+            }
+
+            fn event_filter(&self) -> EventFilter {
+                EventFilter::all().without(EventKind::Progress)
+            }
+        }
+
+        let mut handler = MyHandler::default();
+        replay(transcript.as_slice(), &mut handler).unwrap();
+
+        assert_eq!(handler.status_code, Some(200));
+        assert_eq!(handler.content, b"\x00\xffabcdef");
+
+        let mut handler = MyHandler {
+            abort_after: Some(5),
+            ..Default::default()
+        };
+        assert!(replay(transcript.as_slice(), &mut handler).is_err());
+        assert_eq!(handler.content, b"\x00\xffabc");
+
+        let mut writer = TranscriptWriter::new(Vec::new());
+        writer
+            .write_event(&SessionEvent::ContentReceived(b"abc"))
+            .unwrap();
+        let transcript = writer.into_inner();
+
+        assert!(matches!(
+            replay(transcript.as_slice(), &mut MyHandler::default()),
+            Err(Error::InvalidArgument { .. })
+        ));
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    hash::{Hash, Hasher},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use crate::error::Error;
+
+use super::{Config, Request};
+
+/// How a user agent is chosen from a [`UserAgentRotation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationStrategy {
+    /// Next user agent of the list for each request.
+    RoundRobin,
+    /// Same user agent for all requests to a host.
+    PerHost,
+}
+
+impl FromStr for RotationStrategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "round-robin" => Ok(Self::RoundRobin),
+            "per-host" => Ok(Self::PerHost),
+            _ => Err(Error::InvalidArgument {
+                value: s.to_string(),
+                reason: "unknown rotation strategy".to_string(),
+            }),
+        }
+    }
+}
+
+/// List of user agents that requests take turns using.
+///
+/// Clones share the position of the round-robin strategy.
+#[derive(Debug, Clone)]
+pub struct UserAgentRotation {
+    user_agents: Vec<String>,
+    strategy: RotationStrategy,
+    next_index: Arc<AtomicUsize>,
+}
+
+impl UserAgentRotation {
+    pub fn new(user_agents: Vec<String>, strategy: RotationStrategy) -> Self {
+        Self {
+            user_agents,
+            strategy,
+            next_index: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn user_agents(&self) -> &[String] {
+        &self.user_agents
+    }
+
+    pub fn strategy(&self) -> RotationStrategy {
+        self.strategy
+    }
+
+    /// Returns the user agent for a request to the given host, or `None`
+    /// if the list is empty.
+    pub fn select(&self, host: &str) -> Option<&str> {
+        if self.user_agents.is_empty() {
+            return None;
+        }
+
+        let index = match self.strategy {
+            RotationStrategy::RoundRobin => self.next_index.fetch_add(1, Ordering::Relaxed),
+            RotationStrategy::PerHost => {
+                let mut hasher = std::collections::hash_map::DefaultHasher::new();
+                host.to_ascii_lowercase().hash(&mut hasher);
+                hasher.finish() as usize
+            }
+        };
+
+        Some(&self.user_agents[index % self.user_agents.len()])
+    }
+}
+
+/// Returns the user agent of the request, or the next one of the rotation,
+/// or the one of the config.
+pub(super) fn select_user_agent(config: &Config, request: &Request) -> String {
+    if let Some(user_agent) = request.http_user_agent() {
+        return user_agent.to_string();
+    }
+
+    config
+        .http_user_agent_rotation()
+        .and_then(|rotation| rotation.select(request.url().host_str().unwrap_or_default()))
+        .unwrap_or(config.http_user_agent())
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotation_strategy_parse() {
+        assert_eq!(
+            "per-host".parse::<RotationStrategy>().unwrap(),
+            RotationStrategy::PerHost
+        );
+        assert!("random".parse::<RotationStrategy>().is_err());
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let rotation = UserAgentRotation::new(
+            vec!["a".to_string(), "b".to_string()],
+            RotationStrategy::RoundRobin,
+        );
+        let shared = rotation.clone();
+
+        assert_eq!(rotation.select("example.com"), Some("a"));
+        assert_eq!(shared.select("example.com"), Some("b"));
+        assert_eq!(rotation.select("example.net"), Some("a"));
+    }
+
+    #[test]
+    fn test_per_host() {
+        let rotation = UserAgentRotation::new(
+            (0..10).map(|index| index.to_string()).collect(),
+            RotationStrategy::PerHost,
+        );
+
+        let user_agent = rotation.select("example.com");
+        assert!(user_agent.is_some());
+        assert_eq!(rotation.select("EXAMPLE.com"), user_agent);
+        assert_eq!(rotation.select("example.com"), user_agent);
+
+        let empty = UserAgentRotation::new(Vec::new(), RotationStrategy::PerHost);
+        assert_eq!(empty.select("example.com"), None);
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use trust_dns_resolver::lookup_ip::LookupIp as TrustLookupIp;
+
+use crate::error::Error;
+
+use super::address_lookup;
+
+/// Answers of successful lookups kept until their TTL expires.
+///
+/// The cache can be saved to a JSON file and loaded again by a later run.
+#[derive(Debug, Default)]
+pub struct DnsCache {
+    entries: Mutex<HashMap<String, CacheEntry>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    addresses: Vec<IpAddr>,
+    /// Expiry as seconds since the Unix epoch.
+    expires: u64,
+}
+
+impl DnsCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads a saved cache, dropping expired entries.
+    ///
+    /// A missing file gives an empty cache.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let entries: HashMap<String, CacheEntry> = match File::open(path) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file))
+                .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(error) => return Err(error.into()),
+        };
+
+        let now = unix_time(SystemTime::now());
+        let entries = entries
+            .into_iter()
+            .filter(|(_, entry)| entry.expires > now)
+            .collect();
+
+        Ok(Self {
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// Writes the unexpired entries to a temporary file and renames it over
+    /// the given path.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let now = unix_time(SystemTime::now());
+        let entries = self
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, entry)| entry.expires > now)
+            .map(|(name, entry)| (name.clone(), entry.clone()))
+            .collect::<HashMap<String, CacheEntry>>();
+
+        let mut temp_path = path.to_path_buf().into_os_string();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        serde_json::to_writer(&mut writer, &entries).map_err(std::io::Error::other)?;
+        writer.flush()?;
+        drop(writer);
+
+        std::fs::rename(&temp_path, path)?;
+
+        Ok(())
+    }
+
+    /// Returns the cached answer of an ASCII name with the remaining TTL.
+    pub fn get(&self, ascii_name: &str) -> Option<TrustLookupIp> {
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.get(ascii_name)?;
+        let remaining = entry
+            .expires
+            .checked_sub(unix_time(SystemTime::now()))
+            .filter(|&remaining| remaining > 0)?;
+
+        address_lookup(ascii_name, &entry.addresses, remaining as u32)
+    }
+
+    /// Stores the answer of a lookup for the smallest TTL of its records.
+    pub fn insert(&self, ascii_name: &str, lookup: &TrustLookupIp) {
+        let ttl = remaining_ttl(lookup);
+
+        if ttl.is_zero() {
+            return;
+        }
+
+        let entry = CacheEntry {
+            addresses: lookup.iter().collect(),
+            expires: unix_time(SystemTime::now() + ttl),
+        };
+
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(ascii_name.to_string(), entry);
+    }
+}
+
+fn remaining_ttl(lookup: &TrustLookupIp) -> Duration {
+    let valid_for = lookup
+        .valid_until()
+        .saturating_duration_since(Instant::now());
+    let min_ttl = lookup
+        .as_lookup()
+        .records()
+        .iter()
+        .map(|record| Duration::from_secs(record.ttl().into()))
+        .min()
+        .unwrap_or_default();
+
+    valid_for.min(min_ttl)
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn test_dns_cache() {
+        let cache = DnsCache::new();
+        let addresses = [IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))];
+
+        let lookup = address_lookup("example.com", &addresses, 300).unwrap();
+        cache.insert("example.com", &lookup);
+
+        let zero_lookup = address_lookup("example.net", &addresses, 0).unwrap();
+        cache.insert("example.net", &zero_lookup);
+
+        let cached = cache.get("example.com").unwrap();
+        assert_eq!(cached.iter().collect::<Vec<IpAddr>>(), addresses);
+        assert!(cached.as_lookup().records()[0].ttl() <= 300);
+        assert!(cache.get("example.net").is_none());
+
+        let path =
+            std::env::temp_dir().join(format!("wrecv-dns-cache-test-{}.json", std::process::id()));
+        cache.save(&path).unwrap();
+
+        let loaded = DnsCache::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let cached = loaded.get("example.com").unwrap();
+        assert_eq!(cached.iter().collect::<Vec<IpAddr>>(), addresses);
+    }
+}
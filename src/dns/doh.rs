@@ -0,0 +1,268 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use curl::easy::{Easy, List};
+use trust_dns_resolver::{
+    error::{ResolveError, ResolveErrorKind},
+    lookup::Lookup,
+    lookup_ip::LookupIp,
+    proto::{
+        op::{Message, MessageType, OpCode, Query, ResponseCode},
+        rr::{Name, RecordType},
+        serialize::binary::{BinDecodable, BinEncodable},
+    },
+    Resolver as TrustResolver,
+};
+use url::Url;
+
+const CONTENT_TYPE: &str = "application/dns-message";
+
+/// Largest possible DNS message.
+const MAX_MESSAGE_SIZE: usize = 65535;
+
+/// DNS-over-HTTPS (RFC 8484) client for a server given by URL.
+pub struct DohClient {
+    url: Url,
+    bind_address: Option<IpAddr>,
+    timeout: Duration,
+    bootstrap: Option<Arc<TrustResolver>>,
+    curl_handle: Mutex<Easy>,
+}
+
+impl DohClient {
+    pub fn new(url: Url, bind_address: Option<IpAddr>, timeout: Duration) -> Self {
+        Self {
+            url,
+            bind_address,
+            timeout,
+            bootstrap: None,
+            curl_handle: Mutex::new(Easy::new()),
+        }
+    }
+
+    /// Resolves the host name of the URL with the given resolver instead
+    /// of the system resolver.
+    pub fn set_bootstrap(&mut self, resolver: Arc<TrustResolver>) -> &mut Self {
+        self.bootstrap = Some(resolver);
+        self
+    }
+
+    /// Returns the address of the server of the last query.
+    pub fn peer_address(&self) -> Option<SocketAddr> {
+        let mut curl_handle = self.curl_handle.lock().unwrap();
+        let ip = curl_handle.primary_ip().ok()??.parse::<IpAddr>().ok()?;
+        let port = curl_handle.primary_port().ok()?;
+
+        Some(SocketAddr::new(ip, port))
+    }
+
+    /// Looks up both IPv4 and IPv6 addresses.
+    ///
+    /// An error is only returned if neither lookup has addresses.
+    pub fn lookup_ip(&self, name: &str) -> Result<LookupIp, ResolveError> {
+        let mut name = Name::from_ascii(name)?;
+        name.set_fqdn(true);
+
+        let ipv4 = self.lookup(name.clone(), RecordType::A);
+        let ipv6 = self.lookup(name, RecordType::AAAA);
+
+        match (ipv4, ipv6) {
+            (Ok(ipv4), Ok(ipv6)) => {
+                let records = ipv4
+                    .records()
+                    .iter()
+                    .chain(ipv6.records())
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let valid_until = ipv4.valid_until().min(ipv6.valid_until());
+
+                Ok(
+                    Lookup::new_with_deadline(ipv4.query().clone(), records.into(), valid_until)
+                        .into(),
+                )
+            }
+            (Ok(lookup), Err(_)) | (Err(_), Ok(lookup)) => Ok(lookup.into()),
+            // A failure tells more than a missing record type
+            (Err(error), Err(other)) if is_no_records(&error) => Err(other),
+            (Err(error), Err(_)) => Err(error),
+        }
+    }
+
+    pub fn lookup(&self, name: Name, record_type: RecordType) -> Result<Lookup, ResolveError> {
+        let query = Query::query(name, record_type);
+
+        // ID is 0 as recommended for HTTP caching
+        let mut message = Message::new();
+        message
+            .set_id(0)
+            .set_message_type(MessageType::Query)
+            .set_op_code(OpCode::Query)
+            .set_recursion_desired(true)
+            .add_query(query.clone());
+
+        let response = self.post(&message.to_bytes()?)?;
+        let mut response = Message::from_bytes(&response)?;
+
+        let response_code = response.response_code();
+        let answers = response.take_answers();
+
+        if response_code == ResponseCode::NoError
+            && answers
+                .iter()
+                .any(|record| record_type == RecordType::ANY || record.record_type() == record_type)
+        {
+            Ok(Lookup::new_with_max_ttl(query, answers.into()))
+        } else {
+            Err(ResolveErrorKind::NoRecordsFound {
+                query: Box::new(query),
+                soa: None,
+                negative_ttl: None,
+                response_code,
+                trusted: true,
+            }
+            .into())
+        }
+    }
+
+    /// Returns a curl resolve entry (`host:port:addresses`) with the
+    /// addresses of the host name from the bootstrap resolver.
+    fn bootstrap_resolve_entry(&self) -> Result<Option<String>, ResolveError> {
+        let (bootstrap, host) = match (&self.bootstrap, self.url.host()) {
+            (Some(bootstrap), Some(url::Host::Domain(host))) => (bootstrap, host),
+            _ => return Ok(None),
+        };
+        let port = self.url.port_or_known_default().unwrap_or(443);
+
+        let lookup = bootstrap.lookup_ip(host)?;
+        let addresses = lookup
+            .iter()
+            .map(|address| match address {
+                IpAddr::V4(address) => address.to_string(),
+                IpAddr::V6(address) => format!("[{}]", address),
+            })
+            .collect::<Vec<String>>();
+
+        tracing::debug!(host, ?addresses, "bootstrap resolve");
+
+        Ok(Some(format!("{}:{}:{}", host, port, addresses.join(","))))
+    }
+
+    fn post(&self, body: &[u8]) -> Result<Vec<u8>, ResolveError> {
+        let resolve = self.bootstrap_resolve_entry()?;
+        let mut curl_handle = self.curl_handle.lock().unwrap();
+        let mut response = Vec::new();
+
+        self.perform(&mut curl_handle, body, resolve, &mut response)
+            .map_err(convert_curl_error)?;
+
+        let status_code = curl_handle.response_code().map_err(convert_curl_error)?;
+        let content_type = curl_handle
+            .content_type()
+            .map_err(convert_curl_error)?
+            .unwrap_or_default();
+
+        if status_code != 200 {
+            return Err(ResolveErrorKind::Msg(format!(
+                "DoH server responded with status code {}",
+                status_code
+            ))
+            .into());
+        }
+
+        if !content_type.starts_with(CONTENT_TYPE) {
+            return Err(ResolveErrorKind::Msg(format!(
+                "DoH server responded with content type {:?}",
+                content_type
+            ))
+            .into());
+        }
+
+        Ok(response)
+    }
+
+    fn perform(
+        &self,
+        curl_handle: &mut Easy,
+        body: &[u8],
+        resolve: Option<String>,
+        response: &mut Vec<u8>,
+    ) -> Result<(), curl::Error> {
+        let mut headers = List::new();
+        headers.append(&format!("Content-Type: {}", CONTENT_TYPE))?;
+        headers.append(&format!("Accept: {}", CONTENT_TYPE))?;
+
+        curl_handle.reset();
+        curl_handle.url(self.url.as_str())?;
+        curl_handle.post(true)?;
+        curl_handle.post_fields_copy(body)?;
+        curl_handle.http_headers(headers)?;
+        curl_handle.timeout(self.timeout)?;
+
+        if let Some(address) = self.bind_address {
+            curl_handle.interface(&address.to_string())?;
+        }
+
+        if let Some(resolve) = resolve {
+            let mut list = List::new();
+            list.append(&resolve)?;
+            curl_handle.resolve(list)?;
+        }
+
+        let mut transfer = curl_handle.transfer();
+        transfer.write_function(|data| {
+            if response.len() + data.len() > MAX_MESSAGE_SIZE {
+                return Ok(0);
+            }
+
+            response.extend_from_slice(data);
+            Ok(data.len())
+        })?;
+        transfer.perform()
+    }
+}
+
+fn convert_curl_error(error: curl::Error) -> ResolveError {
+    if error.is_operation_timedout() {
+        ResolveErrorKind::Timeout.into()
+    } else {
+        ResolveErrorKind::Msg(error.to_string()).into()
+    }
+}
+
+fn is_no_records(error: &ResolveError) -> bool {
+    matches!(error.kind(), ResolveErrorKind::NoRecordsFound { .. })
+}
+
+/// Parses a DoH URI template, such as
+/// `https://dns.example/dns-query{?dns}`.
+///
+/// Queries are sent with POST, so the `dns` variable is dropped.
+pub fn parse_doh_url_template(template: &str) -> Option<Url> {
+    let url = Url::parse(template.trim_end_matches("{?dns}")).ok()?;
+
+    match url.scheme() {
+        "https" | "http" if url.host().is_some() => Some(url),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_doh_url_template() {
+        assert_eq!(
+            parse_doh_url_template("https://dns.example:8443/custom/path{?dns}")
+                .unwrap()
+                .as_str(),
+            "https://dns.example:8443/custom/path"
+        );
+        assert!(parse_doh_url_template("https://dns.example/dns-query").is_some());
+        assert!(parse_doh_url_template("ftp://dns.example/").is_none());
+        assert!(parse_doh_url_template("dns.example").is_none());
+    }
+}
@@ -0,0 +1,157 @@
+use std::{collections::HashMap, net::IpAddr, path::Path};
+
+use trust_dns_resolver::lookup_ip::LookupIp as TrustLookupIp;
+
+use crate::error::Error;
+
+/// Time to live of records made from static entries.
+const STATIC_TTL: u32 = 0;
+
+/// Names that resolve to fixed addresses without querying servers.
+#[derive(Debug, Default)]
+pub struct StaticHosts {
+    entries: HashMap<String, Vec<IpAddr>>,
+}
+
+impl StaticHosts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the entries of a hosts file in the `/etc/hosts` format.
+    ///
+    /// Entries with invalid names are logged and skipped.
+    pub fn load_file(&mut self, path: &Path) -> Result<(), Error> {
+        let text = std::fs::read_to_string(path)?;
+        self.load_text(&text);
+
+        Ok(())
+    }
+
+    fn load_text(&mut self, text: &str) {
+        for (line_number, name, address) in parse_hosts(text) {
+            match normalize_name(&name) {
+                Ok(name) => self.entries.entry(name).or_default().push(address),
+                Err(error) => {
+                    tracing::warn!(line_number, name, %error, "skipped invalid hosts entry");
+                }
+            }
+        }
+    }
+
+    /// Sets the addresses of a name, replacing any previous entry.
+    pub fn insert(&mut self, name: &str, addresses: Vec<IpAddr>) -> Result<(), Error> {
+        self.entries.insert(normalize_name(name)?, addresses);
+        Ok(())
+    }
+
+    /// Returns the addresses of an ASCII name as a lookup.
+    pub fn lookup(&self, ascii_name: &str) -> Option<TrustLookupIp> {
+        let addresses = self
+            .entries
+            .get(ascii_name.trim_end_matches('.'))
+            .filter(|addresses| !addresses.is_empty())?;
+
+        super::address_lookup(ascii_name, addresses, STATIC_TTL)
+    }
+}
+
+fn normalize_name(name: &str) -> Result<String, Error> {
+    crate::string::domain_to_ascii(name.trim_end_matches('.'))
+}
+
+/// Parses lines of an address followed by host names into the line
+/// number, name, and address of each entry.
+///
+/// Comments and lines with invalid addresses are ignored.
+fn parse_hosts(text: &str) -> Vec<(usize, String, IpAddr)> {
+    let mut entries = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut fields = line.split_ascii_whitespace();
+
+        let address = match fields.next().and_then(|field| field.parse::<IpAddr>().ok()) {
+            Some(address) => address,
+            None => continue,
+        };
+
+        for name in fields {
+            entries.push((index + 1, name.to_string(), address));
+        }
+    }
+
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_hosts() {
+        let entries = parse_hosts(
+            "# comment\n\
+            127.0.0.1 localhost localhost.localdomain\n\
+            \n\
+            ::1\tip6-localhost # loopback\n\
+            not-an-address example.com\n",
+        );
+
+        assert_eq!(
+            entries,
+            vec![
+                (2, "localhost".to_string(), IpAddr::V4(Ipv4Addr::LOCALHOST)),
+                (
+                    2,
+                    "localhost.localdomain".to_string(),
+                    IpAddr::V4(Ipv4Addr::LOCALHOST)
+                ),
+                (
+                    4,
+                    "ip6-localhost".to_string(),
+                    IpAddr::V6(Ipv6Addr::LOCALHOST)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_static_hosts_skip_invalid_name() {
+        let mut hosts = StaticHosts::new();
+        hosts.load_text(
+            "192.0.2.1 xn--a.example first.example\n\
+            192.0.2.2 second.example\n",
+        );
+
+        assert!(hosts.lookup("xn--a.example").is_none());
+        assert!(hosts.lookup("first.example").is_some());
+        assert!(hosts.lookup("second.example").is_some());
+    }
+
+    #[test]
+    fn test_static_hosts_lookup() {
+        let mut hosts = StaticHosts::new();
+        hosts
+            .insert(
+                "Staging.Example.com.",
+                vec![
+                    IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+                    IpAddr::V6(Ipv6Addr::LOCALHOST),
+                ],
+            )
+            .unwrap();
+
+        let lookup = hosts.lookup("staging.example.com").unwrap();
+        assert_eq!(
+            lookup.iter().collect::<Vec<IpAddr>>(),
+            vec![
+                IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+                IpAddr::V6(Ipv6Addr::LOCALHOST)
+            ]
+        );
+        assert!(hosts.lookup("example.com").is_none());
+    }
+}
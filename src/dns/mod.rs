@@ -0,0 +1,1272 @@
+mod cache;
+mod doh;
+mod hosts;
+mod record;
+
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::error::{Error, NetworkError};
+
+pub use self::record::{DnsRecord, RecordFields, RecordType};
+
+use self::{cache::DnsCache, doh::DohClient, hosts::StaticHosts};
+use serde::Serialize;
+use trust_dns_resolver::{
+    config::ResolverOpts as TrustResolverOpts,
+    config::{NameServerConfig, Protocol, ResolverConfig as TrustResolverConfig},
+    error::{ResolveError, ResolveErrorKind},
+    lookup::Lookup,
+    lookup_ip::LookupIp as TrustLookupIp,
+    proto::op::{Query, ResponseCode},
+    proto::rr::{Name, RData, Record, RecordType as TrustRecordType},
+    Resolver as TrustResolver,
+};
+use url::Url;
+
+/// Default of [`Config::timeout`].
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Upper bound of the timeout of DNS-over-QUIC servers.
+#[cfg(feature = "dns-over-quic")]
+const DOQ_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    doq_servers: Vec<(SocketAddr, String)>,
+    dot_servers: Vec<(SocketAddr, String)>,
+    doh_servers: Vec<(SocketAddr, String)>,
+    doh_urls: Vec<Url>,
+    insecure_doh: bool,
+    udp_servers: Vec<SocketAddr>,
+    system_resolver: bool,
+    bootstrap_servers: Vec<SocketAddr>,
+    static_entries: Vec<(String, Vec<IpAddr>)>,
+    hosts_file: Option<PathBuf>,
+    cache_file: Option<PathBuf>,
+    bind_address: Option<IpAddr>,
+    probe_interval: Option<Duration>,
+    reject_confusable: bool,
+    timeout: Option<Duration>,
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_suggested_servers(mut self) -> Self {
+        self.add_doh_server(
+            SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(9, 9, 9, 10), 443)),
+            "dns10.quad9.net",
+        );
+        self.add_doh_server(
+            SocketAddr::V6(SocketAddrV6::new(
+                Ipv6Addr::new(0x2620, 0xfe, 0, 0, 0, 0, 0, 0x10),
+                443,
+                0,
+                0,
+            )),
+            "dns10.quad9.net",
+        );
+
+        self.add_doh_server(
+            SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(1, 1, 1, 1), 443)),
+            "cloudflare-dns.com",
+        );
+        self.add_doh_server(
+            SocketAddr::V6(SocketAddrV6::new(
+                Ipv6Addr::new(0x2606, 0x4700, 0x4700, 0, 0, 0, 0, 0x1111),
+                443,
+                0,
+                0,
+            )),
+            "cloudflare-dns.com",
+        );
+
+        self.add_doh_server(
+            SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(8, 8, 8, 8), 443)),
+            "dns.google",
+        );
+        self.add_doh_server(
+            SocketAddr::V6(SocketAddrV6::new(
+                Ipv6Addr::new(0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8888),
+                443,
+                0,
+                0,
+            )),
+            "dns.google",
+        );
+        self
+    }
+
+    /// DNS-over-QUIC (RFC 9250) servers, queried before the DoH servers.
+    ///
+    /// They are skipped if [`doq_supported`] is false.
+    pub fn doq_servers(&self) -> &[(SocketAddr, String)] {
+        self.doq_servers.as_ref()
+    }
+
+    pub fn set_doq_servers(&mut self, servers: Vec<(SocketAddr, String)>) -> &mut Self {
+        self.doq_servers = servers;
+        self
+    }
+
+    pub fn add_doq_server<N: Into<String>>(&mut self, address: SocketAddr, domain: N) -> &mut Self {
+        self.doq_servers.push((address, domain.into()));
+        self
+    }
+
+    /// DNS-over-TLS servers, queried before the DoH servers.
+    pub fn dot_servers(&self) -> &[(SocketAddr, String)] {
+        self.dot_servers.as_ref()
+    }
+
+    pub fn set_dot_servers(&mut self, servers: Vec<(SocketAddr, String)>) -> &mut Self {
+        self.dot_servers = servers;
+        self
+    }
+
+    pub fn add_dot_server<N: Into<String>>(&mut self, address: SocketAddr, domain: N) -> &mut Self {
+        self.dot_servers.push((address, domain.into()));
+        self
+    }
+
+    pub fn doh_servers(&self) -> &[(SocketAddr, String)] {
+        self.doh_servers.as_ref()
+    }
+
+    pub fn set_doh_servers(&mut self, servers: Vec<(SocketAddr, String)>) -> &mut Self {
+        self.doh_servers = servers;
+        self
+    }
+
+    pub fn add_doh_server<N: Into<String>>(&mut self, address: SocketAddr, domain: N) -> &mut Self {
+        self.doh_servers.push((address, domain.into()));
+        self
+    }
+
+    /// DoH servers given by URL, queried after the servers given by address.
+    pub fn doh_urls(&self) -> &[Url] {
+        self.doh_urls.as_ref()
+    }
+
+    /// Adds a DoH server by its URL or URI template, such as
+    /// `https://dns.example:8443/custom/dns-query{?dns}`.
+    ///
+    /// The host name of the URL is resolved by the bootstrap servers, or
+    /// the system resolver if there are none. `http://` URLs are rejected
+    /// by [`Resolver::new`] unless [`Self::insecure_doh`] is enabled.
+    pub fn add_doh_url(&mut self, template: &str) -> Result<&mut Self, Error> {
+        let url = doh::parse_doh_url_template(template).ok_or_else(|| Error::InvalidArgument {
+            value: template.to_string(),
+            reason: "not a HTTP or HTTPS URL".to_string(),
+        })?;
+
+        self.doh_urls.push(url);
+        Ok(self)
+    }
+
+    /// Whether [`Self::doh_urls`] may be `http://` URLs, which send queries
+    /// unencrypted, such as to a server for testing.
+    pub fn insecure_doh(&self) -> bool {
+        self.insecure_doh
+    }
+
+    pub fn set_insecure_doh(&mut self, enabled: bool) -> &mut Self {
+        self.insecure_doh = enabled;
+        self
+    }
+
+    /// Plain DNS (UDP) servers, queried after the DoH servers.
+    pub fn udp_servers(&self) -> &[SocketAddr] {
+        self.udp_servers.as_ref()
+    }
+
+    pub fn set_udp_servers(&mut self, servers: Vec<SocketAddr>) -> &mut Self {
+        self.udp_servers = servers;
+        self
+    }
+
+    pub fn add_udp_server(&mut self, address: SocketAddr) -> &mut Self {
+        self.udp_servers.push(address);
+        self
+    }
+
+    /// Whether the resolver of the operating system is queried after all
+    /// other servers.
+    pub fn system_resolver(&self) -> bool {
+        self.system_resolver
+    }
+
+    pub fn set_system_resolver(&mut self, enabled: bool) -> &mut Self {
+        self.system_resolver = enabled;
+        self
+    }
+
+    /// Plain DNS (UDP) servers used to resolve the host names of
+    /// [`Self::doh_urls`].
+    pub fn bootstrap_servers(&self) -> &[SocketAddr] {
+        self.bootstrap_servers.as_ref()
+    }
+
+    pub fn set_bootstrap_servers(&mut self, servers: Vec<SocketAddr>) -> &mut Self {
+        self.bootstrap_servers = servers;
+        self
+    }
+
+    /// Names that resolve to fixed addresses without querying servers.
+    ///
+    /// They take precedence over [`Self::hosts_file`].
+    pub fn static_entries(&self) -> &[(String, Vec<IpAddr>)] {
+        self.static_entries.as_ref()
+    }
+
+    pub fn add_static_entry<N: Into<String>>(
+        &mut self,
+        name: N,
+        addresses: Vec<IpAddr>,
+    ) -> &mut Self {
+        self.static_entries.push((name.into(), addresses));
+        self
+    }
+
+    /// File in the `/etc/hosts` format with additional static entries.
+    pub fn hosts_file(&self) -> Option<&Path> {
+        self.hosts_file.as_deref()
+    }
+
+    pub fn set_hosts_file<P: Into<PathBuf>>(&mut self, path: Option<P>) -> &mut Self {
+        self.hosts_file = path.map(Into::into);
+        self
+    }
+
+    /// File that the answer cache is loaded from when the resolver is
+    /// created and saved to by [`Resolver::save_cache`].
+    pub fn cache_file(&self) -> Option<&Path> {
+        self.cache_file.as_deref()
+    }
+
+    pub fn set_cache_file<P: Into<PathBuf>>(&mut self, path: Option<P>) -> &mut Self {
+        self.cache_file = path.map(Into::into);
+        self
+    }
+
+    pub fn bind_address(&self) -> Option<IpAddr> {
+        self.bind_address
+    }
+
+    pub fn set_bind_address(&mut self, address: Option<IpAddr>) -> &mut Self {
+        self.bind_address = address;
+        self
+    }
+
+    /// How often servers are probed for health and latency by a background
+    /// thread, starting when the resolver is created. Disabled if `None`.
+    pub fn probe_interval(&self) -> Option<Duration> {
+        self.probe_interval
+    }
+
+    pub fn set_probe_interval(&mut self, interval: Option<Duration>) -> &mut Self {
+        self.probe_interval = interval;
+        self
+    }
+
+    /// How long a server is waited for before the next one is tried.
+    ///
+    /// Defaults to 20 seconds. DNS-over-QUIC servers are waited for at most
+    /// 5 seconds, so that an unreachable one delays lookups only briefly.
+    pub fn timeout(&self) -> Duration {
+        self.timeout.unwrap_or(DEFAULT_TIMEOUT)
+    }
+
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Whether names mixing scripts in a confusable way are rejected.
+    pub fn reject_confusable(&self) -> bool {
+        self.reject_confusable
+    }
+
+    pub fn set_reject_confusable(&mut self, enabled: bool) -> &mut Self {
+        self.reject_confusable = enabled;
+        self
+    }
+}
+
+/// Returns whether DNS-over-QUIC servers can be used.
+///
+/// Requires the `dns-over-quic` feature.
+pub fn doq_supported() -> bool {
+    cfg!(feature = "dns-over-quic")
+}
+
+/// Query statistics of a name server.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ServerStats {
+    /// Description of the server.
+    pub name: String,
+    pub queries: u64,
+    pub failures: u64,
+    pub consecutive_failures: u32,
+    /// Smoothed response time of successful queries.
+    pub latency: Option<Duration>,
+    pub last_error: Option<String>,
+}
+
+impl ServerStats {
+    fn record_success(&mut self, elapsed: Duration) {
+        self.queries += 1;
+        self.consecutive_failures = 0;
+        self.latency = Some(match self.latency {
+            Some(latency) => (latency * 7 + elapsed) / 8,
+            None => elapsed,
+        });
+    }
+
+    fn record_failure(&mut self, error: &ResolveError) {
+        self.queries += 1;
+        self.failures += 1;
+        self.consecutive_failures += 1;
+        self.last_error = Some(error.to_string());
+    }
+}
+
+/// Protocol used to query a name server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServerProtocol {
+    Udp,
+    Tls,
+    Https,
+    Quic,
+    /// Resolver of the operating system.
+    System,
+}
+
+impl std::fmt::Display for ServerProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ServerProtocol::Udp => "udp",
+            ServerProtocol::Tls => "tls",
+            ServerProtocol::Https => "https",
+            ServerProtocol::Quic => "quic",
+            ServerProtocol::System => "system",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Where the answer of a lookup came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnswerSource {
+    /// Static entries or the hosts file.
+    Static,
+    /// Answer cache, which doesn't remember the server that answered.
+    Cache,
+    /// Name server that was queried.
+    Server(ServerAnswer),
+}
+
+/// Name server that answered a query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerAnswer {
+    /// Description of the server, as in [`ServerStats::name`].
+    pub name: String,
+    /// Address of the server, if known.
+    ///
+    /// For DoH servers given by URL, it is the address of the last
+    /// connection.
+    pub address: Option<SocketAddr>,
+    pub protocol: ServerProtocol,
+    /// Time from sending the query to receiving the answer.
+    pub round_trip: Duration,
+}
+
+enum Backend {
+    Trust(Box<TrustResolver>),
+    Doh(DohClient),
+    /// Resolver of the operating system (getaddrinfo).
+    System,
+}
+
+impl Backend {
+    fn lookup_ip(&self, name: &str) -> Result<TrustLookupIp, ResolveError> {
+        match self {
+            Backend::Trust(resolver) => resolver.lookup_ip(name),
+            Backend::Doh(client) => client.lookup_ip(name),
+            Backend::System => system_lookup_ip(name),
+        }
+    }
+
+    fn lookup(&self, name: &str, record_type: TrustRecordType) -> Result<Lookup, ResolveError> {
+        match self {
+            Backend::Trust(resolver) => resolver.lookup(name, record_type),
+            Backend::Doh(client) => client.lookup(Name::from_ascii(name)?, record_type),
+            Backend::System => match record_type {
+                TrustRecordType::A | TrustRecordType::AAAA => {
+                    let lookup = system_lookup_ip(name)?;
+                    let records = lookup
+                        .as_lookup()
+                        .records()
+                        .iter()
+                        .filter(|record| record.record_type() == record_type)
+                        .cloned()
+                        .collect::<Vec<Record>>();
+
+                    Ok(Lookup::new_with_max_ttl(
+                        lookup.query().clone(),
+                        records.into(),
+                    ))
+                }
+                _ => Err(ResolveErrorKind::Msg(format!(
+                    "{} records not supported by the system resolver",
+                    record_type
+                ))
+                .into()),
+            },
+        }
+    }
+
+    /// Returns the address of the last connection if the backend doesn't
+    /// have a fixed one.
+    fn peer_address(&self) -> Option<SocketAddr> {
+        match self {
+            Backend::Doh(client) => client.peer_address(),
+            _ => None,
+        }
+    }
+
+    fn lookup_probe(&self, name: &str) -> Result<(), ResolveError> {
+        self.lookup(name, TrustRecordType::A).map(|_| ())
+    }
+}
+
+struct ServerEntry {
+    backend: Backend,
+    address: Option<SocketAddr>,
+    protocol: ServerProtocol,
+    stats: Mutex<ServerStats>,
+}
+
+impl ServerEntry {
+    /// Creates a server handled by a single-server trust-dns resolver.
+    fn new_trust(
+        address: SocketAddr,
+        domain: Option<String>,
+        protocol: ServerProtocol,
+        bind_address: Option<IpAddr>,
+        options: TrustResolverOpts,
+    ) -> Result<Self, Error> {
+        let name = match &domain {
+            Some(domain) => format!("{} ({})", domain, address),
+            None => address.to_string(),
+        };
+        let trust_protocol = match protocol {
+            ServerProtocol::Udp => Protocol::Udp,
+            ServerProtocol::Tls => Protocol::Tls,
+            ServerProtocol::Https => Protocol::Https,
+            #[cfg(feature = "dns-over-quic")]
+            ServerProtocol::Quic => Protocol::Quic,
+            #[allow(unreachable_patterns)]
+            _ => {
+                return Err(Error::UnsupportedFeature {
+                    feature: format!("{} name servers", protocol),
+                })
+            }
+        };
+
+        let mut name_server = NameServerConfig::new(address, trust_protocol);
+        name_server.tls_dns_name = domain;
+        name_server.bind_addr = bind_address.map(|v| SocketAddr::new(v, 0));
+
+        let mut trust_config = TrustResolverConfig::new();
+        trust_config.add_name_server(name_server);
+
+        Ok(Self {
+            backend: Backend::Trust(Box::new(TrustResolver::new(trust_config, options)?)),
+            address: Some(address),
+            protocol,
+            stats: Mutex::new(ServerStats {
+                name,
+                ..Default::default()
+            }),
+        })
+    }
+}
+
+pub struct Resolver {
+    servers: Arc<Vec<ServerEntry>>,
+    static_hosts: StaticHosts,
+    cache: Arc<DnsCache>,
+    cache_file: Option<PathBuf>,
+    probe_interval: Option<Duration>,
+    /// Stops the probe thread when dropped.
+    _probe_stop: Option<mpsc::Sender<()>>,
+    reject_confusable: bool,
+}
+
+impl std::fmt::Debug for Resolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Resolver")
+            .field("servers", &self.servers.len())
+            .field("probe_interval", &self.probe_interval)
+            .field("cache_file", &self.cache_file)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Resolver {
+    pub fn new(config: Config) -> Result<Self, Error> {
+        let mut trust_options = TrustResolverOpts::default();
+        trust_options.timeout = config.timeout();
+        trust_options.use_hosts_file = false;
+
+        let mut servers = Vec::new();
+
+        if let Some(url) = config
+            .doh_urls
+            .iter()
+            .find(|url| url.scheme() != "https" && !config.insecure_doh)
+        {
+            return Err(Error::InvalidArgument {
+                value: url.to_string(),
+                reason: "DoH URL is not HTTPS".to_string(),
+            });
+        }
+
+        if !config.doq_servers.is_empty() && !doq_supported() {
+            if config.doh_servers.is_empty() && config.doh_urls.is_empty() {
+                return Err(Error::UnsupportedFeature {
+                    feature: "DNS-over-QUIC".to_string(),
+                });
+            }
+
+            tracing::warn!("DNS-over-QUIC not supported, using DoH servers only");
+        }
+
+        #[cfg(feature = "dns-over-quic")]
+        for (address, domain) in config.doq_servers {
+            // A QUIC connection attempt has no refusal to fail fast on, so
+            // an unreachable server is only noticed by the timeout
+            let mut doq_options = trust_options;
+            doq_options.timeout = doq_options.timeout.min(DOQ_TIMEOUT);
+            doq_options.attempts = 1;
+
+            servers.push(ServerEntry::new_trust(
+                address,
+                Some(domain),
+                ServerProtocol::Quic,
+                config.bind_address,
+                doq_options,
+            )?);
+        }
+
+        for (address, domain) in config.dot_servers {
+            servers.push(ServerEntry::new_trust(
+                address,
+                Some(domain),
+                ServerProtocol::Tls,
+                config.bind_address,
+                trust_options,
+            )?);
+        }
+
+        for (address, domain) in config.doh_servers {
+            servers.push(ServerEntry::new_trust(
+                address,
+                Some(domain),
+                ServerProtocol::Https,
+                config.bind_address,
+                trust_options,
+            )?);
+        }
+
+        let bootstrap = if config.bootstrap_servers.is_empty() {
+            None
+        } else {
+            let mut trust_config = TrustResolverConfig::new();
+
+            for address in config.bootstrap_servers {
+                let protocol = Protocol::Udp;
+                let mut name_server = NameServerConfig::new(address, protocol);
+                name_server.bind_addr = config.bind_address.map(|v| SocketAddr::new(v, 0));
+
+                trust_config.add_name_server(name_server);
+            }
+
+            Some(Arc::new(TrustResolver::new(trust_config, trust_options)?))
+        };
+
+        for url in config.doh_urls {
+            let name = url.to_string();
+            let mut client = DohClient::new(url, config.bind_address, trust_options.timeout);
+
+            if let Some(bootstrap) = &bootstrap {
+                client.set_bootstrap(bootstrap.clone());
+            }
+
+            servers.push(ServerEntry {
+                backend: Backend::Doh(client),
+                address: None,
+                protocol: ServerProtocol::Https,
+                stats: Mutex::new(ServerStats {
+                    name,
+                    ..Default::default()
+                }),
+            });
+        }
+
+        for address in config.udp_servers {
+            servers.push(ServerEntry::new_trust(
+                address,
+                None,
+                ServerProtocol::Udp,
+                config.bind_address,
+                trust_options,
+            )?);
+        }
+
+        if config.system_resolver {
+            servers.push(ServerEntry {
+                backend: Backend::System,
+                address: None,
+                protocol: ServerProtocol::System,
+                stats: Mutex::new(ServerStats {
+                    name: "system".to_string(),
+                    ..Default::default()
+                }),
+            });
+        }
+
+        let mut static_hosts = StaticHosts::new();
+
+        if let Some(path) = &config.hosts_file {
+            static_hosts.load_file(path)?;
+        }
+
+        for (name, addresses) in config.static_entries {
+            static_hosts.insert(&name, addresses)?;
+        }
+
+        let cache = match &config.cache_file {
+            Some(path) => DnsCache::load(path)?,
+            None => DnsCache::new(),
+        };
+        let cache = Arc::new(cache);
+
+        let servers = Arc::new(servers);
+        let probe_stop = config
+            .probe_interval
+            .map(|interval| spawn_probe_thread(servers.clone(), interval))
+            .transpose()?;
+
+        Ok(Self {
+            servers,
+            static_hosts,
+            cache,
+            cache_file: config.cache_file,
+            probe_interval: config.probe_interval,
+            _probe_stop: probe_stop,
+            reject_confusable: config.reject_confusable,
+        })
+    }
+
+    /// Uses the answer cache of another resolver, such as when resolvers of
+    /// several threads should share answers.
+    pub fn share_cache(&mut self, other: &Resolver) -> &mut Self {
+        self.cache = other.cache.clone();
+        self
+    }
+
+    /// Saves the answer cache to [`Config::cache_file`] if set.
+    pub fn save_cache(&self) -> Result<(), Error> {
+        match &self.cache_file {
+            Some(path) => self.cache.save(path),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the statistics of the servers in the configured order.
+    pub fn server_stats(&self) -> Vec<ServerStats> {
+        self.servers
+            .iter()
+            .map(|server| server.stats.lock().unwrap().clone())
+            .collect()
+    }
+
+    /// Sends a query to every server to measure its health and latency.
+    ///
+    /// Each probe asks for a unique name under the reserved `invalid.`
+    /// domain so the answer can't come from a cache.
+    pub fn probe(&self) {
+        probe_servers(&self.servers);
+    }
+
+    /// Looks up the addresses of a domain name.
+    ///
+    /// Internationalized names are converted to punycode for resolution.
+    ///
+    /// Names with static entries or cached answers are answered without
+    /// querying servers.
+    ///
+    /// Servers are tried in order of health and latency until one gives
+    /// an answer.
+    pub fn lookup_ip_address<S: AsRef<str>>(&self, name: S) -> Result<IpAddressLookup, Error> {
+        let (name, ascii_name) = self.check_name(name.as_ref())?;
+
+        let span = tracing::info_span!("resolver_lookup_ip_address", name, ascii_name);
+        let _guard = span.enter();
+
+        if let Some(lookup) = self.static_hosts.lookup(&ascii_name) {
+            tracing::debug!("lookup IP address static");
+
+            return Ok(IpAddressLookup {
+                name,
+                ascii_name,
+                addresses: lookup.iter().collect(),
+                inner: lookup,
+                source: AnswerSource::Static,
+            });
+        }
+
+        let cache_key = ascii_name.trim_end_matches('.').to_string();
+
+        if let Some(lookup) = self.cache.get(&cache_key) {
+            tracing::debug!("lookup IP address cached");
+
+            return Ok(IpAddressLookup {
+                name,
+                ascii_name,
+                addresses: lookup.iter().collect(),
+                inner: lookup,
+                source: AnswerSource::Cache,
+            });
+        }
+
+        tracing::debug!("lookup IP address start");
+
+        let (lookup, server) = self.query_servers(|backend| backend.lookup_ip(&ascii_name))?;
+
+        tracing::debug!(len = lookup.iter().count(), "lookup IP address ok");
+
+        self.cache.insert(&cache_key, &lookup);
+
+        Ok(IpAddressLookup {
+            name,
+            ascii_name,
+            addresses: lookup.iter().collect(),
+            inner: lookup,
+            source: AnswerSource::Server(server),
+        })
+    }
+
+    /// Looks up the records of a domain name with the given type.
+    ///
+    /// Static entries answer A and AAAA lookups. Answers are not cached.
+    pub fn lookup_records<S: AsRef<str>>(
+        &self,
+        name: S,
+        record_type: RecordType,
+    ) -> Result<RecordLookup, Error> {
+        let (name, ascii_name) = self.check_name(name.as_ref())?;
+
+        let span = tracing::info_span!(
+            "resolver_lookup_records",
+            name,
+            ascii_name,
+            %record_type
+        );
+        let _guard = span.enter();
+
+        let trust_type = record_type.to_trust();
+
+        if let (RecordType::A | RecordType::Aaaa, Some(lookup)) =
+            (record_type, self.static_hosts.lookup(&ascii_name))
+        {
+            tracing::debug!("lookup records static");
+
+            let records = lookup
+                .as_lookup()
+                .records()
+                .iter()
+                .filter(|record| record.record_type() == trust_type)
+                .map(DnsRecord::from)
+                .collect();
+
+            return Ok(RecordLookup {
+                name,
+                ascii_name,
+                records,
+                source: AnswerSource::Static,
+            });
+        }
+
+        tracing::debug!("lookup records start");
+
+        let (lookup, server) =
+            self.query_servers(|backend| backend.lookup(&ascii_name, trust_type))?;
+
+        tracing::debug!(len = lookup.records().len(), "lookup records ok");
+
+        Ok(RecordLookup {
+            name,
+            ascii_name,
+            records: lookup.records().iter().map(DnsRecord::from).collect(),
+            source: AnswerSource::Server(server),
+        })
+    }
+
+    /// Returns the Unicode and ASCII forms of a name if it is allowed.
+    fn check_name(&self, name: &str) -> Result<(String, String), Error> {
+        let ascii_name = crate::string::domain_to_ascii(name)?;
+        let name = crate::string::domain_to_unicode(&ascii_name);
+
+        if self.reject_confusable && crate::string::is_mixed_script_confusable(&name) {
+            return Err(Error::InvalidArgument {
+                value: name,
+                reason: "mixed-script confusable domain name".to_string(),
+            });
+        }
+
+        Ok((name, ascii_name))
+    }
+
+    /// Runs a query on servers in order of health and latency until one
+    /// gives an answer, and returns it with the server that answered.
+    fn query_servers<T, F>(&self, query: F) -> Result<(T, ServerAnswer), Error>
+    where
+        F: Fn(&Backend) -> Result<T, ResolveError>,
+    {
+        let stats = self.server_stats();
+        let mut last_error = None;
+
+        for index in order_servers(&stats) {
+            let server = &self.servers[index];
+            let start = Instant::now();
+            let result = query(&server.backend);
+            let mut stats = server.stats.lock().unwrap();
+
+            match result {
+                Ok(answer) => {
+                    let round_trip = start.elapsed();
+                    stats.record_success(round_trip);
+                    tracing::debug!(server = stats.name, ?round_trip, "query ok");
+
+                    let server_answer = ServerAnswer {
+                        name: stats.name.clone(),
+                        address: server.address.or_else(|| server.backend.peer_address()),
+                        protocol: server.protocol,
+                        round_trip,
+                    };
+
+                    return Ok((answer, server_answer));
+                }
+                Err(error) if is_answer(&error) => {
+                    stats.record_success(start.elapsed());
+                    return Err(error.into());
+                }
+                Err(error) => {
+                    tracing::debug!(server = stats.name, %error, "query failed");
+                    stats.record_failure(&error);
+                    last_error = Some(error);
+                }
+            }
+        }
+
+        match last_error {
+            Some(error) => Err(error.into()),
+            None => Err(NetworkError::Dns("no name servers configured".into()).into()),
+        }
+    }
+}
+
+/// Sends a probe query to every server and records the outcome in its
+/// statistics.
+fn probe_servers(servers: &[ServerEntry]) {
+    let span = tracing::debug_span!("resolver_probe");
+    let _guard = span.enter();
+
+    for server in servers {
+        let start = Instant::now();
+        let result = server.backend.lookup_probe(&probe_name());
+        let mut stats = server.stats.lock().unwrap();
+
+        match result {
+            Ok(_) => stats.record_success(start.elapsed()),
+            Err(error) if is_answer(&error) => stats.record_success(start.elapsed()),
+            Err(error) => stats.record_failure(&error),
+        }
+
+        tracing::debug!(
+            name = stats.name,
+            latency = ?stats.latency,
+            consecutive_failures = stats.consecutive_failures,
+            "probe"
+        );
+    }
+}
+
+/// Starts a thread that probes the servers now and then at the interval,
+/// so that lookups only read the statistics.
+///
+/// The thread stops when the returned sender is dropped.
+fn spawn_probe_thread(
+    servers: Arc<Vec<ServerEntry>>,
+    interval: Duration,
+) -> Result<mpsc::Sender<()>, Error> {
+    let (sender, receiver) = mpsc::channel::<()>();
+
+    std::thread::Builder::new()
+        .name("wrecv-dns-probe".to_string())
+        .spawn(move || loop {
+            probe_servers(&servers);
+
+            match receiver.recv_timeout(interval) {
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                _ => break,
+            }
+        })?;
+
+    Ok(sender)
+}
+
+/// Looks up addresses with the resolver of the operating system.
+fn system_lookup_ip(name: &str) -> Result<TrustLookupIp, ResolveError> {
+    let addresses = system_addresses(name.trim_end_matches('.'))?;
+
+    if addresses.is_empty() {
+        return Err(no_records_error(name, "no addresses"));
+    }
+
+    address_lookup(name, &addresses, 0)
+        .ok_or_else(|| ResolveErrorKind::Msg(format!("invalid name {:?}", name)).into())
+}
+
+/// Error for a name that the system resolver has no addresses for.
+fn no_records_error(name: &str, message: &str) -> ResolveError {
+    tracing::debug!(name, message, "system lookup found no records");
+
+    ResolveErrorKind::NoRecordsFound {
+        query: Box::new(Query::query(
+            Name::from_ascii(name).unwrap_or_default(),
+            TrustRecordType::A,
+        )),
+        soa: None,
+        negative_ttl: None,
+        response_code: ResponseCode::NXDomain,
+        trusted: false,
+    }
+    .into()
+}
+
+/// Returns the addresses of a name from `getaddrinfo`.
+///
+/// Only the errors meaning that the name has no addresses become
+/// `NoRecordsFound`, so others count as failures of the resolver.
+#[cfg(unix)]
+fn system_addresses(name: &str) -> Result<Vec<IpAddr>, ResolveError> {
+    let c_name = std::ffi::CString::new(name)
+        .map_err(|_| ResolveErrorKind::Msg(format!("invalid name {:?}", name)))?;
+
+    // SAFETY: All zero is a valid addrinfo of hints without flags.
+    let mut hints = unsafe { std::mem::zeroed::<libc::addrinfo>() };
+    hints.ai_family = libc::AF_UNSPEC;
+    hints.ai_socktype = libc::SOCK_STREAM;
+
+    let mut list = std::ptr::null_mut::<libc::addrinfo>();
+
+    // SAFETY: The name and hints are valid for the call, and the list is
+    // only read on success.
+    let code = unsafe { libc::getaddrinfo(c_name.as_ptr(), std::ptr::null(), &hints, &mut list) };
+
+    if code != 0 {
+        return Err(getaddrinfo_error(name, code));
+    }
+
+    let mut addresses = Vec::<IpAddr>::new();
+    let mut entry = list;
+
+    while !entry.is_null() {
+        // SAFETY: Entries of the list are valid until it is freed, and
+        // ai_addr points to the socket address type of ai_family.
+        let address = unsafe {
+            let info = &*entry;
+            entry = info.ai_next;
+
+            match info.ai_family {
+                libc::AF_INET => {
+                    let address = &*(info.ai_addr as *const libc::sockaddr_in);
+                    IpAddr::V4(Ipv4Addr::from(u32::from_be(address.sin_addr.s_addr)))
+                }
+                libc::AF_INET6 => {
+                    let address = &*(info.ai_addr as *const libc::sockaddr_in6);
+                    IpAddr::V6(Ipv6Addr::from(address.sin6_addr.s6_addr))
+                }
+                _ => continue,
+            }
+        };
+
+        if !addresses.contains(&address) {
+            addresses.push(address);
+        }
+    }
+
+    // SAFETY: The list was returned by a successful getaddrinfo.
+    unsafe { libc::freeaddrinfo(list) };
+
+    Ok(addresses)
+}
+
+#[cfg(unix)]
+fn getaddrinfo_error(name: &str, code: libc::c_int) -> ResolveError {
+    #[cfg(any(target_os = "linux", target_os = "android", target_vendor = "apple"))]
+    let no_data = code == libc::EAI_NODATA;
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_vendor = "apple")))]
+    let no_data = false;
+
+    if code == libc::EAI_SYSTEM {
+        return ResolveErrorKind::Io(std::io::Error::last_os_error()).into();
+    }
+
+    // SAFETY: gai_strerror returns a static C string for any code.
+    let message = unsafe { std::ffi::CStr::from_ptr(libc::gai_strerror(code)) }
+        .to_string_lossy()
+        .into_owned();
+
+    if code == libc::EAI_NONAME || no_data {
+        no_records_error(name, &message)
+    } else {
+        ResolveErrorKind::Msg(format!("system lookup failed: {}", message)).into()
+    }
+}
+
+/// Returns the addresses of a name from the standard library, which doesn't
+/// tell a name without addresses from other errors.
+#[cfg(not(unix))]
+fn system_addresses(name: &str) -> Result<Vec<IpAddr>, ResolveError> {
+    use std::net::ToSocketAddrs;
+
+    let mut addresses = Vec::<IpAddr>::new();
+
+    for address in (name, 0).to_socket_addrs()? {
+        if !addresses.contains(&address.ip()) {
+            addresses.push(address.ip());
+        }
+    }
+
+    Ok(addresses)
+}
+
+/// Makes a lookup answering A or AAAA records of the given addresses.
+fn address_lookup(ascii_name: &str, addresses: &[IpAddr], ttl: u32) -> Option<TrustLookupIp> {
+    let mut name = Name::from_ascii(ascii_name).ok()?;
+    name.set_fqdn(true);
+
+    let records = addresses
+        .iter()
+        .map(|address| {
+            let rdata = match address {
+                IpAddr::V4(address) => RData::A(*address),
+                IpAddr::V6(address) => RData::AAAA(*address),
+            };
+            Record::from_rdata(name.clone(), ttl, rdata)
+        })
+        .collect::<Vec<Record>>();
+
+    let query = Query::query(name, TrustRecordType::A);
+    let valid_until = Instant::now() + Duration::from_secs(ttl.into());
+
+    Some(Lookup::new_with_deadline(query, records.into(), valid_until).into())
+}
+
+/// Returns server indexes ordered by consecutive failures and then latency.
+///
+/// Servers without a measured latency keep their configured order after
+/// the measured ones.
+fn order_servers(stats: &[ServerStats]) -> Vec<usize> {
+    let mut indexes = (0..stats.len()).collect::<Vec<usize>>();
+
+    indexes.sort_by_key(|&index| {
+        let stats = &stats[index];
+        (
+            stats.consecutive_failures,
+            stats.latency.unwrap_or(Duration::MAX),
+        )
+    });
+
+    indexes
+}
+
+/// Returns whether the error is a definitive answer, such as NXDOMAIN,
+/// rather than a failure of the server.
+fn is_answer(error: &ResolveError) -> bool {
+    match error.kind() {
+        ResolveErrorKind::NoRecordsFound { response_code, .. } => !matches!(
+            *response_code,
+            ResponseCode::ServFail | ResponseCode::Refused
+        ),
+        _ => false,
+    }
+}
+
+fn probe_name() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+
+    format!("wrecv-probe-{:x}-{:x}.invalid.", time, count)
+}
+
+#[derive(Debug, Clone)]
+pub struct IpAddressLookup {
+    name: String,
+    ascii_name: String,
+    inner: TrustLookupIp,
+    addresses: Vec<IpAddr>,
+    source: AnswerSource,
+}
+
+impl IpAddressLookup {
+    /// Unicode form of the looked up name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// ASCII (punycode) form of the looked up name.
+    pub fn ascii_name(&self) -> &str {
+        &self.ascii_name
+    }
+
+    pub fn ip_addresses(&self) -> &[IpAddr] {
+        self.addresses.as_ref()
+    }
+
+    /// Where the answer came from.
+    pub fn source(&self) -> &AnswerSource {
+        &self.source
+    }
+
+    /// Returns the records of the answer.
+    pub fn records(&self) -> Vec<DnsRecord> {
+        self.inner
+            .as_lookup()
+            .records()
+            .iter()
+            .map(DnsRecord::from)
+            .collect()
+    }
+
+    pub fn to_record_string(&self) -> String {
+        let mut buf = String::new();
+        for record in self.inner.as_lookup().records() {
+            buf.push_str(&record.to_string());
+            buf.push_str("\r\n");
+        }
+        buf
+    }
+}
+
+/// Records of a domain name with a specific type.
+#[derive(Debug, Clone)]
+pub struct RecordLookup {
+    name: String,
+    ascii_name: String,
+    records: Vec<DnsRecord>,
+    source: AnswerSource,
+}
+
+impl RecordLookup {
+    /// Unicode form of the looked up name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// ASCII (punycode) form of the looked up name.
+    pub fn ascii_name(&self) -> &str {
+        &self.ascii_name
+    }
+
+    pub fn records(&self) -> &[DnsRecord] {
+        self.records.as_ref()
+    }
+
+    /// Where the answer came from.
+    pub fn source(&self) -> &AnswerSource {
+        &self.source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_servers() {
+        let stats = vec![
+            ServerStats {
+                latency: Some(Duration::from_millis(50)),
+                ..Default::default()
+            },
+            ServerStats::default(),
+            ServerStats {
+                latency: Some(Duration::from_millis(10)),
+                ..Default::default()
+            },
+            ServerStats {
+                latency: Some(Duration::from_millis(1)),
+                consecutive_failures: 2,
+                ..Default::default()
+            },
+        ];
+
+        assert_eq!(order_servers(&stats), vec![2, 0, 1, 3]);
+    }
+
+    #[test]
+    fn test_order_servers_failed_first() {
+        let mut stats = vec![ServerStats::default(), ServerStats::default()];
+        stats[0].record_failure(&ResolveErrorKind::Timeout.into());
+
+        assert_eq!(order_servers(&stats), vec![1, 0]);
+
+        stats[1].record_success(Duration::from_millis(30));
+
+        assert_eq!(order_servers(&stats), vec![1, 0]);
+    }
+
+    #[test]
+    fn test_probe_name() {
+        assert_ne!(probe_name(), probe_name());
+        assert!(probe_name().ends_with(".invalid."));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_system_lookup_ip() {
+        let lookup = system_lookup_ip("localhost").unwrap();
+        assert!(lookup.iter().all(|address| address.is_loopback()));
+
+        assert!(matches!(
+            getaddrinfo_error("example.invalid", libc::EAI_NONAME).kind(),
+            ResolveErrorKind::NoRecordsFound { .. }
+        ));
+        assert!(matches!(
+            getaddrinfo_error("example.invalid", libc::EAI_AGAIN).kind(),
+            ResolveErrorKind::Msg(_)
+        ));
+    }
+}
@@ -0,0 +1,220 @@
+use std::{fmt::Display, net::IpAddr, str::FromStr};
+
+use serde::Serialize;
+use trust_dns_resolver::proto::rr::{RData, Record as TrustRecord, RecordType as TrustRecordType};
+
+use crate::error::Error;
+
+/// Type of records to look up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum RecordType {
+    A,
+    Aaaa,
+    Txt,
+    Mx,
+    Srv,
+    Ns,
+    Cname,
+    Https,
+    /// All records the server is willing to give.
+    Any,
+}
+
+impl RecordType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecordType::A => "A",
+            RecordType::Aaaa => "AAAA",
+            RecordType::Txt => "TXT",
+            RecordType::Mx => "MX",
+            RecordType::Srv => "SRV",
+            RecordType::Ns => "NS",
+            RecordType::Cname => "CNAME",
+            RecordType::Https => "HTTPS",
+            RecordType::Any => "ANY",
+        }
+    }
+
+    pub(super) fn to_trust(self) -> TrustRecordType {
+        match self {
+            RecordType::A => TrustRecordType::A,
+            RecordType::Aaaa => TrustRecordType::AAAA,
+            RecordType::Txt => TrustRecordType::TXT,
+            RecordType::Mx => TrustRecordType::MX,
+            RecordType::Srv => TrustRecordType::SRV,
+            RecordType::Ns => TrustRecordType::NS,
+            RecordType::Cname => TrustRecordType::CNAME,
+            RecordType::Https => TrustRecordType::HTTPS,
+            RecordType::Any => TrustRecordType::ANY,
+        }
+    }
+}
+
+impl Display for RecordType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for RecordType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "A" => Ok(RecordType::A),
+            "AAAA" => Ok(RecordType::Aaaa),
+            "TXT" => Ok(RecordType::Txt),
+            "MX" => Ok(RecordType::Mx),
+            "SRV" => Ok(RecordType::Srv),
+            "NS" => Ok(RecordType::Ns),
+            "CNAME" => Ok(RecordType::Cname),
+            "HTTPS" => Ok(RecordType::Https),
+            "ANY" => Ok(RecordType::Any),
+            _ => Err(Error::InvalidArgument {
+                value: s.to_string(),
+                reason: "unknown record type".to_string(),
+            }),
+        }
+    }
+}
+
+/// Resource record of a lookup answer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DnsRecord {
+    /// Fully qualified owner name.
+    pub name: String,
+    /// Type such as `A` or `CNAME`.
+    pub record_type: String,
+    /// Time to live in seconds.
+    pub ttl: u32,
+    /// Record data in presentation format.
+    pub data: String,
+    /// Record data split into fields for the common record types.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<RecordFields>,
+}
+
+/// Fields of the data of a record.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+#[non_exhaustive]
+pub enum RecordFields {
+    /// A and AAAA records.
+    Address {
+        address: IpAddr,
+    },
+    /// CNAME and NS records.
+    Target {
+        target: String,
+    },
+    Mx {
+        preference: u16,
+        exchange: String,
+    },
+    Srv {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: String,
+    },
+    /// TXT records with each character string decoded lossily as UTF-8.
+    Txt {
+        strings: Vec<String>,
+    },
+    Https {
+        priority: u16,
+        target: String,
+        params: Vec<String>,
+    },
+}
+
+impl From<&TrustRecord> for DnsRecord {
+    fn from(record: &TrustRecord) -> Self {
+        Self {
+            name: record.name().to_string(),
+            record_type: record.record_type().to_string(),
+            ttl: record.ttl(),
+            data: record
+                .data()
+                .map(|data| data.to_string())
+                .unwrap_or_default(),
+            fields: record.data().and_then(record_fields),
+        }
+    }
+}
+
+fn record_fields(data: &RData) -> Option<RecordFields> {
+    let fields = match data {
+        RData::A(address) => RecordFields::Address {
+            address: IpAddr::V4(*address),
+        },
+        RData::AAAA(address) => RecordFields::Address {
+            address: IpAddr::V6(*address),
+        },
+        RData::CNAME(name) | RData::NS(name) => RecordFields::Target {
+            target: name.to_string(),
+        },
+        RData::MX(mx) => RecordFields::Mx {
+            preference: mx.preference(),
+            exchange: mx.exchange().to_string(),
+        },
+        RData::SRV(srv) => RecordFields::Srv {
+            priority: srv.priority(),
+            weight: srv.weight(),
+            port: srv.port(),
+            target: srv.target().to_string(),
+        },
+        RData::TXT(txt) => RecordFields::Txt {
+            strings: txt
+                .txt_data()
+                .iter()
+                .map(|data| String::from_utf8_lossy(data).into_owned())
+                .collect(),
+        },
+        RData::HTTPS(svcb) => RecordFields::Https {
+            priority: svcb.svc_priority(),
+            target: svcb.target_name().to_string(),
+            params: svcb
+                .svc_params()
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect(),
+        },
+        _ => return None,
+    };
+
+    Some(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use trust_dns_resolver::proto::rr::{rdata::MX, Name};
+
+    use super::*;
+
+    #[test]
+    fn test_record_type_parse() {
+        assert_eq!("aaaa".parse::<RecordType>().unwrap(), RecordType::Aaaa);
+        assert_eq!("HTTPS".parse::<RecordType>().unwrap(), RecordType::Https);
+        assert!("SOA".parse::<RecordType>().is_err());
+    }
+
+    #[test]
+    fn test_dns_record_fields() {
+        let name = Name::from_ascii("example.com.").unwrap();
+        let exchange = Name::from_ascii("mail.example.com.").unwrap();
+        let record = TrustRecord::from_rdata(name, 300, RData::MX(MX::new(10, exchange)));
+
+        let record = DnsRecord::from(&record);
+
+        assert_eq!(record.record_type, "MX");
+        assert_eq!(
+            record.fields,
+            Some(RecordFields::Mx {
+                preference: 10,
+                exchange: "mail.example.com.".to_string()
+            })
+        );
+    }
+}
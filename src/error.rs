@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
 
 #[derive(Debug, thiserror::Error)]
@@ -8,6 +10,12 @@ pub enum Error {
     #[error("invalid argument {value} {reason}")]
     InvalidArgument { value: String, reason: String },
 
+    #[error("transfer cancelled")]
+    Cancelled,
+
+    #[error("blocked by request policy {value} {reason}")]
+    Blocked { value: String, reason: String },
+
     #[error(transparent)]
     Parse(#[from] ParseError),
 
@@ -24,6 +32,99 @@ pub enum Error {
     Other(#[from] OtherError),
 }
 
+impl Error {
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Error::UnsupportedFeature { .. } => ErrorCategory::UnsupportedFeature,
+            Error::InvalidArgument { .. } => ErrorCategory::InvalidArgument,
+            Error::Cancelled => ErrorCategory::Cancelled,
+            Error::Blocked { .. } => ErrorCategory::Blocked,
+            Error::Parse(_) => ErrorCategory::Parse,
+            Error::Protocol(_) => ErrorCategory::Protocol,
+            Error::Network(_) => ErrorCategory::Network,
+            Error::Io(_) => ErrorCategory::Io,
+            Error::Other(_) => ErrorCategory::Other,
+        }
+    }
+
+    /// Returns whether trying the operation again later may succeed.
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            Error::Network(_) => true,
+            #[cfg(feature = "curl")]
+            Error::Other(OtherError::Curl(error)) => {
+                error.is_send_error()
+                    || error.is_recv_error()
+                    || error.is_got_nothing()
+                    || error.is_partial_file()
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the libcurl error code if the error originated from libcurl.
+    #[cfg(feature = "curl")]
+    pub fn curl_code(&self) -> Option<curl_sys::CURLcode> {
+        let source = match self {
+            Error::Other(OtherError::Curl(error)) => return Some(error.code()),
+            Error::Network(
+                NetworkError::Connect(source)
+                | NetworkError::Dns(source)
+                | NetworkError::TimedOut(source)
+                | NetworkError::Disconnected(source)
+                | NetworkError::Custom(source),
+            ) => source,
+            Error::Protocol(
+                ProtocolError::InvalidRequest(source)
+                | ProtocolError::InvalidResponse(source)
+                | ProtocolError::Custom(source),
+            ) => source,
+            Error::Protocol(ProtocolError::TlsVerification(error)) => error.source.as_ref()?,
+            _ => return None,
+        };
+
+        source
+            .downcast_ref::<curl::Error>()
+            .map(|error| error.code())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ErrorCategory {
+    UnsupportedFeature,
+    InvalidArgument,
+    Cancelled,
+    Blocked,
+    Parse,
+    Protocol,
+    Network,
+    Io,
+    Other,
+}
+
+impl ErrorCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCategory::UnsupportedFeature => "unsupported_feature",
+            ErrorCategory::InvalidArgument => "invalid_argument",
+            ErrorCategory::Cancelled => "cancelled",
+            ErrorCategory::Blocked => "blocked",
+            ErrorCategory::Parse => "parse",
+            ErrorCategory::Protocol => "protocol",
+            ErrorCategory::Network => "network",
+            ErrorCategory::Io => "io",
+            ErrorCategory::Other => "other",
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "curl")]
 impl From<curl::Error> for Error {
     fn from(value: curl::Error) -> Self {
         if value.is_couldnt_connect() {
@@ -35,7 +136,12 @@ impl From<curl::Error> for Error {
             || value.is_peer_failed_verification()
             || value.is_ssl_issuer_error()
         {
-            Self::Protocol(ProtocolError::TlsVerification(Box::new(value)))
+            let message = value.to_string();
+
+            Self::Protocol(ProtocolError::TlsVerification(
+                TlsVerificationError::new(TlsVerificationKind::from_message(&message), message)
+                    .with_source(Box::new(value)),
+            ))
         } else if value.is_operation_timedout() {
             Self::Network(NetworkError::TimedOut(Box::new(value)))
         } else {
@@ -44,6 +150,7 @@ impl From<curl::Error> for Error {
     }
 }
 
+#[cfg(feature = "dns")]
 impl From<trust_dns_resolver::error::ResolveError> for Error {
     fn from(value: trust_dns_resolver::error::ResolveError) -> Self {
         match value.kind() {
@@ -151,12 +258,139 @@ pub enum ProtocolError {
     InvalidResponse(BoxedError),
 
     #[error("TLS verification error: {0}")]
-    TlsVerification(BoxedError),
+    TlsVerification(TlsVerificationError),
+
+    #[error("header exceeds the size limit of {limit} bytes")]
+    HeaderTooLarge { limit: usize },
 
     #[error(transparent)]
     Custom(#[from] BoxedError),
 }
 
+/// Failed TLS handshake or verification of the server certificate.
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+pub struct TlsVerificationError {
+    kind: TlsVerificationKind,
+    message: String,
+    certificates: Vec<Vec<u8>>,
+    #[source]
+    source: Option<BoxedError>,
+}
+
+impl TlsVerificationError {
+    pub fn new<S: Into<String>>(kind: TlsVerificationKind, message: S) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            certificates: Vec::new(),
+            source: None,
+        }
+    }
+
+    pub fn with_certificates(mut self, certificates: Vec<Vec<u8>>) -> Self {
+        self.certificates = certificates;
+        self
+    }
+
+    pub fn with_source(mut self, source: BoxedError) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Reason of the failure.
+    ///
+    /// The TLS library of the curl backend reports most certificate
+    /// problems alike, so they are often [`TlsVerificationKind::Other`].
+    /// With rustls, libcurl only says "invalid peer certificate signature"
+    /// or "invalid certificate data found", which are always `Other`.
+    pub fn kind(&self) -> TlsVerificationKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// DER encoded certificate chain sent by the server, starting with the
+    /// end entity certificate.
+    ///
+    /// Empty if the handshake failed before the server sent certificates.
+    /// Always empty with the curl backend, as libcurl only gives the chain
+    /// (`CURLINFO_CERTINFO`) after a successful handshake, and never with
+    /// rustls.
+    pub fn certificates(&self) -> &[Vec<u8>] {
+        &self.certificates
+    }
+}
+
+/// Reason of a [`TlsVerificationError`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TlsVerificationKind {
+    /// The certificate is not valid for the host name.
+    HostnameMismatch,
+    Expired,
+    NotYetValid,
+    /// The certificate is not issued by a trusted certificate authority,
+    /// such as a self-signed certificate.
+    UnknownIssuer,
+    Revoked,
+    Other,
+}
+
+impl TlsVerificationKind {
+    /// Classifies an error message of a TLS library.
+    pub(crate) fn from_message(message: &str) -> Self {
+        let message = message.to_ascii_lowercase();
+        let contains_any = |patterns: &[&str]| patterns.iter().any(|p| message.contains(p));
+
+        if contains_any(&[
+            "certnotvalidforname",
+            "not valid for name",
+            "hostname mismatch",
+        ]) {
+            Self::HostnameMismatch
+        } else if contains_any(&[
+            "certexpired",
+            "certificate has expired",
+            "certificate expired",
+        ]) {
+            Self::Expired
+        } else if contains_any(&["certnotvalidyet", "not yet valid"]) {
+            Self::NotYetValid
+        } else if contains_any(&[
+            "unknownissuer",
+            "unknown issuer",
+            "self-signed",
+            "self signed",
+        ]) {
+            Self::UnknownIssuer
+        } else if contains_any(&["certrevoked", "certificate revoked"]) {
+            Self::Revoked
+        } else {
+            Self::Other
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TlsVerificationKind::HostnameMismatch => "hostname_mismatch",
+            TlsVerificationKind::Expired => "expired",
+            TlsVerificationKind::NotYetValid => "not_yet_valid",
+            TlsVerificationKind::UnknownIssuer => "unknown_issuer",
+            TlsVerificationKind::Revoked => "revoked",
+            TlsVerificationKind::Other => "other",
+        }
+    }
+}
+
+impl std::fmt::Display for TlsVerificationKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
 pub enum NetworkError {
@@ -172,6 +406,9 @@ pub enum NetworkError {
     #[error("connection disconnected: {0}")]
     Disconnected(BoxedError),
 
+    #[error("circuit breaker open for host {host}, retry after {retry_after:?}")]
+    CircuitOpen { host: String, retry_after: Duration },
+
     #[error(transparent)]
     Custom(#[from] BoxedError),
 }
@@ -179,12 +416,70 @@ pub enum NetworkError {
 #[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
 pub enum OtherError {
+    #[cfg(feature = "curl")]
     #[error(transparent)]
     Curl(#[from] curl::Error),
 
+    #[cfg(feature = "dns")]
     #[error(transparent)]
     Trust(#[from] trust_dns_resolver::error::ResolveError),
 
     #[error(transparent)]
     Custom(#[from] BoxedError),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "curl")]
+    #[test]
+    fn test_error_curl_code() {
+        let error = Error::from(curl::Error::new(curl_sys::CURLE_COULDNT_CONNECT));
+        assert_eq!(error.category(), ErrorCategory::Network);
+        assert_eq!(error.curl_code(), Some(7));
+        assert!(error.is_retriable());
+
+        let error = Error::from(curl::Error::new(curl_sys::CURLE_RECV_ERROR));
+        assert_eq!(error.category(), ErrorCategory::Other);
+        assert_eq!(error.curl_code(), Some(56));
+        assert!(error.is_retriable());
+
+        let error = Error::from(curl::Error::new(curl_sys::CURLE_PEER_FAILED_VERIFICATION));
+        assert_eq!(error.curl_code(), Some(60));
+        assert!(!error.is_retriable());
+
+        let error = Error::InvalidArgument {
+            value: "a".to_string(),
+            reason: "b".to_string(),
+        };
+        assert_eq!(error.curl_code(), None);
+        assert!(!error.is_retriable());
+    }
+
+    #[test]
+    fn test_tls_verification_kind_from_message() {
+        assert_eq!(
+            TlsVerificationKind::from_message(
+                "invalid peer certificate contents: invalid peer certificate: CertNotValidForName"
+            ),
+            TlsVerificationKind::HostnameMismatch
+        );
+        assert_eq!(
+            TlsVerificationKind::from_message("invalid peer certificate: CertExpired"),
+            TlsVerificationKind::Expired
+        );
+        assert_eq!(
+            TlsVerificationKind::from_message("invalid peer certificate: CertNotValidYet"),
+            TlsVerificationKind::NotYetValid
+        );
+        assert_eq!(
+            TlsVerificationKind::from_message("invalid peer certificate: UnknownIssuer"),
+            TlsVerificationKind::UnknownIssuer
+        );
+        assert_eq!(
+            TlsVerificationKind::from_message("invalid certificate data found"),
+            TlsVerificationKind::Other
+        );
+    }
+}
@@ -1,13 +1,22 @@
 use std::{fmt::Display, hash::Hash};
 
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use url::Url;
+
 use crate::error::Error;
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct RequestHeader {
     pub method: String,
     pub uri: String,
     pub version: String,
     pub fields: HeaderFields,
+    /// Bytes the header was parsed from, empty if not parsed.
+    ///
+    /// Keeps the original casing, order, and line folding of fields.
+    #[serde(skip)]
+    pub raw: Vec<u8>,
 }
 
 impl RequestHeader {
@@ -22,12 +31,17 @@ impl RequestHeader {
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ResponseHeader {
     pub version: String,
     pub status_code: u16,
     pub reason_phrase: String,
     pub fields: HeaderFields,
+    /// Bytes the header was parsed from, empty if not parsed.
+    ///
+    /// Keeps the original casing, order, and line folding of fields.
+    #[serde(skip)]
+    pub raw: Vec<u8>,
 }
 
 impl ResponseHeader {
@@ -42,9 +56,14 @@ impl ResponseHeader {
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ResponseTrailer {
     pub fields: HeaderFields,
+    /// Bytes the header was parsed from, empty if not parsed.
+    ///
+    /// Keeps the original casing, order, and line folding of fields.
+    #[serde(skip)]
+    pub raw: Vec<u8>,
 }
 
 impl ResponseTrailer {
@@ -59,7 +78,8 @@ impl ResponseTrailer {
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct HeaderFields {
     inner: Vec<(FieldName, FieldValue)>,
 }
@@ -127,6 +147,78 @@ impl HeaderFields {
         let key = key.into();
         self.inner.retain(|(k, _v)| k != &key);
     }
+
+    /// Returns the entry of the first field with the name for in-place
+    /// manipulation.
+    pub fn entry<K: Into<FieldName>>(&mut self, key: K) -> FieldEntry<'_> {
+        FieldEntry {
+            fields: self,
+            key: key.into(),
+        }
+    }
+
+    /// Keeps only the fields for which the function returns true.
+    pub fn retain<F: FnMut(&FieldName, &FieldValue) -> bool>(&mut self, mut f: F) {
+        self.inner.retain(|(k, v)| f(k, v));
+    }
+
+    /// Sorts fields by case-insensitive name, keeping the order of fields
+    /// with the same name.
+    pub fn sort_by_name(&mut self) {
+        self.inner
+            .sort_by(|(a, _), (b, _)| a.normalized().cmp(b.normalized()));
+    }
+
+    /// Sorts fields by the key, keeping the order of fields with equal
+    /// keys.
+    pub fn sort_by_key<K: Ord, F: FnMut(&FieldName, &FieldValue) -> K>(&mut self, mut f: F) {
+        self.inner.sort_by_key(|(k, v)| f(k, v));
+    }
+
+    /// Merges fields from `other`, which take precedence: fields with a
+    /// name in `other` are removed and then all fields of `other` are
+    /// appended.
+    pub fn merge(&mut self, other: &HeaderFields) {
+        self.inner.retain(|(k, _v)| !other.contains_key(k));
+        self.inner.extend(other.inner.iter().cloned());
+    }
+
+    /// Merges fields from `other` whose names are not already present, so
+    /// existing fields take precedence.
+    pub fn merge_missing(&mut self, other: &HeaderFields) {
+        for (k, v) in &other.inner {
+            if !self.contains_key(k) {
+                self.inner.push((k.clone(), v.clone()));
+            }
+        }
+    }
+
+    /// Returns the value of `Content-Length` if it is a valid length.
+    pub fn content_length(&self) -> Option<u64> {
+        self.get_text("content-length")?.parse().ok()
+    }
+
+    /// Returns the value of `Content-Type` with surrounding whitespace
+    /// removed.
+    pub fn content_type(&self) -> Option<String> {
+        self.get_text("content-type")
+    }
+
+    /// Returns the value of `Location` resolved against the URL of the
+    /// request.
+    pub fn location(&self, base: &Url) -> Option<Url> {
+        base.join(&self.get_text("location")?).ok()
+    }
+
+    /// Returns the value of `Last-Modified` if it is a valid HTTP date.
+    pub fn last_modified(&self) -> Option<OffsetDateTime> {
+        super::parse_http_date(&self.get_text("last-modified")?)
+    }
+
+    fn get_text(&self, key: &str) -> Option<String> {
+        self.get(key)
+            .map(|value| value.to_string_lossy().trim().to_string())
+    }
 }
 
 impl IntoIterator for HeaderFields {
@@ -153,6 +245,48 @@ impl Extend<(FieldName, FieldValue)> for HeaderFields {
     }
 }
 
+/// Fields with a name in [`HeaderFields`], from [`HeaderFields::entry`].
+#[derive(Debug)]
+pub struct FieldEntry<'a> {
+    fields: &'a mut HeaderFields,
+    key: FieldName,
+}
+
+impl<'a> FieldEntry<'a> {
+    pub fn key(&self) -> &FieldName {
+        &self.key
+    }
+
+    /// Returns the first value, appending the field if there is none.
+    pub fn or_insert<V: Into<FieldValue>>(self, value: V) -> &'a mut FieldValue {
+        self.or_insert_with(|| value)
+    }
+
+    /// Returns the first value, appending the field with the result of the
+    /// function if there is none.
+    pub fn or_insert_with<V: Into<FieldValue>, F: FnOnce() -> V>(self, f: F) -> &'a mut FieldValue {
+        let inner = &mut self.fields.inner;
+        let position = match inner.iter().position(|(k, _v)| k == &self.key) {
+            Some(position) => position,
+            None => {
+                inner.push((self.key, f().into()));
+                inner.len() - 1
+            }
+        };
+
+        &mut inner[position].1
+    }
+
+    /// Calls the function with the first value if there is one.
+    pub fn and_modify<F: FnOnce(&mut FieldValue)>(self, f: F) -> Self {
+        if let Some((_k, v)) = self.fields.inner.iter_mut().find(|(k, _v)| k == &self.key) {
+            f(v);
+        }
+
+        self
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct FieldName {
     inner: String,
@@ -204,6 +338,18 @@ impl From<&FieldName> for FieldName {
     }
 }
 
+impl Serialize for FieldName {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.inner)
+    }
+}
+
+impl<'de> Deserialize<'de> for FieldName {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self::new)
+    }
+}
+
 impl PartialEq for FieldName {
     fn eq(&self, other: &Self) -> bool {
         self.normalized == other.normalized
@@ -260,6 +406,38 @@ impl Display for FieldValue {
     }
 }
 
+/// Text values are serialized as a string and opaque values as an object
+/// with a `base64` field.
+impl Serialize for FieldValue {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            FieldValue::Text(text) => serializer.serialize_str(text),
+            FieldValue::Opaque(data) => SerializedFieldValue::Opaque {
+                base64: crate::string::base64_encode(data),
+            }
+            .serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for FieldValue {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match SerializedFieldValue::deserialize(deserializer)? {
+            SerializedFieldValue::Text(text) => Ok(Self::Text(text)),
+            SerializedFieldValue::Opaque { base64 } => crate::string::base64_decode(base64)
+                .map(Self::Opaque)
+                .map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SerializedFieldValue {
+    Text(String),
+    Opaque { base64: String },
+}
+
 impl From<&str> for FieldValue {
     fn from(value: &str) -> Self {
         Self::Text(value.to_string())
@@ -339,4 +517,145 @@ mod tests {
         fields.clear();
         assert!(fields.is_empty());
     }
+
+    #[test]
+    fn test_header_fields_entry() {
+        let mut fields = HeaderFields::new();
+        fields.append("k1", "v1");
+        fields.append("k2", "v2");
+        fields.append("K1", "v1-1");
+
+        assert_eq!(fields.entry("k1").or_insert("x"), &FieldValue::from("v1"));
+        assert_eq!(fields.entry("k3").or_insert("v3"), &FieldValue::from("v3"));
+        assert_eq!(fields.len(), 4);
+
+        fields
+            .entry("K2")
+            .and_modify(|value| *value = "v2-1".into())
+            .or_insert("x");
+        fields
+            .entry("k4")
+            .and_modify(|value| *value = "x".into())
+            .or_insert_with(|| "v4");
+
+        assert_eq!(
+            fields.iter().collect::<Vec<&(FieldName, FieldValue)>>(),
+            vec![
+                &("k1".into(), "v1".into()),
+                &("k2".into(), "v2-1".into()),
+                &("K1".into(), "v1-1".into()),
+                &("k3".into(), "v3".into()),
+                &("k4".into(), "v4".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_header_fields_bulk() {
+        let mut fields = HeaderFields::new();
+        fields.append("b", "1");
+        fields.append("a", "2");
+        fields.append("C", "3");
+        fields.append("A", "4");
+
+        fields.sort_by_name();
+
+        assert_eq!(
+            fields
+                .iter()
+                .map(|(_k, v)| v.to_string())
+                .collect::<Vec<_>>(),
+            vec!["2", "4", "1", "3"]
+        );
+
+        fields.retain(|name, _value| name != &FieldName::from("b"));
+        assert_eq!(fields.len(), 3);
+
+        fields.sort_by_key(|name, _value| name != &FieldName::from("c"));
+        assert_eq!(fields.as_slice()[0].0.as_str(), "C");
+
+        let mut other = HeaderFields::new();
+        other.append("a", "5");
+        other.append("d", "6");
+
+        let mut merged = fields.clone();
+        merged.merge(&other);
+
+        assert_eq!(
+            merged
+                .iter()
+                .map(|(_k, v)| v.to_string())
+                .collect::<Vec<_>>(),
+            vec!["3", "5", "6"]
+        );
+
+        let mut merged = fields.clone();
+        merged.merge_missing(&other);
+
+        assert_eq!(
+            merged
+                .iter()
+                .map(|(_k, v)| v.to_string())
+                .collect::<Vec<_>>(),
+            vec!["3", "2", "4", "6"]
+        );
+    }
+
+    #[test]
+    fn test_header_fields_typed() {
+        let base = Url::parse("http://example.com/a/b").unwrap();
+        let mut fields = HeaderFields::new();
+
+        assert_eq!(fields.content_length(), None);
+        assert_eq!(fields.content_type(), None);
+        assert_eq!(fields.location(&base), None);
+        assert_eq!(fields.last_modified(), None);
+
+        fields.append("Content-Length", " 123 ");
+        fields.append("Content-Type", "text/html; charset=utf-8");
+        fields.append("Location", "../c?d");
+        fields.append("Last-Modified", "Sun, 06 Nov 1994 08:49:37 GMT");
+
+        assert_eq!(fields.content_length(), Some(123));
+        assert_eq!(
+            fields.content_type().as_deref(),
+            Some("text/html; charset=utf-8")
+        );
+        assert_eq!(
+            fields.location(&base),
+            Some(Url::parse("http://example.com/c?d").unwrap())
+        );
+        assert_eq!(
+            fields.last_modified(),
+            Some(OffsetDateTime::from_unix_timestamp(784111777).unwrap())
+        );
+
+        fields.insert("Content-Length", "-1");
+        fields.insert("Last-Modified", "never");
+
+        assert_eq!(fields.content_length(), None);
+        assert_eq!(fields.last_modified(), None);
+    }
+
+    #[test]
+    fn test_field_value_serde() {
+        let text = FieldValue::from("a\u{FFFD}b");
+        let opaque = FieldValue::from(b"a\xffb".as_slice());
+
+        assert_eq!(serde_json::to_string(&text).unwrap(), "\"a\u{FFFD}b\"");
+        assert_eq!(
+            serde_json::to_string(&opaque).unwrap(),
+            r#"{"base64":"Yf9i"}"#
+        );
+
+        assert_eq!(
+            serde_json::from_str::<FieldValue>("\"a\u{FFFD}b\"").unwrap(),
+            text
+        );
+        assert_eq!(
+            serde_json::from_str::<FieldValue>(r#"{"base64":"Yf9i"}"#).unwrap(),
+            opaque
+        );
+        assert!(serde_json::from_str::<FieldValue>(r#"{"base64":"!"}"#).is_err());
+    }
 }
@@ -1,5 +1,7 @@
 mod common;
 mod parse;
+mod security;
 
 pub use common::*;
 pub use parse::*;
+pub use security::*;
@@ -1,3 +1,5 @@
+use time::{format_description::FormatItem, OffsetDateTime, PrimitiveDateTime};
+
 use crate::error::{Error, ParseError};
 
 use super::{RequestHeader, ResponseHeader, ResponseTrailer};
@@ -8,7 +10,7 @@ pub fn scan_header_boundary(data: &[u8]) -> Option<usize> {
     for line in data.split_inclusive(|&v| v == b'\n') {
         index += line.len();
 
-        if line.iter().all(|v| v.is_ascii_whitespace()) && line.ends_with(&[b'\n']) {
+        if line.iter().all(|v| v.is_ascii_whitespace()) && line.ends_with(b"\n") {
             return Some(index);
         }
     }
@@ -16,17 +18,91 @@ pub fn scan_header_boundary(data: &[u8]) -> Option<usize> {
     None
 }
 
+/// Parses an HTTP date as in `Last-Modified`, in either the preferred
+/// IMF-fixdate format or the obsolete asctime format.
+pub fn parse_http_date(value: &str) -> Option<OffsetDateTime> {
+    const FORMATS: [&str; 2] = [
+        "[weekday repr:short], [day] [month repr:short] [year] [hour]:[minute]:[second] GMT",
+        "[weekday repr:short] [month repr:short] [day padding:space] [hour]:[minute]:[second] [year]",
+    ];
+
+    let value = value.trim();
+
+    FORMATS.iter().find_map(|format| {
+        let format: Vec<FormatItem> = time::format_description::parse(format).ok()?;
+        PrimitiveDateTime::parse(value, &format)
+            .ok()
+            .map(PrimitiveDateTime::assume_utc)
+    })
+}
+
 pub(super) fn parse_request_header(data: &[u8]) -> Result<RequestHeader, Error> {
     let mut headers = [httparse::EMPTY_HEADER; 128];
     let mut request = httparse::Request::new(&mut headers);
 
     match request.parse(data) {
         Ok(status) => match status {
-            httparse::Status::Complete(_) => Ok(request.into()),
+            httparse::Status::Complete(size) => {
+                let mut request = RequestHeader::from(request);
+                request.raw = data[..size].to_vec();
+                Ok(request)
+            }
             httparse::Status::Partial => {
                 Err(ParseError::new("HTTP request header incomplete").into())
             }
         },
+        Err(httparse::Error::Token) => parse_request_header_raw_target(data),
+        Err(error) => Err(ParseError::new("HTTP request header parse error")
+            .with_source(Box::new(error))
+            .into()),
+    }
+}
+
+/// Parses a request header whose target contains bytes not allowed by
+/// httparse, such as one sent using a raw request target.
+///
+/// The target is stored using the escape scheme of
+/// [`crate::string::parse_utf8_escaped`].
+fn parse_request_header_raw_target(data: &[u8]) -> Result<RequestHeader, Error> {
+    let line_end = data
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .ok_or_else(|| ParseError::new("HTTP request header incomplete"))?;
+    let line = data[..line_end]
+        .strip_suffix(b"\r")
+        .unwrap_or(&data[..line_end]);
+
+    let mut parts = line.split(|&byte| byte == b' ');
+
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version), None)
+            if method.is_ascii() && version.starts_with(b"HTTP/") =>
+        {
+            (method, target, version)
+        }
+        _ => return Err(ParseError::new("HTTP request line parse error").into()),
+    };
+
+    let mut request = RequestHeader::new();
+    request.method = String::from_utf8_lossy(method).into_owned();
+    request.uri = crate::string::parse_utf8_escaped(target);
+    request.version = String::from_utf8_lossy(version).into_owned();
+
+    let mut headers = [httparse::EMPTY_HEADER; 128];
+
+    match httparse::parse_headers(&data[line_end + 1..], &mut headers) {
+        Ok(httparse::Status::Complete((size, headers))) => {
+            for header in headers {
+                request.fields.append(header.name, header.value);
+            }
+
+            request.raw = data[..line_end + 1 + size].to_vec();
+
+            Ok(request)
+        }
+        Ok(httparse::Status::Partial) => {
+            Err(ParseError::new("HTTP request header incomplete").into())
+        }
         Err(error) => Err(ParseError::new("HTTP request header parse error")
             .with_source(Box::new(error))
             .into()),
@@ -39,7 +115,11 @@ pub(super) fn parse_response_header(data: &[u8]) -> Result<ResponseHeader, Error
 
     match response.parse(data) {
         Ok(status) => match status {
-            httparse::Status::Complete(_) => Ok(response.into()),
+            httparse::Status::Complete(size) => {
+                let mut response = ResponseHeader::from(response);
+                response.raw = data[..size].to_vec();
+                Ok(response)
+            }
             httparse::Status::Partial => {
                 Err(ParseError::new("HTTP response header incomplete").into())
             }
@@ -58,11 +138,13 @@ pub(super) fn parse_response_trailer(data: &[u8]) -> Result<ResponseTrailer, Err
 
     match result {
         Ok(status) => match status {
-            httparse::Status::Complete((_size, headers)) => {
+            httparse::Status::Complete((size, headers)) => {
                 for header in headers {
                     trailer.fields.append(header.name, header.value);
                 }
 
+                trailer.raw = data[..size].to_vec();
+
                 Ok(trailer)
             }
             httparse::Status::Partial => {
@@ -109,6 +191,19 @@ impl From<httparse::Response<'_, '_>> for ResponseHeader {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_http_date() {
+        let expected = OffsetDateTime::from_unix_timestamp(784111777).unwrap();
+
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(expected)
+        );
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), Some(expected));
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 PST"), None);
+        assert_eq!(parse_http_date("yesterday"), None);
+    }
+
     #[test]
     fn test_scan_header_boundary_empty() {
         assert_eq!(scan_header_boundary(b""), None);
@@ -148,6 +243,23 @@ mod tests {
         assert_eq!(request.fields.get("host"), Some(&"example.com".into()));
     }
 
+    #[test]
+    fn test_parse_request_raw_target() {
+        let request =
+            parse_request_header(b"GET /caf\xe9 HTTP/1.1\r\nHost: example.com\r\n\r\n").unwrap();
+
+        assert_eq!(&request.method, "GET");
+        assert_eq!(&request.uri, "/caf\u{FFFD}\u{E01D9}");
+        assert_eq!(&request.version, "HTTP/1.1");
+        assert_eq!(request.fields.get("host"), Some(&"example.com".into()));
+        assert_eq!(
+            request.raw,
+            b"GET /caf\xe9 HTTP/1.1\r\nHost: example.com\r\n\r\n"
+        );
+
+        assert!(parse_request_header(b"GET /a\xe9 b HTTP/1.1\r\n\r\n").is_err());
+    }
+
     #[test]
     fn test_parse_response() {
         let response =
@@ -161,10 +273,23 @@ mod tests {
         assert_eq!(response.fields.get("content-length"), Some(&"123".into()));
     }
 
+    #[test]
+    fn test_parse_response_raw() {
+        let data = b"HTTP/1.1 200 OK\r\nX-Case: a\r\ncontent-TYPE:  text/plain\r\n\r\nbody";
+        let response = parse_response_header(data).unwrap();
+
+        assert_eq!(response.raw, &data[..data.len() - 4]);
+        assert_eq!(
+            response.fields.get("content-type"),
+            Some(&"text/plain".into())
+        );
+    }
+
     #[test]
     fn test_parse_response_trailer() {
         let trailer = parse_response_trailer("Abc: xyz\r\n\r\n".as_bytes()).unwrap();
 
         assert_eq!(trailer.fields.get("abc"), Some(&"xyz".into()));
+        assert_eq!(trailer.raw, b"Abc: xyz\r\n\r\n");
     }
 }
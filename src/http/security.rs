@@ -0,0 +1,270 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::string::{split_unquoted, unquote};
+
+use super::HeaderFields;
+
+/// Security policy fields of a response, for auditing the security posture
+/// of a site.
+///
+/// Fields that are missing or have no valid directives are left empty.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecurityPolicy {
+    #[serde(default)]
+    pub expect_ct: Option<ExpectCt>,
+    #[serde(default)]
+    pub public_key_pins: Option<PublicKeyPins>,
+    #[serde(default)]
+    pub public_key_pins_report_only: Option<PublicKeyPins>,
+    /// Enforced policies, which may be spread over several fields.
+    #[serde(default)]
+    pub content_security_policy: Vec<ContentSecurityPolicy>,
+    #[serde(default)]
+    pub content_security_policy_report_only: Vec<ContentSecurityPolicy>,
+    /// Allowlists by feature, such as `["self", "https://example.com"]`,
+    /// where an empty list disallows the feature.
+    #[serde(default)]
+    pub permissions_policy: Option<BTreeMap<String, Vec<String>>>,
+}
+
+/// `Expect-CT` field.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExpectCt {
+    pub max_age: Option<u64>,
+    pub enforce: bool,
+    pub report_uri: Option<String>,
+}
+
+/// `Public-Key-Pins` (HPKP) field.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicKeyPins {
+    /// Base64 encoded SHA-256 digests of the pinned public keys.
+    pub pin_sha256: Vec<String>,
+    pub max_age: Option<u64>,
+    pub include_subdomains: bool,
+    pub report_uri: Option<String>,
+}
+
+/// Single `Content-Security-Policy` policy.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentSecurityPolicy {
+    /// Source lists or values by lowercase directive name.
+    pub directives: BTreeMap<String, Vec<String>>,
+}
+
+impl SecurityPolicy {
+    /// Returns the security policy of the response fields, or `None` if
+    /// there are no security policy fields.
+    pub fn from_fields(fields: &HeaderFields) -> Option<Self> {
+        let text = |name: &str| {
+            fields
+                .get_all(name)
+                .map(|value| value.to_string_lossy())
+                .collect::<Vec<String>>()
+        };
+
+        let policy = Self {
+            expect_ct: text("Expect-CT")
+                .first()
+                .map(|value| parse_expect_ct(value)),
+            public_key_pins: text("Public-Key-Pins")
+                .first()
+                .map(|value| parse_public_key_pins(value)),
+            public_key_pins_report_only: text("Public-Key-Pins-Report-Only")
+                .first()
+                .map(|value| parse_public_key_pins(value)),
+            content_security_policy: text("Content-Security-Policy")
+                .iter()
+                .flat_map(|value| parse_content_security_policy(value))
+                .collect(),
+            content_security_policy_report_only: text("Content-Security-Policy-Report-Only")
+                .iter()
+                .flat_map(|value| parse_content_security_policy(value))
+                .collect(),
+            permissions_policy: {
+                let values = text("Permissions-Policy");
+
+                if values.is_empty() {
+                    None
+                } else {
+                    Some(parse_permissions_policy(&values.join(",")))
+                }
+            },
+        };
+
+        if policy == Self::default() {
+            None
+        } else {
+            Some(policy)
+        }
+    }
+}
+
+/// Parses `name=value` directives, returning lowercase names and unquoted
+/// values.
+fn parse_directives(value: &str, separator: char) -> Vec<(String, Option<String>)> {
+    split_unquoted(value, separator)
+        .into_iter()
+        .filter(|directive| !directive.trim().is_empty())
+        .map(|directive| match directive.split_once('=') {
+            Some((name, value)) => (
+                name.trim().to_ascii_lowercase(),
+                Some(unquote(value.trim())),
+            ),
+            None => (directive.trim().to_ascii_lowercase(), None),
+        })
+        .collect()
+}
+
+fn parse_expect_ct(value: &str) -> ExpectCt {
+    let mut expect_ct = ExpectCt::default();
+
+    for (name, value) in parse_directives(value, ',') {
+        match name.as_str() {
+            "max-age" => expect_ct.max_age = value.and_then(|value| value.parse().ok()),
+            "enforce" => expect_ct.enforce = true,
+            "report-uri" => expect_ct.report_uri = value,
+            _ => {}
+        }
+    }
+
+    expect_ct
+}
+
+fn parse_public_key_pins(value: &str) -> PublicKeyPins {
+    let mut pins = PublicKeyPins::default();
+
+    for (name, value) in parse_directives(value, ';') {
+        match name.as_str() {
+            "pin-sha256" => pins.pin_sha256.extend(value),
+            "max-age" => pins.max_age = value.and_then(|value| value.parse().ok()),
+            "includesubdomains" => pins.include_subdomains = true,
+            "report-uri" => pins.report_uri = value,
+            _ => {}
+        }
+    }
+
+    pins
+}
+
+/// Parses a field value, which may have several comma separated policies.
+fn parse_content_security_policy(value: &str) -> Vec<ContentSecurityPolicy> {
+    value
+        .split(',')
+        .map(|policy| {
+            let mut directives = BTreeMap::new();
+
+            for directive in policy.split(';') {
+                let mut tokens = directive.split_ascii_whitespace();
+
+                if let Some(name) = tokens.next() {
+                    // Only the first occurrence of a directive is used
+                    directives
+                        .entry(name.to_ascii_lowercase())
+                        .or_insert_with(|| tokens.map(str::to_string).collect());
+                }
+            }
+
+            ContentSecurityPolicy { directives }
+        })
+        .filter(|policy| !policy.directives.is_empty())
+        .collect()
+}
+
+/// Parses the structured field dictionary of `Permissions-Policy`.
+fn parse_permissions_policy(value: &str) -> BTreeMap<String, Vec<String>> {
+    let mut features = BTreeMap::new();
+
+    for member in split_unquoted(value, ',') {
+        let Some((name, value)) = member.split_once('=') else {
+            continue;
+        };
+        // Parameters of the member are not used
+        let value = split_unquoted(value, ';')[0].trim();
+
+        let allowlist = match value
+            .strip_prefix('(')
+            .and_then(|value| value.strip_suffix(')'))
+        {
+            Some(items) => items.split_ascii_whitespace().map(unquote).collect(),
+            None => vec![unquote(value)],
+        };
+
+        features.insert(name.trim().to_string(), allowlist);
+    }
+
+    features
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_security_policy() {
+        let mut fields = HeaderFields::new();
+        fields.append(
+            "Expect-CT",
+            "max-age=86400, enforce, report-uri=\"https://example.com/ct\"",
+        );
+        fields.append(
+            "Public-Key-Pins-Report-Only",
+            "pin-sha256=\"abc=\"; pin-sha256=\"def=\"; max-age=10; includeSubDomains",
+        );
+        fields.append(
+            "Content-Security-Policy",
+            "default-src 'self'; img-src * data:; default-src 'none', script-src 'self'",
+        );
+        fields.append(
+            "Permissions-Policy",
+            "geolocation=(self \"https://example.com\"), camera=()",
+        );
+        fields.append("Permissions-Policy", "fullscreen=*;report-to=x");
+
+        let policy = SecurityPolicy::from_fields(&fields).unwrap();
+
+        assert_eq!(
+            policy.expect_ct,
+            Some(ExpectCt {
+                max_age: Some(86400),
+                enforce: true,
+                report_uri: Some("https://example.com/ct".to_string()),
+            })
+        );
+        assert_eq!(policy.public_key_pins, None);
+        assert_eq!(
+            policy.public_key_pins_report_only,
+            Some(PublicKeyPins {
+                pin_sha256: vec!["abc=".to_string(), "def=".to_string()],
+                max_age: Some(10),
+                include_subdomains: true,
+                report_uri: None,
+            })
+        );
+
+        assert_eq!(policy.content_security_policy.len(), 2);
+        let directives = &policy.content_security_policy[0].directives;
+        assert_eq!(directives["default-src"], vec!["'self'"]);
+        assert_eq!(directives["img-src"], vec!["*", "data:"]);
+        assert_eq!(
+            policy.content_security_policy[1].directives["script-src"],
+            vec!["'self'"]
+        );
+        assert!(policy.content_security_policy_report_only.is_empty());
+
+        let features = policy.permissions_policy.unwrap();
+        assert_eq!(features["geolocation"], vec!["self", "https://example.com"]);
+        assert!(features["camera"].is_empty());
+        assert_eq!(features["fullscreen"], vec!["*"]);
+    }
+
+    #[test]
+    fn test_security_policy_missing() {
+        let mut fields = HeaderFields::new();
+        fields.append("Content-Type", "text/html");
+
+        assert_eq!(SecurityPolicy::from_fields(&fields), None);
+    }
+}
@@ -1,5 +1,9 @@
+#[cfg(not(any(feature = "curl", feature = "native")))]
+compile_error!("either the curl or the native feature is required");
+
 pub mod client;
-mod dns;
+#[cfg(feature = "dns")]
+pub mod dns;
 pub mod error;
 pub mod http;
 pub mod string;
@@ -16,12 +16,5 @@ fn main() -> ExitCode {
         default_hook(panic_info);
     }));
 
-    match cli::run() {
-        Ok(_) => ExitCode::SUCCESS,
-        Err(error) => {
-            tracing::error!(?error, "main exit failure");
-            eprintln!("{}", error);
-            ExitCode::FAILURE
-        }
-    }
+    cli::run()
 }
@@ -0,0 +1,31 @@
+use ::base64::Engine;
+
+use crate::error::{Error, ParseError};
+
+/// Encodes bytes as standard, padded base64.
+pub fn base64_encode<D: AsRef<[u8]>>(data: D) -> String {
+    ::base64::engine::general_purpose::STANDARD.encode(data)
+}
+
+/// Decodes standard, padded base64.
+pub fn base64_decode<S: AsRef<[u8]>>(input: S) -> Result<Vec<u8>, Error> {
+    ::base64::engine::general_purpose::STANDARD
+        .decode(input)
+        .map_err(|error| {
+            ParseError::new("invalid base64")
+                .with_source(Box::new(error))
+                .into()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64_encode(b"\xffabc"), "/2FiYw==");
+        assert_eq!(base64_decode("/2FiYw==").unwrap(), b"\xffabc");
+        assert!(base64_decode("/2FiYw").is_err());
+    }
+}
@@ -15,14 +15,17 @@ pub fn parse_utf8_escaped(mut input: &[u8]) -> String {
                 let valid = std::str::from_utf8(valid).unwrap();
                 escape_escape_char(valid, &mut buf);
 
-                if let Some(invalid_sequence_length) = error.error_len() {
-                    for byte in &after_valid[0..invalid_sequence_length] {
-                        buf.push(ESCAPE_CHAR);
-                        buf.push(byte_to_escape_seq(*byte));
-                    }
-
-                    input = &after_valid[invalid_sequence_length..]
-                } else {
+                // A missing length is an incomplete sequence at the end
+                let invalid_sequence_length = error.error_len().unwrap_or(after_valid.len());
+
+                for byte in &after_valid[0..invalid_sequence_length] {
+                    buf.push(ESCAPE_CHAR);
+                    buf.push(byte_to_escape_seq(*byte));
+                }
+
+                input = &after_valid[invalid_sequence_length..];
+
+                if input.is_empty() {
                     break;
                 }
             }
@@ -113,6 +116,11 @@ mod tests {
         assert_eq!(parse_utf8_escaped(b"_\x80_"), "_\u{FFFD}\u{E0170}_");
 
         assert_eq!(parse_utf8_escaped(b"_\xEF\xBF\xBD_"), "_\u{FFFD}\u{E007F}_");
+
+        assert_eq!(
+            parse_utf8_escaped(b"_\xE2\x82"),
+            "_\u{FFFD}\u{E01D2}\u{FFFD}\u{E0172}"
+        );
     }
 
     #[test]
@@ -0,0 +1,453 @@
+use std::path::{Path, PathBuf};
+
+use url::Url;
+
+use super::{floor_utf8_boundary, percent_decode};
+
+/// Longest file name in bytes produced by [`sanitize_file_name`].
+///
+/// This leaves room under the common 255 byte limit for collision
+/// suffixes and temporary file extensions.
+pub const MAX_FILE_NAME_LENGTH: usize = 200;
+
+/// File name used when a URL path ends in a directory.
+pub const DEFAULT_FILE_NAME: &str = "index.html";
+
+/// Longest extension kept when a file name is truncated.
+const MAX_EXTENSION_LENGTH: usize = 16;
+
+/// Largest number tried by [`CollisionStrategy::Number`].
+const MAX_COLLISION_NUMBER: usize = 10_000;
+
+/// Device names that can't be used as file names on Windows, with or
+/// without an extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Layout of paths produced by [`url_to_file_path`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilePathStyle {
+    /// Only the file name, such as for saving a single download.
+    #[default]
+    FileName,
+    /// Host name, directories, and file name with the query string, such
+    /// as for mirroring a site.
+    Mirror,
+}
+
+/// What to do when a derived path already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CollisionStrategy {
+    /// Use the path anyway.
+    #[default]
+    Overwrite,
+    /// Insert a number before the extension, as in `index.1.html`.
+    Number,
+    /// Don't use the path.
+    Fail,
+}
+
+/// Returns a relative path, safe to join to an output directory, for
+/// saving the contents of the URL.
+///
+/// A file name from a `Content-Disposition` header value takes priority
+/// over the last URL path segment. Every component is passed through
+/// [`sanitize_file_name`], so the path never contains `..` components,
+/// separators from the URL, or reserved names.
+pub fn url_to_file_path(
+    url: &Url,
+    content_disposition: Option<&str>,
+    style: FilePathStyle,
+) -> PathBuf {
+    let mut segments: Vec<String> = url
+        .path_segments()
+        .map(|segments| {
+            segments
+                .map(|segment| String::from_utf8_lossy(&percent_decode(segment)).into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    let last_segment = segments.pop().unwrap_or_default();
+
+    let file_name = content_disposition
+        .and_then(content_disposition_file_name)
+        .and_then(|name| sanitize_file_name(&name));
+    let file_name = file_name.unwrap_or_else(|| {
+        let name = if last_segment.is_empty() {
+            DEFAULT_FILE_NAME.to_string()
+        } else {
+            last_segment
+        };
+
+        let name = match url.query() {
+            Some(query) if style == FilePathStyle::Mirror => {
+                let query = String::from_utf8_lossy(&percent_decode(query)).into_owned();
+                format!("{}@{}", name, query)
+            }
+            _ => name,
+        };
+
+        sanitize_file_name(&name).unwrap_or_else(|| DEFAULT_FILE_NAME.to_string())
+    });
+
+    let mut path = PathBuf::new();
+
+    if style == FilePathStyle::Mirror {
+        if let Some(host) = url.host_str() {
+            let host = match url.port() {
+                Some(port) => format!("{}:{}", host, port),
+                None => host.to_string(),
+            };
+
+            path.extend(sanitize_file_name(&host));
+        }
+
+        for segment in segments {
+            path.extend(sanitize_file_name(&segment));
+        }
+    }
+
+    path.push(file_name);
+    path
+}
+
+/// Returns the file name from a `Content-Disposition` header value.
+///
+/// The extended `filename*` parameter is preferred over `filename`. Any
+/// directory part of the name is removed but the name is not otherwise
+/// sanitized.
+pub fn content_disposition_file_name(value: &str) -> Option<String> {
+    let mut file_name = None;
+    let mut extended_file_name = None;
+
+    for parameter in split_unquoted(value, ';').into_iter().skip(1) {
+        let Some((name, value)) = parameter.split_once('=') else {
+            continue;
+        };
+
+        match name.trim().to_ascii_lowercase().as_str() {
+            "filename" => file_name = Some(unquote(value.trim())),
+            "filename*" => extended_file_name = decode_extended_value(value.trim()),
+            _ => {}
+        }
+    }
+
+    let name = extended_file_name.or(file_name)?;
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+/// Returns the name made safe to use as a single path component on common
+/// file systems, or `None` if nothing usable remains.
+///
+/// Separators, control characters and characters reserved on Windows are
+/// replaced with `_`. Trailing dots and spaces are removed, reserved
+/// device names such as `CON` are prefixed with `_`, and long names are
+/// truncated to [`MAX_FILE_NAME_LENGTH`] bytes keeping the extension.
+pub fn sanitize_file_name(name: &str) -> Option<String> {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let name = trim_file_name(&name);
+
+    if name.is_empty() {
+        return None;
+    }
+
+    let stem = name.split('.').next().unwrap_or_default();
+    let name = if RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.trim_end().eq_ignore_ascii_case(reserved))
+    {
+        format!("_{}", name)
+    } else {
+        name.to_string()
+    };
+
+    Some(truncate_file_name(&name))
+}
+
+/// Returns the path adjusted according to the strategy when `exists`
+/// reports that it is already used, or `None` if no path is available.
+pub fn resolve_collision<F>(
+    path: &Path,
+    strategy: CollisionStrategy,
+    mut exists: F,
+) -> Option<PathBuf>
+where
+    F: FnMut(&Path) -> bool,
+{
+    if strategy == CollisionStrategy::Overwrite || !exists(path) {
+        return Some(path.to_path_buf());
+    }
+
+    if strategy == CollisionStrategy::Fail {
+        return None;
+    }
+
+    let file_name = path.file_name()?.to_string_lossy();
+    let (stem, extension) = match file_name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, Some(extension)),
+        _ => (file_name.as_ref(), None),
+    };
+
+    (1..=MAX_COLLISION_NUMBER)
+        .map(|number| {
+            let name = match extension {
+                Some(extension) => format!("{}.{}.{}", stem, number, extension),
+                None => format!("{}.{}", file_name, number),
+            };
+            path.with_file_name(name)
+        })
+        .find(|candidate| !exists(candidate))
+}
+
+fn trim_file_name(name: &str) -> &str {
+    name.trim_start().trim_end_matches(['.', ' '])
+}
+
+fn truncate_file_name(name: &str) -> String {
+    if name.len() <= MAX_FILE_NAME_LENGTH {
+        return name.to_string();
+    }
+
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && extension.len() < MAX_EXTENSION_LENGTH => {
+            (stem, Some(extension))
+        }
+        _ => (name, None),
+    };
+
+    let stem_length = match extension {
+        Some(extension) => MAX_FILE_NAME_LENGTH - extension.len() - 1,
+        None => MAX_FILE_NAME_LENGTH,
+    };
+    let stem = &stem[0..floor_utf8_boundary(stem.as_bytes(), stem_length)];
+    let stem = trim_file_name(stem);
+
+    match extension {
+        Some(extension) => format!("{}.{}", stem, extension),
+        None => stem.to_string(),
+    }
+}
+
+/// Splits a header value on a separator outside of quoted strings.
+pub(crate) fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parameters = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for (index, c) in value.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if quoted && c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            quoted = !quoted;
+        } else if c == separator && !quoted {
+            parameters.push(&value[start..index]);
+            start = index + 1;
+        }
+    }
+
+    parameters.push(&value[start..]);
+    parameters
+}
+
+/// Removes the quotes and escapes of a quoted string, or returns the value
+/// as is if not quoted.
+pub(crate) fn unquote(value: &str) -> String {
+    let Some(value) = value
+        .strip_prefix('"')
+        .map(|value| value.strip_suffix('"').unwrap_or(value))
+    else {
+        return value.to_string();
+    };
+
+    let mut output = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            output.extend(chars.next());
+        } else {
+            output.push(c);
+        }
+    }
+
+    output
+}
+
+/// Decodes a RFC 8187 `charset'language'value` parameter value.
+fn decode_extended_value(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+    let charset = parts.next()?;
+    let _language = parts.next()?;
+    let bytes = percent_decode(parts.next()?);
+
+    if charset.eq_ignore_ascii_case("utf-8") {
+        String::from_utf8(bytes).ok()
+    } else if charset.eq_ignore_ascii_case("iso-8859-1") {
+        Some(bytes.into_iter().map(char::from).collect())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_file_name() {
+        assert_eq!(sanitize_file_name("a.txt").as_deref(), Some("a.txt"));
+        assert_eq!(sanitize_file_name("a/b\\c:d").as_deref(), Some("a_b_c_d"));
+        assert_eq!(sanitize_file_name("a\nb\0").as_deref(), Some("a_b_"));
+        assert_eq!(sanitize_file_name("name. . ").as_deref(), Some("name"));
+        assert_eq!(sanitize_file_name(".hidden").as_deref(), Some(".hidden"));
+        assert_eq!(sanitize_file_name("CON").as_deref(), Some("_CON"));
+        assert_eq!(sanitize_file_name("nul.txt").as_deref(), Some("_nul.txt"));
+        assert_eq!(sanitize_file_name("lpt10").as_deref(), Some("lpt10"));
+        assert_eq!(sanitize_file_name(""), None);
+        assert_eq!(sanitize_file_name("."), None);
+        assert_eq!(sanitize_file_name(".."), None);
+        assert_eq!(sanitize_file_name(" . "), None);
+
+        let name = sanitize_file_name(&format!("{}.tar", "é".repeat(200))).unwrap();
+        assert!(name.len() <= MAX_FILE_NAME_LENGTH);
+        assert!(name.ends_with("é.tar"));
+
+        let name = sanitize_file_name(&"a".repeat(300)).unwrap();
+        assert_eq!(name.len(), MAX_FILE_NAME_LENGTH);
+    }
+
+    #[test]
+    fn test_content_disposition_file_name() {
+        assert_eq!(
+            content_disposition_file_name("attachment; filename=\"a \\\"b\\\"; c.txt\"").as_deref(),
+            Some("a \"b\"; c.txt")
+        );
+        assert_eq!(
+            content_disposition_file_name("attachment; filename=plain.txt").as_deref(),
+            Some("plain.txt")
+        );
+        assert_eq!(
+            content_disposition_file_name(
+                "attachment; filename=\"fallback.txt\"; FILENAME*=UTF-8''%E2%82%AC%20rates.txt"
+            )
+            .as_deref(),
+            Some("€ rates.txt")
+        );
+        assert_eq!(
+            content_disposition_file_name("attachment; filename*=iso-8859-1'en'caf%E9").as_deref(),
+            Some("café")
+        );
+        assert_eq!(
+            content_disposition_file_name("attachment; filename=\"../../etc/passwd\"").as_deref(),
+            Some("passwd")
+        );
+        assert_eq!(content_disposition_file_name("inline"), None);
+        assert_eq!(
+            content_disposition_file_name("attachment; filename=\"\""),
+            None
+        );
+    }
+
+    #[test]
+    fn test_url_to_file_path() {
+        let url = Url::parse("https://example.com:8080/a/b%2Fc/file%20name.txt?q=1").unwrap();
+
+        assert_eq!(
+            url_to_file_path(&url, None, FilePathStyle::FileName),
+            PathBuf::from("file name.txt")
+        );
+        assert_eq!(
+            url_to_file_path(&url, None, FilePathStyle::Mirror),
+            PathBuf::from("example.com_8080/a/b_c/file name.txt@q=1")
+        );
+        assert_eq!(
+            url_to_file_path(
+                &url,
+                Some("attachment; filename=\"../report.pdf\""),
+                FilePathStyle::Mirror
+            ),
+            PathBuf::from("example.com_8080/a/b_c/report.pdf")
+        );
+
+        let url = Url::parse("http://example.com/dir/").unwrap();
+
+        assert_eq!(
+            url_to_file_path(&url, None, FilePathStyle::FileName),
+            PathBuf::from("index.html")
+        );
+        assert_eq!(
+            url_to_file_path(
+                &url,
+                Some("attachment; filename=\"..\""),
+                FilePathStyle::Mirror
+            ),
+            PathBuf::from("example.com/dir/index.html")
+        );
+
+        let url = Url::parse("http://example.com/%2E%2E/%2e%2e/aux").unwrap();
+
+        assert_eq!(
+            url_to_file_path(&url, None, FilePathStyle::Mirror),
+            PathBuf::from("example.com/_aux")
+        );
+    }
+
+    #[test]
+    fn test_resolve_collision() {
+        let existing = [
+            PathBuf::from("dir/index.html"),
+            PathBuf::from("dir/index.1.html"),
+            PathBuf::from("dir/README"),
+        ];
+        let exists = |path: &Path| existing.iter().any(|existing| existing == path);
+
+        assert_eq!(
+            resolve_collision(
+                Path::new("dir/index.html"),
+                CollisionStrategy::Overwrite,
+                exists
+            ),
+            Some(PathBuf::from("dir/index.html"))
+        );
+        assert_eq!(
+            resolve_collision(Path::new("dir/index.html"), CollisionStrategy::Fail, exists),
+            None
+        );
+        assert_eq!(
+            resolve_collision(Path::new("dir/new.html"), CollisionStrategy::Fail, exists),
+            Some(PathBuf::from("dir/new.html"))
+        );
+        assert_eq!(
+            resolve_collision(
+                Path::new("dir/index.html"),
+                CollisionStrategy::Number,
+                exists
+            ),
+            Some(PathBuf::from("dir/index.2.html"))
+        );
+        assert_eq!(
+            resolve_collision(Path::new("dir/README"), CollisionStrategy::Number, exists),
+            Some(PathBuf::from("dir/README.1"))
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use crate::error::Error;
+
+/// Converts a domain name to its ASCII (punycode) form.
+pub fn domain_to_ascii(name: &str) -> Result<String, Error> {
+    idna::domain_to_ascii(name).map_err(|error| Error::InvalidArgument {
+        value: name.to_string(),
+        reason: error.to_string(),
+    })
+}
+
+/// Converts a domain name to its Unicode form for display.
+///
+/// Labels that can't be decoded are left as is.
+pub fn domain_to_unicode(name: &str) -> String {
+    idna::domain_to_unicode(name).0
+}
+
+/// Returns whether a label of the domain name mixes scripts in a way
+/// commonly used to spoof other names.
+///
+/// This is a simplified form of the Unicode "highly restrictive" check:
+/// each label may use a single script, or Latin combined with Japanese,
+/// Chinese, or Korean scripts.
+pub fn is_mixed_script_confusable(name: &str) -> bool {
+    let name = domain_to_unicode(name);
+
+    name.split('.').any(|label| {
+        let mut scripts = label
+            .chars()
+            .map(Script::of)
+            .filter(|script| *script != Script::Common)
+            .collect::<Vec<Script>>();
+        scripts.sort();
+        scripts.dedup();
+
+        !(scripts.len() <= 1
+            || ALLOWED_SCRIPT_COMBINATIONS
+                .iter()
+                .any(|allowed| scripts.iter().all(|script| allowed.contains(script))))
+    })
+}
+
+const ALLOWED_SCRIPT_COMBINATIONS: &[&[Script]] = &[
+    &[
+        Script::Latin,
+        Script::Han,
+        Script::Hiragana,
+        Script::Katakana,
+    ],
+    &[Script::Latin, Script::Han, Script::Bopomofo],
+    &[Script::Latin, Script::Han, Script::Hangul],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Script {
+    Common,
+    Latin,
+    Greek,
+    Cyrillic,
+    Armenian,
+    Hebrew,
+    Arabic,
+    Devanagari,
+    Thai,
+    Georgian,
+    Hangul,
+    Hiragana,
+    Katakana,
+    Bopomofo,
+    Han,
+    Other,
+}
+
+impl Script {
+    fn of(ch: char) -> Self {
+        match ch as u32 {
+            0x30..=0x39 | 0x2D | 0x5F | 0x30FC => Self::Common,
+            0x41..=0x5A | 0x61..=0x7A | 0xC0..=0x24F | 0x1E00..=0x1EFF => Self::Latin,
+            0x370..=0x3FF | 0x1F00..=0x1FFF => Self::Greek,
+            0x400..=0x52F => Self::Cyrillic,
+            0x530..=0x58F => Self::Armenian,
+            0x590..=0x5FF => Self::Hebrew,
+            0x600..=0x6FF | 0x750..=0x77F => Self::Arabic,
+            0x900..=0x97F => Self::Devanagari,
+            0xE00..=0xE7F => Self::Thai,
+            0x10A0..=0x10FF => Self::Georgian,
+            0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => Self::Hangul,
+            0x3040..=0x309F => Self::Hiragana,
+            0x30A0..=0x30FF | 0x31F0..=0x31FF => Self::Katakana,
+            0x3100..=0x312F | 0x31A0..=0x31BF => Self::Bopomofo,
+            0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x3FFFF => Self::Han,
+            _ if ch.is_ascii() => Self::Common,
+            _ => Self::Other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_domain_conversion() {
+        assert_eq!(
+            domain_to_ascii("bücher.example").unwrap(),
+            "xn--bcher-kva.example"
+        );
+        assert_eq!(domain_to_unicode("xn--bcher-kva.example"), "bücher.example");
+        assert!(domain_to_ascii("xn--a.example").is_err());
+    }
+
+    #[test]
+    fn test_is_mixed_script_confusable() {
+        assert!(!is_mixed_script_confusable("example.com"));
+        assert!(!is_mixed_script_confusable("bücher.example"));
+        assert!(!is_mixed_script_confusable("пример.рф"));
+        assert!(!is_mixed_script_confusable("日本語とカタカナ-abc.jp"));
+        // Cyrillic "а" in an otherwise Latin label
+        assert!(is_mixed_script_confusable("p\u{0430}ypal.com"));
+        assert!(is_mixed_script_confusable("xn--pypal-4ve.com"));
+    }
+}
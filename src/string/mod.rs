@@ -1,13 +1,13 @@
+mod base64;
 mod escape;
+mod filename;
+mod idn;
+mod percent;
+mod preview;
 
+pub use self::base64::*;
 pub use escape::*;
-
-pub fn preview_bytes(data: &[u8], length: usize) -> String {
-    if data.len() <= length {
-        String::from_utf8_lossy(data).into_owned()
-    } else {
-        let mut text = String::from_utf8_lossy(&data[0..length]).into_owned();
-        text.push('…');
-        text
-    }
-}
+pub use filename::*;
+pub use idn::*;
+pub use percent::*;
+pub use preview::*;
@@ -0,0 +1,183 @@
+use super::{parse_utf8_escaped, utf8_escaped_to_bytes};
+
+/// Set of ASCII bytes that are percent-encoded.
+///
+/// Bytes outside of ASCII are always encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteSet {
+    bits: [u64; 2],
+}
+
+impl ByteSet {
+    pub const fn new() -> Self {
+        Self { bits: [0; 2] }
+    }
+
+    pub const fn add(mut self, byte: u8) -> Self {
+        if byte < 0x80 {
+            self.bits[(byte / 64) as usize] |= 1 << (byte % 64);
+        }
+        self
+    }
+
+    pub const fn remove(mut self, byte: u8) -> Self {
+        if byte < 0x80 {
+            self.bits[(byte / 64) as usize] &= !(1 << (byte % 64));
+        }
+        self
+    }
+
+    pub const fn add_all(mut self, bytes: &[u8]) -> Self {
+        let mut index = 0;
+
+        while index < bytes.len() {
+            self = self.add(bytes[index]);
+            index += 1;
+        }
+
+        self
+    }
+
+    pub const fn contains(&self, byte: u8) -> bool {
+        byte >= 0x80 || self.bits[(byte / 64) as usize] & (1 << (byte % 64)) != 0
+    }
+}
+
+impl Default for ByteSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// C0 control bytes and DEL.
+pub const CONTROLS: ByteSet = ByteSet {
+    bits: [0xFFFF_FFFF, 1 << (0x7F - 64)],
+};
+
+/// WHATWG URL fragment percent-encode set.
+pub const FRAGMENT: ByteSet = CONTROLS.add_all(b" \"<>`");
+
+/// WHATWG URL query percent-encode set.
+pub const QUERY: ByteSet = CONTROLS.add_all(b" \"#<>");
+
+/// WHATWG URL path percent-encode set.
+pub const PATH: ByteSet = QUERY.add_all(b"?`{}");
+
+/// WHATWG URL userinfo percent-encode set.
+pub const USERINFO: ByteSet = PATH.add_all(b"/:;=@[\\]^|");
+
+/// WHATWG URL component percent-encode set.
+pub const COMPONENT: ByteSet = USERINFO.add_all(b"$%&+,");
+
+/// WHATWG `application/x-www-form-urlencoded` percent-encode set.
+pub const FORM_URLENCODED: ByteSet = COMPONENT.add_all(b"!'()~");
+
+/// Encodes bytes in the set as `%XX` using uppercase hex digits.
+pub fn percent_encode(input: &[u8], set: &ByteSet) -> String {
+    let mut buf = String::with_capacity(input.len());
+
+    for &byte in input {
+        if set.contains(byte) {
+            buf.push('%');
+            buf.push(hex_digit(byte >> 4));
+            buf.push(hex_digit(byte & 0x0f));
+        } else {
+            buf.push(byte as char);
+        }
+    }
+
+    buf
+}
+
+/// Decodes `%XX` sequences.
+///
+/// Malformed sequences are kept as is.
+pub fn percent_decode<S: AsRef<[u8]>>(input: S) -> Vec<u8> {
+    let input = input.as_ref();
+    let mut buf = Vec::with_capacity(input.len());
+    let mut index = 0;
+
+    while index < input.len() {
+        let byte = input[index];
+
+        if byte == b'%' {
+            if let (Some(high), Some(low)) = (
+                input.get(index + 1).copied().and_then(hex_value),
+                input.get(index + 2).copied().and_then(hex_value),
+            ) {
+                buf.push(high << 4 | low);
+                index += 3;
+                continue;
+            }
+        }
+
+        buf.push(byte);
+        index += 1;
+    }
+
+    buf
+}
+
+/// Encodes text produced by [`parse_utf8_escaped`] so that escaped invalid
+/// bytes are restored before encoding.
+pub fn percent_encode_escaped<S: AsRef<str>>(input: S, set: &ByteSet) -> String {
+    percent_encode(&utf8_escaped_to_bytes(input), set)
+}
+
+/// Decodes into text where invalid UTF-8 sequences are escaped using
+/// [`parse_utf8_escaped`].
+///
+/// The result can be re-encoded losslessly with [`percent_encode_escaped`].
+pub fn percent_decode_escaped<S: AsRef<[u8]>>(input: S) -> String {
+    parse_utf8_escaped(&percent_decode(input))
+}
+
+fn hex_digit(value: u8) -> char {
+    char::from_digit(value as u32, 16)
+        .unwrap()
+        .to_ascii_uppercase()
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|value| value as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_set() {
+        assert!(CONTROLS.contains(0x00));
+        assert!(CONTROLS.contains(0x7f));
+        assert!(CONTROLS.contains(0x80));
+        assert!(!CONTROLS.contains(b'a'));
+        assert!(PATH.contains(b' '));
+        assert!(!PATH.contains(b'/'));
+        assert!(COMPONENT.contains(b'/'));
+        assert!(!COMPONENT.remove(b'/').contains(b'/'));
+    }
+
+    #[test]
+    fn test_percent_encode() {
+        assert_eq!(percent_encode(b"a b/c", &PATH), "a%20b/c");
+        assert_eq!(percent_encode(b"a b/c", &COMPONENT), "a%20b%2Fc");
+        assert_eq!(percent_encode("é\n".as_bytes(), &CONTROLS), "%C3%A9%0A");
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("a%20b%2f"), b"a b/");
+        assert_eq!(percent_decode("%C3%A9"), "é".as_bytes());
+        assert_eq!(percent_decode("100%"), b"100%");
+        assert_eq!(percent_decode("%zz%4"), b"%zz%4");
+        assert_eq!(percent_decode("%ff"), b"\xff");
+    }
+
+    #[test]
+    fn test_percent_escaped_round_trip() {
+        let text = percent_decode_escaped("a%FFb%C3%A9");
+        assert_eq!(text, "a\u{FFFD}\u{E01EF}bé");
+        assert_eq!(percent_encode_escaped(&text, &COMPONENT), "a%FFb%C3%A9");
+    }
+}
@@ -0,0 +1,104 @@
+use std::fmt::Write;
+
+/// Number of bytes shown on each line of a hex dump.
+const HEX_DUMP_WIDTH: usize = 16;
+
+/// Returns the bytes as lossy text, truncated to at most `length` bytes
+/// without splitting a UTF-8 sequence.
+pub fn preview_bytes(data: &[u8], length: usize) -> String {
+    if data.len() <= length {
+        String::from_utf8_lossy(data).into_owned()
+    } else {
+        let end = floor_utf8_boundary(data, length);
+        let mut text = String::from_utf8_lossy(&data[0..end]).into_owned();
+        text.push('…');
+        text
+    }
+}
+
+/// Returns the largest index not greater than `index` that does not fall
+/// inside a UTF-8 sequence.
+///
+/// Only up to 3 continuation bytes are skipped so invalid data still makes
+/// progress.
+pub fn floor_utf8_boundary(data: &[u8], index: usize) -> usize {
+    if index >= data.len() {
+        return data.len();
+    }
+
+    let mut end = index;
+
+    while end > 0 && index - end < 3 && is_utf8_continuation(data[end]) {
+        end -= 1;
+    }
+
+    if is_utf8_continuation(data[end]) {
+        index
+    } else {
+        end
+    }
+}
+
+fn is_utf8_continuation(byte: u8) -> bool {
+    byte & 0b1100_0000 == 0b1000_0000
+}
+
+/// Formats the bytes as lines of offset, hex and ASCII columns similar to
+/// `hexdump -C`.
+pub fn hex_dump(data: &[u8]) -> String {
+    let mut buf = String::new();
+
+    for (line_index, chunk) in data.chunks(HEX_DUMP_WIDTH).enumerate() {
+        write!(buf, "{:08x} ", line_index * HEX_DUMP_WIDTH).unwrap();
+
+        for column in 0..HEX_DUMP_WIDTH {
+            if column % 8 == 0 {
+                buf.push(' ');
+            }
+
+            match chunk.get(column) {
+                Some(byte) => write!(buf, "{:02x} ", byte).unwrap(),
+                None => buf.push_str("   "),
+            }
+        }
+
+        buf.push_str(" |");
+        buf.extend(chunk.iter().map(|&byte| {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            }
+        }));
+        buf.push_str("|\n");
+    }
+
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preview_bytes() {
+        assert_eq!(preview_bytes(b"abc", 5), "abc");
+        assert_eq!(preview_bytes(b"abcdef", 3), "abc…");
+        assert_eq!(preview_bytes("a\u{20AC}b".as_bytes(), 2), "a…");
+        assert_eq!(preview_bytes("a\u{20AC}b".as_bytes(), 4), "a\u{20AC}…");
+        assert_eq!(
+            preview_bytes(b"\x80\x80\x80\x80\x80", 4),
+            "\u{FFFD}\u{FFFD}\u{FFFD}\u{FFFD}…"
+        );
+    }
+
+    #[test]
+    fn test_hex_dump() {
+        assert_eq!(hex_dump(b""), "");
+        assert_eq!(
+            hex_dump(b"Hello world!\r\n\x00\xffabc"),
+            "00000000  48 65 6c 6c 6f 20 77 6f  72 6c 64 21 0d 0a 00 ff  |Hello world!....|\n\
+             00000010  61 62 63                                          |abc|\n"
+        );
+    }
+}
@@ -1,6 +1,12 @@
 mod common;
 
-use wrecv::client::{Client, Config, Request, SessionControl, SessionEvent, SessionHandler};
+use std::{net::SocketAddr, time::Duration};
+
+use wrecv::client::{
+    CancellationToken, Client, Config, CookieCapture, EventFilter, EventKind, FtpTransferType,
+    HeaderProfile, HttpAuth, Method, Request, RequestPolicy, RotationStrategy, SessionControl,
+    SessionEvent, SessionHandler, TcpKeepalive, TransferStats, UserAgentRotation,
+};
 
 #[tracing_test::traced_test]
 #[test]
@@ -64,3 +70,2092 @@ fn test_client_ftp() {
 
     server.close();
 }
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_stats() {
+    let mut server = common::http::run_test_server();
+
+    let client = Client::new(Config::new());
+
+    struct MyHandler;
+
+    impl SessionHandler for MyHandler {}
+
+    for _ in 0..2 {
+        let request = Request::new(format!("http://{}/", server.address()).parse().unwrap());
+        let (_handler, result) = client.submit(request, MyHandler);
+        result.unwrap();
+    }
+
+    let request = Request::new("gopher://example.com/".parse().unwrap());
+    let (_handler, result) = client.submit(request, MyHandler);
+    assert!(result.is_err());
+
+    let stats = client.stats();
+    assert_eq!(stats.transfers, 2);
+    assert_eq!(stats.responses_by_status_class.get(&2), Some(&2));
+    assert!(stats.bytes_received > 0);
+    assert!(stats.bytes_sent > 0);
+    assert_eq!(stats.connections_new, 1);
+    assert_eq!(stats.connections_reused, 1);
+    assert_eq!(
+        stats
+            .errors
+            .get(&wrecv::error::ErrorCategory::UnsupportedFeature),
+        Some(&1)
+    );
+
+    let host = &stats.hosts["127.0.0.1"];
+    assert_eq!(host.transfers, 2);
+    assert_eq!(host.errors, 0);
+    assert_eq!(host.bytes_received, stats.bytes_received);
+    assert!(host.average_latency().is_some());
+    assert!(host.last_access.is_some());
+    assert!(!stats.hosts.contains_key("example.com"));
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_transfer_id() {
+    let mut server = common::http::run_test_server();
+
+    let client = Client::new(Config::new());
+
+    #[derive(Default)]
+    struct MyHandler {
+        transfer_id: Option<u64>,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            if let SessionEvent::Finished(stats) = event {
+                self.transfer_id = Some(stats.transfer_id);
+            }
+            Ok(())
+        }
+    }
+
+    let request = Request::new(format!("http://{}/", server.address()).parse().unwrap());
+    let (handler1, result) = client.submit(request.clone(), MyHandler::default());
+    result.unwrap();
+    let (handler2, result) = client.submit(request, MyHandler::default());
+    result.unwrap();
+
+    assert!(handler1.transfer_id.is_some());
+    assert!(handler2.transfer_id.is_some());
+    assert_ne!(handler1.transfer_id, handler2.transfer_id);
+
+    assert!(logs_contain("transfer start"));
+    assert!(logs_contain("transfer finish"));
+    assert!(logs_contain(&format!(
+        "transfer_id={}",
+        handler2.transfer_id.unwrap()
+    )));
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_cancel() {
+    let mut server = common::http::run_test_server();
+
+    let client = Client::new(Config::new());
+
+    struct MyHandler {
+        token: CancellationToken,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            if let SessionEvent::HttpResponse(..) = event {
+                self.token.cancel();
+            }
+            Ok(())
+        }
+    }
+
+    let token = client.cancellation_token().clone();
+    let request = Request::new(format!("http://{}/", server.address()).parse().unwrap());
+    let (_handler, result) = client.submit(request.clone(), MyHandler { token });
+    assert!(matches!(result, Err(wrecv::error::Error::Cancelled)));
+
+    let token = client.cancellation_token().clone();
+    let (_handler, result) = client.submit(request, MyHandler { token });
+    assert!(matches!(result, Err(wrecv::error::Error::Cancelled)));
+    assert_eq!(client.stats().transfers, 1);
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_max_header_size() {
+    let mut server = common::http::run_test_server();
+
+    let mut config = Config::new();
+    config.set_http_max_header_size(4096);
+
+    let client = Client::new(config);
+
+    struct MyHandler;
+
+    impl SessionHandler for MyHandler {}
+
+    let request = Request::new(format!("http://{}/", server.address()).parse().unwrap());
+    let (_handler, result) = client.submit(request, MyHandler);
+    result.unwrap();
+
+    let request = Request::new(
+        format!("http://{}/large-header", server.address())
+            .parse()
+            .unwrap(),
+    );
+    let (_handler, result) = client.submit(request, MyHandler);
+    assert!(matches!(
+        result,
+        Err(wrecv::error::Error::Protocol(
+            wrecv::error::ProtocolError::HeaderTooLarge { limit: 4096 }
+        ))
+    ));
+
+    server.close();
+}
+
+#[test]
+fn test_client_reject_confusable() {
+    let mut config = Config::new();
+    config.set_reject_confusable(true);
+
+    let client = Client::new(config);
+
+    struct MyHandler;
+
+    impl SessionHandler for MyHandler {}
+
+    let request = Request::new("http://p\u{0430}ypal.invalid/".parse().unwrap());
+    let (_handler, result) = client.submit(request, MyHandler);
+    assert!(matches!(
+        result,
+        Err(wrecv::error::Error::InvalidArgument { .. })
+    ));
+}
+
+#[test]
+fn test_client_connect_failed() {
+    let address = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let mut config = Config::new();
+    config.set_connect_timeout(Duration::from_secs(5));
+
+    let client = Client::new(config);
+
+    #[derive(Default)]
+    struct MyHandler {
+        attempts: Vec<SocketAddr>,
+        failures: Vec<(SocketAddr, String)>,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            match event {
+                SessionEvent::ConnectAttempt(address) => self.attempts.push(address),
+                SessionEvent::ConnectFailed(address, reason) => {
+                    self.failures.push((address, reason))
+                }
+                _ => {}
+            }
+            Ok(())
+        }
+    }
+
+    let request = Request::new(format!("http://{}/", address).parse().unwrap());
+    let (handler, result) = client.submit(request, MyHandler::default());
+
+    assert!(result.is_err());
+    assert_eq!(handler.attempts, vec![address]);
+    assert_eq!(handler.failures.len(), 1);
+    assert_eq!(handler.failures[0].0, address);
+}
+
+#[test]
+fn test_client_socket_options() {
+    let mut server = common::http::run_test_server();
+
+    let mut config = Config::new();
+    config
+        .set_tcp_nodelay(false)
+        .set_tcp_keepalive(Some(TcpKeepalive {
+            idle: Duration::from_secs(60),
+            interval: Duration::from_secs(10),
+        }))
+        .set_ip_tos(Some(0x20));
+
+    let client = Client::new(config);
+
+    struct MyHandler;
+
+    impl SessionHandler for MyHandler {}
+
+    let request = Request::new(format!("http://{}/", server.address()).parse().unwrap());
+    let (_handler, result) = client.submit(request, MyHandler);
+    assert!(result.is_ok());
+
+    server.close();
+}
+
+#[test]
+fn test_client_max_transfer_duration() {
+    let mut server = common::http::run_test_server();
+
+    let mut config = Config::new();
+    config.set_max_transfer_duration(Some(Duration::from_millis(500)));
+
+    let client = Client::new(config);
+
+    struct MyHandler;
+
+    impl SessionHandler for MyHandler {}
+
+    let request = Request::new(format!("http://{}/slow", server.address()).parse().unwrap());
+    let (_handler, result) = client.submit(request, MyHandler);
+    assert!(matches!(
+        result,
+        Err(wrecv::error::Error::Network(
+            wrecv::error::NetworkError::TimedOut(_)
+        ))
+    ));
+
+    server.close();
+}
+
+#[test]
+fn test_client_circuit_breaker() {
+    let address = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let mut config = Config::new();
+    config.set_circuit_breaker_threshold(2);
+
+    let client = Client::new(config);
+
+    #[derive(Default)]
+    struct MyHandler {
+        opened: Option<(String, Duration)>,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            if let SessionEvent::CircuitOpened(host, duration) = event {
+                self.opened = Some((host, duration));
+            }
+            Ok(())
+        }
+    }
+
+    let url = format!("http://{}/", address);
+
+    let (handler, result) = client.submit(Request::new(url.parse().unwrap()), MyHandler::default());
+    assert!(result.is_err());
+    assert!(handler.opened.is_none());
+
+    let (handler, result) = client.submit(Request::new(url.parse().unwrap()), MyHandler::default());
+    assert!(result.is_err());
+    assert_eq!(
+        handler.opened,
+        Some(("127.0.0.1".to_string(), Duration::from_secs(30)))
+    );
+
+    let (_handler, result) =
+        client.submit(Request::new(url.parse().unwrap()), MyHandler::default());
+    assert!(matches!(
+        result,
+        Err(wrecv::error::Error::Network(
+            wrecv::error::NetworkError::CircuitOpen { .. }
+        ))
+    ));
+}
+
+#[cfg(feature = "curl")]
+#[test]
+fn test_client_verify_status_unsupported() {
+    let ssl_version = curl::Version::get()
+        .ssl_version()
+        .unwrap_or_default()
+        .to_string();
+
+    if !ssl_version.starts_with("rustls") {
+        return;
+    }
+
+    let mut config = Config::new();
+    config.set_verify_status(true);
+
+    let client = Client::new(config);
+
+    struct MyHandler;
+
+    impl SessionHandler for MyHandler {}
+
+    let request = Request::new("https://localhost.invalid/".parse().unwrap());
+    let (_handler, result) = client.submit(request, MyHandler);
+    assert!(matches!(
+        result,
+        Err(wrecv::error::Error::UnsupportedFeature { .. })
+    ));
+}
+
+#[tracing_test::traced_test]
+#[test]
+#[cfg(feature = "dns")]
+fn test_client_resolver() {
+    let mut server = common::http::run_test_server();
+
+    let mut dns_config = wrecv::dns::Config::new();
+    dns_config.add_static_entry("wrecv.test", vec![server.address().ip()]);
+
+    let mut client = Client::new(Config::new());
+    client.set_resolver(Some(std::sync::Arc::new(
+        wrecv::dns::Resolver::new(dns_config).unwrap(),
+    )));
+
+    struct MyHandler;
+
+    impl SessionHandler for MyHandler {}
+
+    let request = Request::new(
+        format!("http://wrecv.test:{}/", server.address().port())
+            .parse()
+            .unwrap(),
+    );
+    let (_handler, result) = client.submit(request, MyHandler);
+    assert!(result.is_ok());
+
+    let request = Request::new("http://missing.wrecv.test/".parse().unwrap());
+    let (_handler, result) = client.submit(request, MyHandler);
+    assert!(result.is_err());
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_http_raw_target() {
+    let mut server = common::http::run_test_server();
+
+    let client = Client::new(Config::new());
+
+    #[derive(Default)]
+    struct MyHandler {
+        uri: Option<String>,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            if let SessionEvent::HttpRequest(_data, header) = event {
+                self.uri = Some(header.uri);
+            }
+            Ok(())
+        }
+    }
+
+    let mut request = Request::new(
+        format!("http://{}/a/../b/%7e", server.address())
+            .parse()
+            .unwrap(),
+    );
+    let (handler, result) = client.submit(request.clone(), MyHandler::default());
+    result.unwrap();
+    assert_eq!(handler.uri.as_deref(), Some("/b/%7e"));
+
+    request.set_http_raw_target(Some(b"/a/../b/%7e".to_vec()));
+    let (handler, result) = client.submit(request.clone(), MyHandler::default());
+    result.unwrap();
+    assert_eq!(handler.uri.as_deref(), Some("/a/../b/%7e"));
+
+    request.set_http_raw_target(Some(b"/caf\xe9".to_vec()));
+    let (handler, result) = client.submit(request.clone(), MyHandler::default());
+    assert!(result.is_ok());
+    assert_eq!(handler.uri.as_deref(), Some("/caf\u{FFFD}\u{E01D9}"));
+
+    request.set_http_raw_target(Some(b"/a b".to_vec()));
+    let (_handler, result) = client.submit(request, MyHandler::default());
+    assert!(matches!(
+        result,
+        Err(wrecv::error::Error::InvalidArgument { .. })
+    ));
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_http_header_order() {
+    let mut server = common::http::run_test_server();
+
+    let mut config = Config::new();
+    config
+        .set_http_header_order(vec!["x-b".into(), "User-Agent".into(), "x-a".into()])
+        .set_http_suppressed_headers(vec!["Accept".into()]);
+    config.http_headers_mut().append("x-a", "1");
+    config.http_headers_mut().append("x-empty", "");
+
+    let client = Client::new(config);
+
+    #[derive(Default)]
+    struct MyHandler {
+        names: Vec<String>,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            if let SessionEvent::HttpRequest(_data, header) = event {
+                self.names = header
+                    .fields
+                    .iter()
+                    .map(|(name, _)| name.normalized().to_string())
+                    .collect();
+            }
+            Ok(())
+        }
+    }
+
+    let mut request = Request::new(format!("http://{}/", server.address()).parse().unwrap());
+    request.http_headers_mut().append("x-b", "2");
+
+    let (handler, result) = client.submit(request, MyHandler::default());
+    result.unwrap();
+    assert_eq!(
+        handler.names,
+        vec![
+            "host",
+            "accept-encoding",
+            "x-b",
+            "user-agent",
+            "x-a",
+            "x-empty"
+        ]
+    );
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_cookie_suppression() {
+    let mut server = common::http::run_test_server();
+
+    let mut config = Config::new();
+    config.set_http_cookies(true);
+
+    let mut client = Client::new(config);
+    let url: url::Url = format!("http://{}/", server.address()).parse().unwrap();
+
+    let mut fields = wrecv::http::HeaderFields::new();
+    fields.append("Set-Cookie", "session=1");
+    client.cookie_jar().parse_from_response(&url, &fields);
+    client.cookie_jar_mut().set_read_only(true);
+
+    #[derive(Default)]
+    struct MyHandler {
+        cookie: Option<String>,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            if let SessionEvent::HttpRequest(_data, header) = event {
+                self.cookie = header
+                    .fields
+                    .get("Cookie")
+                    .map(|value| value.to_string_lossy());
+            }
+            Ok(())
+        }
+    }
+
+    let (handler, result) = client.submit(Request::new(url.clone()), MyHandler::default());
+    result.unwrap();
+    assert_eq!(handler.cookie.as_deref(), Some("session=1"));
+
+    let mut request = Request::new(url);
+    request.set_http_cookies(false);
+
+    let (handler, result) = client.submit(request, MyHandler::default());
+    result.unwrap();
+    assert_eq!(handler.cookie, None);
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_cookie_capture() {
+    let mut server = common::http::run_test_server();
+
+    #[derive(Default)]
+    struct MyHandler {
+        cookie: Option<String>,
+        accepted: Vec<String>,
+        rejected: Vec<String>,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            match event {
+                SessionEvent::HttpRequest(_data, header) => {
+                    self.cookie = header
+                        .fields
+                        .get("Cookie")
+                        .map(|value| value.to_string_lossy());
+                }
+                SessionEvent::CookieAccepted { name } => self.accepted.push(name),
+                SessionEvent::CookieRejected { name, reason } => {
+                    assert!(!reason.is_empty());
+                    self.rejected.push(name);
+                }
+                _ => {}
+            }
+            Ok(())
+        }
+    }
+
+    let mut config = Config::new();
+    config.set_http_cookies(true);
+
+    let client = Client::new(config.clone());
+    let url = format!("http://{}/redirect-cookie", server.address());
+
+    let (handler, result) = client.submit(Request::new(url.parse().unwrap()), MyHandler::default());
+    result.unwrap();
+    assert_eq!(handler.accepted, vec!["hop"]);
+    assert_eq!(handler.rejected, vec!["other"]);
+
+    let url = format!("http://{}/", server.address());
+    let (handler, result) = client.submit(Request::new(url.parse().unwrap()), MyHandler::default());
+    result.unwrap();
+    assert_eq!(handler.cookie.as_deref(), Some("hop=1"));
+
+    config.set_http_cookie_capture(CookieCapture::IgnoreRedirects);
+    let client = Client::new(config);
+
+    for (path, accepted) in [("redirect-cookie", vec![]), ("set-cookie", vec!["key1"])] {
+        let url = format!("http://{}/{}", server.address(), path);
+        let (handler, result) =
+            client.submit(Request::new(url.parse().unwrap()), MyHandler::default());
+        result.unwrap();
+        assert_eq!(handler.accepted, accepted);
+        assert!(handler.rejected.is_empty());
+    }
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_security_policy() {
+    let mut server = common::http::run_test_server();
+    let client = Client::new(Config::new());
+
+    #[derive(Default)]
+    struct MyHandler {
+        finished: Vec<TransferStats>,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            if let SessionEvent::Finished(stats) = event {
+                self.finished.push(stats);
+            }
+            Ok(())
+        }
+    }
+
+    let mut handler = MyHandler::default();
+
+    for path in ["security-policy", ""] {
+        let url = format!("http://{}/{}", server.address(), path);
+        let result;
+        (handler, result) = client.submit(Request::new(url.parse().unwrap()), handler);
+        result.unwrap();
+    }
+
+    let policy = handler.finished[0].security_policy.as_ref().unwrap();
+    assert_eq!(
+        policy.content_security_policy[0].directives["default-src"],
+        vec!["'self'"]
+    );
+    assert!(policy.permissions_policy.as_ref().unwrap()["camera"].is_empty());
+    assert!(policy.expect_ct.is_none());
+    assert!(handler.finished[1].security_policy.is_none());
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_event_filter() {
+    let mut server = common::http::run_test_server();
+    let client = Client::new(Config::new());
+
+    struct MyHandler {
+        filter: EventFilter,
+        kinds: Vec<EventKind>,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            self.kinds.push(event.kind());
+            Ok(())
+        }
+
+        fn event_filter(&self) -> EventFilter {
+            self.filter
+        }
+    }
+
+    let url = format!("http://{}/", server.address());
+    let request = Request::new(url.parse().unwrap());
+    let handler = MyHandler {
+        filter: EventFilter::from_iter([EventKind::HttpResponse, EventKind::Finished]),
+        kinds: Vec::new(),
+    };
+    let (handler, result) = client.submit(request, handler);
+    result.unwrap();
+
+    assert_eq!(
+        handler.kinds,
+        vec![EventKind::HttpResponse, EventKind::Finished]
+    );
+
+    let request = Request::new(url.parse().unwrap());
+    let handler = MyHandler {
+        filter: EventFilter::from_iter([EventKind::HttpResponse]),
+        kinds: Vec::new(),
+    };
+    let (handler, result) = client.submit(request, handler);
+    result.unwrap();
+
+    assert_eq!(handler.kinds, vec![EventKind::HttpResponse]);
+
+    let filter = EventFilter::all().without(EventKind::Progress);
+    assert!(!filter.contains(EventKind::Progress));
+    assert!(filter.contains(EventKind::Trace));
+    assert!(!EventFilter::none().contains(EventKind::Trace));
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_header_profile() {
+    let mut server = common::http::run_test_server();
+
+    let mut config = Config::new();
+    config.set_header_profile(HeaderProfile::Firefox);
+
+    let client = Client::new(config);
+
+    #[derive(Default)]
+    struct MyHandler {
+        header: Option<wrecv::http::RequestHeader>,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            if let SessionEvent::HttpRequest(_data, header) = event {
+                self.header = Some(header);
+            }
+            Ok(())
+        }
+    }
+
+    let request = Request::new(format!("http://{}/", server.address()).parse().unwrap());
+    let (handler, result) = client.submit(request, MyHandler::default());
+    result.unwrap();
+
+    let header = handler.header.unwrap();
+    let names = header
+        .fields
+        .iter()
+        .map(|(name, _)| name.normalized())
+        .filter(|name| *name != "host" && *name != "accept-encoding")
+        .collect::<Vec<&str>>();
+    let expected = HeaderProfile::Firefox
+        .http_headers()
+        .iter()
+        .map(|(name, _)| name.to_ascii_lowercase())
+        .collect::<Vec<String>>();
+
+    assert_eq!(names, expected);
+    assert_eq!(
+        header.fields.get("user-agent").unwrap().to_string(),
+        HeaderProfile::Firefox.user_agent()
+    );
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_http_interim_response() {
+    let address = common::http::run_raw_test_server(
+        b"HTTP/1.1 103 Early Hints\r\n\
+        Link: </style.css>; rel=preload; as=style\r\n\
+        \r\n\
+        HTTP/1.1 200 OK\r\n\
+        Content-Length: 2\r\n\
+        Connection: close\r\n\
+        \r\n\
+        ok",
+    );
+
+    let client = Client::new(Config::new());
+
+    #[derive(Default)]
+    struct MyHandler {
+        interim_status_codes: Vec<u16>,
+        link: Option<String>,
+        status_code: Option<u16>,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            match event {
+                SessionEvent::HttpInterimResponse(_data, header) => {
+                    self.interim_status_codes.push(header.status_code);
+                    self.link = header.fields.get("link").map(|value| value.to_string());
+                }
+                SessionEvent::HttpResponse(_data, header) => {
+                    self.status_code = Some(header.status_code);
+                }
+                _ => {}
+            }
+            Ok(())
+        }
+    }
+
+    let request = Request::new(format!("http://{}/", address).parse().unwrap());
+    let (handler, result) = client.submit(request, MyHandler::default());
+    result.unwrap();
+
+    assert_eq!(handler.interim_status_codes, vec![103]);
+    assert_eq!(
+        handler.link.as_deref(),
+        Some("</style.css>; rel=preload; as=style")
+    );
+    assert_eq!(handler.status_code, Some(200));
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_progress() {
+    let mut server = common::http::run_test_server();
+
+    let mut config = Config::new();
+    config.set_progress_interval(Duration::from_secs(3600));
+
+    let client = Client::new(config);
+
+    #[derive(Default)]
+    struct MyHandler {
+        progress: Vec<(u64, u64, f64, Option<Duration>)>,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            if let SessionEvent::Progress {
+                download_total,
+                download_current,
+                download_rate,
+                eta,
+                ..
+            } = event
+            {
+                self.progress
+                    .push((download_total, download_current, download_rate.average, eta));
+            }
+            Ok(())
+        }
+    }
+
+    let request = Request::new(format!("http://{}/", server.address()).parse().unwrap());
+    let (handler, result) = client.submit(request, MyHandler::default());
+    result.unwrap();
+
+    // The first event and the completed transfer, the rest being throttled
+    assert!(handler.progress.len() <= 2);
+
+    let (total, current, average_rate, eta) = *handler.progress.last().unwrap();
+    assert_eq!(total, 12);
+    assert_eq!(current, 12);
+    assert!(average_rate > 0.0);
+    assert_eq!(eta, Some(Duration::ZERO));
+
+    server.close();
+}
+
+#[test]
+fn test_client_progress_unknown_size() {
+    let address = common::http::run_raw_test_server(GZIP_CHUNKED_RESPONSE);
+
+    let mut config = Config::new();
+    config.set_progress_interval(Duration::from_secs(3600));
+
+    let client = Client::new(config);
+
+    #[derive(Default)]
+    struct MyHandler {
+        progress: Vec<(u64, u64)>,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            if let SessionEvent::Progress {
+                download_total,
+                download_current,
+                ..
+            } = event
+            {
+                self.progress.push((download_total, download_current));
+            }
+            Ok(())
+        }
+    }
+
+    let request = Request::new(format!("http://{}/", address).parse().unwrap());
+    let (handler, result) = client.submit(request, MyHandler::default());
+    result.unwrap();
+
+    // The first event is throttled until the end, which still reports the
+    // final count
+    assert_eq!(handler.progress.last(), Some(&(0, 32)));
+}
+
+const GZIP_HELLO_WORLD: &[u8] = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\xf3\x48\xcd\xc9\xc9\x57\
+    \x28\xcf\x2f\xca\x49\x51\x04\x00\x95\x19\x85\x1b\x0c\x00\x00\x00";
+
+const GZIP_CHUNKED_RESPONSE: &[u8] = b"HTTP/1.1 200 OK\r\n\
+    Content-Encoding: gzip\r\n\
+    Transfer-Encoding: chunked\r\n\
+    Connection: close\r\n\
+    \r\n\
+    10\r\n\
+    \x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\xf3\x48\xcd\xc9\xc9\x57\r\n\
+    10\r\n\
+    \x28\xcf\x2f\xca\x49\x51\x04\x00\x95\x19\x85\x1b\x0c\x00\x00\x00\r\n\
+    0\r\n\
+    \r\n";
+
+#[derive(Default)]
+struct BodyHandler {
+    body: Vec<u8>,
+    content: Vec<u8>,
+}
+
+impl SessionHandler for BodyHandler {
+    fn event(
+        &mut self,
+        _control: &mut dyn SessionControl,
+        event: SessionEvent,
+    ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+        match event {
+            SessionEvent::BodyReceived(data) => self.body.extend_from_slice(data),
+            SessionEvent::ContentReceived(data) => self.content.extend_from_slice(data),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_raw_and_decoded_body() {
+    let address = common::http::run_raw_test_server(GZIP_CHUNKED_RESPONSE);
+    let client = Client::new(Config::new());
+
+    let request = Request::new(format!("http://{}/", address).parse().unwrap());
+    let (handler, result) = client.submit(request, BodyHandler::default());
+    result.unwrap();
+
+    let wire_body = &GZIP_CHUNKED_RESPONSE[GZIP_CHUNKED_RESPONSE
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .unwrap()
+        + 4..];
+
+    assert_eq!(handler.body, wire_body);
+    assert_eq!(handler.content, b"Hello world!");
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_content_decoding_disabled() {
+    let address = common::http::run_raw_test_server(GZIP_CHUNKED_RESPONSE);
+
+    let mut config = Config::new();
+    config
+        .set_http_content_decoding(false)
+        .set_http_raw_body(false);
+
+    let client = Client::new(config);
+
+    let request = Request::new(format!("http://{}/", address).parse().unwrap());
+    let (handler, result) = client.submit(request, BodyHandler::default());
+    result.unwrap();
+
+    assert!(handler.body.is_empty());
+    assert_eq!(handler.content, GZIP_HELLO_WORLD);
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_resolved_addresses() {
+    let mut server = common::http::run_test_server();
+
+    let mut config = Config::new();
+    config.set_dns_timeout(Some(std::time::Duration::from_secs(10)));
+
+    let client = Client::new(config);
+
+    #[derive(Default)]
+    struct MyHandler {
+        stats: Option<TransferStats>,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            if let SessionEvent::Finished(stats) = event {
+                self.stats = Some(stats);
+            }
+            Ok(())
+        }
+    }
+
+    let request = Request::new(
+        format!("http://localhost:{}/", server.address().port())
+            .parse()
+            .unwrap(),
+    );
+
+    let (handler, result) = client.submit(request, MyHandler::default());
+    result.unwrap();
+
+    let stats = handler.stats.unwrap();
+    assert!(stats
+        .resolved_addresses
+        .contains(&std::net::Ipv4Addr::LOCALHOST.into()));
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_head() {
+    let mut server = common::http::run_test_server();
+    let client = Client::new(Config::new());
+
+    #[derive(Default)]
+    struct MyHandler {
+        method: Option<String>,
+        status_code: Option<u16>,
+        content_length: Option<u64>,
+        content: Vec<u8>,
+        finished: Option<TransferStats>,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            match event {
+                SessionEvent::HttpRequest(_data, header) => self.method = Some(header.method),
+                SessionEvent::HttpResponse(_data, header) => {
+                    self.status_code = Some(header.status_code);
+                    self.content_length = header.fields.content_length();
+                }
+                SessionEvent::ContentReceived(data) => self.content.extend_from_slice(data),
+                SessionEvent::Finished(stats) => self.finished = Some(stats),
+                _ => {}
+            }
+            Ok(())
+        }
+    }
+
+    let mut request = Request::new(format!("http://{}/", server.address()).parse().unwrap());
+    request.set_method(Method::Head);
+
+    let (handler, result) = client.submit(request, MyHandler::default());
+    result.unwrap();
+
+    assert_eq!(handler.method.as_deref(), Some("HEAD"));
+    assert_eq!(handler.status_code, Some(200));
+    assert_eq!(handler.content_length, Some(12));
+    assert!(handler.content.is_empty());
+
+    let stats = handler.finished.unwrap();
+    assert_eq!(stats.status_code, Some(200));
+    assert_eq!(stats.response_body_bytes, 0);
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_ftp_proxy() {
+    let mut server = common::ftp::run_test_server();
+    let (proxy_address, tunnel_count) = common::http::run_connect_proxy();
+
+    let mut config = Config::new();
+    config.set_proxy(Some(format!("http://{}", proxy_address).parse().unwrap()));
+
+    let client = Client::new(config);
+    let request = Request::new(format!("ftp://{}/", server.address()).parse().unwrap());
+
+    struct MyHandler;
+
+    impl SessionHandler for MyHandler {}
+
+    let (_handler, result) = client.submit(request, MyHandler);
+    result.unwrap();
+
+    // Control and data connections
+    assert!(tunnel_count.load(std::sync::atomic::Ordering::Relaxed) >= 2);
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_http_proxy_tunnel() {
+    let mut server = common::http::run_test_server();
+    let (proxy_address, tunnel_count) = common::http::run_connect_proxy();
+
+    let mut config = Config::new();
+    config
+        .set_proxy(Some(format!("http://{}", proxy_address).parse().unwrap()))
+        .set_proxy_tunnel(true);
+
+    let client = Client::new(config);
+    let request = Request::new(format!("http://{}/", server.address()).parse().unwrap());
+
+    struct MyHandler;
+
+    impl SessionHandler for MyHandler {}
+
+    let (_handler, result) = client.submit(request, MyHandler);
+    result.unwrap();
+
+    assert_eq!(tunnel_count.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_ftp_conversation() {
+    let mut server = common::ftp::run_test_server();
+    std::fs::write(server.root_path().join("file.txt"), "hello\n").unwrap();
+
+    let client = Client::new(Config::new());
+
+    #[derive(Default)]
+    struct MyHandler {
+        commands: Vec<(String, String)>,
+        replies: Vec<(u16, String)>,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            match event {
+                SessionEvent::FtpCommand { command, argument } => {
+                    self.commands.push((command, argument))
+                }
+                SessionEvent::FtpReply { code, text } => self.replies.push((code, text)),
+                _ => {}
+            }
+            Ok(())
+        }
+    }
+
+    let request = Request::new(
+        format!("ftp://{}/file.txt", server.address())
+            .parse()
+            .unwrap(),
+    );
+
+    let (handler, result) = client.submit(request, MyHandler::default());
+    result.unwrap();
+
+    assert_eq!(handler.replies.first().map(|reply| reply.0), Some(220));
+    assert!(handler.replies.iter().any(|reply| reply.0 == 226));
+    assert_eq!(
+        handler.commands.first().map(|command| command.0.as_str()),
+        Some("USER")
+    );
+    assert!(handler
+        .commands
+        .contains(&("RETR".to_string(), "file.txt".to_string())));
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_ftp_transfer_type() {
+    let mut server = common::ftp::run_test_server();
+    std::fs::write(server.root_path().join("file.txt"), "hello\n").unwrap();
+
+    let client = Client::new(Config::new());
+
+    #[derive(Default)]
+    struct MyHandler {
+        transfer_types: Vec<FtpTransferType>,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            if let SessionEvent::FtpTransferType(transfer_type) = event {
+                self.transfer_types.push(transfer_type);
+            }
+            Ok(())
+        }
+    }
+
+    let mut request = Request::new(
+        format!("ftp://{}/file.txt", server.address())
+            .parse()
+            .unwrap(),
+    );
+    request.set_ftp_transfer_type(Some(FtpTransferType::Ascii));
+
+    let (handler, result) = client.submit(request, MyHandler::default());
+    result.unwrap();
+    assert_eq!(handler.transfer_types, vec![FtpTransferType::Ascii]);
+
+    let mut request = Request::new(
+        format!("ftp://{}/file.txt;type=i", server.address())
+            .parse()
+            .unwrap(),
+    );
+    request.set_ftp_transfer_type(Some(FtpTransferType::Ascii));
+
+    let (handler, result) = client.submit(request, MyHandler::default());
+    result.unwrap();
+    assert_eq!(handler.transfer_types, vec![FtpTransferType::Binary]);
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_accept_language() {
+    let mut server = common::http::run_test_server();
+
+    let mut config = Config::new();
+    config
+        .set_header_profile(HeaderProfile::Firefox)
+        .set_http_accept_language(Some("de-DE,de;q=0.9".to_string()));
+
+    let client = Client::new(config);
+
+    #[derive(Default)]
+    struct MyHandler {
+        header: Option<wrecv::http::RequestHeader>,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            if let SessionEvent::HttpRequest(_data, header) = event {
+                self.header = Some(header);
+            }
+            Ok(())
+        }
+    }
+
+    let request = Request::new(format!("http://{}/", server.address()).parse().unwrap());
+    let (handler, result) = client.submit(request, MyHandler::default());
+    result.unwrap();
+
+    let header = handler.header.unwrap();
+    let values = header
+        .fields
+        .iter()
+        .filter(|(name, _)| name.normalized() == "accept-language")
+        .map(|(_, value)| value.to_string())
+        .collect::<Vec<String>>();
+    let position = header
+        .fields
+        .iter()
+        .filter(|(name, _)| name.normalized() != "host" && name.normalized() != "accept-encoding")
+        .position(|(name, _)| name.normalized() == "accept-language");
+    let expected_position = HeaderProfile::Firefox
+        .http_headers()
+        .iter()
+        .position(|(name, _)| name.eq_ignore_ascii_case("accept-language"));
+
+    assert_eq!(values, vec!["de-DE,de;q=0.9"]);
+    assert_eq!(position, expected_position);
+
+    let mut request = Request::new(format!("http://{}/", server.address()).parse().unwrap());
+    request.http_headers_mut().insert("Accept-Language", "fr");
+
+    let (handler, result) = client.submit(request, MyHandler::default());
+    result.unwrap();
+
+    let header = handler.header.unwrap();
+    assert_eq!(
+        header.fields.get("accept-language").unwrap().to_string(),
+        "fr"
+    );
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_user_agent_rotation() {
+    let mut server = common::http::run_test_server();
+
+    let mut config = Config::new();
+    config.set_http_user_agent_rotation(Some(UserAgentRotation::new(
+        vec!["agent-a".to_string(), "agent-b".to_string()],
+        RotationStrategy::RoundRobin,
+    )));
+
+    let client = Client::new(config);
+
+    #[derive(Default)]
+    struct MyHandler {
+        user_agent: Option<String>,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            if let SessionEvent::HttpRequest(_data, header) = event {
+                self.user_agent = header
+                    .fields
+                    .get("user-agent")
+                    .map(|value| value.to_string());
+            }
+            Ok(())
+        }
+    }
+
+    let url: url::Url = format!("http://{}/", server.address()).parse().unwrap();
+    let mut user_agents = Vec::new();
+
+    for _ in 0..3 {
+        let (handler, result) = client.submit(Request::new(url.clone()), MyHandler::default());
+        result.unwrap();
+        user_agents.push(handler.user_agent.unwrap());
+    }
+
+    let mut request = Request::new(url);
+    request.set_http_user_agent(Some("agent-override".to_string()));
+
+    let (handler, result) = client.submit(request, MyHandler::default());
+    result.unwrap();
+    user_agents.push(handler.user_agent.unwrap());
+
+    assert_eq!(
+        user_agents,
+        vec!["agent-a", "agent-b", "agent-a", "agent-override"]
+    );
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_http_auth_unsupported() {
+    let mut server = common::http::run_test_server();
+
+    for (supported, auth) in [
+        (
+            wrecv::client::ntlm_supported(),
+            HttpAuth::Ntlm {
+                username: "user".to_string(),
+                password: "password".to_string(),
+            },
+        ),
+        (wrecv::client::negotiate_supported(), HttpAuth::Negotiate),
+    ] {
+        if supported {
+            continue;
+        }
+
+        let mut config = Config::new();
+        config.set_http_auth(Some(auth));
+
+        let client = Client::new(config);
+
+        struct MyHandler;
+
+        impl SessionHandler for MyHandler {}
+
+        let request = Request::new(format!("http://{}/", server.address()).parse().unwrap());
+        let (_handler, result) = client.submit(request, MyHandler);
+        assert!(matches!(
+            result,
+            Err(wrecv::error::Error::UnsupportedFeature { .. })
+        ));
+    }
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_connection_info() {
+    let mut server = common::http::run_test_server();
+    let client = Client::new(Config::new());
+
+    #[derive(Default)]
+    struct MyHandler {
+        connected: Option<SocketAddr>,
+        connection: Option<(bool, SocketAddr, SocketAddr)>,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            match event {
+                SessionEvent::Connected(address) => {
+                    assert!(self.connected.is_none());
+                    assert!(self.connection.is_none());
+                    self.connected = Some(address);
+                }
+                SessionEvent::ConnectionInfo {
+                    reused,
+                    local_addr,
+                    remote_addr,
+                } => {
+                    assert!(self.connection.is_none());
+                    self.connection = Some((reused, local_addr, remote_addr));
+                }
+                _ => {}
+            }
+            Ok(())
+        }
+    }
+
+    let mut local_addrs = Vec::new();
+
+    for expected_reused in [false, true] {
+        let request = Request::new(format!("http://{}/", server.address()).parse().unwrap());
+        let (handler, result) = client.submit(request, MyHandler::default());
+        result.unwrap();
+
+        let (reused, local_addr, remote_addr) = handler.connection.unwrap();
+        assert_eq!(reused, expected_reused);
+        assert_eq!(remote_addr, server.address());
+        assert_eq!(handler.connected, (!reused).then_some(remote_addr));
+        local_addrs.push(local_addr);
+    }
+
+    assert_eq!(local_addrs[0], local_addrs[1]);
+    assert_eq!(client.stats().connections_reused, 1);
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_wire_bytes() {
+    const RESPONSE: &[u8] = b"HTTP/1.1 200 OK\r\n\
+        Content-Length: 2\r\n\
+        Connection: close\r\n\
+        \r\n\
+        ok";
+
+    #[derive(Default)]
+    struct MyHandler {
+        request_header_len: u64,
+        stats: Option<wrecv::client::TransferStats>,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            match event {
+                SessionEvent::HeaderSent(data) => {
+                    self.request_header_len += data.len() as u64;
+                }
+                SessionEvent::Finished(stats) => {
+                    self.stats = Some(stats);
+                }
+                _ => {}
+            }
+            Ok(())
+        }
+    }
+
+    let address = common::http::run_raw_test_server(RESPONSE);
+    let client = Client::new(Config::new());
+    let request = Request::new(format!("http://{}/", address).parse().unwrap());
+    let (handler, result) = client.submit(request, MyHandler::default());
+    result.unwrap();
+
+    let stats = handler.stats.unwrap();
+    assert_eq!(stats.request_header_bytes, handler.request_header_len);
+    assert_eq!(stats.request_body_bytes, 0);
+    assert_eq!(stats.response_header_bytes, RESPONSE.len() as u64 - 2);
+    assert_eq!(stats.response_body_bytes, 2);
+    assert_eq!(stats.bytes_received, RESPONSE.len() as u64);
+    assert_eq!(stats.bytes_sent, handler.request_header_len);
+    assert_eq!(stats.tls_overhead_bytes, None);
+    assert_eq!(stats.http_version, Some(wrecv::client::HttpVersion::Http11));
+    assert_eq!(stats.scheme.as_deref(), Some("http"));
+    assert_eq!(stats.alpn_protocol, None);
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_current_transfer() {
+    let mut server = common::http::run_test_server();
+    let client = Client::new(Config::new());
+
+    #[derive(Default)]
+    struct MyHandler {
+        current: Option<(u64, url::Url)>,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            if let SessionEvent::HttpResponse(..) = event {
+                self.current = wrecv::client::current_transfer();
+            }
+            Ok(())
+        }
+    }
+
+    let url: url::Url = format!("http://{}/", server.address()).parse().unwrap();
+
+    assert!(wrecv::client::current_transfer().is_none());
+
+    let (handler, result) = client.submit(Request::new(url.clone()), MyHandler::default());
+    result.unwrap();
+
+    assert_eq!(
+        handler.current.map(|(_, current_url)| current_url),
+        Some(url)
+    );
+    assert!(wrecv::client::current_transfer().is_none());
+
+    server.close();
+}
+
+#[cfg(feature = "native")]
+#[tracing_test::traced_test]
+#[test]
+fn test_client_native_backend() {
+    let mut server = common::http::run_test_server();
+
+    let mut config = Config::new();
+    config.set_backend(wrecv::client::Backend::Native);
+
+    let client = Client::new(config);
+
+    #[derive(Default)]
+    struct MyHandler {
+        status_code: Option<u16>,
+        content: Vec<u8>,
+        trailer: Option<String>,
+        finished: bool,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+            match event {
+                SessionEvent::HttpResponse(_data, response) => {
+                    self.status_code = Some(response.status_code);
+                }
+                SessionEvent::ContentReceived(data) => {
+                    self.content.extend_from_slice(data);
+                }
+                SessionEvent::HttpResponseTrailer(_data, fields) => {
+                    self.trailer = fields
+                        .fields
+                        .get("x-checksum")
+                        .map(|value| value.to_string_lossy());
+                }
+                SessionEvent::Finished(_) => {
+                    self.finished = true;
+                }
+                _ => {}
+            }
+            Ok(())
+        }
+    }
+
+    let request = Request::new(format!("http://{}/", server.address()).parse().unwrap());
+    let (handler, result) = client.submit(request, MyHandler::default());
+    result.unwrap();
+
+    assert_eq!(handler.status_code, Some(200));
+    assert_eq!(handler.content, b"Hello world!");
+    assert!(handler.finished);
+
+    server.close();
+
+    let address = common::http::run_raw_test_server(
+        b"HTTP/1.1 200 OK\r\n\
+        Transfer-Encoding: chunked\r\n\
+        \r\n\
+        5\r\nHello\r\n7\r\n world!\r\n0\r\n\
+        X-Checksum: abc\r\n\
+        \r\n",
+    );
+
+    let request = Request::new(format!("http://{}/", address).parse().unwrap());
+    let (handler, result) = client.submit(request, MyHandler::default());
+    result.unwrap();
+
+    assert_eq!(handler.content, b"Hello world!");
+    assert_eq!(handler.trailer.as_deref(), Some("abc"));
+
+    let request = Request::new("ftp://example.com/".parse().unwrap());
+    let (_handler, result) = client.submit(request, MyHandler::default());
+
+    assert!(matches!(
+        result,
+        Err(wrecv::error::Error::UnsupportedFeature { .. })
+    ));
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_request_policy() {
+    let mut server = common::http::run_test_server();
+
+    struct MyHandler;
+
+    impl SessionHandler for MyHandler {}
+
+    let mut policy = RequestPolicy::new();
+    let mut config = Config::new();
+    config.set_request_policy(Some(policy.clone()));
+
+    let client = Client::new(config);
+
+    // Checked after the host name is resolved
+    let url = format!("http://localhost:{}/", server.address().port());
+    let (_handler, result) = client.submit(Request::new(url.parse().unwrap()), MyHandler);
+    assert!(matches!(result, Err(wrecv::error::Error::Blocked { .. })));
+
+    let url = format!("http://{}/", server.address());
+    let (_handler, result) = client.submit(Request::new(url.parse().unwrap()), MyHandler);
+    assert!(matches!(result, Err(wrecv::error::Error::Blocked { .. })));
+
+    let (_handler, result) = client.submit(
+        Request::new("ftp://example.com/".parse().unwrap()),
+        MyHandler,
+    );
+    assert!(matches!(result, Err(wrecv::error::Error::Blocked { .. })));
+
+    policy.set_allow_loopback(true);
+    let mut config = Config::new();
+    config.set_request_policy(Some(policy));
+
+    let client = Client::new(config);
+    let (_handler, result) = client.submit(Request::new(url.parse().unwrap()), MyHandler);
+    result.unwrap();
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_upload() {
+    let mut server = common::http::run_test_server();
+
+    #[derive(Default)]
+    struct MyHandler {
+        content: Vec<u8>,
+        sent: usize,
+        framing: Option<String>,
+        response: Vec<u8>,
+    }
+
+    impl SessionHandler for MyHandler {
+        fn upload_content(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            buf: &mut [u8],
+        ) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+            // Small pieces so that chunked coding sends several chunks
+            let size = (self.content.len() - self.sent).min(buf.len()).min(5);
+            buf[..size].copy_from_slice(&self.content[self.sent..self.sent + size]);
+            self.sent += size;
+
+            Ok(size)
+        }
+
+        fn event(
+            &mut self,
+            _control: &mut dyn SessionControl,
+            event: SessionEvent,
+        ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+            match event {
+                SessionEvent::HttpResponse(_data, header) => {
+                    self.framing = header
+                        .fields
+                        .get("x-framing")
+                        .map(|value| value.to_string_lossy());
+                }
+                SessionEvent::ContentReceived(data) => self.response.extend_from_slice(data),
+                _ => {}
+            }
+
+            Ok(())
+        }
+    }
+
+    let client = Client::new(Config::new());
+    let url = format!("http://{}/echo", server.address());
+
+    for (method, upload_size, framing) in [
+        (Method::Post, None, "chunked"),
+        (Method::Put, None, "chunked"),
+        (Method::Post, Some(16), "length 16"),
+        (Method::Put, Some(16), "length 16"),
+    ] {
+        let mut request = Request::new(url.parse().unwrap());
+        request.set_method(method).set_upload_size(upload_size);
+
+        let handler = MyHandler {
+            content: b"Hello from stdin".to_vec(),
+            ..Default::default()
+        };
+        let (handler, result) = client.submit(request, handler);
+        result.unwrap();
+
+        assert_eq!(handler.framing.as_deref(), Some(framing), "{:?}", method);
+        assert_eq!(handler.response, b"Hello from stdin");
+    }
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_host_lists() {
+    let mut server = common::http::run_test_server();
+
+    struct MyHandler;
+
+    impl SessionHandler for MyHandler {}
+
+    let name_url = format!("http://localhost:{}/", server.address().port())
+        .parse::<url::Url>()
+        .unwrap();
+    let address_url = format!("http://{}/", server.address())
+        .parse::<url::Url>()
+        .unwrap();
+
+    let submit = |config: Config, url: &url::Url| {
+        let client = Client::new(config);
+        client.submit(Request::new(url.clone()), MyHandler).1
+    };
+    let is_blocked = |result: Result<_, wrecv::error::Error>| {
+        matches!(result, Err(wrecv::error::Error::Blocked { .. }))
+    };
+
+    let mut config = Config::new();
+    config.set_host_allowlist(Some(vec!["*.example.com".parse().unwrap()]));
+    assert!(is_blocked(submit(config.clone(), &name_url)));
+    assert!(is_blocked(submit(config, &address_url)));
+
+    let mut config = Config::new();
+    config.set_host_allowlist(Some(vec!["localhost".parse().unwrap()]));
+    submit(config.clone(), &name_url).unwrap();
+    assert!(is_blocked(submit(config, &address_url)));
+
+    // Checked after the host name is resolved
+    let mut config = Config::new();
+    config.set_host_allowlist(Some(vec![
+        "127.0.0.0/8".parse().unwrap(),
+        "::1/128".parse().unwrap(),
+    ]));
+    submit(config.clone(), &name_url).unwrap();
+    submit(config, &address_url).unwrap();
+
+    let mut config = Config::new();
+    config
+        .set_host_allowlist(Some(vec!["localhost".parse().unwrap()]))
+        .set_host_denylist(vec!["127.0.0.1".parse().unwrap(), "::1".parse().unwrap()]);
+    assert!(is_blocked(submit(config, &name_url)));
+
+    server.close();
+}
+
+#[tracing_test::traced_test]
+#[test]
+fn test_client_get() {
+    let mut server = common::http::run_test_server();
+
+    let client = Client::new(Config::new());
+
+    let response = client
+        .get(format!("http://{}/", server.address()).parse().unwrap())
+        .unwrap();
+    assert_eq!(response.status_code(), Some(200));
+    assert_eq!(response.body(), b"Hello world!");
+    assert!(response.trailer().is_none());
+    assert_eq!(response.transfer_stats().unwrap().status_code, Some(200));
+
+    let response = client
+        .get(
+            format!("http://{}/security-policy", server.address())
+                .parse()
+                .unwrap(),
+        )
+        .unwrap();
+    let header = response.header().unwrap();
+    assert_eq!(
+        header
+            .fields
+            .get("permissions-policy")
+            .map(|value| value.to_string_lossy())
+            .as_deref(),
+        Some("camera=()")
+    );
+    assert_eq!(response.into_body(), b"policy");
+
+    server.close();
+
+    let result = client.get(format!("http://{}/", server.address()).parse().unwrap());
+    assert!(result.is_err());
+}
+
+#[cfg(feature = "native")]
+#[tracing_test::traced_test]
+#[test]
+fn test_client_tls_verification_error() {
+    use wrecv::error::{Error, ProtocolError, TlsVerificationKind};
+
+    let address = common::tls::run_test_server();
+    let url = format!("https://localhost:{}/", address.port());
+
+    let mut config = Config::new();
+    config.set_backend(wrecv::client::Backend::Native);
+
+    match Client::new(config).get(url.parse().unwrap()) {
+        Err(Error::Protocol(ProtocolError::TlsVerification(error))) => {
+            assert_eq!(error.kind(), TlsVerificationKind::UnknownIssuer);
+            assert_eq!(error.certificates(), &[common::tls::certificate()]);
+        }
+        result => panic!("unexpected result {:?}", result),
+    }
+
+    let mut config = Config::new();
+    config
+        .set_backend(wrecv::client::Backend::Native)
+        .set_tls_verification(false);
+
+    let response = Client::new(config).get(url.parse().unwrap()).unwrap();
+    assert_eq!(response.body(), b"Hello world!");
+}
+
+#[cfg(feature = "native")]
+#[tracing_test::traced_test]
+#[test]
+fn test_client_tls_key_log() {
+    let address = common::tls::run_test_server();
+    let url = format!("https://localhost:{}/", address.port());
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("keys.log");
+
+    let mut config = Config::new();
+    config
+        .set_backend(wrecv::client::Backend::Native)
+        .set_tls_verification(false)
+        .set_tls_key_log(Some(path.clone()));
+
+    let response = Client::new(config).get(url.parse().unwrap()).unwrap();
+    assert_eq!(response.body(), b"Hello world!");
+
+    let text = std::fs::read_to_string(&path).unwrap();
+    assert!(!text.is_empty());
+
+    for line in text.lines() {
+        let fields = line.split(' ').collect::<Vec<_>>();
+        assert_eq!(fields.len(), 3);
+        assert_eq!(fields[1].len(), 64);
+    }
+
+    let mut config = Config::new();
+    config.set_tls_key_log(Some(path));
+
+    assert!(matches!(
+        config.validate(),
+        Err(wrecv::error::Error::UnsupportedFeature { .. })
+    ));
+}
+
+#[cfg(all(feature = "curl", feature = "native"))]
+#[tracing_test::traced_test]
+#[test]
+fn test_client_tls_verification_error_curl() {
+    use wrecv::error::{Error, ProtocolError, TlsVerificationKind};
+
+    let address = common::tls::run_test_server();
+    let url = format!("https://localhost:{}/", address.port());
+    let ssl_version = curl::Version::get()
+        .ssl_version()
+        .unwrap_or_default()
+        .to_string();
+
+    match Client::new(Config::new()).get(url.parse().unwrap()) {
+        Err(Error::Protocol(ProtocolError::TlsVerification(error))) => {
+            assert!(error.certificates().is_empty());
+
+            // rustls-ffi doesn't tell certificate problems apart
+            if ssl_version.starts_with("rustls") {
+                assert_eq!(error.kind(), TlsVerificationKind::Other);
+            } else if ssl_version.starts_with("OpenSSL") {
+                assert_eq!(error.kind(), TlsVerificationKind::UnknownIssuer);
+            }
+        }
+        result => panic!("unexpected result {:?}", result),
+    }
+
+    let mut config = Config::new();
+    config.set_tls_verification(false);
+
+    let response = Client::new(config).get(url.parse().unwrap()).unwrap();
+    let tls_overhead_bytes = response.transfer_stats().unwrap().tls_overhead_bytes;
+
+    if ssl_version.starts_with("rustls") {
+        assert_eq!(tls_overhead_bytes, None);
+    } else if ssl_version.starts_with("OpenSSL") {
+        assert!(tls_overhead_bytes.unwrap() > 0);
+    }
+}
+
+#[cfg(feature = "async")]
+#[tracing_test::traced_test]
+#[test]
+fn test_async_client() {
+    let mut server = common::http::run_test_server();
+
+    let client = wrecv::client::AsyncClient::new(Config::new(), 2);
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    runtime.block_on(async {
+        let url = format!("http://{}/", server.address());
+        let slow_url = format!("http://{}/slow", server.address());
+
+        let (response, slow_response) = tokio::join!(
+            client.get(url.parse().unwrap()),
+            client.get(slow_url.parse().unwrap())
+        );
+        assert_eq!(response.unwrap().body(), b"Hello world!");
+        assert_eq!(slow_response.unwrap().body(), b"slow");
+
+        #[derive(Default)]
+        struct MyHandler {
+            status_code: Option<u16>,
+        }
+
+        impl SessionHandler for MyHandler {
+            fn event(
+                &mut self,
+                _control: &mut dyn SessionControl,
+                event: SessionEvent,
+            ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+                if let SessionEvent::HttpResponse(_data, header) = event {
+                    self.status_code = Some(header.status_code);
+                }
+
+                Ok(())
+            }
+        }
+
+        let (handler, result) = client
+            .submit(Request::new(url.parse().unwrap()), MyHandler::default())
+            .await
+            .unwrap();
+        result.unwrap();
+        assert_eq!(handler.status_code, Some(200));
+
+        struct PanicHandler;
+
+        impl SessionHandler for PanicHandler {
+            fn event(
+                &mut self,
+                _control: &mut dyn SessionControl,
+                _event: SessionEvent,
+            ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+                panic!("handler panic");
+            }
+        }
+
+        let result = client
+            .submit(Request::new(url.parse().unwrap()), PanicHandler)
+            .await;
+        assert!(matches!(result, Err(wrecv::error::Error::Other(_))));
+
+        // The worker goes on after the panic
+        let response = client.get(url.parse().unwrap()).await.unwrap();
+        assert_eq!(response.body(), b"Hello world!");
+
+        client.shutdown().await;
+    });
+
+    server.close();
+}
+
+#[test]
+fn test_config_builder() {
+    use wrecv::error::Error;
+
+    let mut builder = Config::builder();
+    builder
+        .set_proxy(Some("socks5h://127.0.0.1:1080".parse().unwrap()))
+        .set_tls_verification(true);
+    let config = builder.build().unwrap();
+    assert!(config.proxy().is_some());
+
+    let mut builder = Config::builder();
+    builder.set_proxy_tunnel(true);
+    assert!(matches!(
+        builder.build(),
+        Err(Error::InvalidArgument { .. })
+    ));
+
+    let mut builder = Config::builder();
+    builder
+        .set_proxy(Some("socks5://127.0.0.1:1080".parse().unwrap()))
+        .set_proxy_tunnel(true);
+    assert!(matches!(
+        builder.build(),
+        Err(Error::InvalidArgument { .. })
+    ));
+
+    let mut builder = Config::builder();
+    builder.set_proxy(Some("ftp://127.0.0.1:21".parse().unwrap()));
+    assert!(matches!(
+        builder.build(),
+        Err(Error::InvalidArgument { .. })
+    ));
+
+    let mut builder = Config::builder();
+    builder.set_verify_status(true).set_tls_verification(false);
+    assert!(matches!(
+        builder.build(),
+        Err(Error::InvalidArgument { .. })
+    ));
+
+    #[cfg(feature = "native")]
+    {
+        let mut builder = Config::builder();
+        builder
+            .set_backend(wrecv::client::Backend::Native)
+            .set_proxy(Some("http://127.0.0.1:3128".parse().unwrap()));
+        assert!(matches!(
+            builder.build(),
+            Err(Error::UnsupportedFeature { feature }) if feature.contains("proxy")
+        ));
+    }
+}
@@ -1,4 +1,8 @@
-use std::{net::{Ipv4Addr, SocketAddr, TcpListener}, time::Duration};
+use std::{
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    path::Path,
+    time::Duration,
+};
 
 use libunftp::{options::Shutdown, Server};
 use tempfile::TempDir;
@@ -16,6 +20,11 @@ impl ServerHandle {
         self.address
     }
 
+    /// Directory served as the root of the server.
+    pub fn root_path(&self) -> &Path {
+        self.temp_dir.as_ref().unwrap().path()
+    }
+
     pub fn close(&mut self) {
         if let Some(sender) = self.shutdown_sender.take() {
             sender.send(()).unwrap();
@@ -60,9 +69,23 @@ pub fn run_test_server() -> ServerHandle {
         });
     });
 
+    wait_for_listen(address);
+
     ServerHandle {
         address,
         shutdown_sender: Some(sender),
         temp_dir: Some(temp_dir),
     }
 }
+
+fn wait_for_listen(address: SocketAddr) {
+    for _ in 0..100 {
+        if TcpStream::connect(address).is_ok() {
+            return;
+        }
+
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    panic!("test ftp server did not start");
+}
@@ -1,6 +1,19 @@
-use std::net::{Ipv4Addr, SocketAddr, TcpListener};
+use std::{
+    io::{Read, Write},
+    net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
-use axum::{http::header::SET_COOKIE, response::Redirect, routing::get, Router};
+use axum::{
+    body::Bytes,
+    http::{header::SET_COOKIE, HeaderMap},
+    response::Redirect,
+    routing::{get, post},
+    Router,
+};
 use tokio::{runtime::Runtime, sync::oneshot::Sender};
 
 pub struct ServerHandle {
@@ -26,6 +39,28 @@ impl Drop for ServerHandle {
     }
 }
 
+/// Responds with the request body and how its length was given.
+async fn echo_body(
+    headers: HeaderMap,
+    body: Bytes,
+) -> (
+    axum::response::AppendHeaders<[(&'static str, String); 1]>,
+    Bytes,
+) {
+    let framing = if let Some(value) = headers.get("transfer-encoding") {
+        value.to_str().unwrap().to_string()
+    } else if let Some(value) = headers.get("content-length") {
+        format!("length {}", value.to_str().unwrap())
+    } else {
+        "none".to_string()
+    };
+
+    (
+        axum::response::AppendHeaders([("x-framing", framing)]),
+        body,
+    )
+}
+
 pub fn run_test_server() -> ServerHandle {
     let app = Router::new()
         .route("/", get(|| async { "Hello world!" }))
@@ -38,6 +73,51 @@ pub fn run_test_server() -> ServerHandle {
                     "cookie",
                 )
             }),
+        )
+        .route(
+            "/redirect-cookie",
+            get(|| async {
+                (
+                    axum::response::AppendHeaders([
+                        (SET_COOKIE, "hop=1"),
+                        (SET_COOKIE, "other=1; Domain=example.com"),
+                    ]),
+                    Redirect::temporary("/"),
+                )
+            }),
+        )
+        .route(
+            "/security-policy",
+            get(|| async {
+                (
+                    axum::response::AppendHeaders([
+                        ("content-security-policy", "default-src 'self'"),
+                        ("permissions-policy", "camera=()"),
+                    ]),
+                    "policy",
+                )
+            }),
+        )
+        .route("/echo", post(echo_body).put(echo_body))
+        .route(
+            "/large-header",
+            get(|| async {
+                (
+                    axum::response::AppendHeaders([("x-large", "a".repeat(10000))]),
+                    "large header",
+                )
+            }),
+        )
+        .route(
+            "/slow",
+            get(|| async {
+                tokio::task::spawn_blocking(|| {
+                    std::thread::sleep(std::time::Duration::from_secs(3))
+                })
+                .await
+                .unwrap();
+                "slow"
+            }),
         );
 
     let (sender, receiver) = tokio::sync::oneshot::channel::<()>();
@@ -70,3 +150,100 @@ pub fn run_test_server() -> ServerHandle {
         shutdown_sender: Some(sender),
     }
 }
+
+/// Runs a server that answers a single connection with the given bytes
+/// after reading the request header.
+pub fn run_raw_test_server(response: &'static [u8]) -> SocketAddr {
+    let listener = TcpListener::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0)).unwrap();
+    let address = listener.local_addr().unwrap();
+
+    std::thread::spawn(move || {
+        let (mut stream, _address) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+            let size = stream.read(&mut buf).unwrap();
+
+            if size == 0 {
+                return;
+            }
+
+            request.extend_from_slice(&buf[..size]);
+        }
+
+        stream.write_all(response).unwrap();
+    });
+
+    address
+}
+
+/// Runs an HTTP proxy that only supports CONNECT tunnels.
+///
+/// Returns the address and the number of tunnels made so far.
+pub fn run_connect_proxy() -> (SocketAddr, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0)).unwrap();
+    let address = listener.local_addr().unwrap();
+    let tunnel_count = Arc::new(AtomicUsize::new(0));
+    let counter = tunnel_count.clone();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                return;
+            };
+            let counter = counter.clone();
+
+            std::thread::spawn(move || run_tunnel(stream, &counter));
+        }
+    });
+
+    (address, tunnel_count)
+}
+
+fn run_tunnel(mut client: TcpStream, counter: &AtomicUsize) {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1];
+
+    // Read byte by byte so that no tunneled data is consumed
+    while !request.ends_with(b"\r\n\r\n") {
+        if client.read(&mut buf).unwrap_or(0) == 0 {
+            return;
+        }
+
+        request.extend_from_slice(&buf);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let target = match request.strip_prefix("CONNECT ") {
+        Some(line) => line.split(' ').next().unwrap_or_default().to_string(),
+        None => {
+            let _ = client.write_all(b"HTTP/1.1 405 Method Not Allowed\r\n\r\n");
+            return;
+        }
+    };
+
+    let Ok(server) = TcpStream::connect(&target) else {
+        let _ = client.write_all(b"HTTP/1.1 502 Bad Gateway\r\n\r\n");
+        return;
+    };
+
+    counter.fetch_add(1, Ordering::Relaxed);
+    client
+        .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+        .unwrap();
+
+    let mut client_reader = client.try_clone().unwrap();
+    let mut server_writer = server.try_clone().unwrap();
+    let mut server_reader = server;
+    let mut client_writer = client;
+
+    let upload = std::thread::spawn(move || {
+        let _ = std::io::copy(&mut client_reader, &mut server_writer);
+        let _ = server_writer.shutdown(Shutdown::Write);
+    });
+
+    let _ = std::io::copy(&mut server_reader, &mut client_writer);
+    let _ = client_writer.shutdown(Shutdown::Write);
+    let _ = upload.join();
+}
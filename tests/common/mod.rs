@@ -1,2 +1,4 @@
 pub mod ftp;
 pub mod http;
+#[cfg(feature = "native")]
+pub mod tls;
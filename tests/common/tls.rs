@@ -0,0 +1,60 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr, TcpListener},
+    sync::Arc,
+};
+
+use base64::Engine;
+use rustls::{Certificate, PrivateKey, ServerConfig, ServerConnection, StreamOwned};
+
+/// Self-signed certificate for `localhost`, valid until 2126.
+const CERTIFICATE: &str = "MIIBkzCCATigAwIBAgIUJjt/mgyVNkK9XjyaFnJ6b0hSes0wCgYIKoZIzj0EAwIwFDESMBAGA1UEAwwJbG9jYWxob3N0MCAXDTI2MTAxNzA4MDg1OVoYDzIxMjYwOTIzMDgwODU5WjAUMRIwEAYDVQQDDAlsb2NhbGhvc3QwWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAAQPHGbkzPjeZRP++aODL6F6XAGrB2BQAK+siGFe6PV9mFaKh3YpT8nXIHr7X/RcD1Y9GbU731Y7iO1qt/v6xz8ko2YwZDAdBgNVHQ4EFgQUMBitt+1mS0Q4trANpNAVJEtEGA0wHwYDVR0jBBgwFoAUMBitt+1mS0Q4trANpNAVJEtEGA0wFAYDVR0RBA0wC4IJbG9jYWxob3N0MAwGA1UdEwEB/wQCMAAwCgYIKoZIzj0EAwIDSQAwRgIhAKK9oBV2nFgRmCCZhvN1wNnF1EXGG/K42ccT+xQaG4aCAiEAossAit4g3yroTKVUQYkCDpUgO71b2ML90ncjkWL5/Zc=";
+
+/// PKCS #8 key of [`CERTIFICATE`].
+const PRIVATE_KEY: &str = "MIGHAgEAMBMGByqGSM49AgEGCCqGSM49AwEHBG0wawIBAQQgMlMYfl7WLwvm1ZpBDo7Lyl/rUxk+4LSkEMuHXJzGEXGhRANCAAQPHGbkzPjeZRP++aODL6F6XAGrB2BQAK+siGFe6PV9mFaKh3YpT8nXIHr7X/RcD1Y9GbU731Y7iO1qt/v6xz8k";
+
+/// Returns the DER encoding of the certificate of the test server.
+pub fn certificate() -> Vec<u8> {
+    base64::engine::general_purpose::STANDARD
+        .decode(CERTIFICATE)
+        .unwrap()
+}
+
+/// Runs a TLS server with a self-signed certificate that answers every
+/// request with "Hello world!".
+pub fn run_test_server() -> SocketAddr {
+    let key = base64::engine::general_purpose::STANDARD
+        .decode(PRIVATE_KEY)
+        .unwrap();
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(vec![Certificate(certificate())], PrivateKey(key))
+        .unwrap();
+    let config = Arc::new(config);
+
+    let listener = TcpListener::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0)).unwrap();
+    let address = listener.local_addr().unwrap();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let connection = ServerConnection::new(config.clone()).unwrap();
+            let mut stream = StreamOwned::new(connection, stream.unwrap());
+
+            std::thread::spawn(move || {
+                use std::io::{Read, Write};
+
+                let mut buf = [0u8; 4096];
+
+                if stream.read(&mut buf).unwrap_or(0) > 0 {
+                    let _ = stream.write_all(
+                        b"HTTP/1.1 200 OK\r\nContent-Length: 12\r\nConnection: close\r\n\r\nHello world!",
+                    );
+                    stream.conn.send_close_notify();
+                    let _ = stream.flush();
+                }
+            });
+        }
+    });
+
+    address
+}
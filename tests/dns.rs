@@ -0,0 +1,400 @@
+#![cfg(feature = "dns")]
+
+use std::{
+    io::{Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, UdpSocket},
+};
+
+use trust_dns_resolver::proto::{
+    op::{Message, MessageType, ResponseCode},
+    rr::{rdata::TXT, RData, Record, RecordType},
+    serialize::binary::{BinDecodable, BinEncodable},
+};
+use wrecv::dns::{
+    AnswerSource, Config, DnsRecord, RecordFields, RecordType as LookupType, Resolver,
+    ServerProtocol,
+};
+
+/// Runs a DoH server at `/custom/dns` that answers A queries with
+/// 192.0.2.1, AAAA queries of names starting with `dual.` with 2001:db8::1,
+/// TXT queries with `v=wrecv`, and names starting with `nx.` with NXDOMAIN.
+fn run_doh_test_server() -> SocketAddr {
+    let listener = TcpListener::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0)).unwrap();
+    let address = listener.local_addr().unwrap();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = stream.unwrap();
+            std::thread::spawn(move || handle_doh_connection(stream));
+        }
+    });
+
+    address
+}
+
+fn handle_doh_connection(mut stream: TcpStream) {
+    loop {
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1];
+
+        while !request.ends_with(b"\r\n\r\n") {
+            if stream.read(&mut buf).unwrap_or(0) == 0 {
+                return;
+            }
+            request.extend_from_slice(&buf);
+        }
+
+        let header = String::from_utf8(request).unwrap();
+        assert!(header.starts_with("POST /custom/dns "));
+
+        let content_length = header
+            .lines()
+            .find_map(|line| {
+                line.to_ascii_lowercase()
+                    .strip_prefix("content-length:")
+                    .map(str::to_string)
+            })
+            .unwrap()
+            .trim()
+            .parse::<usize>()
+            .unwrap();
+
+        let mut body = vec![0u8; content_length];
+        stream.read_exact(&mut body).unwrap();
+
+        let query = Message::from_bytes(&body).unwrap();
+        let query_info = query.queries()[0].clone();
+
+        let mut response = Message::new();
+        response
+            .set_id(query.id())
+            .set_message_type(MessageType::Response)
+            .add_query(query_info.clone());
+
+        if query_info.name().to_ascii().starts_with("nx.") {
+            response.set_response_code(ResponseCode::NXDomain);
+        } else if query_info.query_type() == RecordType::A {
+            response.add_answer(Record::from_rdata(
+                query_info.name().clone(),
+                300,
+                RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+            ));
+        } else if query_info.query_type() == RecordType::AAAA
+            && query_info.name().to_ascii().starts_with("dual.")
+        {
+            response.add_answer(Record::from_rdata(
+                query_info.name().clone(),
+                300,
+                RData::AAAA(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+            ));
+        } else if query_info.query_type() == RecordType::TXT {
+            response.add_answer(Record::from_rdata(
+                query_info.name().clone(),
+                300,
+                RData::TXT(TXT::new(vec!["v=wrecv".to_string()])),
+            ));
+        }
+
+        let body = response.to_bytes().unwrap();
+        let mut output = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/dns-message\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        )
+        .into_bytes();
+        output.extend_from_slice(&body);
+
+        stream.write_all(&output).unwrap();
+    }
+}
+
+/// Runs a plain DNS server that answers every A query with 127.0.0.1.
+fn run_udp_dns_test_server() -> SocketAddr {
+    let socket = UdpSocket::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0)).unwrap();
+    let address = socket.local_addr().unwrap();
+
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 512];
+
+        loop {
+            let (size, peer) = socket.recv_from(&mut buf).unwrap();
+            let query = Message::from_bytes(&buf[..size]).unwrap();
+            let query_info = query.queries()[0].clone();
+
+            let mut response = Message::new();
+            response
+                .set_id(query.id())
+                .set_message_type(MessageType::Response)
+                .set_recursion_available(true)
+                .add_query(query_info.clone());
+
+            if query_info.query_type() == RecordType::A {
+                response.add_answer(Record::from_rdata(
+                    query_info.name().clone(),
+                    300,
+                    RData::A(Ipv4Addr::LOCALHOST),
+                ));
+            }
+
+            socket.send_to(&response.to_bytes().unwrap(), peer).unwrap();
+        }
+    });
+
+    address
+}
+
+#[test]
+fn test_resolver_doh_url() {
+    let address = run_doh_test_server();
+
+    let mut config = Config::new();
+    config.set_insecure_doh(true);
+    config
+        .add_doh_url(&format!("http://{}/custom/dns{{?dns}}", address))
+        .unwrap();
+
+    let resolver = Resolver::new(config).unwrap();
+
+    let lookup = resolver.lookup_ip_address("example.com").unwrap();
+    assert_eq!(
+        lookup.ip_addresses(),
+        &[IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))]
+    );
+
+    assert_eq!(
+        lookup.records(),
+        vec![DnsRecord {
+            name: "example.com.".to_string(),
+            record_type: "A".to_string(),
+            ttl: 300,
+            data: "192.0.2.1".to_string(),
+            fields: Some(RecordFields::Address {
+                address: IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))
+            }),
+        }]
+    );
+
+    match lookup.source() {
+        AnswerSource::Server(server) => {
+            assert_eq!(server.address, Some(address));
+            assert_eq!(server.protocol, ServerProtocol::Https);
+            assert_eq!(server.name, resolver.server_stats()[0].name);
+        }
+        source => panic!("unexpected source {:?}", source),
+    }
+
+    let result = resolver.lookup_ip_address("nx.example.com");
+    assert!(result.is_err());
+
+    let stats = resolver.server_stats();
+    assert_eq!(stats.len(), 1);
+    assert_eq!(stats[0].queries, 2);
+    assert_eq!(stats[0].failures, 0);
+}
+
+#[test]
+fn test_resolver_doh_url_insecure() {
+    let mut config = Config::new();
+    config
+        .add_doh_url("http://doh.wrecv.test/dns-query")
+        .unwrap();
+
+    assert!(Resolver::new(config.clone()).is_err());
+
+    config.set_insecure_doh(true);
+
+    assert!(Resolver::new(config).is_ok());
+}
+
+#[test]
+fn test_resolver_doh_dual_stack() {
+    let address = run_doh_test_server();
+
+    let mut config = Config::new();
+    config.set_insecure_doh(true);
+    config
+        .add_doh_url(&format!("http://{}/custom/dns", address))
+        .unwrap();
+
+    let resolver = Resolver::new(config).unwrap();
+
+    let lookup = resolver.lookup_ip_address("dual.example.com").unwrap();
+    assert_eq!(
+        lookup.ip_addresses(),
+        &[
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+            IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1))
+        ]
+    );
+}
+
+#[test]
+fn test_resolver_doh_bootstrap() {
+    let doh_address = run_doh_test_server();
+    let bootstrap_address = run_udp_dns_test_server();
+
+    let mut config = Config::new();
+    config.set_insecure_doh(true);
+    config
+        .add_doh_url(&format!(
+            "http://doh.wrecv.test:{}/custom/dns",
+            doh_address.port()
+        ))
+        .unwrap();
+    config.set_bootstrap_servers(vec![bootstrap_address]);
+
+    let resolver = Resolver::new(config).unwrap();
+
+    let lookup = resolver.lookup_ip_address("example.com").unwrap();
+    assert_eq!(
+        lookup.ip_addresses(),
+        &[IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))]
+    );
+}
+
+#[test]
+fn test_resolver_doq_fallback() {
+    let address = run_doh_test_server();
+
+    let mut config = Config::new();
+    config.set_insecure_doh(true);
+    config.add_doq_server(
+        SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 1),
+        "doq.wrecv.test",
+    );
+
+    if !wrecv::dns::doq_supported() {
+        assert!(Resolver::new(config.clone()).is_err());
+    }
+
+    config
+        .add_doh_url(&format!("http://{}/custom/dns", address))
+        .unwrap();
+
+    let resolver = Resolver::new(config).unwrap();
+
+    let lookup = resolver.lookup_ip_address("example.com").unwrap();
+    assert_eq!(
+        lookup.ip_addresses(),
+        &[IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))]
+    );
+}
+
+#[test]
+fn test_resolver_static_entry() {
+    let mut config = Config::new();
+    config.add_static_entry("staging.example.com", vec![IpAddr::V4(Ipv4Addr::LOCALHOST)]);
+
+    let resolver = Resolver::new(config).unwrap();
+
+    let lookup = resolver.lookup_ip_address("Staging.Example.com").unwrap();
+    assert_eq!(lookup.ip_addresses(), &[IpAddr::V4(Ipv4Addr::LOCALHOST)]);
+    assert_eq!(lookup.source(), &AnswerSource::Static);
+    assert!(lookup.to_record_string().contains("127.0.0.1"));
+
+    assert!(resolver.lookup_ip_address("example.com").is_err());
+}
+
+#[test]
+fn test_resolver_cache_file() {
+    let address = run_doh_test_server();
+    let path = std::env::temp_dir().join(format!(
+        "wrecv-test-resolver-cache-{}.json",
+        std::process::id()
+    ));
+
+    let mut config = Config::new();
+    config.set_insecure_doh(true);
+    config
+        .add_doh_url(&format!("http://{}/custom/dns", address))
+        .unwrap();
+    config.set_cache_file(Some(&path));
+
+    let resolver = Resolver::new(config.clone()).unwrap();
+    resolver.lookup_ip_address("example.com").unwrap();
+    resolver.save_cache().unwrap();
+
+    let resolver = Resolver::new(config).unwrap();
+    let lookup = resolver.lookup_ip_address("example.com").unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(
+        lookup.ip_addresses(),
+        &[IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))]
+    );
+    assert_eq!(lookup.source(), &AnswerSource::Cache);
+    assert_eq!(resolver.server_stats()[0].queries, 0);
+}
+
+#[test]
+fn test_resolver_udp_server() {
+    let address = run_udp_dns_test_server();
+
+    let mut config = Config::new();
+    config.add_udp_server(address);
+
+    let resolver = Resolver::new(config).unwrap();
+
+    let lookup = resolver.lookup_ip_address("example.com").unwrap();
+    assert_eq!(lookup.ip_addresses(), &[IpAddr::V4(Ipv4Addr::LOCALHOST)]);
+    assert_eq!(resolver.server_stats()[0].name, address.to_string());
+
+    match lookup.source() {
+        AnswerSource::Server(server) => {
+            assert_eq!(server.address, Some(address));
+            assert_eq!(server.protocol, ServerProtocol::Udp);
+        }
+        source => panic!("unexpected source {:?}", source),
+    }
+}
+
+#[test]
+fn test_resolver_lookup_records() {
+    let address = run_doh_test_server();
+
+    let mut config = Config::new();
+    config.set_insecure_doh(true);
+    config
+        .add_doh_url(&format!("http://{}/custom/dns", address))
+        .unwrap();
+
+    let resolver = Resolver::new(config).unwrap();
+
+    let lookup = resolver
+        .lookup_records("example.com", LookupType::Txt)
+        .unwrap();
+    assert_eq!(lookup.records().len(), 1);
+    assert_eq!(lookup.records()[0].record_type, "TXT");
+    assert_eq!(
+        lookup.records()[0].fields,
+        Some(RecordFields::Txt {
+            strings: vec!["v=wrecv".to_string()]
+        })
+    );
+
+    assert!(resolver
+        .lookup_records("example.com", LookupType::Mx)
+        .is_err());
+}
+
+#[test]
+fn test_resolver_background_probe() {
+    let address = run_udp_dns_test_server();
+
+    let mut config = Config::new();
+    config
+        .add_udp_server(address)
+        .set_probe_interval(Some(std::time::Duration::from_secs(3600)));
+
+    let resolver = Resolver::new(config).unwrap();
+
+    for _ in 0..100 {
+        if resolver.server_stats()[0].queries > 0 {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+
+    let stats = resolver.server_stats();
+    assert_eq!(stats[0].queries, 1);
+    assert!(stats[0].latency.is_some());
+}
@@ -0,0 +1,234 @@
+#ifndef WRECV_H
+#define WRECV_H
+
+/* Generated with cbindgen. Do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Kind of a transfer event. See `SessionEvent` for descriptions.
+ */
+typedef enum WrecvEventKind {
+  WRECV_EVENT_KIND_CONNECT_ATTEMPT,
+  WRECV_EVENT_KIND_CONNECT_FAILED,
+  WRECV_EVENT_KIND_CONNECTED,
+  WRECV_EVENT_KIND_TLS_CONNECTED,
+  WRECV_EVENT_KIND_HEADER_RECEIVED,
+  WRECV_EVENT_KIND_HEADER_SENT,
+  WRECV_EVENT_KIND_BODY_RECEIVED,
+  WRECV_EVENT_KIND_BODY_SENT,
+  WRECV_EVENT_KIND_CONTENT_SENT,
+  WRECV_EVENT_KIND_CONTENT_RECEIVED,
+  WRECV_EVENT_KIND_HTTP_REQUEST,
+  WRECV_EVENT_KIND_HTTP_INTERIM_RESPONSE,
+  WRECV_EVENT_KIND_HTTP_RESPONSE,
+  WRECV_EVENT_KIND_HTTP_RESPONSE_TRAILER,
+  WRECV_EVENT_KIND_PROGRESS,
+  WRECV_EVENT_KIND_FINISHED,
+  WRECV_EVENT_KIND_CIRCUIT_OPENED,
+  WRECV_EVENT_KIND_CIRCUIT_CLOSED,
+  WRECV_EVENT_KIND_FTP_TRANSFER_TYPE,
+  WRECV_EVENT_KIND_TRACE,
+  /**
+   * Event added in a later version of the library.
+   */
+  WRECV_EVENT_KIND_OTHER,
+} WrecvEventKind;
+
+/**
+ * Result of a function call.
+ */
+typedef enum WrecvStatus {
+  WRECV_STATUS_OK = 0,
+  WRECV_STATUS_UNSUPPORTED_FEATURE,
+  WRECV_STATUS_INVALID_ARGUMENT,
+  WRECV_STATUS_CANCELLED,
+  WRECV_STATUS_PARSE,
+  WRECV_STATUS_PROTOCOL,
+  WRECV_STATUS_NETWORK,
+  WRECV_STATUS_IO,
+  WRECV_STATUS_OTHER,
+  /**
+   * The library panicked. The handle should not be used further.
+   */
+  WRECV_STATUS_PANIC,
+  WRECV_STATUS_BLOCKED,
+} WrecvStatus;
+
+/**
+ * Client that runs transfers on the thread that created it.
+ */
+typedef struct WrecvClient WrecvClient;
+
+/**
+ * Configuration used to create clients.
+ */
+typedef struct WrecvConfig WrecvConfig;
+
+/**
+ * Transfer event given to a callback.
+ *
+ * Pointers are only valid during the callback. Fields that don't apply to
+ * the kind are zero or NULL.
+ */
+typedef struct WrecvEvent {
+  enum WrecvEventKind kind;
+  /**
+   * Bytes of the event, such as a header or a chunk of the body.
+   */
+  const uint8_t *data;
+  uintptr_t data_len;
+  /**
+   * Text of the event, such as an address or host name.
+   */
+  const char *text;
+  /**
+   * Status code of an HTTP response or a finished transfer.
+   */
+  uint16_t status_code;
+  uint64_t download_current;
+  uint64_t download_total;
+  uint64_t upload_current;
+  uint64_t upload_total;
+} WrecvEvent;
+
+/**
+ * Callback that receives the events of a transfer.
+ *
+ * Returning a nonzero value cancels the transfer.
+ */
+typedef int (*WrecvEventCallback)(const struct WrecvEvent *event, void *user_data);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Returns the message of the last error on the current thread, or NULL.
+ *
+ * The string is valid until the next call into the library on the thread.
+ */
+const char *wrecv_last_error(void);
+
+/**
+ * Creates a configuration with default values.
+ */
+struct WrecvConfig *wrecv_config_new(void);
+
+/**
+ * Frees a configuration. NULL is ignored.
+ *
+ * # Safety
+ *
+ * The pointer must be from [`wrecv_config_new`] and not already freed.
+ */
+void wrecv_config_free(struct WrecvConfig *config);
+
+/**
+ * Sets the User-Agent of HTTP requests.
+ *
+ * # Safety
+ *
+ * The pointers must be valid, and the string NUL terminated.
+ */
+enum WrecvStatus wrecv_config_set_user_agent(struct WrecvConfig *config, const char *user_agent);
+
+/**
+ * Sets the connection timeout in milliseconds.
+ *
+ * # Safety
+ *
+ * The pointer must be valid.
+ */
+enum WrecvStatus wrecv_config_set_connect_timeout(struct WrecvConfig *config,
+                                                  uint64_t milliseconds);
+
+/**
+ * Sets the maximum duration of a transfer in milliseconds, or 0 for no
+ * limit.
+ *
+ * # Safety
+ *
+ * The pointer must be valid.
+ */
+enum WrecvStatus wrecv_config_set_max_transfer_duration(struct WrecvConfig *config,
+                                                        uint64_t milliseconds);
+
+/**
+ * Sets the URL of the proxy, or NULL for no proxy.
+ *
+ * # Safety
+ *
+ * The pointers must be valid, and the string NUL terminated.
+ */
+enum WrecvStatus wrecv_config_set_proxy(struct WrecvConfig *config, const char *url);
+
+/**
+ * Sets whether TLS certificates are verified.
+ *
+ * # Safety
+ *
+ * The pointer must be valid.
+ */
+enum WrecvStatus wrecv_config_set_tls_verification(struct WrecvConfig *config, bool enabled);
+
+/**
+ * Sets whether HTTP cookies are stored and sent.
+ *
+ * # Safety
+ *
+ * The pointer must be valid.
+ */
+enum WrecvStatus wrecv_config_set_http_cookies(struct WrecvConfig *config, bool enabled);
+
+/**
+ * Sets whether the content coding of responses is decoded.
+ *
+ * # Safety
+ *
+ * The pointer must be valid.
+ */
+enum WrecvStatus wrecv_config_set_http_content_decoding(struct WrecvConfig *config, bool enabled);
+
+/**
+ * Creates a client with a copy of the configuration, or returns NULL if
+ * the configuration is NULL.
+ *
+ * # Safety
+ *
+ * The pointer must be valid or NULL.
+ */
+struct WrecvClient *wrecv_client_new(const struct WrecvConfig *config);
+
+/**
+ * Frees a client. NULL is ignored.
+ *
+ * # Safety
+ *
+ * The pointer must be from [`wrecv_client_new`] and not already freed.
+ */
+void wrecv_client_free(struct WrecvClient *client);
+
+/**
+ * Downloads the URL, delivering events to the callback if not NULL.
+ *
+ * On failure, the message is available from [`wrecv_last_error`].
+ *
+ * # Safety
+ *
+ * The pointers must be valid, the string NUL terminated, and the client
+ * used on the thread that created it.
+ */
+enum WrecvStatus wrecv_client_fetch(struct WrecvClient *client,
+                                    const char *url,
+                                    WrecvEventCallback callback,
+                                    void *user_data);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* WRECV_H */
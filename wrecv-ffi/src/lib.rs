@@ -0,0 +1,627 @@
+//! C API of the wrecv client.
+//!
+//! Handles are opaque and owned by the caller, who frees them with the
+//! matching `_free` function. A client must only be used by the thread that
+//! created it. Events of a transfer are delivered to a callback as they
+//! occur, mirroring `SessionHandler`.
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, c_void, CStr, CString},
+    panic::AssertUnwindSafe,
+    time::Duration,
+};
+
+use url::Url;
+use wrecv::{
+    client::{
+        Client, Config, FtpTransferType, Request, SessionControl, SessionEvent, SessionHandler,
+    },
+    error::{BoxedError, Error, ErrorCategory},
+};
+
+/// Configuration used to create clients.
+pub struct WrecvConfig {
+    config: Config,
+}
+
+/// Client that runs transfers on the thread that created it.
+pub struct WrecvClient {
+    client: Client,
+}
+
+/// Result of a function call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WrecvStatus {
+    Ok = 0,
+    UnsupportedFeature,
+    InvalidArgument,
+    Cancelled,
+    Parse,
+    Protocol,
+    Network,
+    Io,
+    Other,
+    /// The library panicked. The handle should not be used further.
+    Panic,
+    Blocked,
+}
+
+impl From<ErrorCategory> for WrecvStatus {
+    fn from(value: ErrorCategory) -> Self {
+        match value {
+            ErrorCategory::UnsupportedFeature => Self::UnsupportedFeature,
+            ErrorCategory::InvalidArgument => Self::InvalidArgument,
+            ErrorCategory::Cancelled => Self::Cancelled,
+            ErrorCategory::Blocked => Self::Blocked,
+            ErrorCategory::Parse => Self::Parse,
+            ErrorCategory::Protocol => Self::Protocol,
+            ErrorCategory::Network => Self::Network,
+            ErrorCategory::Io => Self::Io,
+            ErrorCategory::Other => Self::Other,
+        }
+    }
+}
+
+/// Kind of a transfer event. See `SessionEvent` for descriptions.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WrecvEventKind {
+    ConnectAttempt,
+    ConnectFailed,
+    Connected,
+    TlsConnected,
+    HeaderReceived,
+    HeaderSent,
+    BodyReceived,
+    BodySent,
+    ContentSent,
+    ContentReceived,
+    HttpRequest,
+    HttpInterimResponse,
+    HttpResponse,
+    HttpResponseTrailer,
+    Progress,
+    Finished,
+    CircuitOpened,
+    CircuitClosed,
+    FtpTransferType,
+    Trace,
+    /// Event added in a later version of the library.
+    Other,
+}
+
+/// Transfer event given to a callback.
+///
+/// Pointers are only valid during the callback. Fields that don't apply to
+/// the kind are zero or NULL.
+#[repr(C)]
+#[derive(Debug)]
+pub struct WrecvEvent {
+    pub kind: WrecvEventKind,
+    /// Bytes of the event, such as a header or a chunk of the body.
+    pub data: *const u8,
+    pub data_len: usize,
+    /// Text of the event, such as an address or host name.
+    pub text: *const c_char,
+    /// Status code of an HTTP response or a finished transfer.
+    pub status_code: u16,
+    pub download_current: u64,
+    pub download_total: u64,
+    pub upload_current: u64,
+    pub upload_total: u64,
+}
+
+/// Callback that receives the events of a transfer.
+///
+/// Returning a nonzero value cancels the transfer.
+pub type WrecvEventCallback =
+    Option<unsafe extern "C" fn(event: *const WrecvEvent, user_data: *mut c_void) -> c_int>;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', "")).expect("no NUL bytes");
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+fn fail(status: WrecvStatus, message: String) -> WrecvStatus {
+    set_last_error(message);
+    status
+}
+
+fn invalid_argument(message: &str) -> WrecvStatus {
+    fail(WrecvStatus::InvalidArgument, message.to_string())
+}
+
+/// Runs the function, converting a panic into [`WrecvStatus::Panic`].
+fn catch_panic<F: FnOnce() -> WrecvStatus>(function: F) -> WrecvStatus {
+    match std::panic::catch_unwind(AssertUnwindSafe(function)) {
+        Ok(status) => status,
+        Err(_) => fail(WrecvStatus::Panic, "panic in wrecv".to_string()),
+    }
+}
+
+unsafe fn str_arg<'a>(value: *const c_char) -> Option<&'a str> {
+    if value.is_null() {
+        None
+    } else {
+        CStr::from_ptr(value).to_str().ok()
+    }
+}
+
+/// Returns the message of the last error on the current thread, or NULL.
+///
+/// The string is valid until the next call into the library on the thread.
+#[no_mangle]
+pub extern "C" fn wrecv_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map(|message| message.as_ptr())
+            .unwrap_or(std::ptr::null())
+    })
+}
+
+/// Creates a configuration with default values.
+#[no_mangle]
+pub extern "C" fn wrecv_config_new() -> *mut WrecvConfig {
+    Box::into_raw(Box::new(WrecvConfig {
+        config: Config::new(),
+    }))
+}
+
+/// Frees a configuration. NULL is ignored.
+///
+/// # Safety
+///
+/// The pointer must be from [`wrecv_config_new`] and not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn wrecv_config_free(config: *mut WrecvConfig) {
+    if !config.is_null() {
+        drop(Box::from_raw(config));
+    }
+}
+
+/// Sets the User-Agent of HTTP requests.
+///
+/// # Safety
+///
+/// The pointers must be valid, and the string NUL terminated.
+#[no_mangle]
+pub unsafe extern "C" fn wrecv_config_set_user_agent(
+    config: *mut WrecvConfig,
+    user_agent: *const c_char,
+) -> WrecvStatus {
+    let Some(config) = config.as_mut() else {
+        return invalid_argument("config is NULL");
+    };
+    let Some(user_agent) = str_arg(user_agent) else {
+        return invalid_argument("user agent is NULL or not UTF-8");
+    };
+
+    config.config.set_http_user_agent(user_agent.to_string());
+
+    WrecvStatus::Ok
+}
+
+/// Sets the connection timeout in milliseconds.
+///
+/// # Safety
+///
+/// The pointer must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn wrecv_config_set_connect_timeout(
+    config: *mut WrecvConfig,
+    milliseconds: u64,
+) -> WrecvStatus {
+    let Some(config) = config.as_mut() else {
+        return invalid_argument("config is NULL");
+    };
+
+    config
+        .config
+        .set_connect_timeout(Duration::from_millis(milliseconds));
+
+    WrecvStatus::Ok
+}
+
+/// Sets the maximum duration of a transfer in milliseconds, or 0 for no
+/// limit.
+///
+/// # Safety
+///
+/// The pointer must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn wrecv_config_set_max_transfer_duration(
+    config: *mut WrecvConfig,
+    milliseconds: u64,
+) -> WrecvStatus {
+    let Some(config) = config.as_mut() else {
+        return invalid_argument("config is NULL");
+    };
+
+    config
+        .config
+        .set_max_transfer_duration((milliseconds > 0).then(|| Duration::from_millis(milliseconds)));
+
+    WrecvStatus::Ok
+}
+
+/// Sets the URL of the proxy, or NULL for no proxy.
+///
+/// # Safety
+///
+/// The pointers must be valid, and the string NUL terminated.
+#[no_mangle]
+pub unsafe extern "C" fn wrecv_config_set_proxy(
+    config: *mut WrecvConfig,
+    url: *const c_char,
+) -> WrecvStatus {
+    let Some(config) = config.as_mut() else {
+        return invalid_argument("config is NULL");
+    };
+
+    let proxy = if url.is_null() {
+        None
+    } else {
+        match str_arg(url).map(Url::parse) {
+            Some(Ok(url)) => Some(url),
+            _ => return invalid_argument("invalid proxy URL"),
+        }
+    };
+
+    config.config.set_proxy(proxy);
+
+    WrecvStatus::Ok
+}
+
+/// Sets whether TLS certificates are verified.
+///
+/// # Safety
+///
+/// The pointer must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn wrecv_config_set_tls_verification(
+    config: *mut WrecvConfig,
+    enabled: bool,
+) -> WrecvStatus {
+    let Some(config) = config.as_mut() else {
+        return invalid_argument("config is NULL");
+    };
+
+    config.config.set_tls_verification(enabled);
+
+    WrecvStatus::Ok
+}
+
+/// Sets whether HTTP cookies are stored and sent.
+///
+/// # Safety
+///
+/// The pointer must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn wrecv_config_set_http_cookies(
+    config: *mut WrecvConfig,
+    enabled: bool,
+) -> WrecvStatus {
+    let Some(config) = config.as_mut() else {
+        return invalid_argument("config is NULL");
+    };
+
+    config.config.set_http_cookies(enabled);
+
+    WrecvStatus::Ok
+}
+
+/// Sets whether the content coding of responses is decoded.
+///
+/// # Safety
+///
+/// The pointer must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn wrecv_config_set_http_content_decoding(
+    config: *mut WrecvConfig,
+    enabled: bool,
+) -> WrecvStatus {
+    let Some(config) = config.as_mut() else {
+        return invalid_argument("config is NULL");
+    };
+
+    config.config.set_http_content_decoding(enabled);
+
+    WrecvStatus::Ok
+}
+
+/// Creates a client with a copy of the configuration, or returns NULL if
+/// the configuration is NULL.
+///
+/// # Safety
+///
+/// The pointer must be valid or NULL.
+#[no_mangle]
+pub unsafe extern "C" fn wrecv_client_new(config: *const WrecvConfig) -> *mut WrecvClient {
+    let Some(config) = config.as_ref() else {
+        invalid_argument("config is NULL");
+        return std::ptr::null_mut();
+    };
+
+    Box::into_raw(Box::new(WrecvClient {
+        client: Client::new(config.config.clone()),
+    }))
+}
+
+/// Frees a client. NULL is ignored.
+///
+/// # Safety
+///
+/// The pointer must be from [`wrecv_client_new`] and not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn wrecv_client_free(client: *mut WrecvClient) {
+    if !client.is_null() {
+        drop(Box::from_raw(client));
+    }
+}
+
+/// Downloads the URL, delivering events to the callback if not NULL.
+///
+/// On failure, the message is available from [`wrecv_last_error`].
+///
+/// # Safety
+///
+/// The pointers must be valid, the string NUL terminated, and the client
+/// used on the thread that created it.
+#[no_mangle]
+pub unsafe extern "C" fn wrecv_client_fetch(
+    client: *mut WrecvClient,
+    url: *const c_char,
+    callback: WrecvEventCallback,
+    user_data: *mut c_void,
+) -> WrecvStatus {
+    let Some(client) = client.as_mut() else {
+        return invalid_argument("client is NULL");
+    };
+    let url = match str_arg(url).map(Url::parse) {
+        Some(Ok(url)) => url,
+        _ => return invalid_argument("invalid URL"),
+    };
+
+    catch_panic(|| {
+        let handler = CallbackHandler {
+            callback,
+            user_data,
+        };
+        let (_handler, result) = client.client.submit(Request::new(url), handler);
+
+        match result {
+            Ok(_) => WrecvStatus::Ok,
+            Err(error) => fail(error.category().into(), error.to_string()),
+        }
+    })
+}
+
+struct CallbackHandler {
+    callback: WrecvEventCallback,
+    user_data: *mut c_void,
+}
+
+impl SessionHandler for CallbackHandler {
+    fn event(
+        &mut self,
+        _control: &mut dyn SessionControl,
+        event: SessionEvent,
+    ) -> Result<(), BoxedError> {
+        let Some(callback) = self.callback else {
+            return Ok(());
+        };
+
+        let (mut event, text) = convert_event(event);
+        let text = text.map(|text| CString::new(text.replace('\0', "")).expect("no NUL bytes"));
+
+        if let Some(text) = &text {
+            event.text = text.as_ptr();
+        }
+
+        if unsafe { callback(&event, self.user_data) } != 0 {
+            return Err(Box::new(Error::Cancelled));
+        }
+
+        Ok(())
+    }
+}
+
+fn convert_event(event: SessionEvent) -> (WrecvEvent, Option<String>) {
+    let mut data: &[u8] = &[];
+    let mut text = None;
+    let mut ffi_event = WrecvEvent {
+        kind: WrecvEventKind::Other,
+        data: std::ptr::null(),
+        data_len: 0,
+        text: std::ptr::null(),
+        status_code: 0,
+        download_current: 0,
+        download_total: 0,
+        upload_current: 0,
+        upload_total: 0,
+    };
+
+    ffi_event.kind = match event {
+        SessionEvent::ConnectAttempt(address) => {
+            text = Some(address.to_string());
+            WrecvEventKind::ConnectAttempt
+        }
+        SessionEvent::ConnectFailed(address, reason) => {
+            text = Some(format!("{}: {}", address, reason));
+            WrecvEventKind::ConnectFailed
+        }
+        SessionEvent::Connected(address) => {
+            text = Some(address.to_string());
+            WrecvEventKind::Connected
+        }
+        SessionEvent::TlsConnected(_) => WrecvEventKind::TlsConnected,
+        SessionEvent::HeaderReceived(bytes) => {
+            data = bytes;
+            WrecvEventKind::HeaderReceived
+        }
+        SessionEvent::HeaderSent(bytes) => {
+            data = bytes;
+            WrecvEventKind::HeaderSent
+        }
+        SessionEvent::BodyReceived(bytes) => {
+            data = bytes;
+            WrecvEventKind::BodyReceived
+        }
+        SessionEvent::BodySent(bytes) => {
+            data = bytes;
+            WrecvEventKind::BodySent
+        }
+        SessionEvent::ContentSent(bytes) => {
+            data = bytes;
+            WrecvEventKind::ContentSent
+        }
+        SessionEvent::ContentReceived(bytes) => {
+            data = bytes;
+            WrecvEventKind::ContentReceived
+        }
+        SessionEvent::HttpRequest(bytes, _header) => {
+            data = bytes;
+            WrecvEventKind::HttpRequest
+        }
+        SessionEvent::HttpInterimResponse(bytes, header) => {
+            data = bytes;
+            ffi_event.status_code = header.status_code;
+            WrecvEventKind::HttpInterimResponse
+        }
+        SessionEvent::HttpResponse(bytes, header) => {
+            data = bytes;
+            ffi_event.status_code = header.status_code;
+            WrecvEventKind::HttpResponse
+        }
+        SessionEvent::HttpResponseTrailer(bytes, _trailer) => {
+            data = bytes;
+            WrecvEventKind::HttpResponseTrailer
+        }
+        SessionEvent::Progress {
+            download_total,
+            download_current,
+            upload_total,
+            upload_current,
+            ..
+        } => {
+            ffi_event.download_total = download_total;
+            ffi_event.download_current = download_current;
+            ffi_event.upload_total = upload_total;
+            ffi_event.upload_current = upload_current;
+            WrecvEventKind::Progress
+        }
+        SessionEvent::Finished(stats) => {
+            ffi_event.status_code = stats.status_code.unwrap_or(0);
+            ffi_event.download_current = stats.bytes_received;
+            ffi_event.upload_current = stats.bytes_sent;
+            WrecvEventKind::Finished
+        }
+        SessionEvent::CircuitOpened(host, _duration) => {
+            text = Some(host);
+            WrecvEventKind::CircuitOpened
+        }
+        SessionEvent::CircuitClosed(host) => {
+            text = Some(host);
+            WrecvEventKind::CircuitClosed
+        }
+        SessionEvent::FtpTransferType(transfer_type) => {
+            text = Some(
+                match transfer_type {
+                    FtpTransferType::Ascii => "ascii",
+                    FtpTransferType::Binary => "binary",
+                }
+                .to_string(),
+            );
+            WrecvEventKind::FtpTransferType
+        }
+        SessionEvent::Trace(_kind, bytes) => {
+            data = bytes;
+            WrecvEventKind::Trace
+        }
+        _ => WrecvEventKind::Other,
+    };
+
+    if !data.is_empty() {
+        ffi_event.data = data.as_ptr();
+        ffi_event.data_len = data.len();
+    }
+
+    (ffi_event, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config() {
+        let config = wrecv_config_new();
+
+        unsafe {
+            assert_eq!(
+                wrecv_config_set_user_agent(config, c"test".as_ptr()),
+                WrecvStatus::Ok
+            );
+            assert_eq!(
+                wrecv_config_set_proxy(config, c"not a url".as_ptr()),
+                WrecvStatus::InvalidArgument
+            );
+            assert!(!wrecv_last_error().is_null());
+            assert_eq!(
+                wrecv_config_set_user_agent(std::ptr::null_mut(), c"test".as_ptr()),
+                WrecvStatus::InvalidArgument
+            );
+            wrecv_config_free(config);
+        }
+    }
+
+    #[test]
+    fn test_client_fetch_error() {
+        unsafe extern "C" fn callback(event: *const WrecvEvent, user_data: *mut c_void) -> c_int {
+            let kinds = &mut *(user_data as *mut Vec<WrecvEventKind>);
+            kinds.push((*event).kind);
+            0
+        }
+
+        let mut kinds = Vec::<WrecvEventKind>::new();
+
+        unsafe {
+            let config = wrecv_config_new();
+            let client = wrecv_client_new(config);
+            wrecv_config_free(config);
+
+            assert_eq!(
+                wrecv_client_fetch(
+                    client,
+                    c"not a url".as_ptr(),
+                    Some(callback),
+                    &mut kinds as *mut _ as *mut c_void
+                ),
+                WrecvStatus::InvalidArgument
+            );
+
+            let status = wrecv_client_fetch(
+                client,
+                c"http://127.0.0.1:1/".as_ptr(),
+                Some(callback),
+                &mut kinds as *mut _ as *mut c_void,
+            );
+            assert_eq!(status, WrecvStatus::Network);
+
+            let message = CStr::from_ptr(wrecv_last_error()).to_str().unwrap();
+            assert!(message.contains("connect"));
+
+            wrecv_client_free(client);
+        }
+
+        assert!(kinds.contains(&WrecvEventKind::Finished));
+    }
+}